@@ -0,0 +1,443 @@
+use super::{DisassembledProgram, JumpTable};
+use crate::utils::quote;
+use crate::x86::{Formatter, Instruction, Operand, IR};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Shortest string found without a reference to it
+const MIN_STRING: usize = 3;
+// Fewest entries of a pointer table
+const MIN_POINTERS: usize = 2;
+// Longest string shown in an annotation
+const MAX_ANNOTATION: usize = 32;
+
+/// A NUL-terminated string of the data segment.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DataString {
+    pub address: u16,
+    /// Bytes without the NUL
+    pub bytes: Vec<u8>,
+}
+
+/// Segment the entries of a pointer table point into.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PointerKind {
+    /// Strings of the data segment
+    Data,
+    /// Jump and call targets
+    Text,
+}
+
+/// Consecutive words of the data segment pointing to strings or code.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PointerTable {
+    pub address: u16,
+    pub kind: PointerKind,
+    pub entries: Vec<u16>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReferenceKind {
+    Call,
+    Jump,
+    /// Direct memory operand, like `mov ax, [0010]`
+    Memory,
+    /// Immediate address of a string or table, like `mov ax, 0004`
+    Address,
+}
+
+/// An instruction referencing an address.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Reference {
+    /// Text address of the instruction
+    pub from: u16,
+    pub kind: ReferenceKind,
+}
+
+/// Index of the instructions referencing each data and code address.
+#[derive(Debug, Default)]
+pub struct CrossReferences {
+    data: BTreeMap<u16, Vec<Reference>>,
+    code: BTreeMap<u16, Vec<Reference>>,
+}
+
+impl CrossReferences {
+    /// Instructions referencing the data address.
+    pub fn to_data(&self, address: u16) -> &[Reference] {
+        self.data.get(&address).map_or(&[], |refs| refs.as_slice())
+    }
+
+    /// Instructions calling or jumping to the code address.
+    pub fn to_code(&self, address: u16) -> &[Reference] {
+        self.code.get(&address).map_or(&[], |refs| refs.as_slice())
+    }
+
+    /// Referenced data addresses, in order.
+    pub fn data_addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.data.keys().copied()
+    }
+
+    /// Called or jumped to code addresses, in order.
+    pub fn code_addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.code.keys().copied()
+    }
+}
+
+/// Strings, pointer tables, jump tables and cross-references of a disassembled program.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub strings: Vec<DataString>,
+    pub pointer_tables: Vec<PointerTable>,
+    pub jump_tables: Vec<JumpTable>,
+    pub xrefs: CrossReferences,
+}
+
+impl Analysis {
+    pub fn string_at(&self, address: u16) -> Option<&DataString> {
+        self.strings.iter().find(|s| s.address == address)
+    }
+
+    pub fn pointer_table_at(&self, address: u16) -> Option<&PointerTable> {
+        self.pointer_tables.iter().find(|t| t.address == address)
+    }
+
+    /// Jump table of the data segment at the address.
+    pub fn jump_table_at(&self, address: u16) -> Option<&JumpTable> {
+        self.jump_tables
+            .iter()
+            .find(|t| t.address == address && t.segment == PointerKind::Data)
+    }
+
+    /// Whether a string or a table starts at the data address.
+    pub(super) fn is_known(&self, address: u16) -> bool {
+        self.string_at(address).is_some()
+            || self.pointer_table_at(address).is_some()
+            || self.jump_table_at(address).is_some()
+    }
+
+    /// Comment describing the data referenced by the instruction, if any.
+    pub fn annotation(&self, data: &[u8], instruction: &Instruction) -> Option<String> {
+        let address = data_reference(instruction, |address| self.is_known(address))?;
+        if let Some(string) = self.string_at(address) {
+            return Some(quote(&string.bytes, MAX_ANNOTATION));
+        }
+        if let Some(table) = self.pointer_table_at(address) {
+            let kind = match table.kind {
+                PointerKind::Data => "strings",
+                PointerKind::Text => "code",
+            };
+            return Some(format!("{} pointers to {}", table.entries.len(), kind));
+        }
+        if let Some(table) = self.jump_table_at(address) {
+            return Some(format!("jump table of {} cases", table.cases.len()));
+        }
+        let address = address as usize;
+        match data.get(address..address + 2) {
+            Some(word) => Some(format!("0x{:04x}", u16::from_le_bytes([word[0], word[1]]))),
+            None => data.get(address).map(|byte| format!("0x{:02x}", byte)),
+        }
+    }
+}
+
+impl DisassembledProgram {
+    /// Find the strings, pointer tables and jump tables, and index the references.
+    pub fn analyze(&self) -> Analysis {
+        let starts: BTreeSet<u16> = self
+            .iter_with_address()
+            .map(|(address, _)| address)
+            .collect();
+        let jump_tables = self.jump_tables();
+        let targets: BTreeSet<u16> = self
+            .instructions
+            .iter()
+            .filter_map(|instruction| instruction.ir.branch_target())
+            .chain(jump_tables.iter().flat_map(|table| table.targets()))
+            .filter(|target| starts.contains(target))
+            .collect();
+
+        // Immediates and pointers that may be string addresses
+        let mut candidates: BTreeSet<u16> = self
+            .instructions
+            .iter()
+            .filter_map(|instruction| match &instruction.ir {
+                IR::Mov {
+                    src: Operand::LongImmediate(value),
+                    ..
+                } if !matches!(instruction.raw.first(), Some(0xa0..=0xa3)) => Some(*value),
+                _ => instruction.direct_address(),
+            })
+            .collect();
+        candidates.extend(
+            self.data
+                .chunks_exact(2)
+                .map(|w| u16::from_le_bytes([w[0], w[1]])),
+        );
+
+        let strings = find_strings(&self.data, &candidates);
+        let pointer_tables = find_pointer_tables(&self.data, &strings, &targets);
+
+        let mut analysis = Analysis {
+            strings,
+            pointer_tables,
+            jump_tables,
+            ..Analysis::default()
+        };
+        for (address, instruction) in self.iter_with_address() {
+            if let Some(target) = instruction.ir.branch_target() {
+                let kind = match instruction.ir {
+                    IR::Call { .. } => ReferenceKind::Call,
+                    _ => ReferenceKind::Jump,
+                };
+                let reference = Reference {
+                    from: address,
+                    kind,
+                };
+                analysis
+                    .xrefs
+                    .code
+                    .entry(target)
+                    .or_default()
+                    .push(reference);
+            }
+            if let Some(target) = data_reference(instruction, |target| analysis.is_known(target)) {
+                let kind = match instruction.direct_address() {
+                    Some(_) => ReferenceKind::Memory,
+                    None => ReferenceKind::Address,
+                };
+                let reference = Reference {
+                    from: address,
+                    kind,
+                };
+                analysis
+                    .xrefs
+                    .data
+                    .entry(target)
+                    .or_default()
+                    .push(reference);
+            }
+        }
+        // Jumps through the tables
+        for table in &analysis.jump_tables {
+            for target in table.targets() {
+                let reference = Reference {
+                    from: table.jump,
+                    kind: ReferenceKind::Jump,
+                };
+                analysis
+                    .xrefs
+                    .code
+                    .entry(target)
+                    .or_default()
+                    .push(reference);
+            }
+        }
+        for references in analysis.xrefs.code.values_mut() {
+            references.sort_by_key(|reference| reference.from);
+        }
+        analysis
+    }
+
+    /// Listing with a comment on the instructions referencing data.
+    pub fn format_annotated(&self, formatter: &Formatter) -> String {
+        let analysis = self.analyze();
+        let mut out = String::new();
+        for line in self.format(formatter).lines().zip(&self.instructions) {
+            match analysis.annotation(&self.data, line.1) {
+                Some(annotation) => out.push_str(&format!("{:<44}; {}\n", line.0, annotation)),
+                None => {
+                    out.push_str(line.0);
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Data address referenced by the instruction: a direct memory operand,
+/// or an immediate moved into a register when `known` says it is a string or a table.
+pub(super) fn data_reference(
+    instruction: &Instruction,
+    known: impl Fn(u16) -> bool,
+) -> Option<u16> {
+    if let Some(address) = instruction.direct_address() {
+        return Some(address);
+    }
+    match &instruction.ir {
+        IR::Mov {
+            dest: Operand::Register(_),
+            src: Operand::LongImmediate(value),
+            ..
+        } if known(*value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn printable(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7e | b'\n' | b'\t' | b'\r')
+}
+
+/// Length of the printable NUL-terminated string at the start of `data`.
+fn string_length(data: &[u8]) -> Option<usize> {
+    let length = data.iter().take_while(|&&b| printable(b)).count();
+    (data.get(length) == Some(&0)).then_some(length)
+}
+
+/// Strings of at least `MIN_STRING` bytes after a NUL or a non printable byte,
+/// and the shorter ones after a NUL at a candidate address.
+fn find_strings(data: &[u8], candidates: &BTreeSet<u16>) -> Vec<DataString> {
+    let mut addresses = BTreeSet::new();
+    let mut address = 0;
+    while address < data.len() {
+        match string_length(&data[address..]) {
+            Some(length) if length >= MIN_STRING => {
+                addresses.insert(address);
+                address += length + 1;
+            }
+            _ => address += 1,
+        }
+    }
+    for &candidate in candidates {
+        let candidate = candidate as usize;
+        let boundary = candidate == 0 || data.get(candidate - 1) == Some(&0);
+        let length = data.get(candidate..).and_then(string_length);
+        if boundary && length.map_or(false, |length| length > 0) {
+            addresses.insert(candidate);
+        }
+    }
+    addresses
+        .into_iter()
+        .map(|address| {
+            let length = string_length(&data[address..]).unwrap_or(0);
+            DataString {
+                address: address as u16,
+                bytes: data[address..address + length].to_vec(),
+            }
+        })
+        .collect()
+}
+
+/// Runs of aligned words all pointing to strings, or all to jump and call targets.
+fn find_pointer_tables(
+    data: &[u8],
+    strings: &[DataString],
+    targets: &BTreeSet<u16>,
+) -> Vec<PointerTable> {
+    let kind_of = |word: u16| {
+        if strings.iter().any(|s| s.address == word) {
+            Some(PointerKind::Data)
+        } else if targets.contains(&word) {
+            Some(PointerKind::Text)
+        } else {
+            None
+        }
+    };
+    let mut tables: Vec<PointerTable> = vec![];
+    let mut current: Option<PointerTable> = None;
+    for (index, word) in data.chunks_exact(2).enumerate() {
+        let word = u16::from_le_bytes([word[0], word[1]]);
+        let kind = if word == 0 { None } else { kind_of(word) };
+        match (&mut current, kind) {
+            (Some(table), Some(kind)) if table.kind == kind => table.entries.push(word),
+            (_, kind) => {
+                tables.extend(current.take());
+                current = kind.map(|kind| PointerTable {
+                    address: (index * 2) as u16,
+                    kind,
+                    entries: vec![word],
+                });
+            }
+        }
+    }
+    tables.extend(current);
+    tables.retain(|table| table.entries.len() >= MIN_POINTERS);
+    tables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::Disassemblable;
+    use crate::test_utils::program;
+    use crate::x86::Register;
+
+    #[test]
+    fn test_strings_and_tables() {
+        // "hello\n" at 4, "ok" at 0x0b, a table of pointers to them at 0x0e
+        let mut data = vec![0, 0, 0, 0];
+        data.extend(b"hello\n\0ok\0");
+        data.extend([4, 0, 0x0b, 0, 0, 0]);
+        let mov = Instruction::new(
+            IR::Mov {
+                dest: Operand::Register(Register::AX),
+                src: Operand::LongImmediate(0x000b),
+                byte: false,
+            },
+            vec![0xb8, 0x0b, 0x00],
+        );
+        let disassembled = DisassembledProgram::new(vec![mov], data);
+        let analysis = disassembled.analyze();
+
+        // "ok" is too short, but it is pointed to
+        assert_eq!(
+            analysis.strings,
+            [
+                DataString {
+                    address: 4,
+                    bytes: b"hello\n".to_vec()
+                },
+                DataString {
+                    address: 0x0b,
+                    bytes: b"ok".to_vec()
+                }
+            ]
+        );
+        assert_eq!(
+            analysis.pointer_tables,
+            [PointerTable {
+                address: 0x0e,
+                kind: PointerKind::Data,
+                entries: vec![4, 0x0b]
+            }]
+        );
+        assert_eq!(
+            analysis.xrefs.to_data(0x0b),
+            [Reference {
+                from: 0,
+                kind: ReferenceKind::Address
+            }]
+        );
+        assert_eq!(
+            disassembled.format_annotated(&Formatter::default()),
+            "0000: b80b00        mov ax, 000b            ; \"ok\"\n"
+        );
+    }
+
+    #[test]
+    fn test_xrefs() {
+        let disassembled = program("1.c").disassemble().unwrap();
+        let analysis = disassembled.analyze();
+
+        // main is called from the start up code
+        assert_eq!(
+            analysis.xrefs.to_code(0x39),
+            [Reference {
+                from: 0x0031,
+                kind: ReferenceKind::Call
+            }]
+        );
+        let jumpers: Vec<u16> = analysis
+            .xrefs
+            .to_code(0x0135)
+            .iter()
+            .map(|r| r.from)
+            .collect();
+        assert_eq!(jumpers, [0x004e, 0x0085, 0x00ad, 0x00da, 0x013b]);
+        // Written then read by the start up code
+        let readers: Vec<ReferenceKind> =
+            analysis.xrefs.to_data(2).iter().map(|r| r.kind).collect();
+        assert_eq!(readers, [ReferenceKind::Memory, ReferenceKind::Memory]);
+
+        let listing = disassembled.format_annotated(&Formatter::default());
+        assert!(listing.contains("0028: 8b1e0200      mov bx, [0002]          ; 0x0014\n"));
+    }
+}
@@ -0,0 +1,289 @@
+use super::jump_table::{resolve, LOOK_BACK};
+use super::{parse_instruction, DisassembledProgram, JumpTable};
+use crate::minix::SymbolTable;
+use crate::x86::{Instruction, Operand, IR};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Instructions run in sequence, entered at the first one.
+#[derive(Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// Address after the last instruction
+    pub end: u16,
+    pub instructions: Vec<(u16, Instruction)>,
+    /// Start of the blocks of the function that can run next
+    pub successors: Vec<u16>,
+}
+
+impl BasicBlock {
+    pub fn last(&self) -> Option<&Instruction> {
+        self.instructions.last().map(|(_, instruction)| instruction)
+    }
+}
+
+/// A function and the blocks reachable from its entry without calls.
+#[derive(Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub entry: u16,
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    /// Tables of the indirect jumps and switch helpers, whose targets are blocks
+    pub jump_tables: Vec<JumpTable>,
+}
+
+impl Function {
+    /// Blocks with an edge to the given block.
+    pub fn predecessors(&self, start: u16) -> Vec<u16> {
+        self.blocks
+            .values()
+            .filter(|block| block.successors.contains(&start))
+            .map(|block| block.start)
+            .collect()
+    }
+}
+
+/// Name of the function at the address, without the leading `_` of the C symbols.
+pub fn function_name(symbols: &SymbolTable, address: u16) -> String {
+    match symbols.label_at(address).filter(|s| s.is_global()) {
+        Some(symbol) => symbol
+            .name
+            .strip_prefix('_')
+            .unwrap_or(&symbol.name)
+            .to_string(),
+        None if address == 0 => "start".to_string(),
+        None => format!("sub_{:04x}", address),
+    }
+}
+
+/// Return true if the instruction ends a block without falling through.
+pub(super) fn is_terminator(ir: &IR) -> bool {
+    matches!(
+        ir,
+        IR::Jmp { .. } | IR::Ret { .. } | IR::Hlt | IR::Iret | IR::Undefined
+    )
+}
+
+/// Direct jump or branch target of the instruction, not a call.
+fn jump_target(ir: &IR) -> Option<u16> {
+    match ir {
+        IR::Call { .. } => None,
+        ir => ir.branch_target(),
+    }
+}
+
+impl DisassembledProgram {
+    /// Bytes of the text segment.
+    pub fn text(&self) -> Vec<u8> {
+        self.instructions
+            .iter()
+            .flat_map(|instruction| instruction.raw.iter().copied())
+            .collect()
+    }
+
+    /// Functions of the program, from the entry point, the text symbols and the
+    /// call targets. Instructions are decoded from each entry, following the jumps.
+    pub fn functions(&self, symbols: &SymbolTable) -> Vec<Function> {
+        let text = self.text();
+        let mut entries: BTreeSet<u16> = symbols
+            .functions()
+            .iter()
+            .map(|symbol| symbol.value as u16)
+            .filter(|&address| (address as usize) < text.len())
+            .collect();
+        entries.insert(0);
+
+        let mut functions = BTreeMap::new();
+        let mut pending: Vec<u16> = entries.iter().copied().collect();
+        while let Some(entry) = pending.pop() {
+            if functions.contains_key(&entry) {
+                continue;
+            }
+            let (instructions, jump_tables) = decode_function(&text, &self.data, entry, &entries);
+            for (_, instruction) in instructions.values() {
+                if let IR::Call {
+                    dest: Operand::Displacement(_),
+                } = instruction.ir
+                {
+                    if let Some(target) = instruction.ir.branch_target() {
+                        if (target as usize) < text.len() && entries.insert(target) {
+                            pending.push(target);
+                        }
+                    }
+                }
+            }
+            let function = Function {
+                name: function_name(symbols, entry),
+                entry,
+                blocks: split_blocks(entry, instructions, &entries, &jump_tables),
+                jump_tables,
+            };
+            functions.insert(entry, function);
+        }
+        functions.into_values().collect()
+    }
+}
+
+/// Instructions reachable from the entry, with their size, without entering other functions,
+/// and the jump tables of the indirect jumps.
+fn decode_function(
+    text: &[u8],
+    data: &[u8],
+    entry: u16,
+    entries: &BTreeSet<u16>,
+) -> (BTreeMap<u16, (usize, Instruction)>, Vec<JumpTable>) {
+    let mut instructions = BTreeMap::new();
+    let mut jump_tables: Vec<JumpTable> = vec![];
+    let mut pending = vec![entry];
+    while let Some(mut address) = pending.pop() {
+        // Addresses of the instructions run in sequence up to this one
+        let mut run = vec![];
+        while !instructions.contains_key(&address) {
+            let Some(bytes) = text.get(address as usize..) else {
+                break;
+            };
+            let Ok((instruction, size)) = parse_instruction(bytes, address as usize) else {
+                break;
+            };
+            let next = address.wrapping_add(size as u16);
+            if let Some(target) = jump_target(&instruction.ir) {
+                if !entries.contains(&target) || target == entry {
+                    pending.push(target);
+                }
+            }
+            let terminator = is_terminator(&instruction.ir);
+            let jump = matches!(instruction.ir, IR::Jmp { .. });
+            instructions.insert(address, (size, instruction));
+            run.push(address);
+            if jump {
+                let history: Vec<(u16, &Instruction)> = run[run.len().saturating_sub(LOOK_BACK)..]
+                    .iter()
+                    .map(|address| (*address, &instructions[address].1))
+                    .collect();
+                if let Some(table) = resolve(&history, text, data) {
+                    for target in table.targets() {
+                        if !entries.contains(&target) || target == entry {
+                            pending.push(target);
+                        }
+                    }
+                    jump_tables.push(table);
+                }
+            }
+            if terminator || next as usize >= text.len() || entries.contains(&next) {
+                break;
+            }
+            address = next;
+        }
+    }
+    jump_tables.sort_by_key(|table| table.jump);
+    (instructions, jump_tables)
+}
+
+/// Group the instructions in blocks, starting at the entry, the jump targets
+/// and the targets of the jump tables.
+fn split_blocks(
+    entry: u16,
+    instructions: BTreeMap<u16, (usize, Instruction)>,
+    entries: &BTreeSet<u16>,
+    jump_tables: &[JumpTable],
+) -> BTreeMap<u16, BasicBlock> {
+    let mut leaders = BTreeSet::from([entry]);
+    leaders.extend(jump_tables.iter().flat_map(|table| table.targets()));
+    for (&address, (size, instruction)) in &instructions {
+        if let Some(target) = jump_target(&instruction.ir) {
+            if instructions.contains_key(&target) {
+                leaders.insert(target);
+            }
+            if instruction.ir.is_conditional_jump() {
+                leaders.insert(address.wrapping_add(*size as u16));
+            }
+        }
+    }
+
+    let mut blocks: BTreeMap<u16, BasicBlock> = BTreeMap::new();
+    let mut current: Option<BasicBlock> = None;
+    for (address, (size, instruction)) in instructions {
+        let next = address.wrapping_add(size as u16);
+        let mut block = match current.take() {
+            Some(block) if block.end == address && !leaders.contains(&address) => block,
+            previous => {
+                if let Some(mut previous) = previous {
+                    // Falls through to the next block
+                    if previous.end == address
+                        && !previous.last().map_or(false, |i| is_terminator(&i.ir))
+                    {
+                        previous.successors.push(address);
+                    }
+                    blocks.insert(previous.start, previous);
+                }
+                BasicBlock {
+                    start: address,
+                    end: address,
+                    instructions: vec![],
+                    successors: vec![],
+                }
+            }
+        };
+        let target = jump_target(&instruction.ir);
+        let conditional = instruction.ir.is_conditional_jump();
+        let terminator = is_terminator(&instruction.ir);
+        block.end = next;
+        block.instructions.push((address, instruction));
+        if let Some(target) = target.filter(|t| !entries.contains(t) || *t == entry) {
+            block.successors.push(target);
+        }
+        if let Some(table) = jump_tables.iter().find(|table| table.jump == address) {
+            block.successors.extend(table.targets());
+        }
+        if conditional || terminator {
+            if conditional {
+                block.successors.push(next);
+            }
+            blocks.insert(block.start, block);
+        } else {
+            current = Some(block);
+        }
+    }
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+    // Keep the edges to decoded blocks only
+    let starts: BTreeSet<u16> = blocks.keys().copied().collect();
+    for block in blocks.values_mut() {
+        block.successors.retain(|s| starts.contains(s));
+        block.successors.dedup();
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disassembler::Disassemblable;
+    use crate::test_utils::program;
+
+    #[test]
+    fn test_functions() {
+        let binary = program("6.c");
+        let functions = binary.disassemble().unwrap().functions(&binary.symbols);
+
+        let printo = functions.iter().find(|f| f.name == "printo").unwrap();
+        assert_eq!(printo.entry, 0x0047);
+        // The loop header is entered from the initialization and the back jump
+        let mut predecessors = printo.predecessors(0x0062);
+        predecessors.sort();
+        assert_eq!(predecessors, [0x005b, 0x0067]);
+        assert_eq!(printo.blocks[&0x0062].successors, [0x0082, 0x0067]);
+        // The shared epilogue is another function
+        assert!(!printo.blocks.contains_key(&0x0183));
+
+        // Decoded from the entry, past the data at 008b in 1.c
+        let binary = program("1.c");
+        let functions = binary.disassemble().unwrap().functions(&binary.symbols);
+        let exit = functions.iter().find(|f| f.name == "__exit").unwrap();
+        assert_eq!(exit.entry, 0x0090);
+        assert_eq!(exit.blocks[&0x0090].instructions[0].1.raw, [0x55]);
+        // Jumps to another function are tail calls
+        let write = functions.iter().find(|f| f.name == "write").unwrap();
+        assert_eq!(write.blocks.len(), 1);
+        assert!(write.blocks[&0x008c].successors.is_empty());
+    }
+}
@@ -1,8 +1,8 @@
 use super::parser;
-use super::{error::DisassemblerError, DisassembledProgram};
+use super::{error::DisassemblerError, DisassembledProgram, ProgramDiff, Signatures};
 use crate::{
     minix::Program,
-    x86::{Instruction, IR},
+    x86::{Formatter, FormatterOptions, Instruction, IR},
 };
 
 /// This trait can be used to disassemble a given program binary.
@@ -37,17 +37,101 @@ impl Disassemblable for Program {
     }
 }
 
+/// Formatter of the options following the binary file:
+/// `--syntax=objdump|nasm|intel|att|ack`, `--no-raw`, `--uppercase` and `--unsigned-disp`.
+/// The `--signatures=FILE` and `--diff=FILE` options are used by `decode`.
+fn formatter_from_args(args: &[String]) -> Result<Formatter, DisassemblerError> {
+    let mut options = FormatterOptions::default();
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            // Listings, see `decode`
+            "--reassemble" | "--annotate" | "--json" | "--decompile" | "--frames"
+            | "--make-signatures" => {}
+            _ if arg.starts_with("--signatures=") || arg.starts_with("--diff=") => {}
+            "--no-raw" => options.raw_bytes = false,
+            "--uppercase" => options.uppercase = true,
+            "--unsigned-disp" => options.signed_displacements = false,
+            _ => match arg.strip_prefix("--syntax=") {
+                Some(syntax) => {
+                    options.syntax = syntax.parse().map_err(|_| DisassemblerError::InvalidArgs)?
+                }
+                None => return Err(DisassemblerError::InvalidArgs),
+            },
+        }
+    }
+    Ok(Formatter::new(options))
+}
+
 /// Read a binary from given file and disassemble it.
 pub fn decode(args: Vec<String>) -> Result<String, DisassemblerError> {
     if args.len() < 2 {
         return Err(DisassemblerError::InvalidArgs);
     }
+    let formatter = formatter_from_args(&args)?;
 
     let file = std::fs::File::open(&args[1]).map_err(|_| DisassemblerError::InvalidArgs)?;
-    let program = Program::from_file(file).map_err(|_| DisassemblerError::InvalidArgs)?;
+    let mut program = Program::from_file(file).map_err(|_| DisassemblerError::InvalidArgs)?;
 
+    // Signatures of the functions of a binary with symbols
+    if args.iter().skip(2).any(|arg| arg == "--make-signatures") {
+        return Ok(Signatures::from_program(&program).to_string());
+    }
+    let disassembled = recognize_functions(&mut program, &args)?;
+    // Report of the differences with another binary
+    if let Some(path) = args
+        .iter()
+        .skip(2)
+        .find_map(|arg| arg.strip_prefix("--diff="))
+    {
+        let file = std::fs::File::open(path).map_err(|_| DisassemblerError::InvalidArgs)?;
+        let mut other = Program::from_file(file).map_err(|_| DisassemblerError::InvalidArgs)?;
+        recognize_functions(&mut other, &args)?;
+        let diff = ProgramDiff::new(&program, &other)?;
+        return match args.iter().skip(2).any(|arg| arg == "--json") {
+            true => Ok(diff.to_json()),
+            false => Ok(diff.to_string()),
+        };
+    }
+    // Machine-readable output
+    if args.iter().skip(2).any(|arg| arg == "--json") {
+        return Ok(disassembled.to_json(&program));
+    }
+    // Pseudo-C of the functions
+    if args.iter().skip(2).any(|arg| arg == "--decompile") {
+        return Ok(disassembled.decompile(&program.symbols));
+    }
+    // Frames of the functions, with their variables named in the listing
+    if args.iter().skip(2).any(|arg| arg == "--frames") {
+        return Ok(disassembled.format_frames(&formatter, &program.symbols));
+    }
+    // NASM source of the segments
+    if args.iter().skip(2).any(|arg| arg == "--reassemble") {
+        return Ok(disassembled.to_nasm());
+    }
+    // Comments on the data references
+    if args.iter().skip(2).any(|arg| arg == "--annotate") {
+        return Ok(disassembled.format_annotated(&formatter));
+    }
+    Ok(disassembled.format(&formatter))
+}
+
+/// Disassemble the program, naming the library functions of a binary without symbols
+/// with the MINIX signatures and those of the `--signatures` files.
+fn recognize_functions(
+    program: &mut Program,
+    args: &[String],
+) -> Result<DisassembledProgram, DisassemblerError> {
     let disassembled = program.disassemble()?;
-    Ok(disassembled.to_string())
+    if program.symbols.is_empty() {
+        let mut signatures = Signatures::minix();
+        for arg in args.iter().skip(2) {
+            if let Some(path) = arg.strip_prefix("--signatures=") {
+                signatures.merge(Signatures::from_file(path)?);
+            }
+        }
+        program.symbols = signatures.recognize(&disassembled);
+    }
+    Ok(disassembled)
 }
 
 #[cfg(test)]
@@ -122,4 +206,39 @@ mod tests {
     fn test_decode_c_7() {
         assert_disassemble("7.c");
     }
+
+    #[test]
+    fn test_decode_syntax_options() {
+        let args = |options: &[&str]| {
+            ["minix2_rs", "./tests_data/1.c.out"]
+                .iter()
+                .chain(options)
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>()
+        };
+        let result = decode(args(&["--syntax=nasm", "--no-raw"])).unwrap();
+        let first = result.lines().next().unwrap();
+        assert_eq!(first, "0000: xor bp, bp");
+
+        let result = decode(args(&["--syntax=att", "--uppercase"])).unwrap();
+        assert!(result.lines().next().unwrap().ends_with("XOR %BP, %BP"));
+
+        assert_eq!(
+            decode(args(&["--syntax=masm"])),
+            Err(DisassemblerError::InvalidArgs)
+        );
+    }
+
+    #[test]
+    fn test_decode_diff() {
+        let args = vec![
+            "minix2_rs".to_string(),
+            "./tests_data/2.c.out".to_string(),
+            "--diff=./tests_data/2.c.out".to_string(),
+        ];
+        assert_eq!(
+            decode(args).unwrap(),
+            "functions: 19 matched, 0 changed, 0 removed, 0 added\n"
+        );
+    }
 }
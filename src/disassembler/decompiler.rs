@@ -0,0 +1,157 @@
+mod c_expr;
+mod lower;
+mod statement;
+mod structure;
+
+use self::lower::{lower_function, Context};
+use self::statement::Stmt;
+use super::{DisassembledProgram, Function};
+use crate::minix::SymbolTable;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Pseudo-C of a function.
+fn decompile_function(context: &Context, function: &Function) -> String {
+    let (blocks, returns) = lower_function(context, function);
+    let mut body = structure::structure(&blocks, function.entry);
+    if let Some(Stmt::Return(None)) = body.last() {
+        body.pop();
+    }
+
+    let mut names = BTreeSet::new();
+    body.iter().for_each(|stmt| stmt.variables(&mut names));
+    // Arguments up to the last one used
+    let count = names
+        .iter()
+        .filter_map(|name| name.strip_prefix("arg_")?.parse::<usize>().ok())
+        .max()
+        .map_or(0, |last| last + 1);
+    let args: Vec<String> = (0..count).map(|i| format!("int arg_{}", i)).collect();
+    let locals: Vec<&String> = names.iter().filter(|n| n.starts_with("local_")).collect();
+    let registers: Vec<&String> = ["ax", "bx", "cx", "dx", "si", "di"]
+        .iter()
+        .filter_map(|reg| names.get(*reg))
+        .collect();
+
+    let mut out = String::new();
+    let result = if returns { "int" } else { "void" };
+    let _ = writeln!(out, "/* {:04x} */", function.entry);
+    let _ = writeln!(out, "{} {}({})", result, function.name, args.join(", "));
+    out.push_str("{\n");
+    if !locals.is_empty() {
+        let locals: Vec<&str> = locals.iter().map(|s| s.as_str()).collect();
+        let _ = writeln!(out, "    int {};", locals.join(", "));
+    }
+    if !registers.is_empty() {
+        let registers: Vec<&str> = registers.iter().map(|s| s.as_str()).collect();
+        let _ = writeln!(out, "    register int {};", registers.join(", "));
+    }
+    if !locals.is_empty() || !registers.is_empty() {
+        out.push('\n');
+    }
+    body.iter().for_each(|stmt| stmt.write(&mut out, 1));
+    out.push_str("}\n");
+    out
+}
+
+impl DisassembledProgram {
+    /// Pseudo-C of the functions of the program, with the locals and arguments
+    /// named from their offset to bp, and the `int 20` calls as Minix IPC calls.
+    pub fn decompile(&self, symbols: &SymbolTable) -> String {
+        let functions = self.functions(symbols);
+        let names: BTreeMap<u16, String> = functions
+            .iter()
+            .map(|function| (function.entry, function.name.clone()))
+            .collect();
+        let analysis = self.analyze();
+        let context = Context {
+            symbols,
+            analysis: &analysis,
+            names: &names,
+        };
+        functions
+            .iter()
+            // Shared epilogues of the compiler, like .cret
+            .filter(|function| !function.name.starts_with('.'))
+            .map(|function| decompile_function(&context, function))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disassembler::Disassemblable;
+    use crate::test_utils::program;
+
+    fn decompile(file: &str) -> String {
+        let program = program(file);
+        program.disassemble().unwrap().decompile(&program.symbols)
+    }
+
+    #[test]
+    fn test_decompile() {
+        let decompiled = decompile("6.c");
+        // printo(v) of tests_data/6.c, v and i in di and si
+        assert!(decompiled.contains(
+            "void printo(int arg_0)\n{\n    register int ax, si, di;\n\n    di = arg_0;\n"
+        ));
+        assert!(decompiled.contains("    ax = di < 0 ? 0x31 : 0x30;\n    putchar(ax);\n"));
+        assert!(decompiled.contains(
+            "    for (si = 0; si < 5; si++) {\n        putchar((di >> 0xc & 7) + 0x30);\n        di <<= 3;\n    }\n}\n"
+        ));
+        assert!(decompiled.contains("    printo(0x14e5);\n"));
+        assert!(decompiled.contains(
+            "int putchar(int arg_0)\n{\n    write(1, &arg_0, 1);\n    return (char)arg_0;\n}\n"
+        ));
+        // Shared epilogues are returns
+        assert!(!decompiled.contains(".cret"));
+
+        let decompiled = decompile("1.c");
+        assert!(decompiled.contains("void main()\n{\n    write(1, \"hello\\n\", 6);\n}\n"));
+        // Syscalls, with the IPC function in cx
+        assert!(decompiled.contains(
+            "int _sendre(int arg_0, int arg_1)\n{\n    return sendrec(arg_0, arg_1);\n}\n"
+        ));
+        assert!(decompiled.contains("    return receive(arg_0, arg_1);\n"));
+        assert!(decompiled
+            .contains("        errno = -*(int *)(si + 2);\n        ax = -1;\n    } else {\n"));
+
+        let decompiled = decompile("2.c");
+        assert!(decompiled.contains("void main()\n{\n    putchar(0x61);\n}\n"));
+        assert!(decompiled.contains(
+            "int putchar(int arg_0)\n{\n    write(1, &arg_0, 1);\n    return (char)arg_0;\n}\n"
+        ));
+        // The functions registered with atexit, called backwards by exit
+        assert!(decompiled.contains(
+            "    si = __funcc;\n    for (;;) {\n        si--;\n        if (si < 0) {\n            break;\n        }\n        (*(int *)((si << 1) + 0xe))();\n    }\n"
+        ));
+        assert!(decompiled.contains(
+            "void exit(int arg_0)\n{\n    sub_0063();\n    if (_clean != 0) {\n        _clean();\n    }\n    _exit(arg_0);\n}\n"
+        ));
+
+        let decompiled = decompile("3.c");
+        assert!(decompiled.contains("void main()\n{\n    printf(\"hello\\n\");\n}\n"));
+
+        // The local a, propagated to the call
+        let decompiled = decompile("4.c");
+        assert!(decompiled.contains(
+            "void main()\n{\n    int local_2;\n\n    local_2 = 0x4d2;\n    printf(\"a=%d\\n\", 0x4d2);\n}\n"
+        ));
+
+        // main(argc, argv), with i in si
+        let decompiled = decompile("5.c");
+        assert!(decompiled.contains(
+            "void main(int arg_0, int arg_1)\n{\n    register int si;\n\n    \
+            for (si = 0; arg_0 > si; si++) {\n        \
+            printf(\"argv[%d]=%s\\n\", si, *(int *)((si << 1) + arg_1));\n    }\n}\n"
+        ));
+
+        // The sizes are numbers, even at the address of a string
+        let decompiled = decompile("7.c");
+        assert!(decompiled.contains(
+            "void main()\n{\n    printf(\"long = %d\\n\", 4);\n    \
+            printf(\"short = %d\\n\", 2);\n    printf(\"int = %d\\n\", 2);\n}\n"
+        ));
+    }
+}
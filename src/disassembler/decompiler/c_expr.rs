@@ -0,0 +1,248 @@
+use crate::x86::Register;
+use std::collections::BTreeSet;
+
+/// Expression of the pseudo-C output.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CExpr {
+    Num(u16),
+    Str(String),
+    /// Register, local, argument or global
+    Var(String),
+    Reg(Register),
+    Deref {
+        address: Box<CExpr>,
+        byte: bool,
+    },
+    AddressOf(Box<CExpr>),
+    Cast(&'static str, Box<CExpr>),
+    Unary(&'static str, Box<CExpr>),
+    Binary(&'static str, Box<CExpr>, Box<CExpr>),
+    Cond(Box<CExpr>, Box<CExpr>, Box<CExpr>),
+    /// Call of a function name, or of a pointer
+    Call(Box<CExpr>, Vec<CExpr>),
+}
+
+impl CExpr {
+    pub fn binary(op: &'static str, left: CExpr, right: CExpr) -> Self {
+        // x + -2 is x - 2
+        match (op, right) {
+            ("+", CExpr::Num(n)) if n >= 0x8000 => {
+                CExpr::Binary("-", Box::new(left), Box::new(CExpr::Num(n.wrapping_neg())))
+            }
+            ("+" | "-" | "|" | "^" | "<<" | ">>", CExpr::Num(0)) => left,
+            (op, right) => CExpr::Binary(op, Box::new(left), Box::new(right)),
+        }
+    }
+
+    pub fn unary(op: &'static str, value: CExpr) -> Self {
+        CExpr::Unary(op, Box::new(value))
+    }
+
+    pub fn call(name: &str, args: Vec<CExpr>) -> Self {
+        CExpr::Call(Box::new(CExpr::Var(name.to_string())), args)
+    }
+
+    /// Constant, variable or register, cheap to repeat.
+    pub fn is_simple(&self) -> bool {
+        matches!(self, CExpr::Num(_) | CExpr::Var(_) | CExpr::Reg(_))
+    }
+
+    /// Subexpressions, in evaluation order.
+    fn children(&self) -> Vec<&CExpr> {
+        match self {
+            CExpr::Num(_) | CExpr::Str(_) | CExpr::Var(_) | CExpr::Reg(_) => vec![],
+            CExpr::Deref { address: value, .. }
+            | CExpr::AddressOf(value)
+            | CExpr::Cast(_, value)
+            | CExpr::Unary(_, value) => vec![value],
+            CExpr::Binary(_, left, right) => vec![left, right],
+            CExpr::Cond(cond, then, otherwise) => vec![cond, then, otherwise],
+            CExpr::Call(target, args) => std::iter::once(&**target).chain(args).collect(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut CExpr> {
+        match self {
+            CExpr::Num(_) | CExpr::Str(_) | CExpr::Var(_) | CExpr::Reg(_) => vec![],
+            CExpr::Deref { address: value, .. }
+            | CExpr::AddressOf(value)
+            | CExpr::Cast(_, value)
+            | CExpr::Unary(_, value) => vec![value],
+            CExpr::Binary(_, left, right) => vec![left, right],
+            CExpr::Cond(cond, then, otherwise) => vec![cond, then, otherwise],
+            CExpr::Call(target, args) => std::iter::once(&mut **target).chain(args).collect(),
+        }
+    }
+
+    /// Registers read by the expression.
+    pub fn registers(&self, registers: &mut Vec<Register>) {
+        match self {
+            CExpr::Reg(reg) => registers.push(*reg),
+            value => value
+                .children()
+                .into_iter()
+                .for_each(|child| child.registers(registers)),
+        }
+    }
+
+    /// Names of the variables and registers of the expression.
+    pub fn variables(&self, names: &mut BTreeSet<String>) {
+        match self {
+            CExpr::Var(name) => {
+                names.insert(name.clone());
+            }
+            CExpr::Reg(reg) => {
+                names.insert(reg.to_string());
+            }
+            value => value
+                .children()
+                .into_iter()
+                .for_each(|child| child.variables(names)),
+        }
+    }
+
+    /// Return true if the value depends on the memory, or calls a function.
+    pub fn reads_memory(&self) -> bool {
+        match self {
+            CExpr::Var(_) | CExpr::Deref { .. } | CExpr::Call(..) => true,
+            // The address of a variable is a constant
+            CExpr::AddressOf(value) if matches!(**value, CExpr::Var(_)) => false,
+            value => value.children().iter().any(|child| child.reads_memory()),
+        }
+    }
+
+    /// Return true if the expression calls a function.
+    pub fn has_call(&self) -> bool {
+        match self {
+            CExpr::Call(..) => true,
+            value => value.children().iter().any(|child| child.has_call()),
+        }
+    }
+
+    /// Replace the reads of the register with the value, and return their number.
+    pub fn substitute(&mut self, reg: Register, value: &CExpr) -> usize {
+        match self {
+            CExpr::Reg(read) if *read == reg => {
+                *self = value.clone();
+                1
+            }
+            // Byte of a constant word
+            CExpr::Reg(read) if read.to_word_register() == reg => match value {
+                CExpr::Num(n) if read.is_low_byte() => {
+                    *self = CExpr::Num(n & 0xff);
+                    1
+                }
+                CExpr::Num(n) => {
+                    *self = CExpr::Num(n >> 8);
+                    1
+                }
+                _ => 0,
+            },
+            expr => expr
+                .children_mut()
+                .into_iter()
+                .map(|child| child.substitute(reg, value))
+                .sum(),
+        }
+    }
+
+    /// The opposite condition.
+    pub fn negate(self) -> Self {
+        let opposite = |op| match op {
+            "==" => Some("!="),
+            "!=" => Some("=="),
+            "<" => Some(">="),
+            ">=" => Some("<"),
+            "<=" => Some(">"),
+            ">" => Some("<="),
+            _ => None,
+        };
+        match self {
+            CExpr::Binary(op, left, right) if opposite(op).is_some() => {
+                CExpr::Binary(opposite(op).unwrap(), left, right)
+            }
+            CExpr::Unary("!", value) => *value,
+            value => CExpr::unary("!", value),
+        }
+    }
+
+    /// Return true if the expression mentions the variable or register.
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            CExpr::Var(var) => var == name,
+            CExpr::Reg(reg) => reg.to_string() == name,
+            value => value.children().iter().any(|child| child.mentions(name)),
+        }
+    }
+
+    /// Binding strength, as the C operator precedence.
+    fn precedence(&self) -> u8 {
+        match self {
+            CExpr::Cond(..) => 3,
+            CExpr::Binary(op, ..) => match *op {
+                "*" | "/" | "%" => 13,
+                "+" | "-" => 12,
+                "<<" | ">>" => 11,
+                "<" | "<=" | ">" | ">=" => 10,
+                "==" | "!=" => 9,
+                "&" => 8,
+                "^" => 7,
+                "|" => 6,
+                "&&" => 5,
+                _ => 4,
+            },
+            CExpr::Deref { .. } | CExpr::AddressOf(_) | CExpr::Cast(..) | CExpr::Unary(..) => 14,
+            _ => 16,
+        }
+    }
+}
+
+impl std::fmt::Display for CExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Operand parenthesized when it binds less than the operator
+        let operand = |value: &CExpr, precedence: u8| match value.precedence() < precedence {
+            true => format!("({})", value),
+            false => value.to_string(),
+        };
+        let precedence = self.precedence();
+        match self {
+            CExpr::Num(n) if *n < 10 => write!(f, "{}", n),
+            // Small negative numbers
+            CExpr::Num(n) if *n >= 0xff00 => write!(f, "{}", *n as i16),
+            CExpr::Num(n) => write!(f, "0x{:x}", n),
+            CExpr::Str(s) => write!(f, "\"{}\"", s.escape_default()),
+            CExpr::Var(name) => write!(f, "{}", name),
+            CExpr::Reg(reg) => write!(f, "{}", reg),
+            CExpr::Deref { address, byte } => {
+                let size = if *byte { "char" } else { "int" };
+                write!(f, "*({} *){}", size, operand(address, 15))
+            }
+            CExpr::AddressOf(value) => write!(f, "&{}", operand(value, precedence)),
+            CExpr::Cast(ty, value) => write!(f, "({}){}", ty, operand(value, precedence)),
+            CExpr::Unary(op, value) => write!(f, "{}{}", op, operand(value, precedence)),
+            CExpr::Binary(op, left, right) => write!(
+                f,
+                "{} {} {}",
+                operand(left, precedence),
+                op,
+                // Left associative
+                operand(right, precedence + 1)
+            ),
+            CExpr::Cond(cond, then, otherwise) => write!(
+                f,
+                "{} ? {} : {}",
+                operand(cond, precedence + 1),
+                operand(then, precedence + 1),
+                operand(otherwise, precedence)
+            ),
+            CExpr::Call(target, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                match &**target {
+                    CExpr::Var(name) => write!(f, "{}({})", name, args.join(", ")),
+                    // Pointer to the function
+                    target => write!(f, "({})({})", target, args.join(", ")),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1065 @@
+use super::c_expr::CExpr;
+use super::statement::{Compare, Stmt, SCRATCH};
+use crate::disassembler::frame::{argument_name, local_name};
+use crate::disassembler::{Analysis, BasicBlock, Function, ReferenceKind};
+use crate::il::{flag_name, BinaryOp, Expr, Liftable, MicroOp, UnaryOp};
+use crate::interpreter::Flag;
+use crate::minix::{SymbolSection, SymbolTable};
+use crate::x86::{Formatter, FormatterOptions, Instruction, Operand, Register, IR};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// How a block ends, after its statements.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Exit {
+    /// Falls through, or jumps, to the block
+    Jump(u16),
+    /// Jumps to the first block if the condition holds, to the second otherwise
+    Branch(CExpr, u16, u16),
+    /// Returns, or leaves the function
+    Stop,
+}
+
+/// Statements of a basic block.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub exit: Exit,
+}
+
+/// Program wide information used to name the values.
+pub struct Context<'a> {
+    pub symbols: &'a SymbolTable,
+    pub analysis: &'a Analysis,
+    /// Names of the function entries
+    pub names: &'a BTreeMap<u16, String>,
+}
+
+impl Context<'_> {
+    /// Name of the data symbol at the address.
+    fn global(&self, address: u16) -> Option<String> {
+        self.symbols
+            .symbols
+            .iter()
+            .filter(|s| {
+                matches!(
+                    s.section(),
+                    SymbolSection::Data | SymbolSection::Bss | SymbolSection::Common
+                )
+            })
+            .find(|s| s.value == address as u32)
+            .map(|s| s.name.strip_prefix('_').unwrap_or(&s.name).to_string())
+    }
+
+    /// String literal of a referenced data string at the address.
+    fn string(&self, address: u16) -> Option<CExpr> {
+        let string = self.analysis.string_at(address)?;
+        let referenced = self
+            .analysis
+            .xrefs
+            .to_data(address)
+            .iter()
+            .any(|r| r.kind == ReferenceKind::Address);
+        referenced.then(|| CExpr::Str(string.bytes.iter().map(|&b| b as char).collect()))
+    }
+
+    /// Name of the memory at the registers plus the displacement.
+    fn variable(&self, registers: &[Register], disp: u16) -> Option<String> {
+        let disp = disp as i16;
+        match registers {
+            // Arguments above the return address, locals below the saved bp,
+            // bp being 2 bytes below the SP at the entry
+            [Register::BP] if disp >= 4 && disp % 2 == 0 => Some(argument_name(disp - 2)),
+            [Register::BP] if disp < 0 => Some(local_name(disp)),
+            [] => self.global(disp as u16),
+            _ => None,
+        }
+    }
+
+    /// Name of the called or jumped to function.
+    fn function(&self, address: u16) -> Option<&String> {
+        self.names.get(&address)
+    }
+}
+
+/// Registers and displacement of an address, as `bx + si + 4`.
+fn address_parts(address: &Expr) -> Option<(Vec<Register>, u16)> {
+    match address {
+        Expr::Const(disp) => Some((vec![], *disp)),
+        Expr::Reg(reg) => Some((vec![*reg], 0)),
+        Expr::Binary(BinaryOp::Add, left, right) => {
+            let (mut registers, disp) = address_parts(left)?;
+            let (others, other_disp) = address_parts(right)?;
+            registers.extend(others);
+            Some((registers, disp.wrapping_add(other_disp)))
+        }
+        _ => None,
+    }
+}
+
+/// Return true if the value is a condition, 0 or 1.
+fn is_condition(expr: &Expr) -> bool {
+    use BinaryOp::*;
+    match expr {
+        Expr::Flag(_) | Expr::Unary(UnaryOp::Parity, _) => true,
+        Expr::Binary(Eq | Ne | LtU | LtS | AddOverflows | SubOverflows, ..) => true,
+        Expr::Binary(And | Or, left, right) => is_condition(left) && is_condition(right),
+        _ => false,
+    }
+}
+
+/// Value of the temporaries, and of the registers written before by the same
+/// instruction, in the expression. None if it reads a part of such a register.
+fn resolve(
+    expr: &Expr,
+    temps: &BTreeMap<usize, Expr>,
+    registers: &HashMap<Register, Expr>,
+) -> Option<Expr> {
+    let resolve = |expr| resolve(expr, temps, registers);
+    Some(match expr {
+        Expr::Const(_) | Expr::Flag(_) => expr.clone(),
+        Expr::Temp(n) => temps.get(n)?.clone(),
+        Expr::Reg(reg) => match registers.get(reg) {
+            Some(value) => value.clone(),
+            None if registers
+                .keys()
+                .any(|r| r.to_word_register() == reg.to_word_register()) =>
+            {
+                return None
+            }
+            None => expr.clone(),
+        },
+        Expr::Load { address, byte } => Expr::load(resolve(address)?, *byte),
+        Expr::Unary(op, value) => Expr::unary(*op, resolve(value)?),
+        Expr::Binary(op, left, right) => Expr::binary(*op, resolve(left)?, resolve(right)?),
+        Expr::Ite(cond, then, otherwise) => {
+            Expr::ite(resolve(cond)?, resolve(then)?, resolve(otherwise)?)
+        }
+    })
+}
+
+/// Control flow at the end of an instruction.
+enum End {
+    Next,
+    Call(CExpr),
+    Return,
+    Jump(Expr),
+    /// Conditional jump, on the flags or on cx
+    Branch(Expr),
+    Interrupt(u8),
+}
+
+/// Translation of the micro-ops of an instruction to statements.
+struct Lowering<'a, 'b> {
+    context: &'a Context<'b>,
+    /// Operands in memory are bytes, where the IL accesses words
+    byte: bool,
+    /// dx is the sign or zero extension of ax, for the divisions
+    extended: bool,
+}
+
+impl Lowering<'_, '_> {
+    fn memory(&self, address: &Expr, byte: bool) -> CExpr {
+        let parts = address_parts(address);
+        if let Some(name) = parts
+            .as_ref()
+            .and_then(|(registers, disp)| self.context.variable(registers, *disp))
+        {
+            return CExpr::Var(name);
+        }
+        let address = match parts {
+            // Sum of the registers and displacement
+            Some((registers, disp)) => {
+                let sum = registers.iter().fold(None, |sum, reg| match sum {
+                    None => Some(CExpr::Reg(*reg)),
+                    Some(sum) => Some(CExpr::binary("+", sum, CExpr::Reg(*reg))),
+                });
+                match sum {
+                    Some(sum) => CExpr::binary("+", sum, CExpr::Num(disp)),
+                    None => CExpr::Num(disp),
+                }
+            }
+            None => self.value(address),
+        };
+        CExpr::Deref {
+            address: Box::new(address),
+            byte,
+        }
+    }
+
+    /// C expression of the value.
+    fn value(&self, expr: &Expr) -> CExpr {
+        match expr {
+            Expr::Const(value) => CExpr::Num(*value),
+            Expr::Reg(reg) => CExpr::Reg(*reg),
+            Expr::Flag(flag) => CExpr::Var(flag_name(*flag).to_string()),
+            Expr::Temp(n) => CExpr::Var(format!("t{}", n)),
+            Expr::Load { address, .. } if **address == Expr::Reg(Register::SP) => {
+                CExpr::call("pop", vec![])
+            }
+            Expr::Load { address, byte } => self.memory(address, *byte || self.byte),
+            Expr::Unary(op, value) => {
+                let value = self.value(value);
+                match op {
+                    UnaryOp::Not => CExpr::unary("~", value),
+                    UnaryOp::Neg => CExpr::unary("-", value),
+                    UnaryOp::Parity => CExpr::call("parity", vec![value]),
+                    UnaryOp::SignExtendByte => CExpr::Cast("char", Box::new(value)),
+                    // Same value, as a long
+                    UnaryOp::SignExtendWord => value,
+                    UnaryOp::High => {
+                        CExpr::binary(">>", CExpr::Cast("long", Box::new(value)), CExpr::Num(16))
+                    }
+                }
+            }
+            Expr::Binary(op, left, right) => self.binary(expr, *op, left, right),
+            Expr::Ite(cond, then, otherwise) => match (&**cond, &**then, &**otherwise) {
+                // Sign of the word, as extended by cwd
+                (Expr::Binary(BinaryOp::LtS, value, zero), Expr::Const(0xffff), Expr::Const(0))
+                    if **zero == Expr::Const(0) =>
+                {
+                    CExpr::binary(">>", self.value(value), CExpr::Num(15))
+                }
+                (cond, then, otherwise) => CExpr::Cond(
+                    Box::new(self.value(cond)),
+                    Box::new(self.value(then)),
+                    Box::new(self.value(otherwise)),
+                ),
+            },
+        }
+    }
+
+    fn binary(&self, expr: &Expr, op: BinaryOp, left: &Expr, right: &Expr) -> CExpr {
+        use BinaryOp::*;
+        // Address of an argument or local, as computed by lea
+        if let Some((registers, disp)) = address_parts(expr) {
+            if let (Some(name), [Register::BP]) = (
+                self.context.variable(&registers, disp),
+                registers.as_slice(),
+            ) {
+                return CExpr::AddressOf(Box::new(CExpr::Var(name)));
+            }
+        }
+        // Operations of a value with itself, like xor ax, ax
+        if left == right {
+            match op {
+                Sub | Xor => return CExpr::Num(0),
+                And | Or => return self.value(left),
+                _ => {}
+            }
+        }
+        if is_condition(left) {
+            match (op, right) {
+                (Eq, Expr::Const(0)) => return self.value(left).negate(),
+                (And, right) if is_condition(right) => {
+                    return CExpr::binary("&&", self.value(left), self.value(right))
+                }
+                (Or, right) if is_condition(right) => {
+                    return CExpr::binary("||", self.value(left), self.value(right))
+                }
+                _ => {}
+            }
+        }
+        let (left, right) = (self.value(left), self.value(right));
+        let unsigned = |value| CExpr::Cast("unsigned", Box::new(value));
+        match op {
+            Add => CExpr::binary("+", left, right),
+            Sub => CExpr::binary("-", left, right),
+            MulWide => CExpr::binary("*", left, right),
+            And => CExpr::binary("&", left, right),
+            Or => CExpr::binary("|", left, right),
+            Xor => CExpr::binary("^", left, right),
+            Shl => CExpr::binary("<<", left, right),
+            Shr => CExpr::binary(">>", unsigned(left), right),
+            Sar => CExpr::binary(">>", left, right),
+            Concat if self.extended => right,
+            Concat => CExpr::binary(
+                "|",
+                CExpr::binary("<<", CExpr::Cast("long", Box::new(left)), CExpr::Num(16)),
+                right,
+            ),
+            DivU => CExpr::binary("/", unsigned(left), right),
+            RemU => CExpr::binary("%", unsigned(left), right),
+            Eq => CExpr::binary("==", left, right),
+            Ne => CExpr::binary("!=", left, right),
+            LtU => CExpr::binary("<", unsigned(left), right),
+            LtS => CExpr::binary("<", left, right),
+            AddOverflows => CExpr::call("add_overflows", vec![left, right]),
+            SubOverflows => CExpr::call("sub_overflows", vec![left, right]),
+        }
+    }
+
+    /// Statements of the micro-ops of the instruction, and how it ends. None if
+    /// the instruction has no C equivalent.
+    fn lower(&self, address: u16, instruction: &Instruction) -> Option<(Vec<Stmt>, End)> {
+        let next = address.wrapping_add(instruction.raw.len() as u16);
+        let mut temps = BTreeMap::new();
+        let mut registers = HashMap::new();
+        // Assignments, with the values before the instruction, and the pushed values
+        let mut effects: Vec<(Option<CExpr>, CExpr)> = vec![];
+        // Destinations of the temporaries
+        let mut stored = BTreeMap::new();
+        let mut compare = None;
+        let mut return_address = false;
+        let mut end = End::Next;
+        for op in instruction.lift(address) {
+            match op {
+                MicroOp::SetTemp(n, value) => {
+                    let value = resolve(&value, &temps, &registers)?;
+                    temps.insert(n, value);
+                }
+                // Moved by push, pop, call and ret
+                MicroOp::SetReg(Register::SP, _) => {}
+                MicroOp::SetReg(reg, value) => {
+                    if let Expr::Temp(n) = value {
+                        stored.insert(n, CExpr::Reg(reg));
+                    }
+                    let value = resolve(&value, &temps, &registers)?;
+                    effects.push((Some(CExpr::Reg(reg)), self.value(&value)));
+                    registers.insert(reg, value);
+                }
+                MicroOp::Store {
+                    address: Expr::Reg(Register::SP),
+                    value,
+                    ..
+                } => match resolve(&value, &temps, &registers)? {
+                    Expr::Const(value) if value == next => return_address = true,
+                    value => effects.push((None, self.value(&value))),
+                },
+                MicroOp::Store {
+                    address,
+                    value,
+                    byte,
+                } => {
+                    let address = resolve(&address, &temps, &registers)?;
+                    let target = self.memory(&address, byte || self.byte);
+                    if let Expr::Temp(n) = value {
+                        stored.insert(n, target.clone());
+                    }
+                    let value = resolve(&value, &temps, &registers)?;
+                    effects.push((Some(target), self.value(&value)));
+                }
+                // The comparison is the operation setting zf
+                MicroOp::SetFlag(Flag::Zero, Expr::Binary(BinaryOp::Eq, result, zero))
+                    if *zero == Expr::Const(0) =>
+                {
+                    compare = Some(match (&*result, resolve(&result, &temps, &registers)?) {
+                        (Expr::Temp(n), _) if stored.contains_key(n) => {
+                            (Compare::Value(stored[n].clone()), true)
+                        }
+                        (_, Expr::Binary(BinaryOp::Sub, a, b)) => {
+                            (Compare::Cmp(self.value(&a), self.value(&b)), false)
+                        }
+                        (_, Expr::Binary(BinaryOp::And, a, b)) => {
+                            (Compare::Test(self.value(&a), self.value(&b)), false)
+                        }
+                        (_, value) => (Compare::Value(self.value(&value)), false),
+                    });
+                }
+                MicroOp::SetFlag(..) | MicroOp::DivideError(_) => {}
+                MicroOp::Interrupt(number) => end = End::Interrupt(number),
+                // Repeated string instruction
+                MicroOp::Jump(Expr::Const(target)) if target == address => return None,
+                MicroOp::Jump(target) => {
+                    let target = resolve(&target, &temps, &registers)?;
+                    end = if target == Expr::load(Expr::Reg(Register::SP), false) {
+                        End::Return
+                    } else if return_address {
+                        End::Call(match target {
+                            Expr::Const(address) => {
+                                CExpr::Var(match self.context.function(address) {
+                                    Some(name) => name.clone(),
+                                    None => format!("sub_{:04x}", address),
+                                })
+                            }
+                            target => self.value(&target),
+                        })
+                    } else {
+                        End::Jump(target)
+                    };
+                }
+                // Registers read after the writes of the instruction
+                MicroOp::Branch { cond, .. } => {
+                    let cond = resolve(&cond, &temps, &HashMap::new())?;
+                    if !is_flags(&cond) {
+                        compare = Some((Compare::Cond(self.value(&cond)), true));
+                    }
+                    end = End::Branch(cond);
+                }
+                MicroOp::In { .. } | MicroOp::Out { .. } | MicroOp::Halt => return None,
+                MicroOp::Unsupported(_) => return None,
+            }
+        }
+
+        let mut stmts = vec![];
+        let mut after = None;
+        match compare {
+            // Values before the instruction
+            Some((compare, false)) => stmts.push(Stmt::Compare(compare)),
+            // Destination of the instruction, or registers after it
+            Some((compare, true)) => after = Some(Stmt::Compare(compare)),
+            None => {}
+        }
+        // Assignments in an order where none changes a value read by the next ones
+        while !effects.is_empty() {
+            let index = (0..effects.len()).find(|&index| {
+                let target = &effects[index].0;
+                effects
+                    .iter()
+                    .enumerate()
+                    .all(|(other, effect)| other == index || !clobbers(target, effect))
+            })?;
+            match effects.remove(index) {
+                (Some(target), value) if target == value => {}
+                (Some(target), value) => stmts.push(Stmt::Assign(target, value)),
+                (None, value) => stmts.push(Stmt::Push(value)),
+            }
+        }
+        stmts.extend(after);
+        if stmts.is_empty() && matches!(end, End::Next) {
+            return None;
+        }
+        Some((stmts, end))
+    }
+}
+
+/// Return true if the condition depends only on the flags.
+fn is_flags(cond: &Expr) -> bool {
+    match cond {
+        Expr::Flag(_) | Expr::Const(_) => true,
+        Expr::Unary(_, value) => is_flags(value),
+        Expr::Binary(_, left, right) => is_flags(left) && is_flags(right),
+        Expr::Ite(cond, then, otherwise) => is_flags(cond) && is_flags(then) && is_flags(otherwise),
+        Expr::Reg(_) | Expr::Temp(_) | Expr::Load { .. } => false,
+    }
+}
+
+/// Return true if assigning the target, or pushing when None, changes a value
+/// read by the other assignment or push.
+fn clobbers(target: &Option<CExpr>, (other, value): &(Option<CExpr>, CExpr)) -> bool {
+    let address = match other {
+        Some(CExpr::Deref { address, .. }) => Some(&**address),
+        _ => None,
+    };
+    let mut reads = vec![];
+    value.registers(&mut reads);
+    address
+        .iter()
+        .for_each(|address| address.registers(&mut reads));
+    match target {
+        Some(CExpr::Reg(reg)) => reads
+            .iter()
+            .any(|read| read.to_word_register() == reg.to_word_register()),
+        Some(_) => value.reads_memory() || address.map_or(false, |a| a.reads_memory()),
+        // Below the stack pointer
+        None => false,
+    }
+}
+
+/// Instruction without its bytes, for the `__asm__` statements.
+fn asm(instruction: &Instruction) -> String {
+    let options = FormatterOptions {
+        raw_bytes: false,
+        ..Default::default()
+    };
+    Formatter::new(options).format_instruction(instruction)
+}
+
+/// Return true if the operands in memory of the instruction are bytes.
+fn is_byte(ir: &IR) -> bool {
+    let operands = match ir {
+        // The count of the shifts is a byte
+        IR::Shl { dest, .. } | IR::Shr { dest, .. } | IR::Sar { dest, .. } => vec![dest],
+        ir => ir.operands(),
+    };
+    operands.iter().any(|operand| match operand {
+        Operand::Register(reg) => !reg.is_word_register(),
+        Operand::Immediate(_) => true,
+        _ => false,
+    })
+}
+
+/// Instructions saving and restoring the frame of the function.
+fn is_frame(ir: &IR) -> bool {
+    use Register::*;
+    match ir {
+        IR::Push {
+            src: Operand::Register(BP),
+        } => true,
+        IR::Pop {
+            dest: Operand::Register(BP | SI | DI),
+        } => true,
+        IR::Mov {
+            dest: Operand::Register(dest),
+            src: Operand::Register(src),
+            ..
+        } => matches!((dest, src), (BP, SP) | (SP, BP)),
+        IR::Add {
+            dest: Operand::Register(SP),
+            ..
+        }
+        | IR::Sub {
+            dest: Operand::Register(SP),
+            ..
+        } => true,
+        _ => false,
+    }
+}
+
+/// Size in words of the arguments removed after a call, from the instructions following it.
+fn cleanup(instructions: &[(u16, Instruction)]) -> Option<(usize, usize)> {
+    let pops = instructions
+        .iter()
+        .take_while(|(_, i)| matches!(i.ir, IR::Pop { .. }) && !is_frame(&i.ir))
+        .count();
+    if pops > 0 {
+        return Some((pops, pops));
+    }
+    match instructions.first().map(|(_, i)| &i.ir) {
+        Some(IR::Add {
+            dest: Operand::Register(Register::SP),
+            src,
+        }) => {
+            let size = match src {
+                Operand::Immediate(n) => *n as u16,
+                Operand::LongImmediate(n) => *n,
+                Operand::SignExtendedImmediate(n) => *n as u16,
+                _ => return None,
+            };
+            Some(((size / 2) as usize, 1))
+        }
+        _ => None,
+    }
+}
+
+/// Constant loaded in cx before the instruction, in the block or its only predecessors.
+fn known_cx(function: &Function, block: &BasicBlock, index: usize, depth: usize) -> Option<u16> {
+    for (address, instruction) in block.instructions[..index].iter().rev() {
+        let ops = instruction.lift(*address);
+        // Calls push the return address and jump
+        let call = ops.iter().any(|op| matches!(op, MicroOp::Jump(_)))
+            && ops.iter().any(|op| {
+                matches!(
+                    op,
+                    MicroOp::Store {
+                        address: Expr::Reg(Register::SP),
+                        ..
+                    }
+                )
+            });
+        if call {
+            return None;
+        }
+        for op in ops.iter().rev() {
+            match op {
+                MicroOp::SetReg(Register::CX, Expr::Const(value)) => return Some(*value),
+                MicroOp::SetReg(reg, _) if reg.to_word_register() == Register::CX => return None,
+                MicroOp::Unsupported(_) => return None,
+                _ => {}
+            }
+        }
+    }
+    match function.predecessors(block.start).as_slice() {
+        [single] if depth > 0 => {
+            let previous = &function.blocks[single];
+            known_cx(function, previous, previous.instructions.len(), depth - 1)
+        }
+        _ => None,
+    }
+}
+
+/// Name of the Minix IPC primitive of the function number in cx.
+fn ipc_name(function: u16) -> Option<&'static str> {
+    match function {
+        1 => Some("send"),
+        2 => Some("receive"),
+        3 => Some("sendrec"),
+        _ => None,
+    }
+}
+
+/// Comparison tested by a condition on the flags.
+enum Relation {
+    Signed(&'static str),
+    Unsigned(&'static str),
+    /// On the sign of the result only
+    Sign(&'static str),
+}
+
+/// Comparison of the condition of a conditional jump, as lifted.
+fn relation(cond: &Expr) -> Option<Relation> {
+    use BinaryOp::{And, Eq, Or};
+    let flag = Expr::Flag;
+    let sign_is_overflow = || Expr::binary(Eq, flag(Flag::Sign), flag(Flag::Overflow));
+    let below_or_equal = || Expr::binary(Or, flag(Flag::Carry), flag(Flag::Zero));
+    let relations = [
+        (flag(Flag::Zero), Relation::Signed("==")),
+        (Expr::is_zero(flag(Flag::Zero)), Relation::Signed("!=")),
+        (Expr::is_zero(sign_is_overflow()), Relation::Signed("<")),
+        (sign_is_overflow(), Relation::Signed(">=")),
+        (
+            Expr::binary(Or, flag(Flag::Zero), Expr::is_zero(sign_is_overflow())),
+            Relation::Signed("<="),
+        ),
+        (
+            Expr::binary(And, Expr::is_zero(flag(Flag::Zero)), sign_is_overflow()),
+            Relation::Signed(">"),
+        ),
+        (flag(Flag::Carry), Relation::Unsigned("<")),
+        (Expr::is_zero(flag(Flag::Carry)), Relation::Unsigned(">=")),
+        (below_or_equal(), Relation::Unsigned("<=")),
+        (Expr::is_zero(below_or_equal()), Relation::Unsigned(">")),
+        (flag(Flag::Sign), Relation::Sign("<")),
+        (Expr::is_zero(flag(Flag::Sign)), Relation::Sign(">=")),
+    ];
+    relations
+        .into_iter()
+        .find(|(expr, _)| expr == cond)
+        .map(|(_, relation)| relation)
+}
+
+/// Condition of a conditional jump on the flags, from the last comparison.
+fn condition(context: &Context, cond: &Expr, compare: Option<Compare>) -> CExpr {
+    let zero = |op, value| CExpr::Binary(op, Box::new(value), Box::new(CExpr::Num(0)));
+    match (relation(cond), compare) {
+        (Some(Relation::Signed(op)), Some(Compare::Cmp(a, b))) => return CExpr::binary(op, a, b),
+        (Some(Relation::Unsigned(op)), Some(Compare::Cmp(a, b))) => {
+            return CExpr::binary(op, CExpr::Cast("unsigned", Box::new(a)), b)
+        }
+        (Some(Relation::Sign(op)), Some(Compare::Cmp(a, b))) => {
+            return zero(op, CExpr::binary("-", a, b))
+        }
+        (Some(Relation::Signed(op) | Relation::Sign(op)), Some(Compare::Test(a, b))) => {
+            return zero(op, if a == b { a } else { CExpr::binary("&", a, b) })
+        }
+        (Some(Relation::Signed(op) | Relation::Sign(op)), Some(Compare::Value(value))) => {
+            return zero(op, value)
+        }
+        _ => {}
+    }
+    // Flags, when the comparison is unknown
+    let lowering = Lowering {
+        context,
+        byte: false,
+        extended: false,
+    };
+    lowering.value(cond)
+}
+
+/// Statements of the instructions of a block, and the condition of its conditional jump.
+fn lower_block(
+    context: &Context,
+    function: &Function,
+    block: &BasicBlock,
+) -> (Vec<Stmt>, Option<Expr>) {
+    let mut stmts = vec![];
+    let mut prologue = block.start == function.entry;
+    let mut skip = 0;
+    for (index, (address, instruction)) in block.instructions.iter().enumerate() {
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let ir = &instruction.ir;
+        // Saved registers, and a local reserved with push ax
+        let saves_register = matches!(
+            ir,
+            IR::Push {
+                src: Operand::Register(Register::SI | Register::DI | Register::AX)
+            }
+        );
+        if is_frame(ir) || (prologue && saves_register) {
+            continue;
+        }
+        prologue = false;
+
+        // Sign or zero extended ax, after cwd or xor dx, dx
+        let extended = matches!(
+            stmts.last(),
+            Some(Stmt::Assign(
+                CExpr::Reg(Register::DX),
+                CExpr::Num(0) | CExpr::Binary(">>", ..)
+            ))
+        );
+        let lowering = Lowering {
+            context,
+            byte: is_byte(ir),
+            extended,
+        };
+        let Some((lowered, end)) = lowering.lower(*address, instruction) else {
+            stmts.push(Stmt::Asm(asm(instruction)));
+            continue;
+        };
+        stmts.extend(lowered);
+        match end {
+            End::Next => {}
+            End::Call(target) => {
+                let args = cleanup(&block.instructions[index + 1..]).map(|(args, size)| {
+                    skip = size;
+                    args
+                });
+                stmts.push(Stmt::PendingCall { target, args });
+            }
+            End::Return => stmts.push(Stmt::Return(None)),
+            End::Interrupt(0x20) => {
+                let args = vec![CExpr::Reg(Register::AX), CExpr::Reg(Register::BX)];
+                let call = match known_cx(function, block, index, 8).and_then(ipc_name) {
+                    Some(name) => CExpr::call(name, args),
+                    None => CExpr::call("ipc", [vec![CExpr::Reg(Register::CX)], args].concat()),
+                };
+                stmts.push(Stmt::Assign(CExpr::Reg(Register::AX), call));
+            }
+            End::Interrupt(number) => stmts.push(Stmt::Expr(CExpr::call(
+                "interrupt",
+                vec![CExpr::Num(number as u16)],
+            ))),
+            End::Jump(target) => {
+                let table = function.jump_tables.iter().find(|t| t.jump == *address);
+                match (table, target) {
+                    (Some(table), _) => stmts.push(Stmt::Switch {
+                        value: CExpr::Reg(table.register),
+                        cases: table.cases.clone(),
+                        default: table.default,
+                    }),
+                    (None, Expr::Const(target)) if target != function.entry => {
+                        match context.function(target) {
+                            // Shared epilogue, like .cret
+                            Some(name) if name.starts_with('.') => stmts.push(Stmt::Return(None)),
+                            Some(name) => stmts.push(Stmt::TailCall(name.clone())),
+                            None => {}
+                        }
+                    }
+                    (None, Expr::Const(_)) => {}
+                    (None, target) => stmts.push(Stmt::IndirectJump(lowering.value(&target))),
+                }
+            }
+            End::Branch(cond) => return (stmts, Some(cond)),
+        }
+    }
+    (stmts, None)
+}
+
+/// Registers of `SCRATCH` read before being written by the statements, and written by them.
+fn uses_and_kills(stmts: &[Stmt]) -> (HashSet<Register>, HashSet<Register>) {
+    let (mut uses, mut kills) = (HashSet::new(), HashSet::new());
+    for stmt in stmts {
+        for reg in stmt.reads() {
+            let reg = reg.to_word_register();
+            if SCRATCH.contains(&reg) && !kills.contains(&reg) {
+                uses.insert(reg);
+            }
+        }
+        kills.extend(stmt.kills());
+    }
+    (uses, kills)
+}
+
+/// Scratch registers live at the end of each block.
+fn live_out(
+    function: &Function,
+    stmts: &BTreeMap<u16, Vec<Stmt>>,
+) -> BTreeMap<u16, HashSet<Register>> {
+    let effects: BTreeMap<u16, _> = stmts
+        .iter()
+        .map(|(&start, stmts)| (start, uses_and_kills(stmts)))
+        .collect();
+    let mut live_in: BTreeMap<u16, HashSet<Register>> = BTreeMap::new();
+    let mut live_out: BTreeMap<u16, HashSet<Register>> = BTreeMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (start, block) in function.blocks.iter().rev() {
+            let out: HashSet<Register> = block
+                .successors
+                .iter()
+                .flat_map(|s| live_in.get(s).cloned().unwrap_or_default())
+                .collect();
+            let (uses, kills) = &effects[start];
+            let mut input = uses.clone();
+            input.extend(out.difference(kills));
+            if live_in.get(start) != Some(&input) {
+                live_in.insert(*start, input);
+                changed = true;
+            }
+            live_out.insert(*start, out);
+        }
+    }
+    live_out
+}
+
+/// What happens to a register assigned by a statement.
+enum Fate {
+    /// Read by the statement at the index only, where the value can replace it
+    Fold(usize),
+    /// Constant read by the statement at the index, and later
+    Propagate(usize),
+    /// Never read
+    Dead,
+    Kept,
+}
+
+/// Fate of the value assigned to the register by the statement at the index.
+fn fate(stmts: &[Stmt], index: usize, live: &HashSet<Register>) -> Fate {
+    let Stmt::Assign(CExpr::Reg(reg), value) = &stmts[index] else {
+        return Fate::Kept;
+    };
+    let word = reg.to_word_register();
+    let mut inputs = vec![];
+    value.registers(&mut inputs);
+    let inputs: Vec<Register> = inputs.iter().map(|r| r.to_word_register()).collect();
+    let reads = |stmt: &Stmt| stmt.reads().iter().any(|r| r.to_word_register() == word);
+    let replaces = |stmt: &Stmt| {
+        stmt.kills().contains(&word) || matches!(stmt, Stmt::Assign(CExpr::Reg(r), _) if r == reg)
+    };
+    // Dead after the statement at the index
+    let dead_after = |from: usize| {
+        for stmt in &stmts[from..] {
+            if reads(stmt) {
+                return false;
+            }
+            if replaces(stmt) {
+                return true;
+            }
+        }
+        !live.contains(&word)
+    };
+
+    // Whether the value would be the same at the statement
+    let mut unchanged = true;
+    for (use_index, stmt) in stmts.iter().enumerate().skip(index + 1) {
+        if reads(stmt) {
+            if !unchanged {
+                return Fate::Kept;
+            }
+            let substitutable = stmt.reads().iter().all(|r| {
+                r.to_word_register() != word
+                    || r == reg
+                    || (reg.is_word_register() && matches!(value, CExpr::Num(_)))
+            });
+            let count = stmt.reads().iter().filter(|r| *r == reg).count();
+            if !substitutable || (count > 1 && !value.is_simple()) {
+                return Fate::Kept;
+            }
+            let after = replaces(stmt) || dead_after(use_index + 1);
+            let constant = matches!(value, CExpr::Num(_) | CExpr::AddressOf(_) | CExpr::Str(_));
+            return match (after, constant) {
+                (true, _) => Fate::Fold(use_index),
+                (false, true) => Fate::Propagate(use_index),
+                (false, false) => Fate::Kept,
+            };
+        }
+        if replaces(stmt) {
+            return Fate::Dead;
+        }
+        let clobbers = stmt
+            .writes()
+            .iter()
+            .any(|r| inputs.contains(r) || *r == word);
+        if clobbers || (value.reads_memory() && stmt.writes_memory()) {
+            unchanged = false;
+        }
+    }
+    if live.contains(&word) {
+        Fate::Kept
+    } else {
+        Fate::Dead
+    }
+}
+
+/// Replace the scratch registers read once by the values assigned to them,
+/// and remove the unused assignments.
+fn fold(stmts: &mut Vec<Stmt>, live: &HashSet<Register>) {
+    let mut index = 0;
+    while index < stmts.len() {
+        let (reg, value) = match &stmts[index] {
+            Stmt::Assign(CExpr::Reg(reg), value) if SCRATCH.contains(&reg.to_word_register()) => {
+                (*reg, value.clone())
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        match fate(stmts, index, live) {
+            Fate::Fold(use_index) => {
+                for expr in stmts[use_index].exprs_mut() {
+                    expr.substitute(reg, &value);
+                }
+                stmts.remove(index);
+            }
+            Fate::Propagate(use_index) => {
+                for expr in stmts[use_index].exprs_mut() {
+                    expr.substitute(reg, &value);
+                }
+            }
+            Fate::Dead if value.has_call() => {
+                stmts[index] = Stmt::Expr(value);
+                index += 1;
+            }
+            Fate::Dead => {
+                stmts.remove(index);
+            }
+            Fate::Kept => index += 1,
+        }
+    }
+}
+
+/// Give the pushed values to the calls as arguments.
+fn collect_arguments(context: &Context, stmts: &mut Vec<Stmt>) {
+    let mut index = 0;
+    while index < stmts.len() {
+        let Stmt::PendingCall { target, args } = &stmts[index] else {
+            index += 1;
+            continue;
+        };
+        let (target, count) = (target.clone(), *args);
+        let mut args = vec![];
+        let mut position = index;
+        while position > 0 && count.map_or(true, |count| args.len() < count) {
+            position -= 1;
+            if let Stmt::Push(_) = stmts[position] {
+                let Stmt::Push(arg) = stmts.remove(position) else {
+                    unreachable!()
+                };
+                index -= 1;
+                args.push(arg);
+            }
+        }
+        stmts[index] = Stmt::Assign(
+            CExpr::Reg(Register::AX),
+            CExpr::Call(Box::new(target), string_arguments(context, args)),
+        );
+        index += 1;
+    }
+}
+
+/// Conversions of a printf format, as `d` for `%5d`.
+fn conversions(format: &str) -> Vec<char> {
+    let mut conversions = vec![];
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        // Flags, width, precision and size
+        match chars.find(|c| !"-+ #0123456789.*lh".contains(*c)) {
+            Some('%') | None => {}
+            Some(conversion) => conversions.push(conversion),
+        }
+    }
+    conversions
+}
+
+/// Arguments with the addresses of strings as literals. The arguments following
+/// a format are strings only for its `%s` conversions.
+fn string_arguments(context: &Context, args: Vec<CExpr>) -> Vec<CExpr> {
+    let mut format: Option<std::vec::IntoIter<char>> = None;
+    let mut strings = vec![];
+    for arg in args {
+        let conversion = format.as_mut().map(|format| format.next());
+        let string = match (&arg, conversion) {
+            (CExpr::Num(address), None | Some(Some('s') | None)) => context.string(*address),
+            _ => None,
+        };
+        if let (Some(CExpr::Str(text)), None) = (&string, &format) {
+            if text.contains('%') {
+                format = Some(conversions(text).into_iter());
+            }
+        }
+        strings.push(string.unwrap_or(arg));
+    }
+    strings
+}
+
+/// Return true if ax is assigned before the end of the statements, or of the
+/// predecessor blocks, other than by a call.
+fn sets_result(
+    function: &Function,
+    lowered: &BTreeMap<u16, Vec<Stmt>>,
+    start: u16,
+    end: usize,
+    depth: usize,
+) -> bool {
+    let last = lowered[&start][..end]
+        .iter()
+        .rev()
+        .find(|s| s.writes().contains(&Register::AX));
+    match last {
+        // The system call stubs return the result of the IPC call
+        Some(Stmt::Assign(CExpr::Reg(_), CExpr::Call(target, _))) => matches!(
+            &**target,
+            CExpr::Var(name) if ["send", "receive", "sendrec", "ipc"].contains(&name.as_str())
+        ),
+        Some(Stmt::Assign(CExpr::Reg(_), value)) => !value.has_call(),
+        Some(_) => false,
+        None if depth == 0 => false,
+        None => function.predecessors(start).iter().any(|&p| {
+            let end = lowered[&p].len();
+            sets_result(function, lowered, p, end, depth - 1)
+        }),
+    }
+}
+
+/// Lower the blocks of the function to statements, with the scratch registers
+/// folded into expressions. Return the blocks and whether the function returns ax.
+pub fn lower_function(context: &Context, function: &Function) -> (BTreeMap<u16, Block>, bool) {
+    let mut lowered = BTreeMap::new();
+    let mut jumps = BTreeMap::new();
+    for (&start, block) in &function.blocks {
+        let (mut stmts, jump) = lower_block(context, function, block);
+        // Only the comparison of the conditional jump is used
+        let last = stmts.iter().rposition(|s| matches!(s, Stmt::Compare(_)));
+        let mut index = 0;
+        stmts.retain(|s| {
+            index += 1;
+            !matches!(s, Stmt::Compare(_)) || (jump.is_some() && Some(index - 1) == last)
+        });
+        lowered.insert(start, stmts);
+        jumps.insert(start, jump);
+    }
+    let returns = lowered.iter().any(|(&start, stmts)| {
+        let ret = stmts.iter().position(|s| matches!(s, Stmt::Return(_)));
+        ret.map_or(false, |ret| sets_result(function, &lowered, start, ret, 2))
+    });
+    if returns {
+        for stmt in lowered.values_mut().flatten() {
+            if let Stmt::Return(None) = stmt {
+                *stmt = Stmt::Return(Some(CExpr::Reg(Register::AX)));
+            }
+        }
+    }
+
+    let live = live_out(function, &lowered);
+    let mut blocks = BTreeMap::new();
+    for (start, mut stmts) in lowered {
+        fold(&mut stmts, &live[&start]);
+        collect_arguments(context, &mut stmts);
+        fold(&mut stmts, &live[&start]);
+
+        let block = &function.blocks[&start];
+        let stops = matches!(
+            stmts.last(),
+            Some(Stmt::Return(_) | Stmt::TailCall(_) | Stmt::IndirectJump(_) | Stmt::Switch { .. })
+        ) || block.successors.is_empty();
+        let exit = match &jumps[&start] {
+            Some(jump) if block.successors.len() == 2 => {
+                let compare = match stmts.iter().rposition(|s| matches!(s, Stmt::Compare(_))) {
+                    Some(index) => match stmts.remove(index) {
+                        Stmt::Compare(compare) => Some(compare),
+                        _ => unreachable!(),
+                    },
+                    None => None,
+                };
+                let cond = match compare {
+                    Some(Compare::Cond(cond)) => cond,
+                    compare => condition(context, jump, compare),
+                };
+                Exit::Branch(cond, block.successors[0], block.successors[1])
+            }
+            _ if stops => Exit::Stop,
+            _ => Exit::Jump(block.successors[0]),
+        };
+        stmts.retain(|s| !matches!(s, Stmt::Compare(_)));
+        blocks.insert(start, Block { stmts, exit });
+    }
+    (blocks, returns)
+}
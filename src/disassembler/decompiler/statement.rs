@@ -0,0 +1,304 @@
+use super::c_expr::CExpr;
+use crate::x86::Register;
+use std::fmt::Write;
+
+/// Scratch registers, folded into the expressions reading them.
+pub const SCRATCH: [Register; 4] = [Register::AX, Register::CX, Register::DX, Register::BX];
+
+/// Values compared by the last instruction setting the flags.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Compare {
+    /// `cmp a, b`
+    Cmp(CExpr, CExpr),
+    /// `test a, b`
+    Test(CExpr, CExpr),
+    /// Result of an operation, compared with 0
+    Value(CExpr),
+    /// Condition of the jump itself, like `cx != 0` of `loop`
+    Cond(CExpr),
+}
+
+impl Compare {
+    fn exprs_mut(&mut self) -> Vec<&mut CExpr> {
+        match self {
+            Compare::Cmp(a, b) | Compare::Test(a, b) => vec![a, b],
+            Compare::Value(value) | Compare::Cond(value) => vec![value],
+        }
+    }
+}
+
+/// Statement of the pseudo-C output.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Stmt {
+    Assign(CExpr, CExpr),
+    Expr(CExpr),
+    /// Argument of a following call
+    Push(CExpr),
+    /// Call taking the given number of pushed arguments, all of them if unknown,
+    /// with the result in ax
+    PendingCall {
+        target: CExpr,
+        args: Option<usize>,
+    },
+    Compare(Compare),
+    /// Instruction without a C equivalent
+    Asm(String),
+    Return(Option<CExpr>),
+    /// Jump to another function
+    TailCall(String),
+    IndirectJump(CExpr),
+    /// Jump to the target of the case equal to the value, or else to the default
+    Switch {
+        value: CExpr,
+        cases: Vec<(u16, u16)>,
+        default: Option<u16>,
+    },
+    Goto(u16),
+    Label(u16),
+    Break,
+    Continue,
+    If(CExpr, Vec<Stmt>, Vec<Stmt>),
+    While(CExpr, Vec<Stmt>),
+    DoWhile(Vec<Stmt>, CExpr),
+    For(Box<Stmt>, CExpr, Box<Stmt>, Vec<Stmt>),
+    Forever(Vec<Stmt>),
+}
+
+impl Stmt {
+    /// Expressions evaluated by a simple statement, not by the nested ones.
+    pub fn exprs(&self) -> Vec<&CExpr> {
+        match self {
+            Stmt::Assign(CExpr::Reg(_), value) => vec![value],
+            Stmt::Assign(target, value) => match target {
+                CExpr::Deref { address, .. } => vec![address, value],
+                _ => vec![value],
+            },
+            Stmt::Expr(value)
+            | Stmt::Push(value)
+            | Stmt::Return(Some(value))
+            | Stmt::IndirectJump(value)
+            | Stmt::Switch { value, .. }
+            | Stmt::PendingCall { target: value, .. } => vec![value],
+            Stmt::Compare(Compare::Cmp(a, b) | Compare::Test(a, b)) => vec![a, b],
+            Stmt::Compare(Compare::Value(value) | Compare::Cond(value)) => vec![value],
+            _ => vec![],
+        }
+    }
+
+    pub fn exprs_mut(&mut self) -> Vec<&mut CExpr> {
+        match self {
+            Stmt::Assign(CExpr::Reg(_), value) => vec![value],
+            Stmt::Assign(target, value) => {
+                let mut exprs = match target {
+                    CExpr::Deref { address, .. } => vec![&mut **address],
+                    _ => vec![],
+                };
+                exprs.push(value);
+                exprs
+            }
+            Stmt::Expr(value)
+            | Stmt::Push(value)
+            | Stmt::Return(Some(value))
+            | Stmt::IndirectJump(value)
+            | Stmt::Switch { value, .. }
+            | Stmt::PendingCall { target: value, .. } => vec![value],
+            Stmt::Compare(compare) => compare.exprs_mut(),
+            _ => vec![],
+        }
+    }
+
+    /// Registers read by a simple statement, as written.
+    pub fn reads(&self) -> Vec<Register> {
+        if let Stmt::Asm(_) = self {
+            return Register::iter().collect();
+        }
+        let mut registers = vec![];
+        for expr in self.exprs() {
+            expr.registers(&mut registers);
+        }
+        registers
+    }
+
+    /// Word registers written by a simple statement, entirely or in part.
+    pub fn writes(&self) -> Vec<Register> {
+        match self {
+            Stmt::Assign(CExpr::Reg(reg), _) => vec![reg.to_word_register()],
+            Stmt::PendingCall { .. } => SCRATCH.to_vec(),
+            Stmt::Asm(_) => Register::iter_16().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Word registers entirely replaced by a simple statement.
+    pub fn kills(&self) -> Vec<Register> {
+        match self {
+            Stmt::Assign(CExpr::Reg(reg), _) if reg.is_word_register() => vec![*reg],
+            Stmt::PendingCall { .. } => SCRATCH.to_vec(),
+            _ => vec![],
+        }
+    }
+
+    /// Return true if the statement may change the memory.
+    pub fn writes_memory(&self) -> bool {
+        match self {
+            Stmt::Assign(CExpr::Reg(_), value) => value.has_call(),
+            Stmt::Assign(..) | Stmt::PendingCall { .. } | Stmt::Asm(_) => true,
+            Stmt::Expr(value) => value.has_call(),
+            _ => false,
+        }
+    }
+
+    /// Return true if a `continue` of the statements would leave them,
+    /// not counting the nested loops.
+    pub fn continues(&self) -> bool {
+        match self {
+            Stmt::Continue => true,
+            Stmt::If(_, then, otherwise) => then.iter().chain(otherwise).any(Stmt::continues),
+            _ => false,
+        }
+    }
+
+    /// Names of the variables and registers of the statement, and of the nested ones.
+    pub fn variables(&self, names: &mut std::collections::BTreeSet<String>) {
+        for expr in self.exprs() {
+            expr.variables(names);
+        }
+        match self {
+            Stmt::Assign(target, _) => target.variables(names),
+            Stmt::If(cond, then, otherwise) => {
+                cond.variables(names);
+                then.iter()
+                    .chain(otherwise)
+                    .for_each(|s| s.variables(names));
+            }
+            Stmt::While(cond, body) | Stmt::DoWhile(body, cond) => {
+                cond.variables(names);
+                body.iter().for_each(|s| s.variables(names));
+            }
+            Stmt::For(init, cond, step, body) => {
+                init.variables(names);
+                cond.variables(names);
+                step.variables(names);
+                body.iter().for_each(|s| s.variables(names));
+            }
+            Stmt::Forever(body) => body.iter().for_each(|s| s.variables(names)),
+            _ => {}
+        }
+    }
+
+    /// Statement without the `;`, as in the header of a `for`.
+    fn simple(&self) -> String {
+        match self {
+            Stmt::Assign(target, CExpr::Binary(op, left, right)) if **left == *target => {
+                match (*op, &**right) {
+                    ("+", CExpr::Num(1)) => format!("{}++", target),
+                    ("-", CExpr::Num(1)) => format!("{}--", target),
+                    ("+" | "-" | "*" | "&" | "|" | "^" | "<<" | ">>", right) => {
+                        format!("{} {}= {}", target, op, right)
+                    }
+                    _ => format!(
+                        "{} = {}",
+                        target,
+                        CExpr::Binary(op, left.clone(), right.clone())
+                    ),
+                }
+            }
+            Stmt::Assign(target, value) => format!("{} = {}", target, value),
+            Stmt::Expr(value) => value.to_string(),
+            Stmt::Push(value) => format!("push({})", value),
+            Stmt::PendingCall { target, .. } => format!("{}()", target),
+            Stmt::Compare(compare) => match compare {
+                Compare::Cmp(a, b) => format!("compare({}, {})", a, b),
+                Compare::Test(a, b) => format!("test({}, {})", a, b),
+                Compare::Value(value) => format!("test({})", value),
+                Compare::Cond(cond) => format!("test({})", cond),
+            },
+            Stmt::Asm(text) => format!("__asm__(\"{}\")", text),
+            Stmt::Return(None) => "return".to_string(),
+            Stmt::Return(Some(value)) => format!("return {}", value),
+            Stmt::TailCall(name) => format!("goto {}", name),
+            Stmt::IndirectJump(target) => format!("goto *{}", target),
+            Stmt::Goto(address) => format!("goto loc_{:04x}", address),
+            Stmt::Break => "break".to_string(),
+            Stmt::Continue => "continue".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Write the statement, indented by the given depth.
+    pub fn write(&self, out: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        let block = |out: &mut String, body: &[Stmt]| {
+            body.iter().for_each(|stmt| stmt.write(out, depth + 1));
+        };
+        match self {
+            Stmt::Label(address) => {
+                let _ = writeln!(out, "loc_{:04x}:", address);
+            }
+            Stmt::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                let _ = writeln!(out, "{}switch ({}) {{", indent, value);
+                for (case, target) in cases {
+                    let case = CExpr::Num(*case);
+                    let _ = writeln!(out, "{}case {}: goto loc_{:04x};", indent, case, target);
+                }
+                if let Some(target) = default {
+                    let _ = writeln!(out, "{}default: goto loc_{:04x};", indent, target);
+                }
+                let _ = writeln!(out, "{}}}", indent);
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let _ = writeln!(out, "{}if ({}) {{", indent, cond);
+                block(out, then);
+                match otherwise.as_slice() {
+                    [] => {}
+                    // else if
+                    [nested @ Stmt::If(..)] => {
+                        let mut nested_out = String::new();
+                        nested.write(&mut nested_out, depth);
+                        let _ = write!(out, "{}}} else {}", indent, nested_out.trim_start());
+                        return;
+                    }
+                    otherwise => {
+                        let _ = writeln!(out, "{}}} else {{", indent);
+                        block(out, otherwise);
+                    }
+                }
+                let _ = writeln!(out, "{}}}", indent);
+            }
+            Stmt::While(cond, body) => {
+                let _ = writeln!(out, "{}while ({}) {{", indent, cond);
+                block(out, body);
+                let _ = writeln!(out, "{}}}", indent);
+            }
+            Stmt::DoWhile(body, cond) => {
+                let _ = writeln!(out, "{}do {{", indent);
+                block(out, body);
+                let _ = writeln!(out, "{}}} while ({});", indent, cond);
+            }
+            Stmt::For(init, cond, step, body) => {
+                let _ = writeln!(
+                    out,
+                    "{}for ({}; {}; {}) {{",
+                    indent,
+                    init.simple(),
+                    cond,
+                    step.simple()
+                );
+                block(out, body);
+                let _ = writeln!(out, "{}}}", indent);
+            }
+            Stmt::Forever(body) => {
+                let _ = writeln!(out, "{}for (;;) {{", indent);
+                block(out, body);
+                let _ = writeln!(out, "{}}}", indent);
+            }
+            stmt => {
+                let _ = writeln!(out, "{}{};", indent, stmt.simple());
+            }
+        }
+    }
+}
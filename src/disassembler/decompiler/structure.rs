@@ -0,0 +1,321 @@
+use super::c_expr::CExpr;
+use super::lower::{Block, Exit};
+use super::statement::Stmt;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Loop enclosing a region, for `break` and `continue`.
+#[derive(Clone, Copy)]
+struct Loop {
+    header: u16,
+    exit: Option<u16>,
+    /// Block ending with the condition of a `do while`
+    latch: Option<u16>,
+}
+
+/// Builds the structured statements of the blocks of a function, in address order.
+struct Structurer<'a> {
+    blocks: &'a BTreeMap<u16, Block>,
+    order: Vec<u16>,
+    predecessors: BTreeMap<u16, Vec<u16>>,
+    /// Blocks with a label
+    labels: BTreeSet<u16>,
+    /// Blocks jumped to with a goto
+    gotos: BTreeSet<u16>,
+}
+
+impl Structurer<'_> {
+    fn index(&self, start: u16) -> Option<usize> {
+        self.order.iter().position(|&s| s == start)
+    }
+
+    /// Statements of a jump to the target, when the next block is `next`.
+    fn jump(&mut self, target: u16, next: Option<u16>, context: Option<Loop>) -> Vec<Stmt> {
+        if Some(target) == next {
+            return vec![];
+        }
+        match context {
+            Some(context) if context.exit == Some(target) => vec![Stmt::Break],
+            Some(context) if context.header == target => vec![Stmt::Continue],
+            _ => {
+                self.gotos.insert(target);
+                vec![Stmt::Goto(target)]
+            }
+        }
+    }
+
+    /// Statements of the blocks from `low` to `high`, followed by the block `follow`.
+    fn region(
+        &mut self,
+        low: usize,
+        high: usize,
+        follow: Option<u16>,
+        context: Option<Loop>,
+    ) -> Vec<Stmt> {
+        let mut stmts = vec![];
+        let mut index = low;
+        while index < high {
+            let start = self.order[index];
+            // The body of a loop starting at its header
+            let in_loop = context.map_or(false, |c| c.header == start && index == low);
+            if self.labels.contains(&start) && !in_loop {
+                stmts.push(Stmt::Label(start));
+            }
+            if !in_loop {
+                // Back edges from the region start a loop
+                let latch = self.predecessors[&start]
+                    .iter()
+                    .filter_map(|&p| self.index(p))
+                    .filter(|&p| p >= index && p < high)
+                    .max();
+                if let Some(latch) = latch {
+                    let after = match latch + 1 < high {
+                        true => Some(self.order[latch + 1]),
+                        false => follow,
+                    };
+                    stmts.extend(self.lower_loop(index, latch, after));
+                    index = latch + 1;
+                    continue;
+                }
+            }
+
+            let block = &self.blocks[&start];
+            stmts.extend(block.stmts.iter().cloned());
+            let next = if index + 1 < high {
+                Some(self.order[index + 1])
+            } else {
+                follow
+            };
+            // The condition of a do while is written after the body
+            if context.map_or(false, |c| c.latch == Some(start)) {
+                index += 1;
+                continue;
+            }
+            match block.exit.clone() {
+                // The cases of a switch jump to labels
+                Exit::Stop => {
+                    if let Some(Stmt::Switch { cases, default, .. }) = block.stmts.last() {
+                        let targets = cases.iter().map(|&(_, target)| target);
+                        self.gotos.extend(targets.chain(*default));
+                    }
+                }
+                Exit::Jump(target) => stmts.extend(self.jump(target, next, context)),
+                Exit::Branch(cond, target, fallthrough) => {
+                    // Forward branch over the blocks up to the target
+                    let end = match Some(target) == follow {
+                        true => Some(high),
+                        false => self.index(target).filter(|&t| t > index + 1 && t <= high),
+                    };
+                    if let (Some(end), true) = (end, Some(fallthrough) == next) {
+                        let (then, otherwise, resume) =
+                            self.conditional(index, end, high, target, follow, context);
+                        stmts.push(Stmt::If(cond.negate(), then, otherwise));
+                        index = resume;
+                        continue;
+                    }
+                    let taken = self.jump(target, None, context);
+                    stmts.push(Stmt::If(cond, taken, vec![]));
+                    stmts.extend(self.jump(fallthrough, next, context));
+                }
+            }
+            index += 1;
+        }
+        stmts
+    }
+
+    /// Branches of an `if` skipping the blocks from `index + 1` to `end`,
+    /// and the index of the block following it.
+    fn conditional(
+        &mut self,
+        index: usize,
+        end: usize,
+        high: usize,
+        target: u16,
+        follow: Option<u16>,
+        context: Option<Loop>,
+    ) -> (Vec<Stmt>, Vec<Stmt>, usize) {
+        // The then branch jumping over an else branch
+        let last = &self.blocks[&self.order[end - 1]];
+        if let (Exit::Jump(join), true) = (&last.exit, end < high) {
+            let join = *join;
+            let join_end = match Some(join) == follow {
+                true => Some(high),
+                false => self.index(join).filter(|&j| j > end && j <= high),
+            };
+            let is_loop_jump = context.map_or(false, |c| c.exit == Some(join) || c.header == join);
+            if let (Some(join_end), false) = (join_end, is_loop_jump) {
+                let then = self.region(index + 1, end, Some(join), context);
+                let otherwise = self.region(end, join_end, Some(join), context);
+                return (then, otherwise, join_end);
+            }
+        }
+        let then = self.region(index + 1, end, Some(target), context);
+        (then, vec![], end)
+    }
+
+    /// Statements of the loop from the header at `index` to the latch.
+    fn lower_loop(&mut self, index: usize, latch: usize, follow: Option<u16>) -> Vec<Stmt> {
+        let header = self.order[index];
+        let latch_start = self.order[latch];
+        let inside = |s: u16| self.index(s).map_or(false, |i| i >= index && i <= latch);
+
+        // while, the header leaving the loop
+        if let Exit::Branch(cond, target, fallthrough) = self.blocks[&header].exit.clone() {
+            if index != latch && inside(target) != inside(fallthrough) {
+                let (stay, exit) = match inside(target) {
+                    true => (cond, fallthrough),
+                    false => (cond.negate(), target),
+                };
+                let context = Loop {
+                    header,
+                    exit: Some(exit),
+                    latch: None,
+                };
+                let mut body = self.region(index + 1, latch + 1, Some(header), Some(context));
+                trim_continue(&mut body);
+                let header_stmts = self.blocks[&header].stmts.clone();
+                let mut stmts = vec![];
+                match header_stmts.is_empty() {
+                    true => stmts.push(Stmt::While(stay, body)),
+                    false => {
+                        let mut forever = header_stmts;
+                        forever.push(Stmt::If(stay.negate(), vec![Stmt::Break], vec![]));
+                        forever.extend(body);
+                        stmts.push(Stmt::Forever(forever));
+                    }
+                }
+                stmts.extend(self.leave(exit, follow));
+                return stmts;
+            }
+        }
+
+        // do while, the latch going back to the header
+        if let Exit::Branch(cond, target, fallthrough) = self.blocks[&latch_start].exit.clone() {
+            if target == header || fallthrough == header {
+                let (stay, exit) = match target == header {
+                    true => (cond, fallthrough),
+                    false => (cond.negate(), target),
+                };
+                let context = Loop {
+                    header,
+                    exit: Some(exit),
+                    latch: Some(latch_start),
+                };
+                let mut body = self.region(index, latch + 1, Some(header), Some(context));
+                trim_continue(&mut body);
+                let mut stmts = vec![Stmt::DoWhile(body, stay)];
+                stmts.extend(self.leave(exit, follow));
+                return stmts;
+            }
+        }
+
+        // Endless loop, left with breaks to the block after the latch
+        let context = Loop {
+            header,
+            exit: follow,
+            latch: None,
+        };
+        let mut body = self.region(index, latch + 1, Some(header), Some(context));
+        trim_continue(&mut body);
+        vec![Stmt::Forever(body)]
+    }
+
+    /// Jump after a loop to its exit, when it is not the next block.
+    fn leave(&mut self, exit: u16, follow: Option<u16>) -> Vec<Stmt> {
+        if Some(exit) == follow {
+            return vec![];
+        }
+        self.gotos.insert(exit);
+        vec![Stmt::Goto(exit)]
+    }
+}
+
+/// Remove the `continue` ending a loop body.
+fn trim_continue(body: &mut Vec<Stmt>) {
+    if let Some(Stmt::Continue) = body.last() {
+        body.pop();
+    }
+}
+
+/// Turn an `if` assigning a register in both branches into `?:`,
+/// and `init; while (cond) { ...; step }` into a `for`.
+fn recover(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let mut out: Vec<Stmt> = vec![];
+    for stmt in stmts {
+        let stmt = match stmt {
+            Stmt::If(cond, then, otherwise) => match (&then[..], &otherwise[..]) {
+                ([Stmt::Assign(r @ CExpr::Reg(_), a)], [Stmt::Assign(s, b)]) if r == s => {
+                    let value =
+                        CExpr::Cond(Box::new(cond), Box::new(a.clone()), Box::new(b.clone()));
+                    Stmt::Assign(r.clone(), value)
+                }
+                _ => Stmt::If(cond, recover(then), recover(otherwise)),
+            },
+            Stmt::While(cond, body) => Stmt::While(cond, recover(body)),
+            Stmt::DoWhile(body, cond) => Stmt::DoWhile(recover(body), cond),
+            Stmt::Forever(body) => Stmt::Forever(recover(body)),
+            stmt => stmt,
+        };
+        if let Stmt::While(cond, mut body) = stmt {
+            let counter = match out.last() {
+                Some(Stmt::Assign(target @ (CExpr::Reg(_) | CExpr::Var(_)), _)) => {
+                    Some(target.clone())
+                }
+                _ => None,
+            };
+            let steps = match (&counter, body.last()) {
+                (Some(counter), Some(Stmt::Assign(target, _))) => {
+                    target == counter && cond.mentions(&counter.to_string())
+                }
+                _ => false,
+            };
+            if steps && !body.iter().any(Stmt::continues) {
+                let init = out.pop().unwrap();
+                let step = body.pop().unwrap();
+                out.push(Stmt::For(Box::new(init), cond, Box::new(step), body));
+            } else {
+                out.push(Stmt::While(cond, body));
+            }
+            continue;
+        }
+        out.push(stmt);
+    }
+    out
+}
+
+/// Structured statements of the blocks, entered at the entry.
+pub fn structure(blocks: &BTreeMap<u16, Block>, entry: u16) -> Vec<Stmt> {
+    let order: Vec<u16> = blocks.keys().copied().collect();
+    let mut predecessors: BTreeMap<u16, Vec<u16>> = order.iter().map(|&s| (s, vec![])).collect();
+    for (&start, block) in blocks {
+        let successors = match block.exit {
+            Exit::Jump(target) => vec![target],
+            Exit::Branch(_, target, fallthrough) => vec![target, fallthrough],
+            Exit::Stop => vec![],
+        };
+        for successor in successors {
+            if let Some(predecessors) = predecessors.get_mut(&successor) {
+                predecessors.push(start);
+            }
+        }
+    }
+    let mut structurer = Structurer {
+        blocks,
+        order,
+        predecessors,
+        labels: BTreeSet::new(),
+        gotos: BTreeSet::new(),
+    };
+    // Labels are known after a first pass
+    let mut stmts = vec![];
+    for _ in 0..2 {
+        structurer.labels = std::mem::take(&mut structurer.gotos);
+        stmts = vec![];
+        if structurer.order.first() != Some(&entry) {
+            structurer.gotos.insert(entry);
+            stmts.push(Stmt::Goto(entry));
+        }
+        let high = structurer.order.len();
+        stmts.extend(structurer.region(0, high, None, None));
+    }
+    recover(stmts)
+}
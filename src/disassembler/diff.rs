@@ -0,0 +1,777 @@
+use super::{function_name, Analysis, Disassemblable, DisassemblerError, Function};
+use crate::minix::{Program, Symbol, SymbolSection, SymbolTable};
+use crate::utils::json_string;
+use crate::x86::{Displacement, Formatter, FormatterOptions, Instruction, Operand, Register, IR};
+use std::collections::BTreeMap;
+
+// Unchanged lines shown around the changes of a function
+const CONTEXT: usize = 2;
+
+/// A field of the header that differs between the programs.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HeaderChange {
+    pub field: &'static str,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// An instruction of the diff of a function, with its text address in each program.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DiffLine {
+    Same { old: u16, new: u16, text: String },
+    Removed { old: u16, text: String },
+    Added { new: u16, text: String },
+}
+
+impl DiffLine {
+    pub fn text(&self) -> &str {
+        match self {
+            DiffLine::Same { text, .. }
+            | DiffLine::Removed { text, .. }
+            | DiffLine::Added { text, .. } => text,
+        }
+    }
+
+    fn is_same(&self) -> bool {
+        matches!(self, DiffLine::Same { .. })
+    }
+}
+
+/// A function found in both programs, and the diff of its instructions.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionDiff {
+    pub name: String,
+    /// Name in the new program, different when matched by its instructions
+    pub new_name: String,
+    pub old: u16,
+    pub new: u16,
+    pub lines: Vec<DiffLine>,
+}
+
+impl FunctionDiff {
+    /// Return true if some instructions were removed or added.
+    pub fn is_changed(&self) -> bool {
+        self.lines.iter().any(|line| !line.is_same())
+    }
+}
+
+/// Semantic difference between two programs: the header fields, the functions
+/// matched by name or by instructions, and those found in only one of them.
+/// Instructions are compared with their addresses relocated like the matched
+/// functions, symbols and strings, so code or data moved elsewhere is unchanged.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ProgramDiff {
+    pub header: Vec<HeaderChange>,
+    pub functions: Vec<FunctionDiff>,
+    /// Functions of the old program only, with their address
+    pub removed: Vec<(String, u16)>,
+    /// Functions of the new program only, with their address
+    pub added: Vec<(String, u16)>,
+}
+
+/// An instruction of a function, with its 16-bit immediates, displacements and
+/// call targets replaced by `#` in the template, as they may be addresses, and
+/// the symbol naming each value if any.
+struct Line {
+    address: u16,
+    text: String,
+    template: String,
+    values: Vec<u16>,
+    names: Vec<Option<String>>,
+}
+
+/// A program, with its functions and their instructions.
+struct Side {
+    symbols: SymbolTable,
+    functions: Vec<Function>,
+    bodies: Vec<Vec<Line>>,
+    /// Functions with a symbol, matched by name
+    named: Vec<bool>,
+    analysis: Analysis,
+}
+
+impl Side {
+    fn new(program: &Program, symbols: SymbolTable) -> Result<Self, DisassemblerError> {
+        let disassembled = program.disassemble()?;
+        let analysis = disassembled.analyze();
+
+        // Data symbols instead of the direct memory operands
+        let mut formatter = Formatter::new(FormatterOptions {
+            raw_bytes: false,
+            ..FormatterOptions::default()
+        });
+        for address in disassembled
+            .instructions
+            .iter()
+            .filter_map(|instruction| instruction.direct_address())
+        {
+            if let Some(name) = data_symbol(program, address) {
+                formatter.data_labels.insert(address, name);
+            }
+        }
+
+        let functions = disassembled.functions(&symbols);
+        let bodies = functions
+            .iter()
+            .map(|function| {
+                function
+                    .blocks
+                    .values()
+                    .flat_map(|block| &block.instructions)
+                    .map(|(address, instruction)| {
+                        line(&symbols, &formatter, function, *address, instruction)
+                    })
+                    .collect()
+            })
+            .collect();
+        let named = functions
+            .iter()
+            .map(|f| f.entry == 0 || symbols.label_at(f.entry).is_some_and(|s| s.is_global()))
+            .collect();
+        Ok(Side {
+            symbols,
+            functions,
+            bodies,
+            named,
+            analysis,
+        })
+    }
+
+    /// Functions matched by name, and the other ones by their templates.
+    fn matches(&self, other: &Side) -> BTreeMap<usize, usize> {
+        let mut matches: BTreeMap<usize, usize> = BTreeMap::new();
+        for (i, function) in self.functions.iter().enumerate() {
+            let found = other
+                .functions
+                .iter()
+                .enumerate()
+                .position(|(j, f)| self.named[i] && other.named[j] && f.name == function.name);
+            if let Some(j) = found {
+                matches.insert(i, j);
+            }
+        }
+        for i in 0..self.functions.len() {
+            if matches.contains_key(&i) {
+                continue;
+            }
+            let found = (0..other.functions.len()).find(|j| {
+                !matches.values().any(|m| m == j)
+                    && same_templates(&self.bodies[i], &other.bodies[*j])
+            });
+            if let Some(j) = found {
+                matches.insert(i, j);
+            }
+        }
+        matches
+    }
+
+    /// Symbols with the labels of the other program's functions this one lacks,
+    /// relocated, unless they fall inside an instruction.
+    fn with_labels(&self, other: &Side, anchors: &BTreeMap<u16, u16>, text: u32) -> SymbolTable {
+        let inside = |address: u16| {
+            self.functions
+                .iter()
+                .flat_map(|f| f.blocks.values())
+                .flat_map(|block| &block.instructions)
+                .any(|(start, instruction)| {
+                    *start < address && (address as usize) < *start as usize + instruction.raw.len()
+                })
+        };
+        let mut symbols = self.symbols.clone();
+        for function in &other.functions {
+            let Some(symbol) = other
+                .symbols
+                .label_at(function.entry)
+                .filter(|s| s.is_global())
+            else {
+                continue;
+            };
+            match relocate(anchors, function.entry) {
+                Some(address)
+                    if (address as u32) < text
+                        && self.symbols.label_at(address).is_none()
+                        && !inside(address) =>
+                {
+                    symbols.symbols.push(Symbol {
+                        value: address as u32,
+                        ..symbol.clone()
+                    })
+                }
+                _ => {}
+            }
+        }
+        symbols
+    }
+}
+
+/// Addresses of the same functions, data symbols and strings in both programs,
+/// from which the other addresses are relocated.
+struct Relocations {
+    text: BTreeMap<u16, u16>,
+    data: BTreeMap<u16, u16>,
+    /// Size of the text segment, and of the data and bss segments of the old program
+    text_size: u32,
+    data_size: u32,
+}
+
+impl Relocations {
+    /// Longest common subsequence of the instructions, as removed, added and unchanged lines.
+    fn diff(&self, old: &[Line], new: &[Line]) -> Vec<DiffLine> {
+        let (n, m) = (old.len(), new.len());
+        // Length of the common subsequence of the suffixes
+        let mut common = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                common[i][j] = match self.same(&old[i], &new[j]) {
+                    true => common[i + 1][j + 1] + 1,
+                    false => common[i + 1][j].max(common[i][j + 1]),
+                };
+            }
+        }
+        let mut lines = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && self.same(&old[i], &new[j]) {
+                lines.push(DiffLine::Same {
+                    old: old[i].address,
+                    new: new[j].address,
+                    text: new[j].text.clone(),
+                });
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+                lines.push(DiffLine::Removed {
+                    old: old[i].address,
+                    text: old[i].text.clone(),
+                });
+                i += 1;
+            } else {
+                lines.push(DiffLine::Added {
+                    new: new[j].address,
+                    text: new[j].text.clone(),
+                });
+                j += 1;
+            }
+        }
+        lines
+    }
+
+    /// Return true if the instructions are the same, their values being the same,
+    /// or addresses moved like the closest anchor before them, with no other name.
+    fn same(&self, old: &Line, new: &Line) -> bool {
+        old.template == new.template
+            && same_names(old, new)
+            && old
+                .values
+                .iter()
+                .zip(&new.values)
+                .all(|(&old, &new)| self.same_value(old, new))
+    }
+
+    fn same_value(&self, old: u16, new: u16) -> bool {
+        let relocated = |anchors: &BTreeMap<u16, u16>, size: u32| {
+            (old as u32) < size && relocate(anchors, old) == Some(new)
+        };
+        old == new || relocated(&self.text, self.text_size) || relocated(&self.data, self.data_size)
+    }
+}
+
+impl ProgramDiff {
+    /// Compare the old program with the new one.
+    pub fn new(old: &Program, new: &Program) -> Result<Self, DisassemblerError> {
+        let header = header_fields(old)
+            .into_iter()
+            .zip(header_fields(new))
+            .filter(|(old, new)| old.1 != new.1)
+            .map(|((field, old), (_, new))| HeaderChange { field, old, new })
+            .collect();
+
+        let mut old_side = Side::new(old, old.symbols.clone())?;
+        let mut new_side = Side::new(new, new.symbols.clone())?;
+        let mut matches = old_side.matches(&new_side);
+        let entries = |matches: &BTreeMap<usize, usize>, old_side: &Side, new_side: &Side| {
+            matches
+                .iter()
+                .map(|(&i, &j)| (old_side.functions[i].entry, new_side.functions[j].entry))
+                .collect::<BTreeMap<u16, u16>>()
+        };
+        // A stripped program gets the labels of the other one, like those of the
+        // helpers only jumped to, so that their functions are split the same way
+        let anchors = entries(&matches, &old_side, &new_side);
+        if new.header.syms == 0 || old.header.syms == 0 {
+            if new.header.syms == 0 {
+                let symbols = new_side.with_labels(&old_side, &anchors, new.header.text);
+                new_side = Side::new(new, symbols)?;
+            }
+            if old.header.syms == 0 {
+                let inverse = anchors.iter().map(|(&from, &to)| (to, from)).collect();
+                let symbols = old_side.with_labels(&new_side, &inverse, old.header.text);
+                old_side = Side::new(old, symbols)?;
+            }
+            matches = old_side.matches(&new_side);
+        }
+
+        let relocations = Relocations {
+            text: entries(&matches, &old_side, &new_side),
+            data: data_anchors(old, new, &old_side.analysis, &new_side.analysis),
+            text_size: old.header.text,
+            data_size: old.header.data + old.header.bss,
+        };
+        let functions = matches
+            .iter()
+            .map(|(&i, &j)| FunctionDiff {
+                name: old_side.functions[i].name.clone(),
+                new_name: new_side.functions[j].name.clone(),
+                old: old_side.functions[i].entry,
+                new: new_side.functions[j].entry,
+                lines: relocations.diff(&old_side.bodies[i], &new_side.bodies[j]),
+            })
+            .collect();
+        let removed = old_side
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !matches.contains_key(i))
+            .map(|(_, f)| (f.name.clone(), f.entry))
+            .collect();
+        let added = new_side
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !matches.values().any(|m| m == j))
+            .map(|(_, f)| (f.name.clone(), f.entry))
+            .collect();
+        Ok(ProgramDiff {
+            header,
+            functions,
+            removed,
+            added,
+        })
+    }
+
+    /// Return true if the programs have the same header and functions.
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.removed.is_empty()
+            && self.added.is_empty()
+            && !self.functions.iter().any(FunctionDiff::is_changed)
+    }
+
+    /// JSON document of the diff, with all the lines of the matched functions.
+    pub fn to_json(&self) -> String {
+        let optional = |value: Option<u16>| value.map_or("null".to_string(), |v| v.to_string());
+        let header = self
+            .header
+            .iter()
+            .map(|change| {
+                format!(
+                    "{{\"field\":\"{}\",\"old\":{},\"new\":{}}}",
+                    change.field, change.old, change.new
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let unmatched = |functions: &[(String, u16)]| {
+            functions
+                .iter()
+                .map(|(name, address)| {
+                    format!("{{\"name\":{},\"address\":{}}}", json_string(name), address)
+                })
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        let functions = self
+            .functions
+            .iter()
+            .map(|function| {
+                let lines = function
+                    .lines
+                    .iter()
+                    .map(|line| {
+                        let (op, old, new) = match *line {
+                            DiffLine::Same { old, new, .. } => ("same", Some(old), Some(new)),
+                            DiffLine::Removed { old, .. } => ("removed", Some(old), None),
+                            DiffLine::Added { new, .. } => ("added", None, Some(new)),
+                        };
+                        format!(
+                            "{{\"op\":\"{}\",\"old\":{},\"new\":{},\"text\":{}}}",
+                            op,
+                            optional(old),
+                            optional(new),
+                            json_string(line.text())
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(",");
+                format!(
+                    "{{\"name\":{},\"new_name\":{},\"old\":{},\"new\":{},\"changed\":{},\"lines\":[{}]}}",
+                    json_string(&function.name),
+                    json_string(&function.new_name),
+                    function.old,
+                    function.new,
+                    function.is_changed(),
+                    lines
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"header\":[{}],\"removed\":[{}],\"added\":[{}],\"functions\":[{}]}}",
+            header,
+            unmatched(&self.removed),
+            unmatched(&self.added),
+            functions
+        )
+    }
+}
+
+/// Report of the header changes, the unmatched functions and the changed
+/// instructions of the matched ones, with a few lines of context.
+impl std::fmt::Display for ProgramDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for change in &self.header {
+            writeln!(
+                f,
+                "header {:<8} {:#x} -> {:#x}",
+                change.field, change.old, change.new
+            )?;
+        }
+        let changed: Vec<&FunctionDiff> =
+            self.functions.iter().filter(|f| f.is_changed()).collect();
+        writeln!(
+            f,
+            "functions: {} matched, {} changed, {} removed, {} added",
+            self.functions.len(),
+            changed.len(),
+            self.removed.len(),
+            self.added.len()
+        )?;
+        for (name, address) in &self.removed {
+            writeln!(f, "removed {} ({:04x})", name, address)?;
+        }
+        for (name, address) in &self.added {
+            writeln!(f, "added {} ({:04x})", name, address)?;
+        }
+        for function in changed {
+            let name = match function.name == function.new_name {
+                true => function.name.clone(),
+                false => format!("{} -> {}", function.name, function.new_name),
+            };
+            writeln!(
+                f,
+                "changed {} ({:04x} -> {:04x})",
+                name, function.old, function.new
+            )?;
+            // Lines within `CONTEXT` of a change
+            let lines = &function.lines;
+            let shown = |i: usize| {
+                let range = i.saturating_sub(CONTEXT)..(i + CONTEXT + 1).min(lines.len());
+                lines[range].iter().any(|line| !line.is_same())
+            };
+            let mut skipped = false;
+            for (i, line) in lines.iter().enumerate() {
+                if !shown(i) {
+                    skipped = true;
+                    continue;
+                }
+                if std::mem::take(&mut skipped) {
+                    writeln!(f, "  ...")?;
+                }
+                match line {
+                    DiffLine::Same { old, new, text } => {
+                        writeln!(f, "  {:04x} {:04x}  {}", old, new, text)?
+                    }
+                    DiffLine::Removed { old, text } => writeln!(f, "- {:04x}       {}", old, text)?,
+                    DiffLine::Added { new, text } => writeln!(f, "+      {:04x}  {}", new, text)?,
+                }
+            }
+            if skipped {
+                writeln!(f, "  ...")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return true if the displacement is a word after a ModRM byte with `mod` 10.
+fn long_displacement(raw: &[u8], disp: u16) -> bool {
+    let word = disp.to_le_bytes();
+    (1..raw.len().saturating_sub(1)).any(|i| raw[i..i + 2] == word && raw[i - 1] >> 6 == 2)
+}
+
+/// Address moved like the closest anchor before it.
+fn relocate(anchors: &BTreeMap<u16, u16>, address: u16) -> Option<u16> {
+    anchors
+        .range(..=address)
+        .next_back()
+        .map(|(&from, &to)| to.wrapping_add(address - from))
+}
+
+/// Return true if the functions have the same instructions, but for their values.
+fn same_templates(old: &[Line], new: &[Line]) -> bool {
+    old.len() == new.len()
+        && old
+            .iter()
+            .zip(new)
+            .all(|(old, new)| old.template == new.template && same_names(old, new))
+}
+
+/// Return true if no value is named differently in the programs, a value
+/// without symbol in one of them being compared by address only.
+fn same_names(old: &Line, new: &Line) -> bool {
+    old.names
+        .iter()
+        .zip(&new.names)
+        .all(|(old, new)| old.is_none() || new.is_none() || old == new)
+}
+
+/// Fields of the header that may differ, the magic number as a little-endian word.
+fn header_fields(program: &Program) -> Vec<(&'static str, u32)> {
+    let header = &program.header;
+    vec![
+        ("magic", u16::from_le_bytes(header.magic) as u32),
+        ("flags", header.flags as u32),
+        ("cpu", header.cpu as u32),
+        ("hdrlen", header.hdrlen as u32),
+        ("version", header.version as u32),
+        ("text", header.text),
+        ("data", header.data),
+        ("bss", header.bss),
+        ("entry", header.entry),
+        ("total", header.total),
+        ("syms", header.syms),
+    ]
+}
+
+/// Name of the data or bss symbol at the address, global ones being preferred.
+fn data_symbol(program: &Program, address: u16) -> Option<String> {
+    let symbols = program.symbols.symbols.iter().filter(|s| {
+        s.value == address as u32 && matches!(s.section(), SymbolSection::Data | SymbolSection::Bss)
+    });
+    symbols
+        .clone()
+        .filter(|s| s.is_global())
+        .last()
+        .or(symbols.last())
+        .map(|s| s.name.clone())
+}
+
+/// Data addresses of the symbols with the same name, and of the strings found once in each program.
+fn data_anchors(
+    old: &Program,
+    new: &Program,
+    old_analysis: &Analysis,
+    new_analysis: &Analysis,
+) -> BTreeMap<u16, u16> {
+    let data = |s: &&Symbol| matches!(s.section(), SymbolSection::Data | SymbolSection::Bss);
+    let mut anchors: BTreeMap<u16, u16> = old
+        .symbols
+        .symbols
+        .iter()
+        .filter(data)
+        .filter_map(|symbol| {
+            let other = new.symbols.lookup(&symbol.name).filter(data)?;
+            Some((symbol.value as u16, other.value as u16))
+        })
+        .collect();
+    let unique = |analysis: &Analysis, bytes: &[u8]| {
+        let mut found = analysis.strings.iter().filter(|s| s.bytes == bytes);
+        match (found.next(), found.next()) {
+            (Some(string), None) => Some(string.address),
+            _ => None,
+        }
+    };
+    for string in &old_analysis.strings {
+        if let (Some(from), Some(to)) = (
+            unique(old_analysis, &string.bytes),
+            unique(new_analysis, &string.bytes),
+        ) {
+            anchors.entry(from).or_insert(to);
+        }
+    }
+    anchors
+}
+
+/// Instruction of the function: the blocks it jumps to are numbered, and the
+/// addresses of the functions it calls and of the data are values of the template.
+fn line(
+    symbols: &SymbolTable,
+    formatter: &Formatter,
+    function: &Function,
+    address: u16,
+    instruction: &Instruction,
+) -> Line {
+    let mnemonic = instruction.ir.mnemonic();
+    if let Some(target) = instruction.ir.branch_target() {
+        let block = function.blocks.keys().position(|&start| start == target);
+        let (label, values, names) = match (&instruction.ir, block) {
+            (IR::Call { .. }, _) | (_, None) => {
+                let name = function_name(symbols, target);
+                let symbol = symbols.label_at(target).filter(|s| s.is_global());
+                (name.clone(), vec![target], vec![symbol.map(|_| name)])
+            }
+            (_, Some(block)) => (format!(".L{}", block), vec![], vec![]),
+        };
+        let template = match values.is_empty() {
+            true => format!("{} {}", mnemonic, label),
+            false => format!("{} #", mnemonic),
+        };
+        return Line {
+            address,
+            text: format!("{} {}", mnemonic, label),
+            template,
+            values,
+            names,
+        };
+    }
+
+    let text = formatter.format_instruction(instruction);
+    let mut template = text.clone();
+    let mut values = vec![];
+    let mut names = vec![];
+    for operand in instruction.operands() {
+        let mut name = None;
+        let (value, rendered, replacement) = match operand {
+            Operand::LongImmediate(value) => (value, format!("{:04x}", value), "#"),
+            Operand::MemoryAddress(address) => {
+                let Some(Displacement::Long(disp)) = address.disp else {
+                    continue;
+                };
+                match (address.base, address.index) {
+                    // Named by `formatter`
+                    (None, None) => match formatter.data_labels.get(&(disp as u16)) {
+                        Some(label) => {
+                            name = Some(label.clone());
+                            (disp as u16, format!("[{}]", label), "[#]")
+                        }
+                        None => (disp as u16, format!("[{:04x}]", disp), "[#]"),
+                    },
+                    // Frame variables, and short displacements
+                    (Some(Register::BP), None) => continue,
+                    _ if !long_displacement(&instruction.raw, disp as u16) => continue,
+                    _ if disp < 0 => (disp as u16, format!("-{:x}", -(disp as i32)), "+#"),
+                    _ => (disp as u16, format!("+{:x}", disp), "+#"),
+                }
+            }
+            _ => continue,
+        };
+        if let Some(position) = template.find(' ') {
+            let operands = template[position..].replacen(&rendered, replacement, 1);
+            template.truncate(position);
+            template.push_str(&operands);
+            values.push(value);
+            names.push(name);
+        }
+    }
+    Line {
+        address,
+        text,
+        template,
+        values,
+        names,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::Signatures;
+    use crate::test_utils::program;
+
+    #[test]
+    fn test_same_program() {
+        let diff = ProgramDiff::new(&program("3.c"), &program("3.c")).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.functions.len(), 56);
+        assert!(diff
+            .to_string()
+            .starts_with("functions: 56 matched, 0 changed"));
+    }
+
+    #[test]
+    fn test_moved_addresses() {
+        // 4.c has a longer main, and a shorter string
+        let diff = ProgramDiff::new(&program("3.c"), &program("4.c")).unwrap();
+        assert_eq!(
+            diff.header,
+            vec![
+                HeaderChange {
+                    field: "text",
+                    old: 0x12c0,
+                    new: 0x12d0
+                },
+                HeaderChange {
+                    field: "data",
+                    old: 0x214,
+                    new: 0x212
+                }
+            ]
+        );
+        assert!(diff.removed.is_empty() && diff.added.is_empty());
+        let changed: Vec<&str> = diff
+            .functions
+            .iter()
+            .filter(|f| f.is_changed())
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(changed, vec!["main", "_etext"]);
+
+        // Moved code, data and function pointers
+        let printf = diff.functions.iter().find(|f| f.name == "printf").unwrap();
+        assert_eq!((printf.old, printf.new), (0x7e, 0x87));
+        let clean = diff.functions.iter().find(|f| f.name == "__flush").unwrap();
+        assert!(clean.lines.contains(&DiffLine::Same {
+            old: 0x0848,
+            new: 0x0851,
+            text: "mov [__clean], 1040".to_string()
+        }));
+
+        let main = diff.functions.iter().find(|f| f.name == "main").unwrap();
+        assert!(main.lines.contains(&DiffLine::Added {
+            new: 0x3d,
+            text: "mov dx, 04d2".to_string()
+        }));
+        assert!(main.lines.contains(&DiffLine::Same {
+            old: 0x40,
+            new: 0x48,
+            text: "call printf".to_string()
+        }));
+    }
+
+    #[test]
+    fn test_unmatched_functions() {
+        let diff = ProgramDiff::new(&program("2.c"), &program("6.c")).unwrap();
+        assert_eq!(diff.added, vec![("printo".to_string(), 0x47)]);
+        assert!(diff.to_string().contains("- 003c       mov ax, 0061\n"));
+
+        let json = diff.to_json();
+        assert!(json.starts_with(
+            "{\"header\":[{\"field\":\"text\",\"old\":336,\"new\":400},{\"field\":\"syms\",\"old\":720,\"new\":736}],\
+            \"removed\":[],\"added\":[{\"name\":\"printo\",\"address\":71}],"
+        ));
+        assert!(
+            json.contains("{\"op\":\"removed\",\"old\":64,\"new\":null,\"text\":\"call putchar\"}")
+        );
+    }
+
+    #[test]
+    fn test_stripped_copy() {
+        // Same bytes, without the symbol table but with the recognized functions
+        let mut binary = std::fs::read("./tests_data/1.c.out").unwrap();
+        binary[28..32].copy_from_slice(&[0; 4]);
+        let mut stripped = Program::from_bytes(&binary).unwrap();
+        stripped.symbols = Signatures::minix().recognize(&stripped.disassemble().unwrap());
+
+        let diff = ProgramDiff::new(&program("1.c"), &stripped).unwrap();
+        assert_eq!(
+            diff.header,
+            vec![HeaderChange {
+                field: "syms",
+                old: 0x2c0,
+                new: 0
+            }]
+        );
+        assert!(diff.removed.is_empty() && diff.added.is_empty());
+        assert!(!diff.functions.iter().any(FunctionDiff::is_changed));
+    }
+}
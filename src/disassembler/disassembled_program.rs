@@ -1,14 +1,53 @@
-use crate::x86::{Instruction, IR};
+use crate::x86::{Formatter, Instruction, IR};
 
-/// The sequence of instructions that have been disassembled from a given program binary.
+/// The sequence of instructions that have been disassembled from a given program binary,
+/// and its data segment.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DisassembledProgram {
     pub instructions: Vec<Instruction>,
+    pub data: Vec<u8>,
 }
 
-#[allow(unused_variables)]
 impl DisassembledProgram {
     pub fn new(instructions: Vec<Instruction>, data: Vec<u8>) -> Self {
-        DisassembledProgram { instructions }
+        DisassembledProgram { instructions, data }
+    }
+
+    /// Iterate over the instructions with their text address.
+    pub fn iter_with_address(&self) -> impl Iterator<Item = (u16, &Instruction)> {
+        self.instructions.iter().scan(0u16, |address, instruction| {
+            let current = *address;
+            *address = address.wrapping_add(instruction.raw.len() as u16);
+            Some((current, instruction))
+        })
+    }
+
+    /// Listing of the instructions with their address, in the formatter's syntax.
+    pub fn format(&self, formatter: &Formatter) -> String {
+        let mut out = String::new();
+        for (address, instruction) in self.iter_with_address() {
+            out.push_str(&self.format_line(formatter, address, instruction));
+        }
+        out
+    }
+
+    /// Line of the listing of the instruction.
+    pub(super) fn format_line(
+        &self,
+        formatter: &Formatter,
+        address: u16,
+        instruction: &Instruction,
+    ) -> String {
+        let address = format!("{:04x}", address);
+        let address = match formatter.options.uppercase {
+            true => address.to_uppercase(),
+            false => address,
+        };
+        format!(
+            "{}: {}\n",
+            address,
+            formatter.format_instruction(instruction)
+        )
     }
 }
 
@@ -21,11 +60,6 @@ impl From<Vec<IR>> for DisassembledProgram {
 
 impl std::fmt::Display for DisassembledProgram {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut bytes_count = 0;
-        for instruction in &self.instructions {
-            write!(f, "{:04x}: {}\n", bytes_count, instruction)?;
-            bytes_count += instruction.raw.len();
-        }
-        Ok(())
+        write!(f, "{}", self.format(&Formatter::default()))
     }
 }
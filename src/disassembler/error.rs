@@ -4,6 +4,8 @@ pub enum DisassemblerError {
     InvalidOpcode(u8),
     UnexpectedEOF,
     InvalidModRM,
+    /// Line of a signature file
+    InvalidSignature(String),
 }
 
 impl std::fmt::Display for DisassemblerError {
@@ -15,6 +17,7 @@ impl std::fmt::Display for DisassemblerError {
             }
             DisassemblerError::UnexpectedEOF => write!(f, "Unexpected end of file"),
             DisassemblerError::InvalidModRM => write!(f, "Invalid ModRM byte"),
+            DisassemblerError::InvalidSignature(line) => write!(f, "Invalid signature: {}", line),
         }
     }
 }
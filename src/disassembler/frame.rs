@@ -0,0 +1,554 @@
+use super::cfg::is_terminator;
+use super::{BasicBlock, DisassembledProgram, Function};
+use crate::minix::SymbolTable;
+use crate::x86::{Formatter, Instruction, Operand, Register, IR};
+use std::collections::BTreeMap;
+
+/// Inconsistent use of the stack found by the frame analysis.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StackIssue {
+    /// Paths reaching the block with different stack depths
+    Unbalanced { address: u16, depths: (i16, i16) },
+    /// `ret` with the stack pointer away from the return address
+    BadReturn { address: u16, depth: i16 },
+    /// Stack pointer set to a value the analysis doesn't follow
+    UnknownDepth { address: u16 },
+}
+
+impl StackIssue {
+    /// Address of the instruction or block the issue is found at.
+    pub fn address(&self) -> u16 {
+        match self {
+            StackIssue::Unbalanced { address, .. }
+            | StackIssue::BadReturn { address, .. }
+            | StackIssue::UnknownDepth { address } => *address,
+        }
+    }
+}
+
+impl std::fmt::Display for StackIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackIssue::Unbalanced { address, depths } => write!(
+                f,
+                "paths reach {:04x} with stack depths {} and {}",
+                address,
+                -(depths.0 as i32),
+                -(depths.1 as i32)
+            ),
+            StackIssue::BadReturn { address, depth } if *depth < 0 => write!(
+                f,
+                "ret at {:04x} with {} bytes left on the stack",
+                address,
+                -(*depth as i32)
+            ),
+            StackIssue::BadReturn { address, depth } => write!(
+                f,
+                "ret at {:04x} with {} bytes popped past the return address",
+                address, depth
+            ),
+            StackIssue::UnknownDepth { address } => {
+                write!(
+                    f,
+                    "stack pointer set to an unknown value at {:04x}",
+                    address
+                )
+            }
+        }
+    }
+}
+
+/// Argument or local variable of a frame.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FrameVariable {
+    /// `arg_N` for the Nth word above the return address, `local_N` for `[bp-N]`
+    pub name: String,
+    /// Displacement from bp
+    pub offset: i16,
+    /// Instructions accessing the variable
+    pub accesses: Vec<u16>,
+}
+
+/// Stack usage and frame layout of a function.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    pub name: String,
+    pub entry: u16,
+    /// SP minus the SP at the entry, before each instruction of the function
+    pub depths: BTreeMap<u16, i16>,
+    /// Most bytes pushed or reserved below the SP at the entry
+    pub max_depth: u16,
+    /// Depth bp is set to by `mov bp, sp` in the prologue
+    pub frame_pointer: Option<i16>,
+    /// The prologue pushes the bp of the caller
+    pub saved_bp: bool,
+    /// Callee-saved registers pushed by the prologue, SI and DI
+    pub saved: Vec<Register>,
+    /// Bytes reserved by `sub sp, N` in the prologue
+    pub locals_size: u16,
+    /// Arguments and locals, by displacement from bp
+    pub variables: BTreeMap<i16, FrameVariable>,
+    pub issues: Vec<StackIssue>,
+}
+
+impl Frame {
+    /// Names of the `[bp+N]` operands, by displacement.
+    pub fn labels(&self) -> BTreeMap<i16, String> {
+        self.variables
+            .iter()
+            .map(|(offset, variable)| (*offset, variable.name.clone()))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: max depth {}", self.name, self.max_depth)?;
+        if self.locals_size > 0 {
+            write!(f, ", {} bytes of locals", self.locals_size)?;
+        }
+        let saved: Vec<String> = self
+            .saved_bp
+            .then_some(Register::BP)
+            .iter()
+            .chain(&self.saved)
+            .map(|reg| reg.to_string())
+            .collect();
+        if !saved.is_empty() {
+            write!(f, ", saved {}", saved.join(" "))?;
+        }
+        if self.frame_pointer.is_none() {
+            write!(f, ", no frame pointer")?;
+        }
+        writeln!(f)?;
+        for variable in self.variables.values() {
+            let sign = if variable.offset < 0 { '-' } else { '+' };
+            let offset = (variable.offset as i32).unsigned_abs();
+            writeln!(f, "    {} at bp{}{:x}", variable.name, sign, offset)?;
+        }
+        for issue in &self.issues {
+            writeln!(f, "    {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Value of bp, as the analysis knows it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum FramePointer {
+    /// Still the bp of the caller
+    Caller,
+    /// Set by `mov bp, sp` at the depth
+    Depth(i16),
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct State {
+    sp: i16,
+    bp: FramePointer,
+}
+
+pub(super) fn immediate(operand: &Operand) -> Option<i16> {
+    match operand {
+        Operand::Immediate(n) => Some(*n as i16),
+        Operand::LongImmediate(n) => Some(*n as i16),
+        Operand::SignExtendedImmediate(n) => Some(*n as i16),
+        _ => None,
+    }
+}
+
+/// Return true if the instruction writes the register.
+pub(super) fn writes(ir: &IR, reg: Register) -> bool {
+    let operands = ir.operands();
+    let written = match ir {
+        IR::Push { .. } | IR::Cmp { .. } | IR::Test { .. } | IR::Out { .. } => &[][..],
+        IR::Call { .. } | IR::Jmp { .. } | IR::Ret { .. } | IR::Esc { .. } => &[][..],
+        IR::Xchg { .. } => &operands[..],
+        _ if ir.is_conditional_jump() => &[][..],
+        _ => &operands[..operands.len().min(1)],
+    };
+    written.contains(&&Operand::Register(reg))
+}
+
+/// State after the instruction, or None when the path leaves the function.
+fn step(address: u16, ir: &IR, state: State) -> Result<Option<State>, StackIssue> {
+    use Register::{BP, SP};
+    let State { mut sp, mut bp } = state;
+    let unknown = StackIssue::UnknownDepth { address };
+    match ir {
+        IR::Pop {
+            dest: Operand::Register(SP),
+        } => return Err(unknown),
+        IR::Push { .. } | IR::Pushf => sp = sp.wrapping_sub(2),
+        IR::Pop {
+            dest: Operand::Register(BP),
+        } => {
+            sp = sp.wrapping_add(2);
+            bp = FramePointer::Caller;
+        }
+        IR::Pop { .. } | IR::Popf => sp = sp.wrapping_add(2),
+        IR::Add {
+            dest: Operand::Register(SP),
+            src,
+        } => sp = sp.wrapping_add(immediate(src).ok_or(unknown)?),
+        IR::Sub {
+            dest: Operand::Register(SP),
+            src,
+        } => sp = sp.wrapping_sub(immediate(src).ok_or(unknown)?),
+        IR::Inc {
+            dest: Operand::Register(SP),
+        } => sp = sp.wrapping_add(1),
+        IR::Dec {
+            dest: Operand::Register(SP),
+        } => sp = sp.wrapping_sub(1),
+        IR::Mov {
+            dest: Operand::Register(BP),
+            src: Operand::Register(SP),
+            ..
+        } => bp = FramePointer::Depth(sp),
+        // The frame of the caller is restored by the shared epilogues
+        IR::Mov {
+            dest: Operand::Register(SP),
+            src: Operand::Register(BP),
+            ..
+        } => match bp {
+            FramePointer::Depth(depth) => sp = depth,
+            FramePointer::Caller => return Ok(None),
+            FramePointer::Unknown => return Err(unknown),
+        },
+        IR::Lea {
+            dest: Operand::Register(SP),
+            src: Operand::MemoryAddress(address),
+        } => match (bp, address.base, address.index) {
+            (FramePointer::Depth(depth), Some(BP), None) => {
+                sp = depth.wrapping_add(address.disp.map_or(0, |d| d.into()) as i16)
+            }
+            _ => return Err(unknown),
+        },
+        IR::Ret { .. } if sp != 0 => return Err(StackIssue::BadReturn { address, depth: sp }),
+        IR::Ret { .. } | IR::Iret | IR::Hlt | IR::Undefined => return Ok(None),
+        ir if writes(ir, SP) => return Err(unknown),
+        ir if writes(ir, BP) => bp = FramePointer::Unknown,
+        _ => {}
+    }
+    Ok(Some(State { sp, bp }))
+}
+
+/// Blocks run after the block, including the shared epilogues and the tail calls
+/// it jumps or falls through to.
+fn successors(block: &BasicBlock, blocks: &BTreeMap<u16, &BasicBlock>) -> Vec<u16> {
+    let mut successors = block.successors.clone();
+    let last = block.last();
+    let target = last.and_then(|i| match i.ir {
+        IR::Call { .. } => None,
+        ref ir => ir.branch_target(),
+    });
+    // A call before another function doesn't return, like a call to `_exit`
+    let next = last
+        .filter(|i| !is_terminator(&i.ir) && !matches!(i.ir, IR::Call { .. }))
+        .map(|_| block.end);
+    for address in target.into_iter().chain(next) {
+        if blocks.contains_key(&address) && !successors.contains(&address) {
+            successors.push(address);
+        }
+    }
+    successors
+}
+
+/// Instructions of the prologue: saving bp, setting the frame pointer,
+/// reserving the locals and saving SI and DI.
+fn is_prologue(ir: &IR) -> bool {
+    use Register::*;
+    match ir {
+        IR::Push {
+            src: Operand::Register(BP | SI | DI),
+        } => true,
+        IR::Mov {
+            dest: Operand::Register(BP),
+            src: Operand::Register(SP),
+            ..
+        } => true,
+        IR::Sub {
+            dest: Operand::Register(SP),
+            src,
+        } => immediate(src).is_some(),
+        _ => false,
+    }
+}
+
+impl Frame {
+    /// Follow the stack depth through the blocks of the function, and those of the
+    /// functions it jumps to, from the SP at the entry.
+    fn analyze(function: &Function, shared: &BTreeMap<u16, &BasicBlock>) -> Self {
+        let mut frame = Frame {
+            name: function.name.clone(),
+            entry: function.entry,
+            depths: BTreeMap::new(),
+            max_depth: 0,
+            frame_pointer: None,
+            saved_bp: false,
+            saved: vec![],
+            locals_size: 0,
+            variables: BTreeMap::new(),
+            issues: vec![],
+        };
+        let mut blocks = shared.clone();
+        blocks.extend(function.blocks.iter().map(|(start, block)| (*start, block)));
+
+        // Depths of the slots of the saved registers
+        let mut saved_slots = vec![];
+        let mut reached: BTreeMap<u16, State> = BTreeMap::new();
+        let start = State {
+            sp: 0,
+            bp: FramePointer::Caller,
+        };
+        let mut pending = vec![(function.entry, start)];
+        while let Some((address, mut state)) = pending.pop() {
+            if let Some(seen) = reached.get(&address) {
+                let issue = StackIssue::Unbalanced {
+                    address,
+                    depths: (seen.sp, state.sp),
+                };
+                let reported = frame
+                    .issues
+                    .iter()
+                    .any(|i| matches!(i, StackIssue::Unbalanced { .. }) && i.address() == address);
+                if seen.sp != state.sp && !reported {
+                    frame.issues.push(issue);
+                }
+                continue;
+            }
+            reached.insert(address, state);
+            let Some(block) = blocks.get(&address) else {
+                continue;
+            };
+            let own = function.blocks.contains_key(&address);
+            let mut prologue = address == function.entry;
+            let mut leaves = false;
+            for (address, instruction) in &block.instructions {
+                prologue &= is_prologue(&instruction.ir);
+                if own {
+                    frame.depths.insert(*address, state.sp);
+                    if prologue {
+                        frame.prologue(&instruction.ir, state, &mut saved_slots);
+                    }
+                    frame.variables(*address, instruction, state, &saved_slots);
+                }
+                match step(*address, &instruction.ir, state) {
+                    Ok(Some(next)) => state = next,
+                    Ok(None) => {
+                        leaves = true;
+                        break;
+                    }
+                    Err(issue) => {
+                        if !frame.issues.contains(&issue) {
+                            frame.issues.push(issue);
+                        }
+                        leaves = true;
+                        break;
+                    }
+                }
+                if own {
+                    frame.max_depth = frame.max_depth.max(state.sp.min(0).unsigned_abs());
+                }
+            }
+            if !leaves {
+                for successor in successors(block, &blocks) {
+                    pending.push((successor, state));
+                }
+            }
+        }
+        frame.issues.sort_by_key(StackIssue::address);
+        frame
+    }
+
+    fn prologue(&mut self, ir: &IR, state: State, saved_slots: &mut Vec<i16>) {
+        match ir {
+            IR::Push {
+                src: Operand::Register(Register::BP),
+            } => self.saved_bp |= state.sp == 0,
+            IR::Push {
+                src: Operand::Register(reg),
+            } => {
+                self.saved.push(*reg);
+                saved_slots.push(state.sp.wrapping_sub(2));
+            }
+            IR::Mov { .. } => self.frame_pointer = Some(state.sp),
+            IR::Sub { src, .. } => self.locals_size += immediate(src).unwrap_or(0) as u16,
+            _ => {}
+        }
+    }
+
+    /// Record the `[bp+N]` operands of the instruction as arguments and locals.
+    fn variables(&mut self, address: u16, instruction: &Instruction, state: State, saved: &[i16]) {
+        let FramePointer::Depth(frame_pointer) = state.bp else {
+            return;
+        };
+        for operand in instruction.ir.operands() {
+            let Operand::MemoryAddress(memory) = operand else {
+                continue;
+            };
+            if memory.base != Some(Register::BP) || memory.index.is_some() {
+                continue;
+            }
+            let offset = memory.disp.map_or(0, |d| d.into()) as i16;
+            // Depth of the slot from the SP at the entry, the return address being at 0
+            let slot = frame_pointer.wrapping_add(offset);
+            let name = if slot >= 2 && slot % 2 == 0 {
+                argument_name(slot)
+            } else if offset < 0 && slot < frame_pointer && !saved.contains(&slot) {
+                local_name(offset)
+            } else {
+                continue;
+            };
+            let variable = self.variables.entry(offset).or_insert(FrameVariable {
+                name,
+                offset,
+                accesses: vec![],
+            });
+            if !variable.accesses.contains(&address) {
+                variable.accesses.push(address);
+            }
+        }
+    }
+}
+
+/// Name of the argument at the depth `slot` from the SP at the entry,
+/// `arg_0` for the word above the return address.
+pub(super) fn argument_name(slot: i16) -> String {
+    format!("arg_{}", slot / 2 - 1)
+}
+
+/// Name of the local at `offset` below bp.
+pub(super) fn local_name(offset: i16) -> String {
+    format!("local_{:x}", -(offset as i32))
+}
+
+impl DisassembledProgram {
+    /// Stack depths and frame layouts of the functions.
+    pub fn frames(&self, symbols: &SymbolTable) -> Vec<Frame> {
+        let functions = self.functions(symbols);
+        let mut blocks = BTreeMap::new();
+        for function in &functions {
+            for (start, block) in &function.blocks {
+                blocks.entry(*start).or_insert(block);
+            }
+        }
+        functions
+            .iter()
+            .map(|function| Frame::analyze(function, &blocks))
+            .collect()
+    }
+
+    /// Listing with the frame of each function, and its arguments and locals
+    /// named in the `bp` relative operands.
+    pub fn format_frames(&self, formatter: &Formatter, symbols: &SymbolTable) -> String {
+        let frames = self.frames(symbols);
+        let mut owners: BTreeMap<u16, &Frame> = BTreeMap::new();
+        for frame in &frames {
+            for address in frame.depths.keys() {
+                owners.entry(*address).or_insert(frame);
+            }
+        }
+        let mut formatter = formatter.clone();
+        let mut out = String::new();
+        for (address, instruction) in self.iter_with_address() {
+            if let Some(frame) = frames.iter().find(|f| f.entry == address) {
+                for line in frame.to_string().lines() {
+                    out.push_str(&format!("; {}\n", line));
+                }
+            }
+            formatter.frame_labels = owners
+                .get(&address)
+                .map(|frame| frame.labels())
+                .unwrap_or_default();
+            out.push_str(&self.format_line(&formatter, address, instruction));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{parse_instruction, Disassemblable};
+    use crate::test_utils::program;
+
+    fn disassemble(text: &[u8]) -> DisassembledProgram {
+        let mut instructions = vec![];
+        let mut address = 0;
+        while address < text.len() {
+            let (instruction, size) = parse_instruction(&text[address..], address).unwrap();
+            instructions.push(instruction);
+            address += size;
+        }
+        DisassembledProgram::new(instructions, vec![])
+    }
+
+    #[test]
+    fn test_frames() {
+        let binary = program("6.c");
+        let frames = binary.disassemble().unwrap().frames(&binary.symbols);
+
+        // Balanced through the shared epilogue at 0183
+        let printo = frames.iter().find(|f| f.name == "printo").unwrap();
+        assert_eq!(printo.issues, []);
+        assert!(printo.saved_bp);
+        assert_eq!(printo.frame_pointer, Some(-2));
+        assert_eq!(printo.saved, [Register::SI, Register::DI]);
+        assert_eq!(printo.max_depth, 8);
+        assert_eq!(printo.depths[&0x005f], -8);
+        assert_eq!(printo.variables[&4].name, "arg_0");
+        assert_eq!(printo.variables[&4].accesses, [0x004c]);
+
+        let main = frames.iter().find(|f| f.entry == 0x0100).unwrap();
+        assert_eq!(main.locals_size, 0x18);
+        assert_eq!(main.variables[&-0x14].name, "local_14");
+        assert_eq!(main.variables[&8].name, "arg_2");
+    }
+
+    #[test]
+    fn test_unbalanced() {
+        let disassembled = disassemble(&[
+            0x55, // push bp
+            0x89, 0xe5, // mov bp, sp
+            0x09, 0xc0, // or ax, ax
+            0x74, 0x01, // je 0008
+            0x50, // push ax
+            0x8b, 0x46, 0x04, // mov ax, [bp+4]
+            0x5d, // pop bp
+            0xc3, // ret
+        ]);
+        let frames = disassembled.frames(&SymbolTable::default());
+        assert_eq!(
+            frames[0].issues,
+            [
+                StackIssue::Unbalanced {
+                    address: 0x0008,
+                    depths: (-4, -2)
+                },
+                StackIssue::BadReturn {
+                    address: 0x000c,
+                    depth: -2
+                },
+            ]
+        );
+        let formatter = Formatter::default();
+        assert_eq!(
+            disassembled.format_frames(&formatter, &SymbolTable::default()),
+            "; start: max depth 4, saved bp\n\
+             ;     arg_0 at bp+4\n\
+             ;     paths reach 0008 with stack depths 4 and 2\n\
+             ;     ret at 000c with 2 bytes left on the stack\n\
+             0000: 55            push bp\n\
+             0001: 89e5          mov bp, sp\n\
+             0003: 09c0          or ax, ax\n\
+             0005: 7401          je 0008\n\
+             0007: 50            push ax\n\
+             0008: 8b4604        mov ax, [arg_0]\n\
+             000b: 5d            pop bp\n\
+             000c: c3            ret\n"
+        );
+    }
+}
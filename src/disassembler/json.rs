@@ -0,0 +1,226 @@
+use super::DisassembledProgram;
+use crate::minix::Program;
+use crate::utils::json_string;
+use crate::x86::{Formatter, Instruction, Operand, IR};
+
+impl DisassembledProgram {
+    /// JSON document of the program: header, segments, symbols,
+    /// and the instructions with their operands, branch target and symbol.
+    ///
+    /// This schema is written for other tools and doesn't depend on the `serde`
+    /// feature. The serde derives follow the Rust types instead, with the enum
+    /// variants as keys like `{"LongImmediate":4}`, and don't match it.
+    pub fn to_json(&self, program: &Program) -> String {
+        let header = &program.header;
+        let symbols = program
+            .symbols
+            .symbols
+            .iter()
+            .map(|symbol| {
+                format!(
+                    "{{\"name\":{},\"value\":{},\"section\":\"{}\"}}",
+                    json_string(&symbol.name),
+                    symbol.value,
+                    format!("{:?}", symbol.section()).to_lowercase()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let instructions = self
+            .iter_with_address()
+            .map(|(address, instruction)| instruction_json(program, address, instruction))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        format!(
+            "{{\"header\":{{\"magic\":[{},{}],\"flags\":{},\"cpu\":{},\"hdrlen\":{},\"version\":{},\"text\":{},\"data\":{},\"bss\":{},\"entry\":{},\"total\":{},\"syms\":{}}},\
+            \"segments\":{{\"text\":{{\"offset\":{},\"size\":{}}},\"data\":{{\"offset\":{},\"size\":{}}},\"bss\":{{\"address\":{},\"size\":{}}}}},\
+            \"symbols\":[{}],\"instructions\":[{}]}}",
+            header.magic[0],
+            header.magic[1],
+            header.flags,
+            header.cpu,
+            header.hdrlen,
+            header.version,
+            header.text,
+            header.data,
+            header.bss,
+            header.entry,
+            header.total,
+            header.syms,
+            header.hdrlen,
+            header.text,
+            header.hdrlen as u32 + header.text,
+            header.data,
+            header.data,
+            header.bss,
+            symbols,
+            instructions
+        )
+    }
+}
+
+fn instruction_json(program: &Program, address: u16, instruction: &Instruction) -> String {
+    let (prefix, ir) = match &instruction.ir {
+        IR::Rep { z, string_ir } => {
+            let prefix = match (z, string_ir.as_ref()) {
+                (false, IR::Cmps { .. } | IR::Scas { .. }) => "\"repne\"",
+                _ => "\"rep\"",
+            };
+            (prefix, string_ir.as_ref())
+        }
+        ir => ("null", ir),
+    };
+    let operands = instruction
+        .operands()
+        .iter()
+        .map(operand_json)
+        .collect::<Vec<String>>()
+        .join(",");
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    let target = instruction.ir.branch_target();
+    let target_symbol = target
+        .and_then(|target| program.symbols.label_at(target))
+        .map(|symbol| json_string(&symbol.name));
+    let symbol = program
+        .symbols
+        .function_at(address)
+        .map(|symbol| json_string(&symbol.name));
+
+    format!(
+        "{{\"offset\":{},\"raw\":\"{}\",\"prefix\":{},\"mnemonic\":\"{}\",\"operands\":[{}],\"text\":{},\"target\":{},\"target_symbol\":{},\"symbol\":{}}}",
+        address,
+        instruction
+            .raw
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<String>>()
+            .concat(),
+        prefix,
+        ir.mnemonic(),
+        operands,
+        json_string(&Formatter::default().format_ir(&instruction.ir)),
+        optional(target.map(|target| target.to_string())),
+        optional(target_symbol),
+        optional(symbol)
+    )
+}
+
+fn operand_json(operand: &Operand) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+    match operand {
+        Operand::Register(register) => {
+            format!("{{\"type\":\"register\",\"register\":\"{}\"}}", register)
+        }
+        Operand::Immediate(value) => format!(
+            "{{\"type\":\"immediate\",\"value\":{},\"size\":8,\"sign_extended\":false}}",
+            value
+        ),
+        Operand::LongImmediate(value) => format!(
+            "{{\"type\":\"immediate\",\"value\":{},\"size\":16,\"sign_extended\":false}}",
+            value
+        ),
+        Operand::SignExtendedImmediate(value) => format!(
+            "{{\"type\":\"immediate\",\"value\":{},\"size\":8,\"sign_extended\":true}}",
+            value
+        ),
+        Operand::MemoryAddress(address) => format!(
+            "{{\"type\":\"memory\",\"base\":{},\"index\":{},\"displacement\":{}}}",
+            optional(address.base.map(|r| format!("\"{}\"", r))),
+            optional(address.index.map(|r| format!("\"{}\"", r))),
+            optional(
+                address
+                    .disp
+                    .map(|d| (Into::<u16>::into(d) as i16).to_string())
+            )
+        ),
+        Operand::Displacement(d) => format!(
+            "{{\"type\":\"target\",\"address\":{}}}",
+            Into::<u16>::into(*d)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disassembler::{decode, Disassemblable};
+    use crate::test_utils::program;
+
+    #[test]
+    fn test_to_json() {
+        let program = program("1.c");
+        let json = program.disassemble().unwrap().to_json(&program);
+
+        assert!(json.starts_with(
+            "{\"header\":{\"magic\":[1,3],\"flags\":32,\"cpu\":4,\"hdrlen\":32,\"version\":0,\"text\":320,\"data\":20,"
+        ));
+        assert!(json.contains(
+            "\"segments\":{\"text\":{\"offset\":32,\"size\":320},\"data\":{\"offset\":352,\"size\":20},"
+        ));
+        assert!(json.contains("{\"name\":\"_main\",\"value\":57,\"section\":\"text\"}"));
+        // call main
+        assert!(json.contains(
+            "{\"offset\":49,\"raw\":\"e80500\",\"prefix\":null,\"mnemonic\":\"call\",\
+            \"operands\":[{\"type\":\"target\",\"address\":57}],\"text\":\"call 0039\",\
+            \"target\":57,\"target_symbol\":\"_main\",\"symbol\":\"crtso\"}"
+        ));
+        // mov bx, [0002]
+        assert!(json.contains(
+            "\"operands\":[{\"type\":\"register\",\"register\":\"bx\"},\
+            {\"type\":\"memory\",\"base\":null,\"index\":null,\"displacement\":2}]"
+        ));
+    }
+
+    #[test]
+    fn test_json_parses() {
+        for name in ["1.c", "2.c", "3.c", "4.c", "5.c", "6.c", "7.c"] {
+            let args = vec![
+                "minix2_rs".to_string(),
+                format!("./tests_data/{}.out", name),
+                "--json".to_string(),
+            ];
+            let json = decode(args).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+            let program = program(name);
+            let disassembled = program.disassemble().unwrap();
+            assert_eq!(value["header"]["text"], program.header.text);
+            assert_eq!(value["segments"]["data"]["size"], program.header.data);
+            let instructions = value["instructions"].as_array().unwrap();
+            assert_eq!(
+                instructions.len(),
+                disassembled.instructions.len(),
+                "{}",
+                name
+            );
+            for (instruction, (address, _)) in
+                instructions.iter().zip(disassembled.iter_with_address())
+            {
+                assert_eq!(instruction["offset"], address, "{}", name);
+                assert!(instruction["operands"].is_array(), "{}", name);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::x86::{Instruction, Operand, IR};
+
+        for name in ["1.c", "2.c", "3.c", "4.c", "5.c", "6.c", "7.c"] {
+            let disassembled = program(name).disassemble().unwrap();
+            for instruction in &disassembled.instructions {
+                let json = serde_json::to_string(instruction).unwrap();
+                let parsed: Instruction = serde_json::from_str(&json).unwrap();
+                assert_eq!(&parsed, instruction, "{}", json);
+
+                let json = serde_json::to_string(&instruction.ir).unwrap();
+                assert_eq!(serde_json::from_str::<IR>(&json).unwrap(), instruction.ir);
+            }
+        }
+
+        let operand = Operand::LongImmediate(0xfffe);
+        let json = serde_json::to_string(&operand).unwrap();
+        assert_eq!(serde_json::from_str::<Operand>(&json).unwrap(), operand);
+    }
+}
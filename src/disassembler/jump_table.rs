@@ -0,0 +1,473 @@
+use super::frame::{immediate, writes};
+use super::{parse_instruction, DisassembledProgram, PointerKind};
+use crate::x86::{Instruction, Operand, Register, IR};
+
+// Most cases read from a table
+const MAX_CASES: usize = 1024;
+// Instructions searched back from the jump for the table and the bound of the index
+pub(super) const LOOK_BACK: usize = 8;
+
+/// How the jump selects the case of a table.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JumpTableKind {
+    /// `jmp [bx+table]` with the index bounded by a `cmp` and a `ja`
+    Indexed,
+    /// ACK `.csa2` descriptor: default, lower bound, range and a target per value
+    Dense,
+    /// ACK `.csb2` descriptor: default, count and the value and target of each case
+    Sparse,
+}
+
+/// Targets of an indirect jump, read from a table of the text or data segment.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JumpTable {
+    /// Text address of the jump
+    pub jump: u16,
+    /// Address of the table, or of the descriptor of the ACK helpers
+    pub address: u16,
+    /// Segment of the table
+    pub segment: PointerKind,
+    pub kind: JumpTableKind,
+    /// Register holding the switched value at the jump
+    pub register: Register,
+    /// Value of the register and target of each case, without the zero targets
+    pub cases: Vec<(u16, u16)>,
+    /// Target when no case matches, None when the value traps or isn't checked
+    pub default: Option<u16>,
+}
+
+impl JumpTable {
+    /// Distinct targets of the cases and the default, in order.
+    pub fn targets(&self) -> Vec<u16> {
+        let mut targets: Vec<u16> = self
+            .cases
+            .iter()
+            .map(|&(_, target)| target)
+            .chain(self.default)
+            .collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+}
+
+impl DisassembledProgram {
+    /// Jump tables of the indirect jumps and of the jumps to the ACK switch helpers.
+    pub fn jump_tables(&self) -> Vec<JumpTable> {
+        let text = self.text();
+        let instructions: Vec<(u16, &Instruction)> = self.iter_with_address().collect();
+        (0..instructions.len())
+            .filter(|&index| matches!(instructions[index].1.ir, IR::Jmp { .. }))
+            .filter_map(|index| {
+                let history = &instructions[(index + 1).saturating_sub(LOOK_BACK)..=index];
+                resolve(history, &text, &self.data)
+            })
+            .collect()
+    }
+}
+
+/// Table of the jump ending the history, from the instructions run before it.
+pub(super) fn resolve(
+    history: &[(u16, &Instruction)],
+    text: &[u8],
+    data: &[u8],
+) -> Option<JumpTable> {
+    let (&(jump, last), before) = history.split_last()?;
+    match &last.ir {
+        IR::Jmp {
+            dest: Operand::MemoryAddress(address),
+            ..
+        } => {
+            let register = match (address.base, address.index) {
+                (Some(register), None) | (None, Some(register)) => register,
+                _ => return None,
+            };
+            indexed(
+                jump,
+                before,
+                before.len(),
+                register,
+                address.disp?.into(),
+                text,
+                data,
+            )
+        }
+        IR::Jmp {
+            dest: Operand::Register(register),
+            ..
+        } => {
+            // mov bx, [bx+table]; jmp bx
+            let (index, (_, load)) = before
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(_, (_, i))| writes(&i.ir, *register))?;
+            let IR::Mov {
+                src: Operand::MemoryAddress(address),
+                ..
+            } = load.ir
+            else {
+                return None;
+            };
+            let register = match (address.base, address.index) {
+                (Some(register), None) | (None, Some(register)) => register,
+                _ => return None,
+            };
+            let table = address.disp?.into();
+            indexed(jump, before, index, register, table, text, data)
+        }
+        IR::Jmp { .. } => {
+            let helper = last.ir.branch_target()?;
+            let kind = helper_kind(text, helper)?;
+            // mov bx, descriptor; ...; jmp .csb2
+            let (_, load) = before
+                .iter()
+                .rev()
+                .find(|(_, i)| clobbers(&i.ir, Register::BX))?;
+            let IR::Mov {
+                dest: Operand::Register(Register::BX),
+                src: Operand::LongImmediate(descriptor),
+                ..
+            } = load.ir
+            else {
+                return None;
+            };
+            described(jump, kind, descriptor, text, data)
+        }
+        _ => None,
+    }
+}
+
+/// Table of `jmp [register+table]`, with the index register scaled and
+/// bounded by a `cmp` and a `ja` or `jnb` before it. The instructions from
+/// `load` up to the jump load the target from the table.
+fn indexed(
+    jump: u16,
+    history: &[(u16, &Instruction)],
+    load: usize,
+    register: Register,
+    table: u16,
+    text: &[u8],
+    data: &[u8],
+) -> Option<JumpTable> {
+    let index = register;
+    let mut register = register;
+    let mut scaled = false;
+    // Index of the `cmp`, cases and target of the values above the bound
+    let mut bound: Option<(usize, u16, u16)> = None;
+    let mut above = None;
+    let mut lower = 0u16;
+    for (position, (_, instruction)) in history[..load].iter().enumerate().rev() {
+        let ir = &instruction.ir;
+        match ir {
+            IR::Shl {
+                dest: Operand::Register(r),
+                src: Operand::Immediate(1),
+            } if *r == register && !scaled => scaled = true,
+            IR::Add {
+                dest: Operand::Register(r),
+                src: Operand::Register(s),
+            } if *r == register && *s == register && !scaled => scaled = true,
+            IR::Mov {
+                dest: Operand::Register(r),
+                src: Operand::Register(s),
+                ..
+            } if *r == register && s.is_word_register() && bound.is_none() => register = *s,
+            IR::Jnbe { .. } if bound.is_none() => above = ir.branch_target().map(|t| (t, 1)),
+            IR::Jnb { .. } if bound.is_none() => above = ir.branch_target().map(|t| (t, 0)),
+            IR::Cmp {
+                dest: Operand::Register(r),
+                src,
+                ..
+            } if *r == register && bound.is_none() => {
+                let (default, extra) = above?;
+                let count = (immediate(src)? as u16).checked_add(extra)?;
+                bound = Some((position, count, default));
+            }
+            IR::Sub {
+                dest: Operand::Register(r),
+                src,
+            } if *r == register && bound.is_some() => {
+                lower = immediate(src).map_or(0, |n| n as u16);
+                break;
+            }
+            _ if clobbers(ir, register) || ir.branch_target().is_some() => break,
+            _ => {}
+        }
+    }
+    let (position, count, default) = bound?;
+    if !scaled || count == 0 || count as usize > MAX_CASES {
+        return None;
+    }
+    let intact = |register| {
+        history[position + 1..]
+            .iter()
+            .all(|(_, i)| !clobbers(&i.ir, register))
+    };
+    // Switch on the compared value, or else on the scaled index read by the jump
+    let (register, lower, step) = if intact(register) {
+        (register, lower, 1)
+    } else if history[load..].iter().all(|(_, i)| !clobbers(&i.ir, index)) {
+        (index, 0, 2)
+    } else {
+        return None;
+    };
+    let (segment, words) = read_words(text, data, table, count as usize)?;
+    let cases = words
+        .into_iter()
+        .enumerate()
+        .map(|(i, target)| (lower.wrapping_add(i as u16 * step), target))
+        .collect();
+    Some(JumpTable {
+        jump,
+        address: table,
+        segment,
+        kind: JumpTableKind::Indexed,
+        register,
+        cases,
+        default: Some(default),
+    })
+}
+
+/// Table of a jump to an ACK switch helper, from its descriptor.
+fn described(
+    jump: u16,
+    kind: JumpTableKind,
+    descriptor: u16,
+    text: &[u8],
+    data: &[u8],
+) -> Option<JumpTable> {
+    let (segment, header) = read_descriptor(text, data, descriptor, 3)?;
+    let nonzero = |target: u16| (target != 0).then_some(target);
+    let mut cases = vec![];
+    let address = descriptor.wrapping_add(4);
+    match kind {
+        JumpTableKind::Sparse => {
+            let count = header[1] as usize;
+            if count > MAX_CASES {
+                return None;
+            }
+            let words = read_in(segment, text, data, address, count * 2)?;
+            for pair in words.chunks_exact(2) {
+                cases.extend(nonzero(pair[1]).map(|target| (pair[0], target)));
+            }
+        }
+        _ => {
+            let count = header[2] as usize + 1;
+            if count > MAX_CASES {
+                return None;
+            }
+            let words = read_in(segment, text, data, address.wrapping_add(2), count)?;
+            for (index, target) in words.into_iter().enumerate() {
+                let value = header[1].wrapping_add(index as u16);
+                cases.extend(nonzero(target).map(|target| (value, target)));
+            }
+        }
+    }
+    if cases
+        .iter()
+        .any(|&(_, target)| target as usize >= text.len())
+    {
+        return None;
+    }
+    Some(JumpTable {
+        jump,
+        address: descriptor,
+        segment,
+        kind,
+        register: Register::AX,
+        cases,
+        default: nonzero(header[0]).filter(|&target| (target as usize) < text.len()),
+    })
+}
+
+/// Kind of the ACK switch helper at the address, from its first instructions:
+/// `mov dx, [bx]` then `sub ax, [bx+2]` or `mov cx, [bx+2]`.
+fn helper_kind(text: &[u8], address: u16) -> Option<JumpTableKind> {
+    let (first, size) = parse_instruction(text.get(address as usize..)?, address as usize).ok()?;
+    let next = address as usize + size;
+    let (second, _) = parse_instruction(text.get(next..)?, next).ok()?;
+    let word_at = |operand: &Operand, disp: u16| match operand {
+        Operand::MemoryAddress(a) => {
+            a.base == Some(Register::BX)
+                && a.index.is_none()
+                && a.disp.map_or(0, |d| d.into()) == disp
+        }
+        _ => false,
+    };
+    match (&first.ir, &second.ir) {
+        (
+            IR::Mov {
+                dest: Operand::Register(Register::DX),
+                src: default,
+                ..
+            },
+            second,
+        ) if word_at(default, 0) => match second {
+            IR::Sub {
+                dest: Operand::Register(Register::AX),
+                src,
+            } if word_at(src, 2) => Some(JumpTableKind::Dense),
+            IR::Mov {
+                dest: Operand::Register(Register::CX),
+                src,
+                ..
+            } if word_at(src, 2) => Some(JumpTableKind::Sparse),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Return true if the instruction writes the word register or one of its halves.
+fn clobbers(ir: &IR, register: Register) -> bool {
+    Register::iter()
+        .filter(|r| r.to_word_register() == register)
+        .any(|r| writes(ir, r))
+}
+
+/// Words of the table, from the data segment or else the text segment,
+/// when they are all text addresses.
+fn read_words(
+    text: &[u8],
+    data: &[u8],
+    address: u16,
+    count: usize,
+) -> Option<(PointerKind, Vec<u16>)> {
+    [PointerKind::Data, PointerKind::Text]
+        .into_iter()
+        .find_map(|segment| {
+            let words = read_in(segment, text, data, address, count)?;
+            words
+                .iter()
+                .all(|&target| (target as usize) < text.len())
+                .then_some((segment, words))
+        })
+}
+
+/// Header words of a descriptor, from the data segment or else the text segment.
+fn read_descriptor(
+    text: &[u8],
+    data: &[u8],
+    address: u16,
+    count: usize,
+) -> Option<(PointerKind, Vec<u16>)> {
+    [PointerKind::Data, PointerKind::Text]
+        .into_iter()
+        .find_map(|segment| Some((segment, read_in(segment, text, data, address, count)?)))
+}
+
+/// Little-endian words at the address of the segment.
+fn read_in(
+    segment: PointerKind,
+    text: &[u8],
+    data: &[u8],
+    address: u16,
+    count: usize,
+) -> Option<Vec<u16>> {
+    let bytes = match segment {
+        PointerKind::Data => data,
+        PointerKind::Text => text,
+    };
+    let start = address as usize;
+    let words = bytes.get(start..start + count * 2)?;
+    Some(
+        words
+            .chunks_exact(2)
+            .map(|w| u16::from_le_bytes([w[0], w[1]]))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::Disassemblable;
+    use crate::minix::SymbolTable;
+    use crate::test_utils::program;
+
+    #[test]
+    fn test_ack_switch() {
+        let binary = program("3.c");
+        let disassembled = binary.disassemble().unwrap();
+
+        // mov bx, 005e; mov ax, dx; jmp .csb2
+        let tables = disassembled.jump_tables();
+        assert_eq!(tables.len(), 5);
+        assert_eq!(
+            tables[0],
+            JumpTable {
+                jump: 0x01ad,
+                address: 0x005e,
+                segment: PointerKind::Data,
+                kind: JumpTableKind::Sparse,
+                register: Register::AX,
+                cases: vec![(0x20, 0x0128), (0x40, 0x014f)],
+                default: Some(0x017b),
+            }
+        );
+
+        // The cases are blocks of the function
+        let functions = disassembled.functions(&binary.symbols);
+        let function = functions.iter().find(|f| f.entry == 0x0114).unwrap();
+        assert_eq!(function.jump_tables, tables[..2]);
+        assert!(function.blocks.contains_key(&0x0128));
+        assert_eq!(
+            function.blocks[&0x01a2].successors,
+            [0x0128, 0x014f, 0x017b]
+        );
+
+        let analysis = disassembled.analyze();
+        assert!(analysis
+            .xrefs
+            .to_code(0x014f)
+            .iter()
+            .any(|r| r.from == 0x01ad));
+        assert!(disassembled
+            .decompile(&binary.symbols)
+            .contains("case 0x20: goto loc_0128;"));
+    }
+
+    #[test]
+    fn test_indexed() {
+        let text = [
+            0x8b, 0x46, 0x04, // mov ax, [bp+4]
+            0x83, 0xe8, 0x01, // sub ax, 1
+            0x83, 0xf8, 0x02, // cmp ax, 2
+            0x77, 0x0b, // ja 0016
+            0x89, 0xc3, // mov bx, ax
+            0xd1, 0xe3, // shl bx, 1
+            0xff, 0xa7, 0x04, 0x00, // jmp [bx+0004]
+            0x40, // inc ax
+            0x48, // dec ax
+            0xc3, // ret
+            0xc3, // ret
+        ];
+        let mut instructions = vec![];
+        let mut address = 0;
+        while address < text.len() {
+            let (instruction, size) = parse_instruction(&text[address..], address).unwrap();
+            instructions.push(instruction);
+            address += size;
+        }
+        let data = vec![0, 0, 0, 0, 0x13, 0, 0x14, 0, 0x15, 0];
+        let disassembled = DisassembledProgram::new(instructions, data);
+
+        let table = JumpTable {
+            jump: 0x000f,
+            address: 0x0004,
+            segment: PointerKind::Data,
+            kind: JumpTableKind::Indexed,
+            register: Register::AX,
+            cases: vec![(1, 0x0013), (2, 0x0014), (3, 0x0015)],
+            default: Some(0x0016),
+        };
+        assert_eq!(disassembled.jump_tables(), [table.clone()]);
+        let functions = disassembled.functions(&SymbolTable::default());
+        assert_eq!(functions[0].jump_tables, [table]);
+        assert_eq!(
+            functions[0].blocks[&0x000b].successors,
+            [0x0013, 0x0014, 0x0015, 0x0016]
+        );
+    }
+}
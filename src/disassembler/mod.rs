@@ -1,9 +1,26 @@
+mod analysis;
+mod cfg;
 mod decoder;
+mod decompiler;
+mod diff;
 mod disassembled_program;
 mod error;
+mod frame;
+mod json;
+mod jump_table;
+mod nasm;
 mod parser;
+mod signature;
 
+pub use self::analysis::{
+    Analysis, CrossReferences, DataString, PointerKind, PointerTable, Reference, ReferenceKind,
+};
+pub use self::cfg::{function_name, BasicBlock, Function};
 pub use self::decoder::{decode, Disassemblable};
+pub use self::diff::{DiffLine, FunctionDiff, HeaderChange, ProgramDiff};
 pub use self::disassembled_program::DisassembledProgram;
 pub use self::error::DisassemblerError;
+pub use self::frame::{Frame, FrameVariable, StackIssue};
+pub use self::jump_table::{JumpTable, JumpTableKind};
 pub use self::parser::parse_instruction;
+pub use self::signature::{Signature, Signatures};
@@ -0,0 +1,374 @@
+use super::analysis::{data_reference, Analysis};
+use super::DisassembledProgram;
+use crate::x86::{Formatter, FormatterOptions, Instruction, Syntax, IR};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Bytes per `db` line
+const BYTES_PER_LINE: usize = 8;
+
+impl DisassembledProgram {
+    /// NASM source rebuilding the text and data segments byte for byte,
+    /// with `nasm -f bin`. The a.out header isn't part of the output.
+    ///
+    /// Jump and call targets, direct data references and the immediate
+    /// addresses of strings and tables are labelled.
+    /// Instructions that NASM would encode differently are kept as `db`.
+    pub fn to_nasm(&self) -> String {
+        let mut formatter = Formatter::new(FormatterOptions {
+            syntax: Syntax::Nasm,
+            raw_bytes: false,
+            ..FormatterOptions::default()
+        });
+        formatter.labels = self.text_labels();
+        let analysis = self.analyze();
+        let (data_labels, words) = self.data_labels(&analysis);
+        formatter.address_labels = data_labels
+            .iter()
+            .filter(|(&address, _)| analysis.is_known(address))
+            .map(|(&address, label)| (address, label.clone()))
+            .collect();
+        formatter.data_labels = data_labels;
+
+        let mut out = String::from("bits 16\ncpu 8086\n\nsection .text vstart=0\n");
+        let padding = self.text_padding(&formatter.labels);
+        for (address, instruction) in self.iter_with_address() {
+            if address >= padding {
+                break;
+            }
+            if let Some(label) = formatter.labels.get(&address) {
+                out.push_str(&format!("{}:\n", label));
+            }
+            // Targets inside an instruction of a misparse split its bytes
+            let end = address as usize + instruction.raw.len();
+            let inner: Vec<u16> = formatter
+                .labels
+                .range(address.saturating_add(1)..)
+                .map(|(&target, _)| target)
+                .take_while(|&target| (target as usize) < end)
+                .collect();
+            if !inner.is_empty() {
+                let mut start = 0;
+                for target in inner {
+                    let split = (target - address) as usize;
+                    let line = bytes(&instruction.raw[start..split]);
+                    match start {
+                        0 => out.push_str(&format!(
+                            "    {:<30}; {}\n",
+                            line,
+                            formatter.format_instruction(instruction)
+                        )),
+                        _ => out.push_str(&format!("    {}\n", line)),
+                    }
+                    out.push_str(&format!("{}:\n", formatter.labels[&target]));
+                    start = split;
+                }
+                out.push_str(&format!("    {}\n", bytes(&instruction.raw[start..])));
+            } else if is_canonical(instruction) {
+                out.push_str(&format!(
+                    "    {}\n",
+                    formatter.format_instruction(instruction)
+                ));
+            } else {
+                out.push_str(&format!(
+                    "    {:<30}; {}\n",
+                    bytes(&instruction.raw),
+                    formatter.format_instruction(instruction)
+                ));
+            }
+        }
+        let text_end = self
+            .iter_with_address()
+            .last()
+            .map_or(0, |(address, instruction)| {
+                address as usize + instruction.raw.len()
+            });
+        if text_end > padding as usize {
+            out.push_str(&format!("    times {} db 0\n", text_end - padding as usize));
+        }
+
+        out.push_str("\nsection .data follows=.text align=1 vstart=0\n");
+        let mut boundaries: Vec<usize> = formatter
+            .data_labels
+            .keys()
+            .map(|&address| address as usize)
+            .collect();
+        boundaries.push(self.data.len());
+        let mut start = 0;
+        for end in boundaries {
+            out.push_str(&data_lines(&self.data[start..end]));
+            start = end;
+            let Some(label) = formatter.data_labels.get(&(end as u16)) else {
+                continue;
+            };
+            out.push_str(&format!("{}:\n", label));
+            // First word of the data read and written as a word
+            if words.contains(&(end as u16)) && end + 2 <= self.data.len() {
+                let word = u16::from_le_bytes([self.data[end], self.data[end + 1]]);
+                out.push_str(&format!("    dw 0x{:04x}\n", word));
+                start = end + 2;
+            }
+        }
+        out
+    }
+
+    /// Labels of the jump and call targets in the text, starting an instruction
+    /// or inside one when the linear sweep misparsed the code.
+    fn text_labels(&self) -> BTreeMap<u16, String> {
+        let text_end: usize = self.instructions.iter().map(|i| i.raw.len()).sum();
+        self.instructions
+            .iter()
+            .filter_map(|instruction| instruction.ir.branch_target())
+            .filter(|&target| (target as usize) < text_end)
+            .map(|target| (target, format!("loc_{:04x}", target)))
+            .collect()
+    }
+
+    /// Labels of the direct memory operands and the immediate string and table
+    /// addresses inside the data segment, and the addresses accessed as words.
+    fn data_labels(&self, analysis: &Analysis) -> (BTreeMap<u16, String>, BTreeSet<u16>) {
+        let mut labels = BTreeMap::new();
+        let mut words = BTreeSet::new();
+        for instruction in &self.instructions {
+            let known = |address| analysis.is_known(address);
+            let Some(address) = data_reference(instruction, known) else {
+                continue;
+            };
+            if address as usize > self.data.len() {
+                continue;
+            }
+            labels.insert(address, format!("data_{:04x}", address));
+            let direct = instruction.direct_address().is_some();
+            if direct
+                && instruction
+                    .raw
+                    .first()
+                    .map_or(false, |opcode| opcode & 1 == 1)
+            {
+                words.insert(address);
+            }
+        }
+        // A labelled word would hide the next label
+        let labelled: Vec<u16> = labels.keys().copied().collect();
+        words.retain(|address| !labelled.contains(&(address + 1)));
+        (labels, words)
+    }
+
+    /// Address of the trailing zero bytes that pad the text segment, without label.
+    fn text_padding(&self, labels: &BTreeMap<u16, String>) -> u16 {
+        let mut padding = None;
+        for (address, instruction) in self.iter_with_address() {
+            let end = address as usize + instruction.raw.len();
+            let labelled = labels
+                .range(address..)
+                .next()
+                .is_some_and(|(&label, _)| (label as usize) < end);
+            let zero = instruction.raw.iter().all(|&b| b == 0) && !labelled;
+            padding = match (zero, padding) {
+                (true, None) => Some(address),
+                (true, padding) => padding,
+                (false, _) => None,
+            };
+        }
+        padding.unwrap_or(u16::MAX)
+    }
+}
+
+fn bytes(raw: &[u8]) -> String {
+    let bytes: Vec<String> = raw.iter().map(|b| format!("0x{:02x}", b)).collect();
+    format!("db {}", bytes.join(", "))
+}
+
+/// `db` lines of the data, with `times` for runs of zeros.
+fn data_lines(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let zeros = rest.iter().take_while(|&&b| b == 0).count();
+        if zeros >= BYTES_PER_LINE {
+            out.push_str(&format!("    times {} db 0\n", zeros));
+            rest = &rest[zeros..];
+            continue;
+        }
+        let line = rest.len().min(BYTES_PER_LINE);
+        out.push_str(&format!("    {}\n", bytes(&rest[..line])));
+        rest = &rest[line..];
+    }
+    out
+}
+
+fn has_mod_reg_rm(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x00..=0x03
+            | 0x08..=0x0b
+            | 0x10..=0x13
+            | 0x18..=0x1b
+            | 0x20..=0x23
+            | 0x28..=0x2b
+            | 0x30..=0x33
+            | 0x38..=0x3b
+            | 0x80..=0x8f
+            | 0xc4..=0xc7
+            | 0xd0..=0xd3
+            | 0xd8..=0xdf
+            | 0xf6
+            | 0xf7
+            | 0xfe
+            | 0xff
+    )
+}
+
+fn fits_byte(value: u16) -> bool {
+    (-128..=127).contains(&(value as i16))
+}
+
+/// Return true if NASM encodes the instruction text into the same bytes.
+/// NASM takes the shortest encoding, and the `r/m, reg` form for two registers.
+fn is_canonical(instruction: &Instruction) -> bool {
+    let raw = &instruction.raw;
+    let Some(&opcode) = raw.first() else {
+        return false;
+    };
+    match &instruction.ir {
+        IR::Undefined | IR::Baa | IR::Esc { .. } | IR::Lock => return false,
+        // rep is f3, and repne f2 only for cmps and scas
+        IR::Rep { z, string_ir } => {
+            return *z || matches!(**string_ir, IR::Cmps { .. } | IR::Scas { .. })
+        }
+        // int 3 is cc
+        IR::Int { int_type: 3 } => return opcode == 0xcc,
+        _ => {}
+    }
+    // Segment registers, xchg ax, ax, far return and the alias of 80
+    if matches!(opcode, 0x82 | 0x8c | 0x8e | 0x90 | 0xca | 0xcb) {
+        return false;
+    }
+    let immediate = || u16::from_le_bytes([raw[raw.len() - 2], raw[raw.len() - 1]]);
+    // Word immediate to the accumulator, 83 when it fits a byte
+    if opcode < 0x40 && opcode & 0x07 == 0x05 {
+        return !fits_byte(immediate());
+    }
+    if !has_mod_reg_rm(opcode) {
+        return true;
+    }
+
+    let Some(&mod_reg_rm) = raw.get(1) else {
+        return false;
+    };
+    let (mode, reg, rm) = (mod_reg_rm >> 6, (mod_reg_rm >> 3) & 0x7, mod_reg_rm & 0x7);
+    if mode == 0b11 {
+        return match opcode {
+            // reg, r/m form of two registers
+            0x00..=0x3b | 0x88..=0x8b => opcode & 0x02 == 0,
+            0x86 | 0x87 | 0x8d | 0xc4 | 0xc5 | 0xc6 | 0xc7 | 0x8f => false,
+            // Accumulator forms
+            0x80 | 0x81 if rm == 0 => false,
+            0xf6 | 0xf7 if reg == 0 && rm == 0 => false,
+            0x81 => !fits_byte(immediate()),
+            // Register forms of push, inc and dec
+            0xff => !matches!(reg, 0 | 1 | 6),
+            _ => true,
+        };
+    }
+
+    // Shortest displacement
+    let shortest = match mode {
+        0b01 => raw.get(2).map_or(false, |&disp| disp != 0 || rm == 0b110),
+        0b10 => raw.len() >= 4 && !fits_byte(u16::from_le_bytes([raw[2], raw[3]])),
+        _ => true,
+    };
+    shortest
+        && match opcode {
+            // a0 to a3 for the accumulator
+            0x88..=0x8b => !(mode == 0 && rm == 0b110 && reg == 0),
+            0x81 => !fits_byte(immediate()),
+            // Far calls and jumps
+            0xff => !matches!(reg, 3 | 5),
+            _ => true,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{parse_instruction, Disassemblable};
+    use crate::test_utils::program;
+
+    fn canonical(bytes: &[u8]) -> bool {
+        let (instruction, _) = parse_instruction(bytes, 0).unwrap();
+        is_canonical(&instruction)
+    }
+
+    #[test]
+    fn test_canonical_encodings() {
+        // mov ax, bx: 89 d8, not 8b c3
+        assert!(canonical(&[0x89, 0xd8]));
+        assert!(!canonical(&[0x8b, 0xc3]));
+        // mov ax, [0010]: a1 10 00
+        assert!(canonical(&[0xa1, 0x10, 0x00]));
+        assert!(!canonical(&[0x8b, 0x06, 0x10, 0x00]));
+        // [bp+4] with a byte displacement, [bp+0] needs one
+        assert!(canonical(&[0x8b, 0x46, 0x04]));
+        assert!(!canonical(&[0x8b, 0x86, 0x04, 0x00]));
+        assert!(canonical(&[0x8b, 0x46, 0x00]));
+        assert!(!canonical(&[0x8b, 0x40, 0x00]));
+        // add ax, 5 is 83 c0 05, add ax, 1234 is 05 34 12
+        assert!(canonical(&[0x83, 0xc0, 0x05]));
+        assert!(!canonical(&[0x05, 0x05, 0x00]));
+        assert!(canonical(&[0x05, 0x34, 0x12]));
+        assert!(!canonical(&[0x81, 0xc0, 0x34, 0x12]));
+        // inc ax is 40
+        assert!(!canonical(&[0xff, 0xc0]));
+        assert!(canonical(&[0xcd, 0x20]));
+    }
+
+    #[test]
+    fn test_to_nasm() {
+        let program = program("1.c");
+        let source = program.disassemble().unwrap().to_nasm();
+        let lines: Vec<&str> = source.lines().collect();
+
+        assert_eq!(
+            lines[..4],
+            ["bits 16", "cpu 8086", "", "section .text vstart=0"]
+        );
+        // Call targets and jumps get labels
+        let main = lines.iter().position(|&l| l == "loc_0039:").unwrap();
+        assert_eq!(lines[main + 1], "    push bp");
+        assert!(lines.contains(&"    jmp short loc_0135"));
+        assert!(lines.contains(&"    call loc_0039"));
+        // Also inside the instructions of a misparse
+        assert!(lines.contains(&"    call loc_008c"));
+        let target = lines.iter().position(|&l| l == "loc_008c:").unwrap();
+        assert_eq!(lines[target + 1], "    db 0xe9");
+        // The address of "hello\n" moved into ax, but not the numbers
+        assert!(lines.contains(&"    mov ax, data_0004"));
+        assert!(lines.contains(&"data_0004:"));
+        assert!(lines.contains(&"    mov ax, 0x1"));
+        // The text padding
+        assert!(lines.contains(&"    times 3 db 0"));
+    }
+
+    // Needs nasm on the PATH: cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn test_to_nasm_round_trip() {
+        for name in ["1.c", "2.c", "3.c", "4.c", "5.c", "6.c", "7.c"] {
+            let program = program(name);
+            let source = program.disassemble().unwrap().to_nasm();
+            let path = std::env::temp_dir().join(format!("minix2-rs-{}.asm", name));
+            std::fs::write(&path, &source).unwrap();
+            let output = path.with_extension("bin");
+            let status = std::process::Command::new("nasm")
+                .args(["-f", "bin", "-o"])
+                .arg(&output)
+                .arg(&path)
+                .status()
+                .expect("nasm is not installed");
+            assert!(status.success(), "{}", name);
+            let bytes = std::fs::read(&output).unwrap();
+            let expected = [&program.text_segment[..], &program.data_segment[..]].concat();
+            assert_eq!(bytes, expected, "{}", name);
+        }
+    }
+}
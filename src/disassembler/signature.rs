@@ -0,0 +1,415 @@
+use super::{parse_instruction, DisassembledProgram, DisassemblerError};
+use crate::minix::{Program, Symbol, SymbolSection, SymbolTable};
+use crate::x86::{Displacement, Instruction, Operand, IR};
+use std::collections::BTreeMap;
+
+// Longest pattern, from the start of the function
+const MAX_LENGTH: usize = 32;
+// Fewest bytes of a signature that aren't relocated, counting the resolved references
+const MIN_FIXED: usize = 3;
+// Smaller words are constants rather than the addresses of symbols
+const MIN_ADDRESS: u16 = 0x10;
+// `n_sclass` of the recognized functions: external text symbol
+const TEXT_EXTERNAL: u8 = 0x12;
+
+/// Signatures of the ACK libc routines, built from the binaries of `tests_data`.
+const MINIX_SIGNATURES: &str = include_str!("../../signatures/minix.sig");
+
+/// Byte pattern of the start of a library function, with None for the bytes
+/// relocated by the linker.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature {
+    pub name: String,
+    pub pattern: Vec<Option<u8>>,
+    /// Offset of the relative word of a call or jump, and the function it reaches
+    pub references: Vec<(usize, String)>,
+}
+
+impl Signature {
+    /// Return true if the bytes start with the pattern.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.pattern.len()
+            && self
+                .pattern
+                .iter()
+                .zip(bytes)
+                .all(|(expected, byte)| expected.map_or(true, |expected| expected == *byte))
+    }
+
+    /// Number of bytes that aren't relocated, or are known from the references.
+    pub fn fixed(&self) -> usize {
+        self.pattern.iter().filter(|byte| byte.is_some()).count() + 2 * self.references.len()
+    }
+
+    /// Return true if the calls and jumps of the pattern matched at the address
+    /// reach the functions found, when they are.
+    fn reaches(&self, text: &[u8], address: usize, found: &BTreeMap<&str, u16>) -> bool {
+        self.references.iter().all(|(offset, name)| {
+            let word = address + offset;
+            let relative = u16::from_le_bytes([text[word], text[word + 1]]);
+            let target = ((word + 2) as u16).wrapping_add(relative);
+            found
+                .get(name.as_str())
+                .map_or(true, |&address| address == target)
+        })
+    }
+}
+
+/// `name 55 89 e5 e8 .. .. @4:__exit`
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for byte in &self.pattern {
+            match byte {
+                Some(byte) => write!(f, " {:02x}", byte)?,
+                None => write!(f, " ..")?,
+            }
+        }
+        for (offset, name) in &self.references {
+            write!(f, " @{}:{}", offset, name)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Signature {
+    type Err = DisassemblerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DisassemblerError::InvalidSignature(s.to_string());
+        let mut fields = s.split_whitespace();
+        let name = fields.next().ok_or_else(invalid)?.to_string();
+        let mut pattern = vec![];
+        let mut references = vec![];
+        for field in fields {
+            match field.strip_prefix('@') {
+                Some(reference) => {
+                    let (offset, target) = reference.split_once(':').ok_or_else(invalid)?;
+                    let offset: usize = offset.parse().map_err(|_| invalid())?;
+                    references.push((offset, target.to_string()));
+                }
+                None if field == ".." => pattern.push(None),
+                None => pattern.push(Some(u8::from_str_radix(field, 16).map_err(|_| invalid())?)),
+            }
+        }
+        let inside = |(offset, _): &(usize, String)| offset + 2 <= pattern.len();
+        if pattern.is_empty() || !references.iter().all(inside) {
+            return Err(invalid());
+        }
+        Ok(Signature {
+            name,
+            pattern,
+            references,
+        })
+    }
+}
+
+/// Signatures recognizing library functions in binaries without symbols.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Signatures {
+    pub signatures: Vec<Signature>,
+}
+
+impl Signatures {
+    /// Signatures of the ACK and Minix libc routines.
+    pub fn minix() -> Self {
+        Signatures::parse(MINIX_SIGNATURES).expect("valid built-in signatures")
+    }
+
+    /// Signatures of the file at the path.
+    pub fn from_file(path: &str) -> Result<Self, DisassemblerError> {
+        let s = std::fs::read_to_string(path).map_err(|_| DisassemblerError::InvalidArgs)?;
+        Signatures::parse(&s)
+    }
+
+    /// Signatures of a file with one signature per line, and `#` comments.
+    pub fn parse(s: &str) -> Result<Self, DisassemblerError> {
+        let signatures = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::parse)
+            .collect::<Result<Vec<Signature>, DisassemblerError>>()?;
+        Ok(Signatures { signatures })
+    }
+
+    /// Signatures of the functions of a binary with symbols, but `_main`.
+    /// The calls and jumps out of the function, the direct memory operands
+    /// and the words equal to a symbol address are relocated.
+    pub fn from_program(program: &Program) -> Self {
+        let text = program.text_segment.as_slice();
+        let symbols = &program.symbols;
+        let mut signatures = Signatures::default();
+        for symbol in symbols.functions() {
+            // Aliases of the same address have the range of the last one
+            let Some(range) = symbols.function_range(&symbol.name) else {
+                continue;
+            };
+            if symbol.name == "_main" || symbols.label_at(range.start) != Some(symbol) {
+                continue;
+            }
+            let end = (range.end as usize).min(text.len());
+            let (pattern, references) = pattern(text, range.start as usize, end, symbols);
+            let signature = Signature {
+                name: symbol.name.clone(),
+                pattern,
+                references,
+            };
+            if signature.fixed() >= MIN_FIXED {
+                signatures.add(signature);
+            }
+        }
+        signatures
+    }
+
+    /// Add the signature, unless it is already known.
+    pub fn add(&mut self, signature: Signature) {
+        if !self.signatures.contains(&signature) {
+            self.signatures.push(signature);
+        }
+    }
+
+    /// Add the signatures of another set.
+    pub fn merge(&mut self, other: Signatures) {
+        for signature in other.signatures {
+            self.add(signature);
+        }
+    }
+
+    /// Symbols of the functions recognized in the text segment of the program.
+    /// A signature matching several addresses is ambiguous and not used, and at an
+    /// address matched by several signatures the one with the most fixed bytes is used.
+    /// The calls and jumps of a signature must reach the functions found before.
+    pub fn recognize(&self, program: &DisassembledProgram) -> SymbolTable {
+        let text = program.text();
+        let mut found: BTreeMap<u16, &Signature> = BTreeMap::new();
+        loop {
+            let names: BTreeMap<&str, u16> = found
+                .iter()
+                .map(|(&address, signature)| (signature.name.as_str(), address))
+                .collect();
+            let mut changed = false;
+            for signature in &self.signatures {
+                // Fixed bytes, with the references to the functions found
+                let resolved = signature
+                    .references
+                    .iter()
+                    .filter(|(_, name)| names.contains_key(name.as_str()))
+                    .count();
+                let fixed = signature.fixed() - 2 * (signature.references.len() - resolved);
+                if fixed < MIN_FIXED {
+                    continue;
+                }
+                // Not inside the functions found, like a tail jump matching a stub
+                let inside = |address: usize| {
+                    found.iter().any(|(&start, known)| {
+                        let start = start as usize;
+                        start < address && address < start + known.pattern.len()
+                    })
+                };
+                let mut matches = (0..text.len()).filter(|&address| {
+                    signature.matches(&text[address..])
+                        && signature.reaches(&text, address, &names)
+                        && !inside(address)
+                });
+                let (Some(address), None) = (matches.next(), matches.next()) else {
+                    continue;
+                };
+                match found.get(&(address as u16)) {
+                    Some(known) if known == &signature || known.fixed() >= fixed => {}
+                    _ => {
+                        found.insert(address as u16, signature);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        // A name found at several addresses is ambiguous too
+        let mut names: BTreeMap<&str, usize> = BTreeMap::new();
+        for signature in found.values() {
+            *names.entry(&signature.name).or_default() += 1;
+        }
+        let symbols = found
+            .into_iter()
+            .filter(|(_, signature)| names[signature.name.as_str()] == 1)
+            .map(|(address, signature)| Symbol {
+                name: signature.name.clone(),
+                value: address as u32,
+                sclass: TEXT_EXTERNAL,
+                numaux: 0,
+                type_: 0,
+            })
+            .collect();
+        SymbolTable { symbols }
+    }
+}
+
+impl std::fmt::Display for Signatures {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for signature in &self.signatures {
+            writeln!(f, "{}", signature)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return true if the word is the address of a symbol, rather than a constant.
+fn is_address(symbols: &SymbolTable, value: u16) -> bool {
+    value >= MIN_ADDRESS
+        && symbols.symbols.iter().any(|s| {
+            s.value == value as u32
+                && !matches!(
+                    s.section(),
+                    SymbolSection::Undefined | SymbolSection::Absolute
+                )
+        })
+}
+
+/// Pattern of the instructions from `start` up to `end`, at most `MAX_LENGTH` bytes,
+/// and the functions called or jumped to.
+fn pattern(
+    text: &[u8],
+    start: usize,
+    end: usize,
+    symbols: &SymbolTable,
+) -> (Vec<Option<u8>>, Vec<(usize, String)>) {
+    let mut pattern = vec![];
+    let mut references = vec![];
+    let mut address = start;
+    while address < end && pattern.len() < MAX_LENGTH {
+        let Ok((instruction, size)) = parse_instruction(&text[address..end], address) else {
+            break;
+        };
+        let mut bytes: Vec<Option<u8>> = instruction.raw.iter().copied().map(Some).collect();
+        let outside = mask_relocations(&instruction, start..end, symbols, &mut bytes);
+        // Name of the function reached by the call or jump
+        let target = instruction.ir.branch_target();
+        let reached = target.and_then(|target| symbols.label_at(target));
+        if let (Some(offset), Some(symbol)) = (outside, reached.filter(|s| s.is_global())) {
+            references.push((pattern.len() + offset, symbol.name.clone()));
+        }
+        pattern.extend(bytes);
+        address += size;
+    }
+    pattern.truncate(MAX_LENGTH);
+    references.retain(|(offset, _)| offset + 2 <= MAX_LENGTH);
+    (pattern, references)
+}
+
+/// Replace the relocated bytes of the instruction by None, and return the offset
+/// of the relative word of a call or jump out of the function.
+fn mask_relocations(
+    instruction: &Instruction,
+    function: std::ops::Range<usize>,
+    symbols: &SymbolTable,
+    bytes: &mut [Option<u8>],
+) -> Option<usize> {
+    let raw = &instruction.raw;
+    let mut mask = |position: Option<usize>| {
+        if let Some(position) = position {
+            bytes[position] = None;
+            bytes[position + 1] = None;
+        }
+    };
+    // Word of the value in the instruction, after the opcode
+    let find = |value: u16, last: bool| {
+        let word = value.to_le_bytes();
+        let mut positions = (1..raw.len().saturating_sub(1)).filter(|&i| raw[i..i + 2] == word);
+        match last {
+            true => positions.last(),
+            false => positions.next(),
+        }
+    };
+    // Calls and jumps out of the function
+    if let (IR::Call { .. } | IR::Jmp { .. }, Some(0xe8 | 0xe9)) = (&instruction.ir, raw.first()) {
+        let outside = instruction
+            .ir
+            .branch_target()
+            .map_or(true, |target| !function.contains(&(target as usize)));
+        if !outside {
+            return None;
+        }
+        let offset = raw.len() - 2;
+        mask(Some(offset));
+        return Some(offset);
+    }
+    for operand in instruction.operands() {
+        match operand {
+            Operand::MemoryAddress(address) => {
+                let Some(disp @ Displacement::Long(_)) = address.disp else {
+                    continue;
+                };
+                let disp: u16 = disp.into();
+                let direct = address.base.is_none() && address.index.is_none();
+                if direct || is_address(symbols, disp) {
+                    mask(find(disp, false));
+                }
+            }
+            Operand::LongImmediate(value) if is_address(symbols, value) => mask(find(value, true)),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::Disassemblable;
+    use crate::test_utils::program;
+
+    #[test]
+    fn test_from_program() {
+        let signatures = Signatures::from_program(&program("1.c"));
+        let names: Vec<&str> = signatures
+            .signatures
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert!(!names.contains(&"_main"));
+        // The call and the jumps to other functions are relocated
+        let exit = &signatures.signatures[names.iter().position(|&n| n == "_exit").unwrap()];
+        assert_eq!(
+            exit.to_string(),
+            "_exit 55 89 e5 e8 .. .. 83 3e .. .. 00 74 06 8b 1e .. .. ff d3 \
+             ff 76 04 e8 .. .. 5b e9 .. .. @23:__exit @27:.cret"
+        );
+        // A stub is known by the function it jumps to
+        assert!(names.contains(&"_write"));
+        assert_eq!(Signatures::parse(&signatures.to_string()), Ok(signatures));
+
+        assert_eq!(
+            "_write e9 .. .. @2:__write".parse::<Signature>(),
+            Err(DisassemblerError::InvalidSignature(
+                "_write e9 .. .. @2:__write".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_recognize() {
+        // Signatures of 3.c find the same functions at other addresses in 7.c
+        let signatures = Signatures::from_program(&program("3.c"));
+        let seven = program("7.c");
+        let symbols = signatures.recognize(&seven.disassemble().unwrap());
+        let address = |name: &str| symbols.lookup(name).map(|s| s.value);
+        assert_eq!(address("_exit"), Some(0x80));
+        assert_eq!(address("_printf"), Some(0x9d));
+        assert_eq!(address("_malloc"), Some(0xb3f));
+        assert_eq!(address("__sendre"), Some(0x10b4));
+        for symbol in &symbols.symbols {
+            let expected = seven.symbols.label_at(symbol.value as u16).unwrap();
+            assert_eq!(symbol.name, expected.name);
+        }
+        assert_eq!(symbols.function_at(0x00c0).unwrap().name, "___exit");
+
+        // The built-in signatures find the stubs of 1.c
+        let one = program("1.c");
+        let symbols = Signatures::minix().recognize(&one.disassemble().unwrap());
+        assert_eq!(symbols.lookup("_write").map(|s| s.value), Some(0x8c));
+        assert_eq!(symbols.lookup("__exit").map(|s| s.value), Some(0x88));
+    }
+}
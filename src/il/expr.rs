@@ -0,0 +1,223 @@
+use crate::interpreter::Flag;
+use crate::x86::Register;
+
+/// Operations of one operand.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    /// 1 if the number of set bits is even
+    Parity,
+    /// Low byte sign extended to a word
+    SignExtendByte,
+    /// Word sign extended to 32 bits
+    SignExtendWord,
+    /// High word of a 32 bits value
+    High,
+}
+
+impl UnaryOp {
+    /// Value of the operation, words are in the low 16 bits.
+    pub fn apply(self, value: u32) -> u32 {
+        let low = value as u16;
+        match self {
+            UnaryOp::Not => !low as u32,
+            UnaryOp::Neg => low.wrapping_neg() as u32,
+            UnaryOp::Parity => (low.count_ones() % 2 == 0) as u32,
+            UnaryOp::SignExtendByte => low as u8 as i8 as i16 as u16 as u32,
+            UnaryOp::SignExtendWord => low as i16 as i32 as u32,
+            UnaryOp::High => value >> 16,
+        }
+    }
+}
+
+/// Operations of two operands.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    /// Product of two words, on 32 bits
+    MulWide,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Sar,
+    /// 32 bits value of a high and a low word
+    Concat,
+    /// Unsigned division of 32 bits values, 0 when dividing by 0
+    DivU,
+    /// Unsigned remainder of 32 bits values, 0 when dividing by 0
+    RemU,
+    Eq,
+    Ne,
+    /// Unsigned less than
+    LtU,
+    /// Signed less than, of words
+    LtS,
+    /// 1 if the signed addition of two words overflows
+    AddOverflows,
+    /// 1 if the signed subtraction of two words overflows
+    SubOverflows,
+}
+
+impl BinaryOp {
+    /// Value of the operation, words are in the low 16 bits.
+    pub fn apply(self, left: u32, right: u32) -> u32 {
+        let (a, b) = (left as u16, right as u16);
+        match self {
+            BinaryOp::Add => a.wrapping_add(b) as u32,
+            BinaryOp::Sub => a.wrapping_sub(b) as u32,
+            BinaryOp::MulWide => a as u32 * b as u32,
+            BinaryOp::And => (a & b) as u32,
+            BinaryOp::Or => (a | b) as u32,
+            BinaryOp::Xor => (a ^ b) as u32,
+            BinaryOp::Shl => a.wrapping_shl(b as u32) as u32,
+            BinaryOp::Shr => a.wrapping_shr(b as u32) as u32,
+            BinaryOp::Sar => (a as i16).wrapping_shr(b as u32) as u16 as u32,
+            BinaryOp::Concat => (a as u32) << 16 | b as u32,
+            BinaryOp::DivU => left.checked_div(right).unwrap_or(0),
+            BinaryOp::RemU => left.checked_rem(right).unwrap_or(0),
+            BinaryOp::Eq => (left == right) as u32,
+            BinaryOp::Ne => (left != right) as u32,
+            BinaryOp::LtU => (left < right) as u32,
+            BinaryOp::LtS => ((a as i16) < (b as i16)) as u32,
+            BinaryOp::AddOverflows => (a as i16).overflowing_add(b as i16).1 as u32,
+            BinaryOp::SubOverflows => (a as i16).overflowing_sub(b as i16).1 as u32,
+        }
+    }
+}
+
+/// Expression computing a value from the machine state.
+///
+/// Values are words, except the 32 bits results of `Concat`, `MulWide`,
+/// `DivU`, `RemU` and `SignExtendWord`. Conditions are 0 or 1.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Const(u16),
+    Reg(Register),
+    Flag(Flag),
+    /// Value computed earlier by the same instruction
+    Temp(usize),
+    /// Word, or byte, of the data memory at the address
+    Load {
+        address: Box<Expr>,
+        byte: bool,
+    },
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// Second value if the condition is 0, first value otherwise
+    Ite(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn load(address: Expr, byte: bool) -> Self {
+        Expr::Load {
+            address: Box::new(address),
+            byte,
+        }
+    }
+
+    pub fn unary(op: UnaryOp, value: Expr) -> Self {
+        Expr::Unary(op, Box::new(value))
+    }
+
+    pub fn binary(op: BinaryOp, left: Expr, right: Expr) -> Self {
+        Expr::Binary(op, Box::new(left), Box::new(right))
+    }
+
+    pub fn ite(cond: Expr, then: Expr, otherwise: Expr) -> Self {
+        Expr::Ite(Box::new(cond), Box::new(then), Box::new(otherwise))
+    }
+
+    /// 1 if the condition is 0.
+    pub fn is_zero(cond: Expr) -> Self {
+        Expr::binary(BinaryOp::Eq, cond, Expr::Const(0))
+    }
+}
+
+/// Short lowercase name of the flag, as in `zf`.
+pub fn flag_name(flag: Flag) -> &'static str {
+    match flag {
+        Flag::Zero => "zf",
+        Flag::Sign => "sf",
+        Flag::Parity => "pf",
+        Flag::Carry => "cf",
+        Flag::Overflow => "of",
+        Flag::Direction => "df",
+        Flag::Interrupt => "if",
+        Flag::Trap => "tf",
+        Flag::Aux => "af",
+        Flag::PageFault => "pagefault",
+    }
+}
+
+/// Operation printed as pseudo-C, from the operand and the operand parenthesized.
+pub(crate) fn format_unary(op: UnaryOp, value: impl std::fmt::Display, operand: String) -> String {
+    match op {
+        UnaryOp::Not => format!("~{}", operand),
+        UnaryOp::Neg => format!("-{}", operand),
+        UnaryOp::Parity => format!("parity({})", value),
+        UnaryOp::SignExtendByte => format!("(i8){}", operand),
+        UnaryOp::SignExtendWord => format!("(i32)(i16){}", operand),
+        UnaryOp::High => format!("{} >> 16", operand),
+    }
+}
+
+/// Operation printed as pseudo-C, from the parenthesized operands.
+pub(crate) fn format_binary(op: BinaryOp, left: String, right: String) -> String {
+    let symbol = match op {
+        BinaryOp::Concat => return format!("{} << 16 | {}", left, right),
+        BinaryOp::Sar => return format!("(i16){} >> {}", left, right),
+        BinaryOp::LtS => return format!("(i16){} < (i16){}", left, right),
+        BinaryOp::AddOverflows => return format!("add_overflows({}, {})", left, right),
+        BinaryOp::SubOverflows => return format!("sub_overflows({}, {})", left, right),
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::MulWide => "*",
+        BinaryOp::And => "&",
+        BinaryOp::Or => "|",
+        BinaryOp::Xor => "^",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::DivU => "/",
+        BinaryOp::RemU => "%",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne => "!=",
+        BinaryOp::LtU => "<",
+    };
+    format!("{} {} {}", left, symbol, right)
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Nested operations are parenthesized
+        let operand = |expr: &Expr| match expr {
+            Expr::Binary(..) | Expr::Ite(..) => format!("({})", expr),
+            _ => expr.to_string(),
+        };
+        match self {
+            Expr::Const(value) if *value < 10 => write!(f, "{}", value),
+            Expr::Const(value) => write!(f, "0x{:x}", value),
+            Expr::Reg(reg) => write!(f, "{}", reg),
+            Expr::Flag(flag) => write!(f, "{}", flag_name(*flag)),
+            Expr::Temp(n) => write!(f, "t{}", n),
+            Expr::Load { address, byte } => {
+                let size = if *byte { "u8" } else { "u16" };
+                write!(f, "*({} *)({})", size, address)
+            }
+            Expr::Unary(op, value) => write!(f, "{}", format_unary(*op, value, operand(value))),
+            Expr::Binary(op, left, right) => {
+                write!(f, "{}", format_binary(*op, operand(left), operand(right)))
+            }
+            Expr::Ite(cond, then, otherwise) => write!(
+                f,
+                "{} ? {} : {}",
+                operand(cond),
+                operand(then),
+                operand(otherwise)
+            ),
+        }
+    }
+}
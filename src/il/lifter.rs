@@ -0,0 +1,714 @@
+use super::expr::{BinaryOp, Expr, UnaryOp};
+use super::micro_op::MicroOp;
+use crate::interpreter::Flag;
+use crate::x86::{Address, Displacement, Instruction, Operand, Register, IR};
+
+/// This trait can be used to lift instructions to micro-ops.
+pub trait Liftable {
+    /// Micro-ops of the instruction at the given address.
+    fn lift(&self, address: u16) -> Vec<MicroOp>;
+}
+
+/// Instructions are lifted with the semantics of the VM: operands in memory are words,
+/// and the flags are computed from the word result. Instructions that the VM doesn't
+/// execute have their 8086 semantics on words.
+impl Liftable for Instruction {
+    fn lift(&self, address: u16) -> Vec<MicroOp> {
+        let next = address.wrapping_add(self.raw.len() as u16);
+        let mut lifter = Lifter::default();
+        lifter.lift(&self.ir, address, next);
+        lifter.ops
+    }
+}
+
+#[derive(Default)]
+struct Lifter {
+    ops: Vec<MicroOp>,
+    temps: usize,
+}
+
+fn reg(reg: Register) -> Expr {
+    Expr::Reg(reg)
+}
+
+fn flag(flag: Flag) -> Expr {
+    Expr::Flag(flag)
+}
+
+fn constant(value: u16) -> Expr {
+    Expr::Const(value)
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::binary(op, left, right)
+}
+
+/// Effective address of a memory operand.
+fn effective_address(address: &Address) -> Expr {
+    let mut parts = address
+        .base
+        .into_iter()
+        .chain(address.index)
+        .map(reg)
+        .chain(address.disp.map(|d| constant(d.into())));
+    let first = parts.next().unwrap_or(constant(0));
+    parts.fold(first, |sum, part| binary(BinaryOp::Add, sum, part))
+}
+
+/// Value of an operand, as read by the VM.
+fn read(operand: &Operand) -> Expr {
+    match operand {
+        Operand::Register(r) => reg(*r),
+        Operand::Immediate(value) => constant(*value as u16),
+        Operand::LongImmediate(value) => constant(*value),
+        Operand::SignExtendedImmediate(value) => constant(*value as i16 as u16),
+        Operand::MemoryAddress(address) => Expr::load(effective_address(address), false),
+        Operand::Displacement(Displacement::Short(d)) => constant(*d as i16 as u16),
+        Operand::Displacement(Displacement::Long(d)) => constant(*d as u16),
+    }
+}
+
+/// Condition of a conditional jump, other than the loops.
+fn jump_condition(ir: &IR) -> Option<Expr> {
+    let sign_is_overflow = || binary(BinaryOp::Eq, flag(Flag::Sign), flag(Flag::Overflow));
+    let cond = match ir {
+        IR::Je { .. } => flag(Flag::Zero),
+        IR::Jne { .. } => Expr::is_zero(flag(Flag::Zero)),
+        IR::Jb { .. } => flag(Flag::Carry),
+        IR::Jnb { .. } => Expr::is_zero(flag(Flag::Carry)),
+        IR::Jbe { .. } => binary(BinaryOp::Or, flag(Flag::Carry), flag(Flag::Zero)),
+        IR::Jnbe { .. } => Expr::is_zero(binary(BinaryOp::Or, flag(Flag::Carry), flag(Flag::Zero))),
+        IR::Jl { .. } => Expr::is_zero(sign_is_overflow()),
+        IR::Jnl { .. } => sign_is_overflow(),
+        IR::Jle { .. } => binary(
+            BinaryOp::Or,
+            flag(Flag::Zero),
+            Expr::is_zero(sign_is_overflow()),
+        ),
+        IR::Jnle { .. } => binary(
+            BinaryOp::And,
+            Expr::is_zero(flag(Flag::Zero)),
+            sign_is_overflow(),
+        ),
+        IR::Jp { .. } => flag(Flag::Parity),
+        IR::Jnp { .. } => Expr::is_zero(flag(Flag::Parity)),
+        IR::Jo { .. } => flag(Flag::Overflow),
+        IR::Jno { .. } => Expr::is_zero(flag(Flag::Overflow)),
+        IR::Js { .. } => flag(Flag::Sign),
+        IR::Jns { .. } => Expr::is_zero(flag(Flag::Sign)),
+        IR::Jcxz { .. } => binary(BinaryOp::Eq, reg(Register::CX), constant(0)),
+        _ => return None,
+    };
+    Some(cond)
+}
+
+impl Lifter {
+    fn push(&mut self, op: MicroOp) {
+        self.ops.push(op);
+    }
+
+    /// Keep the value in a new temporary.
+    fn temp(&mut self, value: Expr) -> Expr {
+        let n = self.temps;
+        self.temps += 1;
+        self.push(MicroOp::SetTemp(n, value));
+        Expr::Temp(n)
+    }
+
+    fn set_flag(&mut self, f: Flag, value: Expr) {
+        self.push(MicroOp::SetFlag(f, value));
+    }
+
+    fn write(&mut self, operand: &Operand, value: Expr) {
+        let op = match operand {
+            Operand::Register(r) => MicroOp::SetReg(*r, value),
+            Operand::MemoryAddress(address) => MicroOp::Store {
+                address: effective_address(address),
+                value,
+                byte: false,
+            },
+            _ => MicroOp::Unsupported("write to an immediate"),
+        };
+        self.push(op);
+    }
+
+    /// Zero, sign and parity flags of the result.
+    fn set_szp(&mut self, result: &Expr) {
+        self.set_flag(
+            Flag::Zero,
+            binary(BinaryOp::Eq, result.clone(), constant(0)),
+        );
+        self.set_flag(
+            Flag::Sign,
+            binary(BinaryOp::LtS, result.clone(), constant(0)),
+        );
+        self.set_flag(Flag::Parity, Expr::unary(UnaryOp::Parity, result.clone()));
+    }
+
+    /// `and`, `or`, `xor` and `test`: clear OF and CF.
+    fn logic(&mut self, op: BinaryOp, dest: &Operand, src: &Operand, store: bool) {
+        let src = self.temp(read(src));
+        let dest_value = self.temp(read(dest));
+        let result = self.temp(binary(op, dest_value, src));
+        if store {
+            self.write(dest, result.clone());
+        }
+        self.set_flag(Flag::Overflow, constant(0));
+        self.set_flag(Flag::Carry, constant(0));
+        self.set_szp(&result);
+    }
+
+    /// Push a word on the stack.
+    fn push_word(&mut self, value: Expr) {
+        self.push(MicroOp::SetReg(
+            Register::SP,
+            binary(BinaryOp::Sub, reg(Register::SP), constant(2)),
+        ));
+        self.push(MicroOp::Store {
+            address: reg(Register::SP),
+            value,
+            byte: false,
+        });
+    }
+
+    /// Signed overflow of `dest + src + carry`, or of `dest - src - carry`.
+    fn overflow(dest: &Expr, src: &Expr, result: &Expr, add: bool) -> Expr {
+        let sign_bit = |value: Expr| binary(BinaryOp::Shr, value, constant(15));
+        let same_signs = binary(BinaryOp::Eq, sign_bit(dest.clone()), sign_bit(src.clone()));
+        binary(
+            BinaryOp::And,
+            if add {
+                same_signs
+            } else {
+                Expr::is_zero(same_signs)
+            },
+            binary(
+                BinaryOp::Ne,
+                sign_bit(dest.clone()),
+                sign_bit(result.clone()),
+            ),
+        )
+    }
+
+    fn lift(&mut self, ir: &IR, address: u16, next: u16) {
+        if let Some(cond) = jump_condition(ir) {
+            let target = ir.operands().first().map_or(constant(next), |d| read(d));
+            self.push(MicroOp::Branch { cond, target });
+            return;
+        }
+        match ir {
+            IR::Mov { dest, src, .. } => {
+                let value = read(src);
+                self.write(dest, value);
+            }
+            IR::Lea { dest, src } => {
+                let ea = match src {
+                    Operand::MemoryAddress(address) => effective_address(address),
+                    _ => return self.push(MicroOp::Unsupported("lea")),
+                };
+                self.write(dest, ea);
+            }
+            IR::Xchg { dest, src } => {
+                let dest_value = self.temp(read(dest));
+                let src_value = self.temp(read(src));
+                self.write(dest, src_value);
+                self.write(src, dest_value);
+            }
+            IR::Add { dest, src } => {
+                let src = self.temp(read(src));
+                let dest_value = self.temp(read(dest));
+                let result = self.temp(binary(BinaryOp::Add, dest_value.clone(), src.clone()));
+                self.write(dest, result.clone());
+                self.set_flag(
+                    Flag::Overflow,
+                    binary(BinaryOp::AddOverflows, dest_value.clone(), src.clone()),
+                );
+                self.set_flag(
+                    Flag::Carry,
+                    binary(BinaryOp::LtS, result.clone(), dest_value.clone()),
+                );
+                self.set_szp(&result);
+                let nibble = |value: Expr| binary(BinaryOp::And, value, constant(0xf));
+                self.set_flag(
+                    Flag::Aux,
+                    binary(
+                        BinaryOp::LtU,
+                        constant(0xf),
+                        binary(BinaryOp::Add, nibble(dest_value), nibble(src)),
+                    ),
+                );
+            }
+            IR::Sub { dest, src } | IR::Cmp { dest, src, .. } => {
+                let cmp = matches!(ir, IR::Cmp { .. });
+                let src = self.temp(read(src));
+                let dest_value = self.temp(read(dest));
+                let result = self.temp(binary(BinaryOp::Sub, dest_value.clone(), src.clone()));
+                if !cmp {
+                    self.write(dest, result.clone());
+                }
+                self.set_flag(
+                    Flag::Overflow,
+                    binary(BinaryOp::SubOverflows, dest_value.clone(), src.clone()),
+                );
+                // The VM compares the signed words
+                let carry = if cmp {
+                    binary(BinaryOp::LtS, dest_value.clone(), src.clone())
+                } else {
+                    binary(BinaryOp::LtS, src.clone(), dest_value.clone())
+                };
+                self.set_flag(Flag::Carry, carry);
+                self.set_szp(&result);
+                if cmp {
+                    let nibble = |value: Expr| binary(BinaryOp::And, value, constant(0xf));
+                    self.set_flag(
+                        Flag::Aux,
+                        binary(BinaryOp::LtU, nibble(dest_value), nibble(src)),
+                    );
+                }
+            }
+            IR::Adc { dest, src } | IR::Ssb { dest, src } => {
+                let add = matches!(ir, IR::Adc { .. });
+                let op = if add { BinaryOp::Add } else { BinaryOp::Sub };
+                let src = self.temp(read(src));
+                let dest_value = self.temp(read(dest));
+                let result = self.temp(binary(
+                    op,
+                    binary(op, dest_value.clone(), src.clone()),
+                    flag(Flag::Carry),
+                ));
+                self.write(dest, result.clone());
+                self.set_flag(
+                    Flag::Overflow,
+                    Self::overflow(&dest_value, &src, &result, add),
+                );
+                // Carry out, or the result is unchanged with the carry in
+                let (less, equal) = if add {
+                    (
+                        binary(BinaryOp::LtU, result.clone(), dest_value.clone()),
+                        binary(BinaryOp::Eq, result.clone(), dest_value.clone()),
+                    )
+                } else {
+                    (
+                        binary(BinaryOp::LtU, dest_value.clone(), src.clone()),
+                        binary(BinaryOp::Eq, dest_value.clone(), src.clone()),
+                    )
+                };
+                self.set_flag(
+                    Flag::Carry,
+                    binary(
+                        BinaryOp::Or,
+                        less,
+                        binary(BinaryOp::And, equal, flag(Flag::Carry)),
+                    ),
+                );
+                self.set_szp(&result);
+            }
+            IR::Inc { dest } | IR::Dec { dest } => {
+                let op = if matches!(ir, IR::Inc { .. }) {
+                    BinaryOp::Add
+                } else {
+                    BinaryOp::Sub
+                };
+                let dest_value = self.temp(read(dest));
+                let result = self.temp(binary(op, dest_value, constant(1)));
+                self.write(dest, result.clone());
+                self.set_flag(Flag::Overflow, constant(0));
+                self.set_szp(&result);
+            }
+            IR::Neg { dest } => {
+                let dest_value = self.temp(read(dest));
+                let result = self.temp(Expr::unary(UnaryOp::Neg, dest_value.clone()));
+                self.write(dest, result.clone());
+                self.set_flag(
+                    Flag::Carry,
+                    binary(BinaryOp::Ne, dest_value.clone(), constant(0)),
+                );
+                self.set_flag(
+                    Flag::Overflow,
+                    binary(BinaryOp::Eq, dest_value, constant(0x8000)),
+                );
+                self.set_szp(&result);
+            }
+            IR::Not { dest } => {
+                let value = Expr::unary(UnaryOp::Not, read(dest));
+                self.write(dest, value);
+            }
+            IR::And { dest, src } => self.logic(BinaryOp::And, dest, src, true),
+            IR::Or { dest, src } => self.logic(BinaryOp::Or, dest, src, true),
+            IR::Xor { dest, src } => self.logic(BinaryOp::Xor, dest, src, true),
+            IR::Test { dest, src, .. } => self.logic(BinaryOp::And, dest, src, false),
+            IR::Shl { dest, src } | IR::Shr { dest, src } | IR::Sar { dest, src } => {
+                let count = self.temp(read(src));
+                let dest_value = self.temp(read(dest));
+                let op = match ir {
+                    IR::Shl { .. } => BinaryOp::Shl,
+                    IR::Shr { .. } => BinaryOp::Shr,
+                    _ => BinaryOp::Sar,
+                };
+                let result = self.temp(binary(op, dest_value.clone(), count.clone()));
+                self.write(dest, result.clone());
+                // CF is the last bit shifted out
+                let last_bit = match op {
+                    BinaryOp::Shl => binary(BinaryOp::Sub, constant(16), count.clone()),
+                    _ => binary(BinaryOp::Sub, count.clone(), constant(1)),
+                };
+                self.set_flag(
+                    Flag::Carry,
+                    binary(
+                        BinaryOp::Ne,
+                        binary(
+                            BinaryOp::And,
+                            dest_value.clone(),
+                            binary(BinaryOp::Shl, constant(1), last_bit),
+                        ),
+                        constant(0),
+                    ),
+                );
+                // OF only for 1 bit shifts
+                if op != BinaryOp::Sar {
+                    let overflow = match op {
+                        BinaryOp::Shl => binary(
+                            BinaryOp::Ne,
+                            binary(BinaryOp::And, dest_value, constant(0x8000)),
+                            constant(0),
+                        ),
+                        _ => binary(BinaryOp::Shr, dest_value, constant(15)),
+                    };
+                    self.set_flag(
+                        Flag::Overflow,
+                        Expr::ite(
+                            binary(BinaryOp::Eq, count, constant(1)),
+                            overflow,
+                            flag(Flag::Overflow),
+                        ),
+                    );
+                }
+                self.set_szp(&result);
+            }
+            IR::Rol { dest, src } | IR::Ror { dest, src } => {
+                let rol = matches!(ir, IR::Rol { .. });
+                let count = self.temp(binary(BinaryOp::And, read(src), constant(0xf)));
+                let dest_value = self.temp(read(dest));
+                let (left, right) = if rol {
+                    (count.clone(), binary(BinaryOp::Sub, constant(16), count))
+                } else {
+                    (binary(BinaryOp::Sub, constant(16), count.clone()), count)
+                };
+                let result = self.temp(binary(
+                    BinaryOp::Or,
+                    binary(BinaryOp::Shl, dest_value.clone(), left),
+                    binary(BinaryOp::Shr, dest_value, right),
+                ));
+                self.write(dest, result.clone());
+                let carry = if rol {
+                    binary(BinaryOp::And, result, constant(1))
+                } else {
+                    binary(BinaryOp::Shr, result, constant(15))
+                };
+                self.set_flag(Flag::Carry, carry);
+            }
+            // Through the carry, by 1 bit
+            IR::Rcl { dest, src } | IR::Rcr { dest, src } => {
+                if *src != Operand::Immediate(1) {
+                    return self.push(MicroOp::Unsupported(ir.mnemonic()));
+                }
+                let dest_value = self.temp(read(dest));
+                let (result, carry) = if matches!(ir, IR::Rcl { .. }) {
+                    (
+                        binary(
+                            BinaryOp::Or,
+                            binary(BinaryOp::Shl, dest_value.clone(), constant(1)),
+                            flag(Flag::Carry),
+                        ),
+                        binary(BinaryOp::Shr, dest_value, constant(15)),
+                    )
+                } else {
+                    (
+                        binary(
+                            BinaryOp::Or,
+                            binary(BinaryOp::Shr, dest_value.clone(), constant(1)),
+                            binary(BinaryOp::Shl, flag(Flag::Carry), constant(15)),
+                        ),
+                        binary(BinaryOp::And, dest_value, constant(1)),
+                    )
+                };
+                let result = self.temp(result);
+                self.write(dest, result);
+                self.set_flag(Flag::Carry, carry);
+            }
+            IR::Mul { dest } => {
+                let product = self.temp(binary(BinaryOp::MulWide, reg(Register::AX), read(dest)));
+                let high = Expr::unary(UnaryOp::High, product.clone());
+                self.push(MicroOp::SetReg(Register::AX, product));
+                self.push(MicroOp::SetReg(Register::DX, high.clone()));
+                let carry = binary(BinaryOp::Ne, high, constant(0));
+                self.set_flag(Flag::Carry, carry.clone());
+                self.set_flag(Flag::Overflow, carry);
+            }
+            IR::Div { dest } => {
+                let divisor = self.temp(Expr::unary(UnaryOp::SignExtendWord, read(dest)));
+                let dividend = self.temp(binary(
+                    BinaryOp::Concat,
+                    reg(Register::DX),
+                    reg(Register::AX),
+                ));
+                let quotient = self.temp(binary(BinaryOp::DivU, dividend.clone(), divisor.clone()));
+                let remainder = self.temp(binary(BinaryOp::RemU, dividend, divisor.clone()));
+                self.push(MicroOp::DivideError(binary(
+                    BinaryOp::Or,
+                    binary(BinaryOp::Eq, divisor, constant(0)),
+                    binary(BinaryOp::LtU, constant(0xffff), quotient.clone()),
+                )));
+                self.push(MicroOp::SetReg(Register::AX, quotient));
+                self.push(MicroOp::SetReg(Register::DX, remainder));
+            }
+            IR::Cbw => self.push(MicroOp::SetReg(
+                Register::AX,
+                Expr::unary(UnaryOp::SignExtendByte, reg(Register::AL)),
+            )),
+            IR::Cwd => self.push(MicroOp::SetReg(
+                Register::DX,
+                Expr::ite(
+                    binary(BinaryOp::LtS, reg(Register::AX), constant(0)),
+                    constant(0xffff),
+                    constant(0),
+                ),
+            )),
+            IR::Push { src } => {
+                let value = self.temp(read(src));
+                self.push_word(value);
+            }
+            IR::Pop { dest } => {
+                let value = self.temp(Expr::load(reg(Register::SP), false));
+                self.push(MicroOp::SetReg(
+                    Register::SP,
+                    binary(BinaryOp::Add, reg(Register::SP), constant(2)),
+                ));
+                self.write(dest, value);
+            }
+            IR::Call { dest } => {
+                let target = self.temp(read(dest));
+                self.push_word(constant(next));
+                self.push(MicroOp::Jump(target));
+            }
+            IR::Ret { src } => {
+                let released = match src {
+                    Some(Operand::Immediate(value)) => *value as u16,
+                    Some(Operand::LongImmediate(value)) => *value,
+                    _ => 0,
+                };
+                let target = self.temp(Expr::load(reg(Register::SP), false));
+                self.push(MicroOp::SetReg(
+                    Register::SP,
+                    binary(
+                        BinaryOp::Add,
+                        reg(Register::SP),
+                        constant(released.wrapping_add(2)),
+                    ),
+                ));
+                self.push(MicroOp::Jump(target));
+            }
+            IR::Jmp { dest, .. } => self.push(MicroOp::Jump(read(dest))),
+            IR::Loop { dest } | IR::Loopz { dest } | IR::Loopnz { dest } => {
+                let target = self.temp(read(dest));
+                self.push(MicroOp::SetReg(
+                    Register::CX,
+                    binary(BinaryOp::Sub, reg(Register::CX), constant(1)),
+                ));
+                let counting = binary(BinaryOp::Ne, reg(Register::CX), constant(0));
+                let cond = match ir {
+                    IR::Loopz { .. } => binary(BinaryOp::And, counting, flag(Flag::Zero)),
+                    IR::Loopnz { .. } => {
+                        binary(BinaryOp::And, counting, Expr::is_zero(flag(Flag::Zero)))
+                    }
+                    _ => counting,
+                };
+                self.push(MicroOp::Branch { cond, target });
+            }
+            IR::Int { int_type } => self.push(MicroOp::Interrupt(*int_type)),
+            IR::In { dest, src } => match dest {
+                Operand::Register(dest) => self.push(MicroOp::In {
+                    dest: *dest,
+                    port: read(src),
+                }),
+                _ => self.push(MicroOp::Unsupported("in")),
+            },
+            IR::Out { dest, src } => self.push(MicroOp::Out {
+                port: read(dest),
+                value: read(src),
+            }),
+            IR::Xlat => self.push(MicroOp::SetReg(
+                Register::AL,
+                Expr::load(
+                    binary(BinaryOp::Add, reg(Register::BX), reg(Register::AL)),
+                    true,
+                ),
+            )),
+            IR::Clc => self.set_flag(Flag::Carry, constant(0)),
+            IR::Stc => self.set_flag(Flag::Carry, constant(1)),
+            IR::Cmc => self.set_flag(Flag::Carry, Expr::is_zero(flag(Flag::Carry))),
+            IR::Cld => self.set_flag(Flag::Direction, constant(0)),
+            IR::Std => self.set_flag(Flag::Direction, constant(1)),
+            IR::Cli => self.set_flag(Flag::Interrupt, constant(0)),
+            IR::Sti => self.set_flag(Flag::Interrupt, constant(1)),
+            IR::Hlt => self.push(MicroOp::Halt),
+            IR::Movs { word }
+            | IR::Cmps { word }
+            | IR::Scas { word }
+            | IR::Lods { word }
+            | IR::Stos { word } => self.string(ir, *word),
+            // Repeat the string instruction while CX isn't 0
+            IR::Rep { z, string_ir } => {
+                self.push(MicroOp::Branch {
+                    cond: binary(BinaryOp::Eq, reg(Register::CX), constant(0)),
+                    target: constant(next),
+                });
+                self.lift(string_ir, address, next);
+                self.push(MicroOp::SetReg(
+                    Register::CX,
+                    binary(BinaryOp::Sub, reg(Register::CX), constant(1)),
+                ));
+                if matches!(**string_ir, IR::Cmps { .. } | IR::Scas { .. }) {
+                    self.push(MicroOp::Branch {
+                        cond: binary(BinaryOp::Ne, flag(Flag::Zero), constant(*z as u16)),
+                        target: constant(next),
+                    });
+                }
+                self.push(MicroOp::Jump(constant(address)));
+            }
+            ir => self.push(MicroOp::Unsupported(ir.mnemonic())),
+        }
+    }
+
+    /// String instruction, stepping SI and DI with the direction flag.
+    fn string(&mut self, ir: &IR, word: bool) {
+        let byte = !word;
+        let accumulator = reg(if word { Register::AX } else { Register::AL });
+        let size = if word { 2 } else { 1 };
+        let step = |index: Register| {
+            MicroOp::SetReg(
+                index,
+                binary(
+                    BinaryOp::Add,
+                    reg(index),
+                    Expr::ite(
+                        flag(Flag::Direction),
+                        constant((-size as i16) as u16),
+                        constant(size as u16),
+                    ),
+                ),
+            )
+        };
+        let (source, destination) = (
+            Expr::load(reg(Register::SI), byte),
+            Expr::load(reg(Register::DI), byte),
+        );
+        match ir {
+            IR::Movs { .. } => {
+                self.push(MicroOp::Store {
+                    address: reg(Register::DI),
+                    value: source,
+                    byte,
+                });
+                self.push(step(Register::SI));
+                self.push(step(Register::DI));
+            }
+            IR::Stos { .. } => {
+                self.push(MicroOp::Store {
+                    address: reg(Register::DI),
+                    value: accumulator,
+                    byte,
+                });
+                self.push(step(Register::DI));
+            }
+            IR::Lods { .. } => {
+                self.push(MicroOp::SetReg(
+                    if word { Register::AX } else { Register::AL },
+                    source,
+                ));
+                self.push(step(Register::SI));
+            }
+            _ => {
+                let cmps = matches!(ir, IR::Cmps { .. });
+                let left = self.temp(if cmps { source } else { accumulator });
+                let right = self.temp(destination);
+                let result = self.temp(binary(BinaryOp::Sub, left.clone(), right.clone()));
+                self.set_flag(
+                    Flag::Overflow,
+                    binary(BinaryOp::SubOverflows, left.clone(), right.clone()),
+                );
+                self.set_flag(Flag::Carry, binary(BinaryOp::LtU, left, right));
+                self.set_szp(&result);
+                if cmps {
+                    self.push(step(Register::SI));
+                }
+                self.push(step(Register::DI));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::parse_instruction;
+
+    fn lifted(bytes: &[u8], address: u16) -> Vec<String> {
+        let (instruction, _) = parse_instruction(bytes, address as usize).unwrap();
+        instruction
+            .lift(address)
+            .iter()
+            .map(|op| op.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_lift() {
+        // sub ax, [bp+4]
+        assert_eq!(
+            lifted(&[0x2b, 0x46, 0x04], 0),
+            [
+                "t0 = *(u16 *)(bp + 4);",
+                "t1 = ax;",
+                "t2 = t1 - t0;",
+                "ax = t2;",
+                "of = sub_overflows(t1, t0);",
+                "cf = (i16)t0 < (i16)t1;",
+                "zf = t2 == 0;",
+                "sf = (i16)t2 < (i16)0;",
+                "pf = parity(t2);",
+            ]
+        );
+        // call 0039 at 0031
+        assert_eq!(
+            lifted(&[0xe8, 0x05, 0x00], 0x31),
+            [
+                "t0 = 0x39;",
+                "sp = sp - 2;",
+                "*(u16 *)(sp) = 0x34;",
+                "goto t0;",
+            ]
+        );
+        // loopnz 0010 at 0020
+        assert_eq!(
+            lifted(&[0xe0, 0xee], 0x20),
+            [
+                "t0 = 0x10;",
+                "cx = cx - 1;",
+                "if ((cx != 0) & (zf == 0)) goto t0;",
+            ]
+        );
+        // rep movsw
+        assert_eq!(
+            lifted(&[0xf3, 0xa5], 0x40),
+            [
+                "if (cx == 0) goto 0x42;",
+                "*(u16 *)(di) = *(u16 *)(si);",
+                "si = si + (df ? 0xfffe : 2);",
+                "di = di + (df ? 0xfffe : 2);",
+                "cx = cx - 1;",
+                "goto 0x40;",
+            ]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use super::expr::Expr;
+use super::micro_op::MicroOp;
+use crate::interpreter::{Flag, OpcodeExecErrors, StepOutcome};
+use crate::x86::Register;
+
+/// Machine state read and written by the micro-ops.
+pub trait Machine {
+    fn register(&self, reg: Register) -> u16;
+    fn set_register(&mut self, reg: Register, value: u16);
+    fn flag(&self, flag: Flag) -> bool;
+    fn set_flag(&mut self, flag: Flag, value: bool);
+    /// Word, or byte, of the data memory
+    fn load(&self, address: u16, byte: bool) -> u16;
+    fn store(&mut self, address: u16, value: u16, byte: bool);
+    fn set_ip(&mut self, ip: u16);
+    fn interrupt(&mut self, number: u8) -> Result<(), OpcodeExecErrors>;
+    fn port_in(&mut self, port: u16) -> u16;
+    fn port_out(&mut self, port: u16, value: u16);
+}
+
+/// Run the micro-ops of an instruction on the machine,
+/// with the instruction pointer already on the next instruction.
+/// A jump, or a taken branch, ends the instruction.
+pub fn execute<M: Machine>(
+    ops: &[MicroOp],
+    machine: &mut M,
+) -> Result<StepOutcome, OpcodeExecErrors> {
+    let mut temps = vec![];
+    for op in ops {
+        match op {
+            MicroOp::SetReg(reg, value) => {
+                let value = eval(value, machine, &temps) as u16;
+                machine.set_register(*reg, value);
+            }
+            MicroOp::SetFlag(flag, value) => {
+                let value = eval(value, machine, &temps) != 0;
+                machine.set_flag(*flag, value);
+            }
+            MicroOp::SetTemp(n, value) => {
+                let value = eval(value, machine, &temps);
+                if temps.len() <= *n {
+                    temps.resize(*n + 1, 0);
+                }
+                temps[*n] = value;
+            }
+            MicroOp::Store {
+                address,
+                value,
+                byte,
+            } => {
+                let (address, value) =
+                    (eval(address, machine, &temps), eval(value, machine, &temps));
+                machine.store(address as u16, value as u16, *byte);
+            }
+            MicroOp::Jump(target) => {
+                let target = eval(target, machine, &temps) as u16;
+                machine.set_ip(target);
+                break;
+            }
+            MicroOp::Branch { cond, target } => {
+                if eval(cond, machine, &temps) != 0 {
+                    let target = eval(target, machine, &temps) as u16;
+                    machine.set_ip(target);
+                    break;
+                }
+            }
+            MicroOp::DivideError(cond) => {
+                if eval(cond, machine, &temps) != 0 {
+                    return Err(OpcodeExecErrors::DivideError);
+                }
+            }
+            MicroOp::Interrupt(number) => machine.interrupt(*number)?,
+            MicroOp::In { dest, port } => {
+                let port = eval(port, machine, &temps) as u16;
+                let value = machine.port_in(port);
+                machine.set_register(*dest, value);
+            }
+            MicroOp::Out { port, value } => {
+                let (port, value) = (eval(port, machine, &temps), eval(value, machine, &temps));
+                machine.port_out(port as u16, value as u16);
+            }
+            MicroOp::Halt => return Ok(StepOutcome::Halted),
+            MicroOp::Unsupported(mnemonic) => panic!("{}: Not implemented", mnemonic),
+        }
+    }
+    Ok(StepOutcome::Running)
+}
+
+/// Value of the expression, words are in the low 16 bits.
+pub fn eval<M: Machine>(expr: &Expr, machine: &M, temps: &[u32]) -> u32 {
+    let word = |expr: &Expr| eval(expr, machine, temps) & 0xffff;
+    match expr {
+        Expr::Const(value) => *value as u32,
+        Expr::Reg(reg) => machine.register(*reg) as u32,
+        Expr::Flag(flag) => machine.flag(*flag) as u32,
+        Expr::Temp(n) => temps.get(*n).copied().unwrap_or(0),
+        Expr::Load { address, byte } => machine.load(word(address) as u16, *byte) as u32,
+        Expr::Unary(op, value) => op.apply(eval(value, machine, temps)),
+        Expr::Binary(op, left, right) => {
+            op.apply(eval(left, machine, temps), eval(right, machine, temps))
+        }
+        Expr::Ite(cond, then, otherwise) => {
+            if eval(cond, machine, temps) != 0 {
+                eval(then, machine, temps)
+            } else {
+                eval(otherwise, machine, temps)
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use super::expr::{flag_name, Expr};
+use crate::interpreter::Flag;
+use crate::x86::Register;
+
+/// Single effect of an instruction on the machine state.
+///
+/// The micro-ops of an instruction run in order, with the instruction pointer
+/// already on the next instruction, up to a jump or a taken branch.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MicroOp {
+    SetReg(Register, Expr),
+    SetFlag(Flag, Expr),
+    /// Keep a value for the next micro-ops, as `Expr::Temp`
+    SetTemp(usize, Expr),
+    Store {
+        address: Expr,
+        value: Expr,
+        byte: bool,
+    },
+    Jump(Expr),
+    /// Jump if the condition isn't 0
+    Branch {
+        cond: Expr,
+        target: Expr,
+    },
+    /// Divide error if the condition isn't 0
+    DivideError(Expr),
+    Interrupt(u8),
+    In {
+        dest: Register,
+        port: Expr,
+    },
+    Out {
+        port: Expr,
+        value: Expr,
+    },
+    Halt,
+    /// Instruction without semantics in the IL, by mnemonic
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for MicroOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MicroOp::SetReg(reg, value) => write!(f, "{} = {};", reg, value),
+            MicroOp::SetFlag(flag, value) => write!(f, "{} = {};", flag_name(*flag), value),
+            MicroOp::SetTemp(n, value) => write!(f, "t{} = {};", n, value),
+            MicroOp::Store {
+                address,
+                value,
+                byte,
+            } => {
+                let size = if *byte { "u8" } else { "u16" };
+                write!(f, "*({} *)({}) = {};", size, address, value)
+            }
+            MicroOp::Jump(target) => write!(f, "goto {};", target),
+            MicroOp::Branch { cond, target } => write!(f, "if ({}) goto {};", cond, target),
+            MicroOp::DivideError(cond) => write!(f, "if ({}) divide_error();", cond),
+            MicroOp::Interrupt(number) => write!(f, "interrupt(0x{:x});", number),
+            MicroOp::In { dest, port } => write!(f, "{} = in({});", dest, port),
+            MicroOp::Out { port, value } => write!(f, "out({}, {});", port, value),
+            MicroOp::Halt => write!(f, "halt();"),
+            MicroOp::Unsupported(mnemonic) => write!(f, "__asm__(\"{}\");", mnemonic),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+mod expr;
+mod lifter;
+mod machine;
+mod micro_op;
+
+pub use self::expr::{flag_name, BinaryOp, Expr, UnaryOp};
+pub(crate) use self::expr::{format_binary, format_unary};
+pub use self::lifter::Liftable;
+pub use self::machine::{eval, execute, Machine};
+pub use self::micro_op::MicroOp;
@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use crate::disassembler::DisassembledProgram;
+use crate::minix::SymbolTable;
+
+const COVERAGE_MAGIC: &str = "minix-coverage 1";
+
+/// Execution counts collected while interpreting a program.
+/// Counts of several runs of the same program can be merged.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Coverage {
+    /// Number of executions of the instruction at each address
+    pub hits: BTreeMap<u16, u64>,
+    /// Number of times the control went from an instruction (first) to the next executed one (second)
+    pub edges: BTreeMap<(u16, u16), u64>,
+}
+
+/// Coverage of a single instruction of the disassembled program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CoverageLine {
+    /// Line of the instruction in the disassembly listing, starting at 1
+    pub line: usize,
+    pub address: u16,
+    /// Function containing the instruction, if the program has symbols
+    pub function: Option<String>,
+    pub hits: u64,
+    /// (taken, not taken) counts, for conditional jumps
+    pub branch: Option<(u64, u64)>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    /// Count the execution of the instruction at `ip`, followed by the instruction at `next_ip`.
+    pub fn hit(&mut self, ip: u16, next_ip: Option<u16>) {
+        *self.hits.entry(ip).or_insert(0) += 1;
+        if let Some(next_ip) = next_ip {
+            *self.edges.entry((ip, next_ip)).or_insert(0) += 1;
+        }
+    }
+
+    /// Add the counts of another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (ip, count) in &other.hits {
+            *self.hits.entry(*ip).or_insert(0) += count;
+        }
+        for (edge, count) in &other.edges {
+            *self.edges.entry(*edge).or_insert(0) += count;
+        }
+    }
+
+    /// Map the counts to the instructions of the program.
+    pub fn lines(&self, program: &DisassembledProgram, symbols: &SymbolTable) -> Vec<CoverageLine> {
+        program
+            .iter_with_address()
+            .enumerate()
+            .map(|(i, (address, instruction))| {
+                let branch = instruction.ir.is_conditional_jump().then(|| {
+                    let fallthrough = address.wrapping_add(instruction.raw.len() as u16);
+                    self.edges.range((address, 0)..=(address, u16::MAX)).fold(
+                        (0, 0),
+                        |(taken, not_taken), ((_, to), count)| {
+                            if *to == fallthrough {
+                                (taken, not_taken + count)
+                            } else {
+                                (taken + count, not_taken)
+                            }
+                        },
+                    )
+                });
+                CoverageLine {
+                    line: i + 1,
+                    address,
+                    function: symbols.function_at(address).map(|s| s.name.clone()),
+                    hits: self.hits.get(&address).copied().unwrap_or(0),
+                    branch,
+                }
+            })
+            .collect()
+    }
+
+    /// Disassembly listing with the execution count of each instruction,
+    /// and the taken / not taken counts of conditional jumps.
+    pub fn listing(&self, program: &DisassembledProgram, symbols: &SymbolTable) -> String {
+        let mut out = String::new();
+        let mut function = None;
+        for (line, (_, instruction)) in self
+            .lines(program, symbols)
+            .iter()
+            .zip(program.iter_with_address())
+        {
+            if line.function.is_some() && line.function != function {
+                out.push_str(&format!(
+                    "{}:\n",
+                    line.function.as_deref().unwrap_or_default()
+                ));
+                function.clone_from(&line.function);
+            }
+            let hits = match line.hits {
+                0 => "#####".to_string(),
+                n => n.to_string(),
+            };
+            let text = format!("{:>9}  {:04x}: {}", hits, line.address, instruction);
+            match line.branch {
+                Some((taken, not_taken)) => out.push_str(&format!(
+                    "{:<48} ; taken {}, not taken {}\n",
+                    text, taken, not_taken
+                )),
+                None => out.push_str(&format!("{}\n", text)),
+            }
+        }
+        out
+    }
+
+    /// lcov tracefile, with the lines of the disassembly listing as source lines.
+    pub fn lcov(
+        &self,
+        program: &DisassembledProgram,
+        symbols: &SymbolTable,
+        source: &str,
+    ) -> String {
+        let lines = self.lines(program, symbols);
+        let mut out = format!("TN:\nSF:{}\n", source);
+
+        let functions = functions(&lines);
+        for (name, first) in &functions {
+            out.push_str(&format!("FN:{},{}\n", first.line, name));
+        }
+        for (name, first) in &functions {
+            out.push_str(&format!("FNDA:{},{}\n", first.hits, name));
+        }
+        out.push_str(&format!("FNF:{}\n", functions.len()));
+        out.push_str(&format!(
+            "FNH:{}\n",
+            functions.iter().filter(|(_, first)| first.hits > 0).count()
+        ));
+
+        let (mut branches, mut branches_hit) = (0, 0);
+        for line in &lines {
+            let Some((taken, not_taken)) = line.branch else {
+                continue;
+            };
+            for (i, count) in [taken, not_taken].iter().enumerate() {
+                // `-` marks branches of instructions that were never executed
+                let count = match line.hits {
+                    0 => "-".to_string(),
+                    _ => count.to_string(),
+                };
+                out.push_str(&format!("BRDA:{},0,{},{}\n", line.line, i, count));
+            }
+            branches += 2;
+            branches_hit += [taken, not_taken].iter().filter(|c| **c > 0).count();
+        }
+        out.push_str(&format!("BRF:{}\nBRH:{}\n", branches, branches_hit));
+
+        for line in &lines {
+            out.push_str(&format!("DA:{},{}\n", line.line, line.hits));
+        }
+        out.push_str(&format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            lines.len(),
+            lines.iter().filter(|l| l.hits > 0).count()
+        ));
+        out
+    }
+
+    /// Cobertura XML report, with a class per function.
+    pub fn cobertura(
+        &self,
+        program: &DisassembledProgram,
+        symbols: &SymbolTable,
+        source: &str,
+    ) -> String {
+        let lines = self.lines(program, symbols);
+        let source = xml_escape(source);
+
+        let mut classes: Vec<(String, Vec<&CoverageLine>)> = Vec::new();
+        for line in &lines {
+            let name = line.function.clone().unwrap_or_else(|| source.clone());
+            match classes.last_mut() {
+                Some((last, class_lines)) if *last == name => class_lines.push(line),
+                _ => classes.push((name, vec![line])),
+            }
+        }
+
+        let all: Vec<&CoverageLine> = lines.iter().collect();
+        let mut out = String::from("<?xml version=\"1.0\" ?>\n");
+        out.push_str(&format!(
+            "<coverage {} version=\"1\" timestamp=\"0\">\n",
+            rates(&all)
+        ));
+        out.push_str(&format!(
+            "  <sources>\n    <source>.</source>\n  </sources>\n  <packages>\n    <package name=\"{}\" {}>\n      <classes>\n",
+            source,
+            rates(&all)
+        ));
+        for (name, class_lines) in &classes {
+            out.push_str(&format!(
+                "        <class name=\"{}\" filename=\"{}\" {}>\n          <methods/>\n          <lines>\n",
+                xml_escape(name),
+                source,
+                rates(class_lines)
+            ));
+            for line in class_lines {
+                match line.branch {
+                    Some((taken, not_taken)) => {
+                        let covered = [taken, not_taken].iter().filter(|c| **c > 0).count();
+                        out.push_str(&format!(
+                            "            <line number=\"{}\" hits=\"{}\" branch=\"true\" condition-coverage=\"{}% ({}/2)\"/>\n",
+                            line.line,
+                            line.hits,
+                            covered * 50,
+                            covered
+                        ));
+                    }
+                    None => out.push_str(&format!(
+                        "            <line number=\"{}\" hits=\"{}\" branch=\"false\"/>\n",
+                        line.line, line.hits
+                    )),
+                }
+            }
+            out.push_str("          </lines>\n        </class>\n");
+        }
+        out.push_str("      </classes>\n    </package>\n  </packages>\n</coverage>\n");
+        out
+    }
+
+    /// Write the raw counts, to be merged with the counts of later runs.
+    pub fn save<W: Write>(&self, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", COVERAGE_MAGIC)?;
+        for (ip, count) in &self.hits {
+            writeln!(output, "hit {:04x} {}", ip, count)?;
+        }
+        for ((from, to), count) in &self.edges {
+            writeln!(output, "edge {:04x} {:04x} {}", from, to, count)?;
+        }
+        Ok(())
+    }
+
+    /// Read raw counts written by `save`.
+    pub fn load<R: BufRead>(input: R) -> io::Result<Coverage> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid coverage line: {}", line),
+            )
+        };
+
+        let mut lines = input.lines();
+        match lines.next() {
+            Some(Ok(line)) if line == COVERAGE_MAGIC => {}
+            Some(Err(e)) => return Err(e),
+            _ => return Err(invalid("missing header")),
+        }
+
+        let mut coverage = Coverage::new();
+        for line in lines {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let address = |s: &str| u16::from_str_radix(s, 16).map_err(|_| invalid(&line));
+            let count = |s: &str| s.parse::<u64>().map_err(|_| invalid(&line));
+            match words.as_slice() {
+                [] => {}
+                ["hit", ip, n] => {
+                    *coverage.hits.entry(address(ip)?).or_insert(0) += count(n)?;
+                }
+                ["edge", from, to, n] => {
+                    *coverage
+                        .edges
+                        .entry((address(from)?, address(to)?))
+                        .or_insert(0) += count(n)?;
+                }
+                _ => return Err(invalid(&line)),
+            }
+        }
+        Ok(coverage)
+    }
+}
+
+/// First line of each function, in order of appearance.
+fn functions(lines: &[CoverageLine]) -> Vec<(&str, &CoverageLine)> {
+    let mut functions: Vec<(&str, &CoverageLine)> = Vec::new();
+    for line in lines {
+        if let Some(name) = &line.function {
+            if functions.last().map_or(true, |(last, _)| last != name) {
+                functions.push((name, line));
+            }
+        }
+    }
+    functions
+}
+
+/// Cobertura line and branch rate attributes.
+fn rates(lines: &[&CoverageLine]) -> String {
+    let covered = lines.iter().filter(|l| l.hits > 0).count();
+    let branches = lines.iter().filter(|l| l.branch.is_some()).count() * 2;
+    let branches_covered: usize = lines
+        .iter()
+        .filter_map(|l| l.branch)
+        .map(|(taken, not_taken)| [taken, not_taken].iter().filter(|c| **c > 0).count())
+        .sum();
+    let rate = |covered: usize, valid: usize| match valid {
+        0 => 1.0,
+        _ => covered as f64 / valid as f64,
+    };
+    format!(
+        "line-rate=\"{:.4}\" branch-rate=\"{:.4}\" lines-covered=\"{}\" lines-valid=\"{}\" branches-covered=\"{}\" branches-valid=\"{}\"",
+        rate(covered, lines.len()),
+        rate(branches_covered, branches),
+        covered,
+        lines.len(),
+        branches_covered,
+        branches
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::Disassemblable;
+    use crate::interpreter::vm::{VmIrExecutable, VM};
+    use crate::test_utils::program;
+
+    fn run_with_coverage(file: &str) -> (Coverage, DisassembledProgram, SymbolTable) {
+        let program = program(file);
+        let disassembled = program.disassemble().unwrap();
+        let symbols = program.symbols.clone();
+
+        let mut vm = VM::from(program);
+        vm.coverage = Some(Coverage::new());
+        vm.run().unwrap();
+        (vm.coverage.unwrap(), disassembled, symbols)
+    }
+
+    #[test]
+    fn test_coverage_counts() {
+        let (coverage, program, symbols) = run_with_coverage("1.c");
+        let lines = coverage.lines(&program, &symbols);
+
+        // push bp, the first instruction of main
+        let main = lines.iter().find(|l| l.address == 0x0039).unwrap();
+        assert_eq!(main.hits, 1);
+        assert_eq!(main.function.as_deref(), Some("_main"));
+
+        // jnb 0028, falls through once
+        let jnb = lines.iter().find(|l| l.address == 0x0017).unwrap();
+        assert_eq!(jnb.line, 10);
+        assert_eq!(jnb.branch, Some((0, 1)));
+
+        // hlt, never reached as the program exits before
+        let hlt = lines.iter().find(|l| l.address == 0x0038).unwrap();
+        assert_eq!(hlt.hits, 0);
+    }
+
+    #[test]
+    fn test_coverage_reports() {
+        let (coverage, program, symbols) = run_with_coverage("1.c");
+
+        let listing = coverage.listing(&program, &symbols);
+        assert!(listing.contains("_main:\n        1  0039: 55            push bp\n"));
+        assert!(listing.contains("    #####  0038: f4            hlt\n"));
+        assert!(listing.contains("jnb 0028          ; taken 0, not taken 1\n"));
+
+        let lcov = coverage.lcov(&program, &symbols, "1.c.s");
+        assert!(lcov.starts_with("TN:\nSF:1.c.s\n"));
+        assert!(lcov.contains("FN:25,_main\n"));
+        assert!(lcov.contains("FNDA:1,_main\n"));
+        assert!(lcov.contains("BRDA:10,0,0,0\nBRDA:10,0,1,1\n"));
+        assert!(lcov.ends_with("end_of_record\n"));
+
+        let xml = coverage.cobertura(&program, &symbols, "1.c.s");
+        assert!(xml.contains("<class name=\"_main\" filename=\"1.c.s\""));
+        assert!(xml.contains(
+            "<line number=\"10\" hits=\"1\" branch=\"true\" condition-coverage=\"50% (1/2)\"/>"
+        ));
+    }
+
+    #[test]
+    fn test_coverage_merge_and_reload() {
+        let (coverage, _, _) = run_with_coverage("1.c");
+
+        let mut saved = Vec::new();
+        coverage.save(&mut saved).unwrap();
+        let mut merged = Coverage::load(saved.as_slice()).unwrap();
+        assert_eq!(merged, coverage);
+
+        merged.merge(&coverage);
+        assert_eq!(merged.hits[&0x0039], 2);
+        assert_eq!(merged.edges[&(0x0017, 0x0019)], 2);
+
+        assert!(Coverage::load("hit 0039 1\n".as_bytes()).is_err());
+    }
+}
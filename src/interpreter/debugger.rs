@@ -0,0 +1,383 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+
+use super::error::InterpreterError;
+use super::recorder::Reversible;
+use super::vm::{StepOutcome, VmIrExecutable, VM};
+use super::watchpoint::{WatchKind, Watchpoint, Watchpoints};
+use crate::x86::Register;
+
+const MAX_CONTINUE_STEPS: usize = 1000000;
+
+/// Interactive debugger over a recorded execution of the VM.
+///
+/// Commands:
+/// - `s`, `step [n]`: execute the next `n` instructions
+/// - `c`, `continue`: execute until a breakpoint or the end of the program
+/// - `rs`, `reverse-step [n]`: undo the last `n` instructions
+/// - `rc`, `reverse-continue`: undo instructions until a breakpoint or the start of the program
+/// - `goto <n>`: move to the state after `n` instructions
+/// - `last-write <addr>`: move back to the last instruction that wrote to the address
+/// - `b`, `break <addr>`: add a breakpoint
+/// - `d`, `delete <addr>`: remove a breakpoint
+/// - `w`, `watch <start>[-<end>] [r|w|rw]`: stop on accesses to the data memory, writes by default
+/// - `watch-log <start>[-<end>] [r|w|rw]`: log accesses to the data memory without stopping
+/// - `unwatch <n>`: remove a watchpoint
+/// - `watches`: show watchpoints and logged accesses
+/// - `r`, `regs`: show registers
+/// - `x <addr> [n]`: show `n` words of data memory, up to its end
+/// - `q`, `quit`: leave the debugger
+pub struct Debugger {
+    pub vm: VM,
+    pub breakpoints: BTreeSet<u16>,
+}
+
+impl Debugger {
+    pub fn new(mut vm: VM) -> Self {
+        vm.start_recording();
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Execute instructions until a breakpoint is reached or the program stops.
+    pub fn continue_(&mut self) -> Result<StepOutcome, InterpreterError> {
+        for _ in 0..MAX_CONTINUE_STEPS {
+            match self.vm.step()? {
+                StepOutcome::Running => {}
+                outcome => return Ok(outcome),
+            }
+            let watched = self
+                .vm
+                .watchpoints
+                .as_ref()
+                .is_some_and(|w| !w.hits.is_empty());
+            if watched || self.breakpoints.contains(&self.vm.ip) {
+                return Ok(StepOutcome::Running);
+            }
+        }
+        Err(InterpreterError::CycleLimitExceeded)
+    }
+
+    /// Undo instructions until a breakpoint is reached or the start of the recording.
+    pub fn reverse_continue(&mut self) -> bool {
+        self.vm.reverse_continue(&self.breakpoints)
+    }
+
+    /// Execute a single debugger command and return its output.
+    pub fn command(&mut self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return String::new();
+        };
+        let args: Vec<&str> = words.collect();
+
+        match command {
+            "s" | "step" => {
+                let n = parse_count(args.first(), 1);
+                let mut outcome = Ok(StepOutcome::Running);
+                for _ in 0..n {
+                    outcome = self.vm.step();
+                    let watched = self
+                        .vm
+                        .watchpoints
+                        .as_ref()
+                        .is_some_and(|w| !w.hits.is_empty());
+                    if outcome != Ok(StepOutcome::Running) || watched {
+                        break;
+                    }
+                }
+                self.report(outcome)
+            }
+            "c" | "continue" => {
+                let outcome = self.continue_();
+                self.report(outcome)
+            }
+            "rs" | "reverse-step" => {
+                let n = parse_count(args.first(), 1);
+                for _ in 0..n {
+                    if !self.vm.reverse_step() {
+                        break;
+                    }
+                }
+                self.location()
+            }
+            "rc" | "reverse-continue" => {
+                if self.reverse_continue() {
+                    self.location()
+                } else {
+                    format!("start of recording\n{}", self.location())
+                }
+            }
+            "goto" => match args.first().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => {
+                    let outcome = self.vm.goto(n);
+                    self.report(outcome)
+                }
+                None => "usage: goto <instruction count>\n".to_string(),
+            },
+            "last-write" => match args.first().and_then(|a| parse_address(a)) {
+                Some(address) => {
+                    let index = self
+                        .vm
+                        .recorder
+                        .as_ref()
+                        .and_then(|r| r.last_write(address));
+                    match index {
+                        Some(index) => {
+                            // Stop right before the writing instruction
+                            let _ = self.vm.goto(index);
+                            self.location()
+                        }
+                        None => format!("no recorded write to {:04x}\n", address),
+                    }
+                }
+                None => "usage: last-write <addr>\n".to_string(),
+            },
+            "b" | "break" => match args.first().and_then(|a| parse_address(a)) {
+                Some(address) => {
+                    self.breakpoints.insert(address);
+                    format!("breakpoint at {:04x}\n", address)
+                }
+                None => "usage: break <addr>\n".to_string(),
+            },
+            "d" | "delete" => match args.first().and_then(|a| parse_address(a)) {
+                Some(address) => {
+                    self.breakpoints.remove(&address);
+                    format!("deleted breakpoint at {:04x}\n", address)
+                }
+                None => "usage: delete <addr>\n".to_string(),
+            },
+            "w" | "watch" | "watch-log" => {
+                let range = args.first().and_then(|a| parse_range(a));
+                let kind = match args.get(1).copied() {
+                    None | Some("w") => Some(WatchKind::Write),
+                    Some("r") => Some(WatchKind::Read),
+                    Some("rw") => Some(WatchKind::Access),
+                    Some(_) => None,
+                };
+                match (range, kind) {
+                    (Some(range), Some(kind)) => {
+                        let log_only = command == "watch-log";
+                        let watchpoints = self.vm.watchpoints.get_or_insert_with(Watchpoints::new);
+                        let index = watchpoints.add(Watchpoint {
+                            range: range.clone(),
+                            kind,
+                            log_only,
+                        });
+                        format!(
+                            "watchpoint {} on {:04x}-{:04x}\n",
+                            index, range.start, range.end
+                        )
+                    }
+                    _ => format!("usage: {} <start>[-<end>] [r|w|rw]\n", command),
+                }
+            }
+            "unwatch" => {
+                let index = args.first().and_then(|n| n.parse::<usize>().ok());
+                let removed = match (index, &mut self.vm.watchpoints) {
+                    (Some(index), Some(watchpoints)) => watchpoints.remove(index),
+                    _ => false,
+                };
+                match (index, removed) {
+                    (Some(index), true) => format!("deleted watchpoint {}\n", index),
+                    (Some(index), false) => format!("no watchpoint {}\n", index),
+                    (None, _) => "usage: unwatch <n>\n".to_string(),
+                }
+            }
+            "watches" => {
+                let mut out = String::new();
+                if let Some(watchpoints) = &self.vm.watchpoints {
+                    for (index, watchpoint) in watchpoints.iter() {
+                        out.push_str(&format!(
+                            "watchpoint {}: {:04x}-{:04x} {:?}{}\n",
+                            index,
+                            watchpoint.range.start,
+                            watchpoint.range.end,
+                            watchpoint.kind,
+                            if watchpoint.log_only { " (log)" } else { "" }
+                        ));
+                    }
+                    for hit in &watchpoints.log {
+                        out.push_str(&format!("{}\n", hit));
+                    }
+                }
+                out
+            }
+            "r" | "regs" => self.registers(),
+            "x" => match args.first().and_then(|a| parse_address(a)) {
+                Some(address) => {
+                    let n = parse_count(args.get(1), 8);
+                    let mut out = format!("{:04x}:", address);
+                    // The dump stops at the end of the memory, with the last byte alone
+                    for word in self.vm.data.peek_bytes(address, 2 * n).chunks(2) {
+                        match word {
+                            [low, high] => {
+                                out.push_str(&format!(" {:04x}", u16::from_le_bytes([*low, *high])))
+                            }
+                            _ => out.push_str(&format!(" {:02x}", word[0])),
+                        }
+                    }
+                    out.push('\n');
+                    out
+                }
+                None => "usage: x <addr> [n]\n".to_string(),
+            },
+            _ => format!("unknown command: {}\n", command),
+        }
+    }
+
+    /// Read commands from `input` until `quit` or end of input.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> std::io::Result<()> {
+        write!(output, "{}(dbg) ", self.location())?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if matches!(line.trim(), "q" | "quit") {
+                break;
+            }
+            write!(output, "{}(dbg) ", self.command(&line))?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, outcome: Result<StepOutcome, InterpreterError>) -> String {
+        let hits = match &mut self.vm.watchpoints {
+            Some(watchpoints) => watchpoints.take_hits(),
+            None => Vec::new(),
+        };
+        let hits = hits
+            .iter()
+            .map(|hit| format!("{}\n", hit))
+            .collect::<Vec<String>>()
+            .concat();
+        let status = match outcome {
+            Ok(StepOutcome::Running) => String::new(),
+            Ok(StepOutcome::Halted) => "halted\n".to_string(),
+            Ok(StepOutcome::Exited(status)) => format!("exited with status {}\n", status),
+            Ok(StepOutcome::Faulted) => "faulted\n".to_string(),
+            Err(e) => format!("{}\n", e),
+        };
+        format!("{}{}{}", hits, status, self.location())
+    }
+
+    fn location(&self) -> String {
+        format!("#{} ip={:04x}\n", self.vm.instruction_count(), self.vm.ip)
+    }
+
+    fn registers(&self) -> String {
+        let mut out = String::new();
+        for reg in Register::iter_16() {
+            out.push_str(&format!("{}={:04x} ", reg, self.vm.regs.get(reg)));
+        }
+        out.push_str(&format!("ip={:04x}\n", self.vm.ip));
+        out
+    }
+}
+
+fn parse_address(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+/// Parse `start-end`, or a single address as a one byte range.
+fn parse_range(s: &str) -> Option<Range<u16>> {
+    match s.split_once('-') {
+        Some((start, end)) => Some(parse_address(start)?..parse_address(end)?),
+        None => {
+            let address = parse_address(s)?;
+            Some(address..address.wrapping_add(1))
+        }
+    }
+}
+
+fn parse_count(s: Option<&&str>, default: usize) -> usize {
+    s.and_then(|n| n.parse().ok()).unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::program;
+
+    fn debugger(file: &str) -> Debugger {
+        Debugger::new(VM::from(program(file)))
+    }
+
+    #[test]
+    fn test_debugger_commands() {
+        let mut dbg = debugger("1.c");
+
+        assert_eq!(dbg.command("break 0039"), "breakpoint at 0039\n");
+        assert_eq!(dbg.command("continue"), "#21 ip=0039\n");
+        assert_eq!(dbg.command("step 2"), "#23 ip=003c\n");
+        assert_eq!(dbg.command("reverse-step"), "#22 ip=003a\n");
+        assert_eq!(dbg.command("reverse-continue"), "#21 ip=0039\n");
+        assert_eq!(dbg.command("goto 0"), "#0 ip=0000\n");
+        assert_eq!(dbg.command("rc"), "start of recording\n#0 ip=0000\n");
+        assert_eq!(dbg.command("foo"), "unknown command: foo\n");
+
+        assert_eq!(dbg.command("x 0 2"), "0000: 0000 0014\n");
+        assert_eq!(dbg.command("x fffd"), "fffd: 0000 00\n");
+    }
+
+    #[test]
+    fn test_debugger_watchpoints() {
+        let mut dbg = debugger("1.c");
+
+        assert_eq!(dbg.command("step 3"), "#3 ip=0006\n");
+        assert_eq!(
+            dbg.command("watch 0002-0004"),
+            "watchpoint 0 on 0002-0004\n"
+        );
+        assert_eq!(
+            dbg.command("continue"),
+            "watchpoint 0: #14 ip=0024 write [0002] 0014 -> 0010 (2 bytes)\n#15 ip=0028\n"
+        );
+        assert_eq!(dbg.command("unwatch 0"), "deleted watchpoint 0\n");
+        assert_eq!(
+            dbg.command("watch-log 0002 r"),
+            "watchpoint 1 on 0002-0003\n"
+        );
+        assert_eq!(dbg.command("step"), "#16 ip=002c\n");
+        assert_eq!(
+            dbg.command("watches"),
+            "watchpoint 1: 0002-0003 Read (log)\nwatchpoint 1: #15 ip=0028 read [0002] 0010 -> 0010 (2 bytes)\n"
+        );
+    }
+
+    #[test]
+    fn test_debugger_watchpoints_on_replay() {
+        let mut dbg = debugger("1.c");
+
+        assert_eq!(dbg.command("step 20"), "#20 ip=0031\n");
+        assert_eq!(dbg.command("rs 17"), "#3 ip=0006\n");
+        dbg.command("watch 0002-0004");
+        assert_eq!(
+            dbg.command("continue"),
+            "watchpoint 0: #14 ip=0024 write [0002] 0014 -> 0010 (2 bytes)\n#15 ip=0028\n"
+        );
+        dbg.command("unwatch 0");
+        dbg.command("watch-log 0002 r");
+        assert_eq!(dbg.command("goto 10"), "#10 ip=0019\n");
+        assert_eq!(dbg.command("goto 16"), "#16 ip=002c\n");
+        assert_eq!(
+            dbg.command("watches"),
+            "watchpoint 1: 0002-0003 Read (log)\nwatchpoint 1: #15 ip=0028 read [0002] 0010 -> 0010 (2 bytes)\n"
+        );
+    }
+
+    #[test]
+    fn test_debugger_repl() {
+        let mut dbg = debugger("1.c");
+        let mut output = Vec::new();
+        dbg.repl("s\nr\nq\n".as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "#0 ip=0000\n(dbg) #1 ip=0002\n(dbg) ax=0000 cx=0000 dx=0000 bx=0000 sp=ffda bp=0000 si=0000 di=0000 ip=0002\n(dbg) "
+        );
+    }
+}
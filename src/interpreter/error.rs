@@ -1,6 +1,6 @@
 #[derive(Debug, PartialEq)]
 pub enum OpcodeExecErrors {
-    ExitCatch,
+    ExitCatch(u16),
     UnimplementedSyscall(usize),
     UnimplementedInterrupt(usize),
     DivideError,
@@ -9,7 +9,7 @@ pub enum OpcodeExecErrors {
 impl std::fmt::Display for OpcodeExecErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            OpcodeExecErrors::ExitCatch => write!(f, "Exit catch"),
+            OpcodeExecErrors::ExitCatch(status) => write!(f, "Exit catch ({})", status),
             OpcodeExecErrors::UnimplementedSyscall(n) => write!(f, "Unimplemented syscall {}", n),
             OpcodeExecErrors::UnimplementedInterrupt(n) => {
                 write!(f, "Unimplemented interrupt {}", n)
@@ -24,6 +24,7 @@ pub enum InterpreterError {
     InvalidArgs,
     CycleLimitExceeded,
     OpcodeExecutionError(OpcodeExecErrors),
+    IoError(String),
 }
 
 impl std::fmt::Display for InterpreterError {
@@ -32,6 +33,7 @@ impl std::fmt::Display for InterpreterError {
             InterpreterError::InvalidArgs => write!(f, "Invalid arguments"),
             InterpreterError::CycleLimitExceeded => write!(f, "Cycle limit exceeded"),
             InterpreterError::OpcodeExecutionError(e) => write!(f, "Execution error: {}", e),
+            InterpreterError::IoError(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
@@ -32,6 +32,22 @@ impl Flag {
         .copied()
     }
 
+    /// Bit of the flag in the 8086 FLAGS register, if it has one.
+    pub fn bit(&self) -> Option<u16> {
+        match self {
+            Flag::Carry => Some(0),
+            Flag::Parity => Some(2),
+            Flag::Aux => Some(4),
+            Flag::Zero => Some(6),
+            Flag::Sign => Some(7),
+            Flag::Trap => Some(8),
+            Flag::Interrupt => Some(9),
+            Flag::Direction => Some(10),
+            Flag::Overflow => Some(11),
+            Flag::PageFault => None,
+        }
+    }
+
     pub fn result(&self, value: i16) -> bool {
         match self {
             Flag::Zero => value == 0,
@@ -75,6 +91,20 @@ impl FlagSet {
     pub fn clear(&mut self, flag: Flag) {
         self.set(flag, false);
     }
+
+    /// Value of the 8086 FLAGS register.
+    pub fn to_word(&self) -> u16 {
+        Flag::iter()
+            .filter(|flag| self.get(*flag))
+            .filter_map(|flag| flag.bit())
+            .fold(0, |word, bit| word | (1 << bit))
+    }
+}
+
+impl Default for FlagSet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl std::fmt::Display for FlagSet {
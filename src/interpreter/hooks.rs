@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use super::memory::MemoryAccess;
+use super::snapshot::Snapshot;
+use super::vm::{StepOutcome, VM};
+use crate::minix::message::Message;
+use crate::x86::Instruction;
+
+/// What the VM does after the hooks of a code address.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HookAction {
+    /// Execute the instruction at the address
+    Continue,
+    /// Skip the function entered at the address: return to the caller
+    /// with the value in AX, as its `ret` would
+    Return(u16),
+}
+
+type InstructionHook = Box<dyn FnMut(&mut VM, u16, &Instruction)>;
+type StepHook = Box<dyn FnMut(&mut VM, u16, StepOutcome)>;
+type MemoryHook = Box<dyn FnMut(&mut VM, u16, &MemoryAccess)>;
+type SyscallEntryHook = Box<dyn FnMut(&mut VM, &Message) -> Option<Message>>;
+type SyscallExitHook = Box<dyn FnMut(&mut VM, &Message, &mut Message)>;
+type CodeHook = Box<dyn FnMut(&mut VM) -> HookAction>;
+
+/// Callbacks of a program embedding the VM, called in the order they were added.
+/// The hooks are taken out of the VM while they run, so they can't add other hooks.
+#[derive(Default)]
+pub struct Hooks {
+    before: Vec<InstructionHook>,
+    after: Vec<StepHook>,
+    memory: Vec<MemoryHook>,
+    syscall_entry: Vec<SyscallEntryHook>,
+    syscall_exit: Vec<SyscallExitHook>,
+    code: BTreeMap<u16, Vec<CodeHook>>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Hooks::default()
+    }
+
+    /// Call the hook before each instruction, with its address.
+    pub fn on_instruction(&mut self, hook: impl FnMut(&mut VM, u16, &Instruction) + 'static) {
+        self.before.push(Box::new(hook));
+    }
+
+    /// Call the hook after each instruction, with its address and the outcome of the step.
+    pub fn on_step(&mut self, hook: impl FnMut(&mut VM, u16, StepOutcome) + 'static) {
+        self.after.push(Box::new(hook));
+    }
+
+    /// Call the hook on each read and write of the data memory, with the address
+    /// of the instruction. The accesses of an instruction are seen once it is executed.
+    pub fn on_memory(&mut self, hook: impl FnMut(&mut VM, u16, &MemoryAccess) + 'static) {
+        self.memory.push(Box::new(hook));
+    }
+
+    /// Call the hook with the request message of each syscall, the servers being
+    /// in `vm.ipc`. A returned reply is sent instead of the server's, which doesn't
+    /// handle the request.
+    pub fn on_syscall_entry(
+        &mut self,
+        hook: impl FnMut(&mut VM, &Message) -> Option<Message> + 'static,
+    ) {
+        self.syscall_entry.push(Box::new(hook));
+    }
+
+    /// Call the hook with the request and the reply of each syscall, before the
+    /// reply is received. Changing the reply overrides the result of the call.
+    pub fn on_syscall_exit(&mut self, hook: impl FnMut(&mut VM, &Message, &mut Message) + 'static) {
+        self.syscall_exit.push(Box::new(hook));
+    }
+
+    /// Call the hook before executing the instruction at the address,
+    /// like the entry of a function to stub.
+    pub fn on_address(&mut self, address: u16, hook: impl FnMut(&mut VM) -> HookAction + 'static) {
+        self.code.entry(address).or_default().push(Box::new(hook));
+    }
+
+    /// Run the instruction hooks, then the hooks of the address until one skips the function.
+    pub(super) fn before(&mut self, vm: &mut VM, snapshot: &Snapshot) -> HookAction {
+        if let Some(instruction) = &snapshot.instruction {
+            for hook in &mut self.before {
+                hook(vm, snapshot.ip, instruction);
+            }
+        }
+        for hook in self.code.get_mut(&snapshot.ip).into_iter().flatten() {
+            if let action @ HookAction::Return(_) = hook(vm) {
+                return action;
+            }
+        }
+        HookAction::Continue
+    }
+
+    /// Run the memory hooks on the accesses of the instruction, then the step hooks.
+    pub(super) fn after(
+        &mut self,
+        vm: &mut VM,
+        ip: u16,
+        accesses: &[MemoryAccess],
+        outcome: StepOutcome,
+    ) {
+        for access in accesses {
+            for hook in &mut self.memory {
+                hook(vm, ip, access);
+            }
+        }
+        for hook in &mut self.after {
+            hook(vm, ip, outcome);
+        }
+    }
+
+    /// Reply of the first syscall entry hook overriding the request, if any.
+    pub(super) fn syscall_entry(&mut self, vm: &mut VM, request: &Message) -> Option<Message> {
+        self.syscall_entry
+            .iter_mut()
+            .find_map(|hook| hook(vm, request))
+    }
+
+    pub(super) fn syscall_exit(&mut self, vm: &mut VM, request: &Message, reply: &mut Message) {
+        for hook in &mut self.syscall_exit {
+            hook(vm, request, reply);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{AccessKind, Coverage, Recorder, ShadowStack};
+    use crate::minix::message::call;
+    use crate::test_utils::program;
+    use crate::x86::Register;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_instruction_and_memory_hooks() {
+        let program = program("1.c");
+        let main = program.symbols.lookup("_main").unwrap().value as u16;
+        let mut vm = VM::from(program);
+        let steps = Rc::new(RefCell::new(vec![]));
+        let writes = Rc::new(RefCell::new(vec![]));
+
+        let mut hooks = Hooks::new();
+        let before = steps.clone();
+        hooks.on_instruction(move |_, ip, _| before.borrow_mut().push(ip));
+        let after = steps.clone();
+        hooks.on_step(move |_, ip, outcome| {
+            assert_eq!(after.borrow().last(), Some(&ip));
+            if outcome != StepOutcome::Running {
+                after.borrow_mut().push(0xffff);
+            }
+        });
+        let written = writes.clone();
+        hooks.on_memory(move |_, ip, access| {
+            if access.kind == AccessKind::Write {
+                written
+                    .borrow_mut()
+                    .push((ip, access.address, access.value()));
+            }
+        });
+        vm.hooks = Some(hooks);
+
+        // main returns the result of write
+        assert_eq!(vm.run_until_stop(), Ok(StepOutcome::Exited(6)));
+        let steps = steps.borrow();
+        assert_eq!(&steps[..3], &[0x0000, 0x0002, 0x0004]);
+        assert!(steps.contains(&main));
+        assert_eq!(steps.last(), Some(&0xffff));
+        // call main pushes the return address
+        assert!(writes.borrow().contains(&(0x0031, 0xffd2, 0x0034)));
+    }
+
+    #[test]
+    fn test_function_hook() {
+        let program = program("1.c");
+        let write = program.symbols.lookup("_write").unwrap().value as u16;
+        let mut vm = VM::from(program);
+        let calls = Rc::new(RefCell::new(vec![]));
+
+        let mut hooks = Hooks::new();
+        // write(1, "hello\n", 6) returns 7 without the syscall
+        hooks.on_address(write, |vm| {
+            let sp = vm.regs.get(Register::SP);
+            assert_eq!(vm.data.read_word(sp + 2), 1);
+            HookAction::Return(vm.data.read_word(sp + 6) + 1)
+        });
+        let entered = calls.clone();
+        hooks.on_syscall_entry(move |_, request| {
+            entered.borrow_mut().push(request.m_type());
+            None
+        });
+        vm.hooks = Some(hooks);
+
+        assert_eq!(vm.run_until_stop(), Ok(StepOutcome::Exited(7)));
+        assert_eq!(*calls.borrow(), vec![call::EXIT]);
+    }
+
+    #[test]
+    fn test_function_hook_observers() {
+        let program = program("1.c");
+        let write = program.symbols.lookup("_write").unwrap().value as u16;
+        let bss_end = (program.header.data + program.header.bss) as u16;
+        let mut vm = VM::from(program);
+        vm.recorder = Some(Recorder::new());
+        vm.coverage = Some(Coverage::new());
+        vm.shadow_stack = Some(ShadowStack::new(bss_end));
+
+        let mut hooks = Hooks::new();
+        hooks.on_address(write, |vm| {
+            vm.data.write_word(0x0200, 0x1234);
+            HookAction::Return(6)
+        });
+        vm.hooks = Some(hooks);
+
+        assert_eq!(vm.run_until_stop(), Ok(StepOutcome::Exited(6)));
+        // write is not executed, and its frame is popped by the return
+        assert!(!vm.coverage.unwrap().hits.contains_key(&write));
+        assert!(vm.shadow_stack.unwrap().reports.is_empty());
+        // The write of the hook is part of the step
+        let recorder = vm.recorder.unwrap();
+        let step = recorder
+            .history()
+            .iter()
+            .find(|step| step.ip.0 == write)
+            .unwrap();
+        assert!(step.memory.contains(&(0x0200, 0x00, 0x34)));
+        assert!(step
+            .regs
+            .iter()
+            .any(|&(r, _, value)| r == Register::AX && value == 6));
+    }
+
+    #[test]
+    fn test_syscall_hooks() {
+        let mut vm = VM::from(program("1.c"));
+        let results = Rc::new(RefCell::new(vec![]));
+
+        let mut hooks = Hooks::new();
+        hooks.on_syscall_entry(|_, request| {
+            (request.m_type() == call::WRITE).then(|| Message::new(42))
+        });
+        hooks.on_syscall_exit(|_, request, reply| {
+            if request.m_type() == call::WRITE {
+                reply.set_m_type(reply.m_type() + 1);
+            }
+        });
+        let exited = results.clone();
+        hooks.on_syscall_exit(move |_, request, reply| {
+            exited.borrow_mut().push((request.m_type(), reply.result()));
+        });
+        vm.hooks = Some(hooks);
+
+        // The process exits before the reply of exit
+        assert_eq!(vm.run_until_stop(), Ok(StepOutcome::Exited(43)));
+        assert_eq!(*results.borrow(), vec![(call::WRITE, 43)]);
+    }
+
+    #[test]
+    fn test_syscall_hooks_see_the_servers() {
+        let program = program("1.c");
+        let bss_end = (program.header.data + program.header.bss) as u16;
+        let mut vm = VM::from(program);
+        let breaks = Rc::new(RefCell::new(vec![]));
+
+        let mut hooks = Hooks::new();
+        let entered = breaks.clone();
+        hooks.on_syscall_entry(move |vm, _| {
+            entered.borrow_mut().push(vm.ipc.mm.brk);
+            None
+        });
+        hooks.on_syscall_exit(|vm, request, _| {
+            if request.m_type() == call::WRITE {
+                vm.ipc.mm.brk = 0x1234;
+            }
+        });
+        vm.hooks = Some(hooks);
+
+        // main returns the result of write
+        assert_eq!(vm.run_until_stop(), Ok(StepOutcome::Exited(6)));
+        // The break of write, then the one changed by the exit hook of write
+        assert_eq!(*breaks.borrow(), vec![bss_end, 0x1234]);
+        assert_eq!(vm.ipc.mm.brk, 0x1234);
+    }
+}
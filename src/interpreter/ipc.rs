@@ -0,0 +1,505 @@
+mod fs;
+mod mm;
+mod syscalls;
+mod tty;
+
+pub use fs::FileSystem;
+pub use mm::MemoryManager;
+pub use syscalls::{SyscallHandler, SyscallPolicy, SyscallTable, UnknownSyscall};
+pub use tty::{ioctl, sgtty, termios, Termios, Tty, TtyState};
+
+use super::error::OpcodeExecErrors;
+use super::vm::VM;
+use crate::minix::message::{call, errno, Field, Message};
+
+/// Process number of the memory manager.
+pub const MM: u16 = 0;
+/// Process number of the file system.
+pub const FS: u16 = 1;
+/// Source of `receive` accepting a message from any process.
+pub const ANY: u16 = 0x7ace;
+
+/// `send` function code, in CX.
+pub const SEND: u16 = 1;
+/// `receive` function code, in CX.
+pub const RECEIVE: u16 = 2;
+/// `sendrec` function code, `send` then `receive` from the same process.
+pub const BOTH: u16 = 3;
+
+/// Kernel errors returned in AX.
+pub mod kernel_error {
+    /// Destination address illegal
+    pub const E_BAD_DEST: i16 = -1001;
+    /// Source address illegal
+    pub const E_BAD_SRC: i16 = -1002;
+    /// `receive` failed: no message present
+    pub const E_NO_MESSAGE: i16 = -1007;
+    /// Only valid functions are `send`, `receive` and `sendrec`
+    pub const E_BAD_FCN: i16 = -1009;
+}
+
+/// A server process, receiving the requests of the user process.
+pub trait Server {
+    /// Handle the request in the caller's message at `message`, and return the reply.
+    /// Only the fields used by the call are read from the caller.
+    fn handle(
+        &mut self,
+        vm: &mut VM,
+        number: u16,
+        message: u16,
+    ) -> Result<Message, OpcodeExecErrors>;
+}
+
+/// Server handling a Minix call, as in the call tables of MM and FS.
+pub fn server_of(number: u16) -> Option<u16> {
+    match number {
+        call::EXIT
+        | call::FORK
+        | call::WAIT
+        | call::WAITPID
+        | call::BRK
+        | call::GETPID
+        | call::SETUID
+        | call::GETUID
+        | call::PTRACE
+        | call::ALARM
+        | call::PAUSE
+        | call::KILL
+        | call::SETGID
+        | call::GETGID
+        | call::EXEC
+        | call::SETSID
+        | call::GETPGRP
+        | call::SIGACTION
+        | call::SIGSUSPEND
+        | call::SIGPENDING
+        | call::SIGPROCMASK
+        | call::SIGRETURN
+        | call::REBOOT
+        | call::SVRCTL => Some(MM),
+        call::READ
+        | call::WRITE
+        | call::OPEN
+        | call::CLOSE
+        | call::CREAT
+        | call::LINK
+        | call::UNLINK
+        | call::CHDIR
+        | call::TIME
+        | call::MKNOD
+        | call::CHMOD
+        | call::CHOWN
+        | call::STAT
+        | call::LSEEK
+        | call::MOUNT
+        | call::UMOUNT
+        | call::STIME
+        | call::UTIME
+        | call::FSTAT
+        | call::ACCESS
+        | call::SYNC
+        | call::RENAME
+        | call::MKDIR
+        | call::RMDIR
+        | call::DUP
+        | call::PIPE
+        | call::TIMES
+        | call::IOCTL
+        | call::FCNTL
+        | call::UMASK
+        | call::CHROOT => Some(FS),
+        _ => None,
+    }
+}
+
+/// Kernel message passing between the user process and the MM and FS servers.
+/// Servers reply synchronously, the replies wait in a queue until received.
+#[derive(Debug, Default)]
+pub struct Ipc {
+    pub mm: MemoryManager,
+    pub fs: FileSystem,
+    /// Handlers and policy of the calls, before the servers
+    pub syscalls: SyscallTable,
+    // Replies not received yet
+    pending: Vec<Message>,
+}
+
+/// State of the servers changed by the calls, restored when moving back in a recording.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ServerState {
+    mm: MemoryManager,
+    tty: TtyState,
+    pending: Vec<Message>,
+}
+
+impl Ipc {
+    /// IPC for a program whose data and bss segments end at `bss_end`.
+    pub fn new(bss_end: u16) -> Self {
+        Ipc {
+            mm: MemoryManager::new(bss_end),
+            ..Ipc::default()
+        }
+    }
+
+    pub fn state(&self) -> ServerState {
+        ServerState {
+            mm: self.mm.clone(),
+            tty: self.fs.tty.state(),
+            pending: self.pending.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: &ServerState) {
+        self.mm.clone_from(&state.mm);
+        self.fs.tty.restore(&state.tty);
+        self.pending.clone_from(&state.pending);
+    }
+
+    /// Handle an `int 0x20` of the VM, with the servers of `vm.ipc`: the function in CX,
+    /// the other process in AX and the message in BX.
+    /// Return the status for AX, 0 or a kernel error.
+    pub fn sys_call(
+        vm: &mut VM,
+        function: u16,
+        src_dest: u16,
+        message: u16,
+    ) -> Result<i16, OpcodeExecErrors> {
+        match function {
+            SEND => Ipc::send(vm, src_dest, message),
+            RECEIVE => Ok(Ipc::receive(vm, src_dest, message)),
+            BOTH => match Ipc::send(vm, src_dest, message)? {
+                0 => Ok(Ipc::receive(vm, src_dest, message)),
+                status => Ok(status),
+            },
+            _ => Ok(kernel_error::E_BAD_FCN),
+        }
+    }
+
+    /// Deliver the message to a server, and queue its reply.
+    /// The hooks and the handlers see the servers in `vm.ipc`.
+    pub fn send(vm: &mut VM, dest: u16, message: u16) -> Result<i16, OpcodeExecErrors> {
+        // User processes can only talk to the servers
+        if dest != MM && dest != FS {
+            return Ok(kernel_error::E_BAD_DEST);
+        }
+        // The kernel copies the whole message, it must lie in the data segment
+        let Some(number) =
+            Message::read_fields(&vm.data, message, &[Field::Type]).map(|request| request.m_type())
+        else {
+            return Ok(-errno::EFAULT);
+        };
+        // The hooks may reply instead of the server, and change the reply
+        let mut hooks = vm.hooks.take();
+        let request = hooks.as_ref().map(|_| Message::peek(&vm.data, message));
+        let overridden = match (&mut hooks, &request) {
+            (Some(hooks), Some(request)) => hooks.syscall_entry(vm, request),
+            _ => None,
+        };
+        vm.hooks = hooks;
+        let mut reply = match overridden {
+            Some(reply) => reply,
+            None => {
+                // Only the table is out of the VM while a handler runs
+                let mut syscalls = std::mem::take(&mut vm.ipc.syscalls);
+                let reply = syscalls.dispatch(vm, number, dest, message);
+                vm.ipc.syscalls = syscalls;
+                reply?
+            }
+        };
+        if let (Some(mut hooks), Some(request)) = (vm.hooks.take(), &request) {
+            hooks.syscall_exit(vm, request, &mut reply);
+            vm.hooks = Some(hooks);
+        }
+        reply.set_source(dest);
+        vm.ipc.pending.push(reply);
+        Ok(0)
+    }
+
+    /// Copy the first queued reply from `src` into the caller's message.
+    pub fn receive(vm: &mut VM, src: u16, message: u16) -> i16 {
+        if src != ANY && src != MM && src != FS {
+            return kernel_error::E_BAD_SRC;
+        }
+        if !Message::fits(&vm.data, message) {
+            return -errno::EFAULT;
+        }
+        match vm
+            .ipc
+            .pending
+            .iter()
+            .position(|reply| src == ANY || reply.source() == src)
+        {
+            Some(index) => {
+                vm.ipc.pending.remove(index).write(&mut vm.data, message);
+                0
+            }
+            None => kernel_error::E_NO_MESSAGE,
+        }
+    }
+}
+
+/// Reply of the server `dest` to the call, the server being out of `vm.ipc` meanwhile.
+fn serve(vm: &mut VM, dest: u16, number: u16, message: u16) -> Result<Message, OpcodeExecErrors> {
+    match dest {
+        MM => {
+            let mut mm = std::mem::take(&mut vm.ipc.mm);
+            let reply = mm.handle(vm, number, message);
+            vm.ipc.mm = mm;
+            reply
+        }
+        _ => {
+            let mut fs = std::mem::take(&mut vm.ipc.fs);
+            let reply = fs.handle(vm, number, message);
+            vm.ipc.fs = fs;
+            reply
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::VmIrExecutable;
+    use crate::minix::message::{
+        BrkReply, BrkRequest, ReadRequest, Reply, Request, TransferRequest, WriteRequest,
+    };
+    use crate::x86::Register;
+
+    fn run_sys_call(function: u16, src_dest: u16, request: Message) -> (VM, Message) {
+        run_with(SyscallTable::default(), function, src_dest, request)
+    }
+
+    // mov ax, src_dest; mov bx, 0100; mov cx, function; int 20; hlt
+    fn run_with(
+        syscalls: SyscallTable,
+        function: u16,
+        src_dest: u16,
+        request: Message,
+    ) -> (VM, Message) {
+        let [dest_lo, dest_hi] = src_dest.to_le_bytes();
+        let mut vm = VM {
+            text: crate::interpreter::Memory::from(vec![
+                0xb8,
+                dest_lo,
+                dest_hi,
+                0xbb,
+                0x00,
+                0x01,
+                0xb9,
+                function as u8,
+                0x00,
+                0xcd,
+                0x20,
+                0xf4,
+            ]),
+            ipc: Ipc {
+                syscalls,
+                ..Ipc::new(0x0200)
+            },
+            ..VM::default()
+        };
+        vm.regs.set(Register::SP, 0x0f00);
+        request.write(&mut vm.data, 0x0100);
+        while vm.step() == Ok(crate::interpreter::StepOutcome::Running) {}
+        let reply = Message::peek(&vm.data, 0x0100);
+        (vm, reply)
+    }
+
+    #[test]
+    fn test_sendrec_brk() {
+        let (vm, reply) = run_sys_call(BOTH, MM, BrkRequest { addr: 0x0400 }.to_message());
+        assert_eq!(vm.regs.get(Register::AX), 0);
+        assert_eq!(reply.source(), MM);
+        assert_eq!(reply.result(), 0);
+        assert_eq!(reply.m2().p1, 0x0400);
+        assert_eq!(vm.ipc.mm.brk, 0x0400);
+
+        // Into the stack
+        let (vm, reply) = run_sys_call(BOTH, MM, BrkRequest { addr: 0x0e00 }.to_message());
+        assert_eq!(reply.result(), -errno::ENOMEM);
+        assert_eq!(reply.m2().p1, 0xffff);
+        assert_eq!(vm.ipc.mm.brk, 0x0200);
+    }
+
+    #[test]
+    fn test_routing() {
+        // brk belongs to MM
+        let (_, reply) = run_sys_call(BOTH, FS, BrkRequest { addr: 0x0400 }.to_message());
+        assert_eq!((reply.source(), reply.result()), (FS, -errno::EINVAL));
+
+        // Tasks can't be reached from a user process
+        let (vm, reply) = run_sys_call(BOTH, 0xfffd, Message::new(call::GETPID));
+        assert_eq!(vm.regs.get(Register::AX) as i16, kernel_error::E_BAD_DEST);
+        assert_eq!(reply.m_type(), call::GETPID);
+
+        let (vm, _) = run_sys_call(4, MM, Message::new(call::GETPID));
+        assert_eq!(vm.regs.get(Register::AX) as i16, kernel_error::E_BAD_FCN);
+    }
+
+    #[test]
+    fn test_send_then_receive() {
+        let (mut vm, request) = run_sys_call(SEND, MM, Message::new(call::GETPID));
+        // The reply is queued, the message is unchanged
+        assert_eq!(request.m_type(), call::GETPID);
+
+        assert_eq!(
+            Ipc::receive(&mut vm, FS, 0x0100),
+            kernel_error::E_NO_MESSAGE
+        );
+        assert_eq!(Ipc::receive(&mut vm, ANY, 0x0100), 0);
+        let reply = Message::peek(&vm.data, 0x0100);
+        assert_eq!((reply.source(), reply.result()), (MM, mm::PID));
+        assert_eq!(
+            Ipc::receive(&mut vm, MM, 0x0100),
+            kernel_error::E_NO_MESSAGE
+        );
+    }
+
+    #[test]
+    fn test_syscall_handlers() {
+        let syscalls = || {
+            let mut syscalls = SyscallTable::new();
+            // Override a call of MM
+            syscalls.register(call::GETPID, |_: &mut VM, _| Ok(Message::new(42)));
+            // Add a call MM doesn't implement, reading the request
+            syscalls.register(call::FORK, |vm: &mut VM, message| {
+                let request = Message::peek(&vm.data, message);
+                Ok(Message::new(request.word(Field::M1I1)))
+            });
+            syscalls
+        };
+
+        let (_, reply) = run_with(syscalls(), BOTH, MM, Message::new(call::GETPID));
+        assert_eq!(reply.result(), 42);
+        let mut fork = Message::new(call::FORK);
+        fork.set_word(Field::M1I1, 5);
+        let (_, reply) = run_with(syscalls(), BOTH, MM, fork);
+        assert_eq!((reply.source(), reply.result()), (MM, 5));
+    }
+
+    #[test]
+    fn test_syscall_handler_servers() {
+        let mut syscalls = SyscallTable::new();
+        // Move the break by a page, replying with the old one
+        syscalls.register(call::BRK, |vm: &mut VM, _| {
+            let old = vm.ipc.mm.brk;
+            vm.ipc.mm.brk += 0x0100;
+            Ok(BrkReply {
+                result: 0,
+                addr: old,
+            }
+            .to_message())
+        });
+        let (vm, reply) = run_with(syscalls, BOTH, MM, BrkRequest { addr: 0 }.to_message());
+        assert_eq!(reply.m2().p1, 0x0200);
+        assert_eq!(vm.ipc.mm.brk, 0x0300);
+    }
+
+    #[test]
+    fn test_syscall_policy() {
+        let denied = || {
+            let mut syscalls = SyscallTable::new();
+            syscalls.deny(call::GETPID);
+            syscalls
+        };
+        let (vm, reply) = run_with(denied(), BOTH, MM, Message::new(call::GETPID));
+        assert_eq!(vm.regs.get(Register::AX), 0);
+        assert_eq!(reply.result(), -errno::EPERM);
+        let (_, reply) = run_with(denied(), BOTH, MM, Message::new(call::GETUID));
+        assert_eq!(reply.result(), 0);
+
+        let allowed = || {
+            let mut syscalls = SyscallTable::new();
+            syscalls.policy = SyscallPolicy::DenyByDefault;
+            syscalls.allow(call::BRK);
+            syscalls
+        };
+        let (_, reply) = run_with(allowed(), BOTH, MM, Message::new(call::GETPID));
+        assert_eq!(reply.result(), -errno::EPERM);
+        let brk = BrkRequest { addr: 0x0400 }.to_message();
+        let (vm, reply) = run_with(allowed(), BOTH, MM, brk);
+        assert_eq!((reply.result(), vm.ipc.mm.brk), (0, 0x0400));
+    }
+
+    #[test]
+    fn test_unknown_syscall() {
+        // The run stops on the int
+        let (vm, reply) = run_sys_call(BOTH, MM, Message::new(call::FORK));
+        assert_eq!(vm.ip, 0x000b);
+        assert_eq!(reply.m_type(), call::FORK);
+
+        let mut syscalls = SyscallTable::new();
+        syscalls.unknown = UnknownSyscall::Enosys;
+        let (vm, reply) = run_with(syscalls, BOTH, MM, Message::new(call::FORK));
+        assert_eq!(reply.result(), -errno::ENOSYS);
+        assert_eq!(vm.ipc.syscalls.log, vec![call::FORK]);
+
+        // No server has the call
+        let (vm, reply) = run_sys_call(BOTH, MM, Message::new(99));
+        assert_eq!(vm.ip, 0x000b);
+        assert_eq!(reply.m_type(), 99);
+        let mut syscalls = SyscallTable::new();
+        syscalls.unknown = UnknownSyscall::Enosys;
+        let (vm, reply) = run_with(syscalls, BOTH, FS, Message::new(99));
+        assert_eq!(reply.result(), -errno::ENOSYS);
+        assert_eq!(vm.ipc.syscalls.log, vec![99]);
+    }
+
+    #[test]
+    fn test_read_stdin() {
+        let mut vm = VM::default();
+        let mut fs = FileSystem {
+            tty: Tty::with_input(&b"ls -l\x08\x08a\n"[..], std::io::sink()),
+        };
+        let read = |fd, nbytes| {
+            ReadRequest(TransferRequest {
+                fd,
+                buffer: 0x0200,
+                nbytes,
+            })
+            .to_message()
+        };
+        read(0, 80).write(&mut vm.data, 0x0100);
+        let reply = fs.handle(&mut vm, call::READ, 0x0100).unwrap();
+        assert_eq!(reply.result(), 5);
+        assert_eq!(vm.data.read_bytes(0x0200, 5), b"ls a\n");
+
+        read(1, 80).write(&mut vm.data, 0x0100);
+        let reply = fs.handle(&mut vm, call::READ, 0x0100).unwrap();
+        assert_eq!(reply.result(), -errno::EBADF);
+        read(0, 0x1000).write(&mut vm.data, 0x0100);
+        let reply = fs.handle(&mut vm, call::READ, 0x0100).unwrap();
+        assert_eq!(reply.result(), -errno::EFAULT);
+    }
+
+    #[test]
+    fn test_write_past_memory() {
+        let mut vm = VM::default();
+        let mut fs = FileSystem::default();
+        let end = vm.data.len() as u16;
+        WriteRequest(TransferRequest {
+            fd: 1,
+            buffer: end - 0x10,
+            nbytes: 0x100,
+        })
+        .to_message()
+        .write(&mut vm.data, 0x0100);
+        let reply = fs.handle(&mut vm, call::WRITE, 0x0100).unwrap();
+        assert_eq!(reply.result(), -errno::EFAULT);
+    }
+
+    #[test]
+    fn test_message_past_memory() {
+        let mut vm = VM::default();
+        let message = vm.data.len() as u16 - 8;
+        assert_eq!(
+            Message::read_fields(&vm.data, message, &[Field::Type]),
+            None
+        );
+        assert_eq!(
+            Ipc::sys_call(&mut vm, BOTH, MM, message),
+            Ok(-errno::EFAULT)
+        );
+        assert_eq!(Ipc::receive(&mut vm, ANY, message), -errno::EFAULT);
+    }
+}
@@ -0,0 +1,130 @@
+use super::tty::Tty;
+use super::Server;
+use crate::interpreter::error::OpcodeExecErrors;
+use crate::interpreter::vm::VM;
+use crate::minix::message::{
+    call, errno, IoctlRequest, LongReply, Message, ReadRequest, Reply, Request, StatusReply,
+    WriteRequest,
+};
+
+/// The file system: reads from the standard input, writes to the standard output
+/// and error, terminal ioctls and time.
+#[derive(Debug, Default)]
+pub struct FileSystem {
+    /// Terminal of the standard descriptors
+    pub tty: Tty,
+}
+
+impl FileSystem {
+    fn read(&mut self, vm: &mut VM, message: u16) -> StatusReply {
+        let Some(ReadRequest(request)) = ReadRequest::read(&vm.data, message) else {
+            return StatusReply {
+                result: -errno::EFAULT,
+            };
+        };
+        if request.fd != 0 {
+            return StatusReply {
+                result: -errno::EBADF,
+            };
+        }
+        if request.buffer as usize + request.nbytes as usize > vm.data.len() {
+            return StatusReply {
+                result: -errno::EFAULT,
+            };
+        }
+        let result = match self.tty.read(request.nbytes as usize) {
+            Ok(bytes) => {
+                vm.data.write_bytes(request.buffer, &bytes);
+                bytes.len() as i16
+            }
+            Err(errno) => errno,
+        };
+        vm.trace(
+            format!(
+                "\n<read({}, {:#06x}, {}) => {}>",
+                request.fd, request.buffer, request.nbytes, result
+            )
+            .as_str(),
+        );
+        StatusReply { result }
+    }
+
+    fn write(&mut self, vm: &VM, message: u16) -> StatusReply {
+        let Some(WriteRequest(request)) = WriteRequest::read(&vm.data, message) else {
+            return StatusReply {
+                result: -errno::EFAULT,
+            };
+        };
+        if request.fd != 1 && request.fd != 2 {
+            return StatusReply {
+                result: -errno::EBADF,
+            };
+        }
+        if request.buffer as usize + request.nbytes as usize > vm.data.len() {
+            return StatusReply {
+                result: -errno::EFAULT,
+            };
+        }
+
+        // Return nb of bytes written
+        let return_value = request.nbytes;
+        let content =
+            String::from_utf8_lossy(vm.data.read_bytes(request.buffer, request.nbytes as usize));
+        vm.trace(
+            format!(
+                "\n<write({}, {:#06x}, {}){} => {}>",
+                request.fd, request.buffer, request.nbytes, content, return_value
+            )
+            .as_str(),
+        );
+        // if not trace
+        if !vm.trace {
+            match request.fd {
+                1 => print!("{}", content),
+                _ => eprint!("{}", content),
+            }
+        }
+        StatusReply {
+            result: return_value as i16,
+        }
+    }
+
+    fn ioctl(&mut self, vm: &mut VM, message: u16) -> StatusReply {
+        let Some(IoctlRequest { fd, request, data }) = IoctlRequest::read(&vm.data, message) else {
+            return StatusReply {
+                result: -errno::EFAULT,
+            };
+        };
+        vm.trace(format!("\n<ioctl({}, {:#04x}, {:#04x})>", fd, request, data).as_str());
+        StatusReply {
+            result: self.tty.ioctl(&mut vm.data, fd, request, data),
+        }
+    }
+
+    fn time(&mut self) -> LongReply {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        LongReply {
+            result: 0,
+            value: now as i32,
+        }
+    }
+}
+
+impl Server for FileSystem {
+    fn handle(
+        &mut self,
+        vm: &mut VM,
+        number: u16,
+        message: u16,
+    ) -> Result<Message, OpcodeExecErrors> {
+        match number {
+            call::READ => Ok(self.read(vm, message).to_message()),
+            call::WRITE => Ok(self.write(vm, message).to_message()),
+            call::IOCTL => Ok(self.ioctl(vm, message).to_message()),
+            call::TIME => Ok(self.time().to_message()),
+            _ => Err(OpcodeExecErrors::UnimplementedSyscall(number as usize)),
+        }
+    }
+}
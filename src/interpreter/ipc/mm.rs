@@ -0,0 +1,91 @@
+use super::Server;
+use crate::interpreter::error::OpcodeExecErrors;
+use crate::interpreter::vm::VM;
+use crate::minix::message::{
+    call, errno, BrkReply, BrkRequest, ExitRequest, Field, Message, Reply, Request, StatusReply,
+};
+use crate::x86::Register;
+
+/// Process id of the user process.
+pub const PID: i16 = 7;
+/// Process id of its parent, init.
+pub const PARENT_PID: i16 = 1;
+
+// Bytes kept free between the break and the stack
+const SAFETY_BYTES: u16 = 384 * 2;
+
+/// The memory manager: process exit, break and identity calls.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MemoryManager {
+    /// Current break, end of the heap
+    pub brk: u16,
+    pub uid: i16,
+    pub gid: i16,
+}
+
+impl MemoryManager {
+    /// Memory manager of a program whose data and bss segments end at `bss_end`.
+    pub fn new(bss_end: u16) -> Self {
+        MemoryManager {
+            brk: bss_end,
+            ..MemoryManager::default()
+        }
+    }
+
+    fn brk(&mut self, vm: &VM, message: u16) -> BrkReply {
+        let Some(BrkRequest { addr }) = BrkRequest::read(&vm.data, message) else {
+            return BrkReply {
+                result: -errno::EFAULT,
+                addr: 0xffff,
+            };
+        };
+        let sp = vm.regs.get(Register::SP);
+        let reply = if (addr as u32) + (SAFETY_BYTES as u32) > sp as u32 {
+            BrkReply {
+                result: -errno::ENOMEM,
+                addr: 0xffff,
+            }
+        } else {
+            self.brk = addr;
+            BrkReply { result: 0, addr }
+        };
+        vm.trace(format!("\n<brk({:#06x}) => {}>", addr, reply.result).as_str());
+        reply
+    }
+
+    /// Reply with an id in `m_type` and another one in m2_i1.
+    fn ids(result: i16, other: i16) -> Message {
+        let mut reply = Message::new(result as u16);
+        reply.set_word(Field::M2I1, other as u16);
+        reply
+    }
+}
+
+impl Server for MemoryManager {
+    fn handle(
+        &mut self,
+        vm: &mut VM,
+        number: u16,
+        message: u16,
+    ) -> Result<Message, OpcodeExecErrors> {
+        match number {
+            call::EXIT => {
+                // No reply, the process is gone
+                let Some(ExitRequest { status }) = ExitRequest::read(&vm.data, message) else {
+                    return Ok(StatusReply {
+                        result: -errno::EFAULT,
+                    }
+                    .to_message());
+                };
+                vm.trace(format!("\n<exit({})>", status).as_str());
+                Err(OpcodeExecErrors::ExitCatch(status as u16))
+            }
+            call::BRK => Ok(self.brk(vm, message).to_message()),
+            // Real and effective ids are the same
+            call::GETPID => Ok(Self::ids(PID, PARENT_PID)),
+            call::GETUID => Ok(Self::ids(self.uid, self.uid)),
+            call::GETGID => Ok(Self::ids(self.gid, self.gid)),
+            _ => Err(OpcodeExecErrors::UnimplementedSyscall(number as usize)),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{serve, server_of};
+use crate::interpreter::error::OpcodeExecErrors;
+use crate::interpreter::vm::VM;
+use crate::minix::message::{errno, Message};
+
+/// Handler of a Minix call, used instead of the server's. It sees the servers
+/// in `vm.ipc`, but not the syscall table.
+pub trait SyscallHandler {
+    /// Handle the request in the caller's message at `message`, and return the reply.
+    fn handle(&mut self, vm: &mut VM, message: u16) -> Result<Message, OpcodeExecErrors>;
+}
+
+impl<F> SyscallHandler for F
+where
+    F: FnMut(&mut VM, u16) -> Result<Message, OpcodeExecErrors>,
+{
+    fn handle(&mut self, vm: &mut VM, message: u16) -> Result<Message, OpcodeExecErrors> {
+        self(vm, message)
+    }
+}
+
+/// Calls reaching their handler or server. The others fail with EPERM.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum SyscallPolicy {
+    /// All the calls but the denied ones
+    #[default]
+    AllowAll,
+    /// Only the allowed calls and those with a handler, `exit` included
+    DenyByDefault,
+}
+
+/// What a call no server implements does.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum UnknownSyscall {
+    /// Stop the run with `UnimplementedSyscall`
+    #[default]
+    Abort,
+    /// Log the call and fail with ENOSYS
+    Enosys,
+}
+
+/// Handlers of the Minix calls added or overridden by an embedding program,
+/// and the calls allowed, in front of the MM and FS servers.
+#[derive(Default)]
+pub struct SyscallTable {
+    handlers: BTreeMap<u16, Box<dyn SyscallHandler>>,
+    allowed: BTreeSet<u16>,
+    denied: BTreeSet<u16>,
+    pub policy: SyscallPolicy,
+    pub unknown: UnknownSyscall,
+    /// Unimplemented calls made, that failed with ENOSYS
+    pub log: Vec<u16>,
+}
+
+impl SyscallTable {
+    pub fn new() -> Self {
+        SyscallTable::default()
+    }
+
+    /// Handle the call with the handler instead of the server, and return the previous handler.
+    pub fn register(
+        &mut self,
+        number: u16,
+        handler: impl SyscallHandler + 'static,
+    ) -> Option<Box<dyn SyscallHandler>> {
+        self.handlers.insert(number, Box::new(handler))
+    }
+
+    /// Give the call back to the server.
+    pub fn unregister(&mut self, number: u16) -> Option<Box<dyn SyscallHandler>> {
+        self.handlers.remove(&number)
+    }
+
+    /// Allow the call with the `DenyByDefault` policy.
+    pub fn allow(&mut self, number: u16) {
+        self.denied.remove(&number);
+        self.allowed.insert(number);
+    }
+
+    /// Fail the call with EPERM, even if it has a handler.
+    pub fn deny(&mut self, number: u16) {
+        self.allowed.remove(&number);
+        self.denied.insert(number);
+    }
+
+    pub fn is_allowed(&self, number: u16) -> bool {
+        !self.denied.contains(&number)
+            && match self.policy {
+                SyscallPolicy::AllowAll => true,
+                SyscallPolicy::DenyByDefault => {
+                    self.allowed.contains(&number) || self.handlers.contains_key(&number)
+                }
+            }
+    }
+
+    /// Reply to the call sent to `dest` with its handler, or with `dest` if it handles it.
+    pub(super) fn dispatch(
+        &mut self,
+        vm: &mut VM,
+        number: u16,
+        dest: u16,
+        message: u16,
+    ) -> Result<Message, OpcodeExecErrors> {
+        if !self.is_allowed(number) {
+            vm.trace(format!("\n<syscall {} denied>", number).as_str());
+            return Ok(Message::new(-errno::EPERM as u16));
+        }
+        if let Some(handler) = self.handlers.get_mut(&number) {
+            return handler.handle(vm, message);
+        }
+        match server_of(number) {
+            Some(server) if server == dest => {}
+            // no_sys of the other server
+            Some(_) => return Ok(Message::new(-errno::EINVAL as u16)),
+            None => return self.unimplemented(vm, number),
+        }
+        match serve(vm, dest, number, message) {
+            Err(OpcodeExecErrors::UnimplementedSyscall(_)) => self.unimplemented(vm, number),
+            reply => reply,
+        }
+    }
+
+    /// Stop the run, or fail the call with ENOSYS, as set by `unknown`.
+    fn unimplemented(&mut self, vm: &mut VM, number: u16) -> Result<Message, OpcodeExecErrors> {
+        match self.unknown {
+            UnknownSyscall::Abort => Err(OpcodeExecErrors::UnimplementedSyscall(number as usize)),
+            UnknownSyscall::Enosys => {
+                vm.trace(format!("\n<syscall {} unimplemented>", number).as_str());
+                self.log.push(number);
+                Ok(Message::new(-errno::ENOSYS as u16))
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for SyscallTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyscallTable")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("allowed", &self.allowed)
+            .field("denied", &self.denied)
+            .field("policy", &self.policy)
+            .field("unknown", &self.unknown)
+            .field("log", &self.log)
+            .finish()
+    }
+}
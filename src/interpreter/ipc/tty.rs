@@ -0,0 +1,670 @@
+use std::collections::VecDeque;
+use std::io::{IsTerminal, Read, Write};
+
+use crate::interpreter::memory::Memory;
+use crate::minix::message::errno;
+
+/// Terminal ioctl requests, the low word of the `_IOR` and `_IOW` codes of Minix.
+pub mod ioctl {
+    /// `tcgetattr`, `struct termios`
+    pub const TCGETS: u16 = 0x5408;
+    /// `tcsetattr(TCSANOW)`
+    pub const TCSETS: u16 = 0x5409;
+    /// `tcsetattr(TCSADRAIN)`
+    pub const TCSETSW: u16 = 0x540a;
+    /// `tcsetattr(TCSAFLUSH)`, discarding the input not read
+    pub const TCSETSF: u16 = 0x540b;
+    /// `struct sgttyb`
+    pub const TIOCGETP: u16 = 0x7408;
+    /// Set the `struct sgttyb`, discarding the input not read
+    pub const TIOCSETP: u16 = 0x7409;
+    /// Set the `struct tchars`
+    pub const TIOCSETC: u16 = 0x7411;
+    /// `struct tchars`
+    pub const TIOCGETC: u16 = 0x7412;
+}
+
+/// Flags and control character indexes of `struct termios`, as in Minix.
+pub mod termios {
+    // c_iflag
+    pub const BRKINT: u16 = 0x0001;
+    pub const ICRNL: u16 = 0x0002;
+    pub const IGNCR: u16 = 0x0008;
+    pub const INLCR: u16 = 0x0020;
+    pub const ISTRIP: u16 = 0x0080;
+    pub const IXON: u16 = 0x0200;
+    pub const IXANY: u16 = 0x0800;
+    // c_oflag
+    pub const OPOST: u16 = 0x0001;
+    pub const ONLCR: u16 = 0x0002;
+    pub const XTABS: u16 = 0x0004;
+    // c_cflag
+    pub const CREAD: u16 = 0x0002;
+    pub const CSIZE: u16 = 0x000c;
+    pub const CS5: u16 = 0x0000;
+    pub const CS6: u16 = 0x0004;
+    pub const CS7: u16 = 0x0008;
+    pub const CS8: u16 = 0x000c;
+    pub const HUPCL: u16 = 0x0020;
+    pub const PARENB: u16 = 0x0040;
+    pub const PARODD: u16 = 0x0080;
+    // c_lflag
+    pub const ECHO: u16 = 0x0001;
+    pub const ECHOE: u16 = 0x0002;
+    pub const ECHOK: u16 = 0x0004;
+    pub const ECHONL: u16 = 0x0008;
+    pub const ICANON: u16 = 0x0010;
+    pub const IEXTEN: u16 = 0x0020;
+    pub const ISIG: u16 = 0x0040;
+    // c_cc
+    pub const VEOF: usize = 0;
+    pub const VEOL: usize = 1;
+    pub const VERASE: usize = 2;
+    pub const VINTR: usize = 3;
+    pub const VKILL: usize = 4;
+    pub const VMIN: usize = 5;
+    pub const VQUIT: usize = 6;
+    pub const VTIME: usize = 7;
+    pub const VSUSP: usize = 8;
+    pub const VSTART: usize = 9;
+    pub const VSTOP: usize = 10;
+    pub const NCCS: usize = 20;
+    /// Control character disabled
+    pub const VDISABLE: u8 = 0xff;
+    /// Speed of the terminal lines
+    pub const B9600: u16 = 0x9600;
+}
+
+/// Flags of `struct sgttyb`.
+pub mod sgtty {
+    pub const XTABS: u16 = 0o6000;
+    pub const BITS8: u16 = 0o1400;
+    pub const BITS7: u16 = 0o1000;
+    pub const BITS6: u16 = 0o0400;
+    pub const BITS5: u16 = 0o0000;
+    pub const EVENP: u16 = 0o0200;
+    pub const ODDP: u16 = 0o0100;
+    pub const RAW: u16 = 0o0040;
+    pub const CRMOD: u16 = 0o0020;
+    pub const ECHO: u16 = 0o0010;
+    pub const CBREAK: u16 = 0o0002;
+}
+
+// termios speeds, at the index of the sgttyb speed
+const SPEEDS: [u16; 16] = [
+    0, 0x0050, 0x0075, 0x0110, 0x0134, 0x0150, 0x0200, 0x0300, 0x0600, 0x1200, 0x1800, 0x2400,
+    0x4800, 0x9600, 0x1920, 0x3840,
+];
+
+/// Terminal settings, `struct termios`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Termios {
+    pub iflag: u16,
+    pub oflag: u16,
+    pub cflag: u16,
+    pub lflag: u16,
+    pub ispeed: u16,
+    pub ospeed: u16,
+    pub cc: [u8; termios::NCCS],
+}
+
+impl Default for Termios {
+    /// Settings of a Minix console: cooked mode with echo.
+    fn default() -> Self {
+        use termios::*;
+        let mut cc = [VDISABLE; NCCS];
+        cc[VEOF] = 0x04;
+        cc[VERASE] = 0x08;
+        cc[VINTR] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VMIN] = 1;
+        cc[VQUIT] = 0x1c;
+        cc[VTIME] = 0;
+        cc[VSUSP] = 0x1a;
+        cc[VSTART] = 0x11;
+        cc[VSTOP] = 0x13;
+        Termios {
+            iflag: BRKINT | ICRNL | IXON | IXANY,
+            oflag: OPOST | ONLCR,
+            cflag: CREAD | CS8 | HUPCL,
+            lflag: ISIG | IEXTEN | ICANON | ECHO | ECHOE,
+            ispeed: B9600,
+            ospeed: B9600,
+            cc,
+        }
+    }
+}
+
+impl Termios {
+    /// Size of `struct termios`.
+    pub const SIZE: usize = 12 + termios::NCCS;
+
+    pub fn read(data: &Memory, address: u16) -> Self {
+        let word = |offset: u16| data.read_word(address + offset);
+        let mut cc = [0; termios::NCCS];
+        cc.copy_from_slice(data.read_bytes(address + 12, termios::NCCS));
+        Termios {
+            iflag: word(0),
+            oflag: word(2),
+            cflag: word(4),
+            lflag: word(6),
+            ispeed: word(8),
+            ospeed: word(10),
+            cc,
+        }
+    }
+
+    pub fn write(&self, data: &mut Memory, address: u16) {
+        let words = [
+            self.iflag,
+            self.oflag,
+            self.cflag,
+            self.lflag,
+            self.ispeed,
+            self.ospeed,
+        ];
+        for (i, word) in words.iter().enumerate() {
+            data.write_word(address + 2 * i as u16, *word);
+        }
+        data.write_bytes(address + 12, &self.cc);
+    }
+
+    /// Input read by lines, with the erase and kill characters.
+    pub fn is_canonical(&self) -> bool {
+        self.lflag & termios::ICANON != 0
+    }
+
+    /// The byte is the enabled control character at the index of `cc`.
+    fn is_control(&self, byte: u8, index: usize) -> bool {
+        self.cc[index] != termios::VDISABLE && self.cc[index] == byte
+    }
+
+    /// `struct sgttyb`: speeds, erase and kill characters and flags.
+    pub fn sgttyb(&self) -> [u8; 6] {
+        use termios::*;
+        let speed = |speed| SPEEDS.iter().position(|&s| s == speed).unwrap_or(0) as u8;
+        let mut flags = match self.cflag & CSIZE {
+            CS5 => sgtty::BITS5,
+            CS6 => sgtty::BITS6,
+            CS7 => sgtty::BITS7,
+            _ => sgtty::BITS8,
+        };
+        if self.cflag & PARENB != 0 {
+            flags |= match self.cflag & PARODD {
+                0 => sgtty::EVENP,
+                _ => sgtty::ODDP,
+            };
+        }
+        if !self.is_canonical() {
+            flags |= match self.lflag & ISIG {
+                0 => sgtty::RAW,
+                _ => sgtty::CBREAK,
+            };
+        }
+        if self.lflag & ECHO != 0 {
+            flags |= sgtty::ECHO;
+        }
+        if self.oflag & ONLCR != 0 {
+            flags |= sgtty::CRMOD;
+        }
+        if self.oflag & XTABS != 0 {
+            flags |= sgtty::XTABS;
+        }
+        let [low, high] = flags.to_le_bytes();
+        [
+            speed(self.ispeed),
+            speed(self.ospeed),
+            self.cc[VERASE],
+            self.cc[VKILL],
+            low,
+            high,
+        ]
+    }
+
+    /// Change the settings to those of a `struct sgttyb`, as the Minix tty driver.
+    pub fn set_sgttyb(&mut self, sgttyb: &[u8; 6]) {
+        use termios::*;
+        let [ispeed, ospeed, erase, kill, low, high] = *sgttyb;
+        let flags = u16::from_le_bytes([low, high]);
+        if let Some(&speed) = SPEEDS.get(ispeed as usize) {
+            self.ispeed = speed;
+        }
+        if let Some(&speed) = SPEEDS.get(ospeed as usize) {
+            self.ospeed = speed;
+        }
+        self.cc[VERASE] = erase;
+        self.cc[VKILL] = kill;
+
+        if flags & sgtty::RAW != 0 {
+            self.iflag &= !(ICRNL | IXON);
+            self.oflag &= !OPOST;
+            self.lflag &= !(ICANON | ISIG | IEXTEN);
+        } else {
+            self.oflag |= OPOST;
+            self.lflag |= ISIG | IEXTEN;
+            match flags & sgtty::CBREAK {
+                0 => self.lflag |= ICANON,
+                _ => self.lflag &= !ICANON,
+            }
+        }
+        if !self.is_canonical() {
+            self.cc[VMIN] = 1;
+            self.cc[VTIME] = 0;
+        }
+        if flags & sgtty::CRMOD != 0 {
+            self.iflag |= ICRNL;
+            self.oflag |= ONLCR;
+        } else {
+            self.iflag &= !ICRNL;
+            self.oflag &= !ONLCR;
+        }
+        match flags & sgtty::ECHO {
+            0 => self.lflag &= !ECHO,
+            _ => self.lflag |= ECHO,
+        }
+        match flags & sgtty::XTABS {
+            0 => self.oflag &= !XTABS,
+            _ => self.oflag |= XTABS,
+        }
+        self.cflag &= !(CSIZE | PARENB | PARODD);
+        self.cflag |= match flags & sgtty::BITS8 {
+            sgtty::BITS5 => CS5,
+            sgtty::BITS6 => CS6,
+            sgtty::BITS7 => CS7,
+            _ => CS8,
+        };
+        match flags & (sgtty::EVENP | sgtty::ODDP) {
+            sgtty::EVENP => self.cflag |= PARENB,
+            sgtty::ODDP => self.cflag |= PARENB | PARODD,
+            _ => {}
+        }
+    }
+
+    /// `struct tchars`: interrupt, quit, start, stop, end of file and break characters.
+    pub fn tchars(&self) -> [u8; 6] {
+        use termios::*;
+        [VINTR, VQUIT, VSTART, VSTOP, VEOF, VEOL].map(|index| self.cc[index])
+    }
+
+    pub fn set_tchars(&mut self, tchars: &[u8; 6]) {
+        use termios::*;
+        for (index, byte) in [VINTR, VQUIT, VSTART, VSTOP, VEOF, VEOL].iter().zip(tchars) {
+            self.cc[*index] = *byte;
+        }
+    }
+}
+
+/// The terminal of the process: its settings, the standard descriptors open on it,
+/// and the line discipline of the reads from the standard input.
+/// Without access to the host terminal settings, the input of a host terminal is
+/// still edited and echoed by the host, line by line.
+pub struct Tty {
+    pub termios: Termios,
+    /// Standard input, output and error open on the terminal, answering `isatty`
+    pub terminal: [bool; 3],
+    /// Bytes typed, or those of the redirected standard input
+    pub input: Box<dyn Read>,
+    /// Where the input is echoed, `None` when the host terminal echoes it
+    pub echo: Option<Box<dyn Write>>,
+    // Input processed but not read yet
+    queue: VecDeque<u8>,
+    // Line being edited, in canonical mode
+    line: Vec<u8>,
+}
+
+impl Default for Tty {
+    /// No terminal, reading the standard input of the host.
+    fn default() -> Self {
+        Tty {
+            termios: Termios::default(),
+            terminal: [false; 3],
+            input: Box::new(std::io::stdin()),
+            echo: None,
+            queue: VecDeque::new(),
+            line: vec![],
+        }
+    }
+}
+
+impl std::fmt::Debug for Tty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tty")
+            .field("termios", &self.termios)
+            .field("terminal", &self.terminal)
+            .field("queue", &self.queue)
+            .field("line", &self.line)
+            .finish()
+    }
+}
+
+/// Settings and input of the terminal not read yet, restored when moving
+/// back in a recording.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TtyState {
+    termios: Termios,
+    queue: VecDeque<u8>,
+    line: Vec<u8>,
+}
+
+impl Tty {
+    /// Terminal of the standard descriptors of the host open on one.
+    pub fn host() -> Self {
+        Tty {
+            terminal: [
+                std::io::stdin().is_terminal(),
+                std::io::stdout().is_terminal(),
+                std::io::stderr().is_terminal(),
+            ],
+            ..Tty::default()
+        }
+    }
+
+    pub fn state(&self) -> TtyState {
+        TtyState {
+            termios: self.termios,
+            queue: self.queue.clone(),
+            line: self.line.clone(),
+        }
+    }
+
+    /// Go back to the state, the bytes taken from `input` since being kept in it.
+    pub fn restore(&mut self, state: &TtyState) {
+        self.termios = state.termios;
+        self.queue.clone_from(&state.queue);
+        self.line.clone_from(&state.line);
+    }
+
+    /// Terminal typing the input, with the line discipline and the echo to `echo`.
+    pub fn with_input(input: impl Read + 'static, echo: impl Write + 'static) -> Self {
+        Tty {
+            terminal: [true; 3],
+            input: Box::new(input),
+            echo: Some(Box::new(echo)),
+            ..Tty::default()
+        }
+    }
+
+    pub fn is_terminal(&self, fd: i16) -> bool {
+        (0..3).contains(&fd) && self.terminal[fd as usize]
+    }
+
+    /// Handle a terminal ioctl on the descriptor, with the structure at `address`.
+    /// Return 0 or a negative errno.
+    pub fn ioctl(&mut self, data: &mut Memory, fd: i16, request: u16, address: u16) -> i16 {
+        if !(0..3).contains(&fd) {
+            return -errno::EBADF;
+        }
+        if !self.terminal[fd as usize] {
+            return -errno::ENOTTY;
+        }
+        let size = match request {
+            ioctl::TCGETS | ioctl::TCSETS | ioctl::TCSETSW | ioctl::TCSETSF => Termios::SIZE,
+            ioctl::TIOCGETP | ioctl::TIOCSETP | ioctl::TIOCGETC | ioctl::TIOCSETC => 6,
+            _ => return -errno::ENOTTY,
+        };
+        if address as usize + size > data.len() {
+            return -errno::EFAULT;
+        }
+        let bytes = |data: &Memory| {
+            let mut bytes = [0; 6];
+            bytes.copy_from_slice(data.read_bytes(address, 6));
+            bytes
+        };
+        match request {
+            ioctl::TCGETS => self.termios.write(data, address),
+            ioctl::TCSETS | ioctl::TCSETSW => self.termios = Termios::read(data, address),
+            ioctl::TCSETSF => {
+                self.termios = Termios::read(data, address);
+                self.flush();
+            }
+            ioctl::TIOCGETP => data.write_bytes(address, &self.termios.sgttyb()),
+            ioctl::TIOCSETP => {
+                self.termios.set_sgttyb(&bytes(data));
+                self.flush();
+            }
+            ioctl::TIOCGETC => data.write_bytes(address, &self.termios.tchars()),
+            _ => self.termios.set_tchars(&bytes(data)),
+        }
+        0
+    }
+
+    /// Read up to `nbytes` from the standard input: a line in canonical mode,
+    /// at least `VMIN` bytes otherwise, or what the host returns when it isn't a terminal.
+    /// Return the bytes, or a negative errno.
+    pub fn read(&mut self, nbytes: usize) -> Result<Vec<u8>, i16> {
+        if nbytes == 0 {
+            return Ok(vec![]);
+        }
+        if !self.terminal[0] {
+            let mut buffer = vec![0; nbytes];
+            let count = self.input.read(&mut buffer).map_err(|_| -errno::EIO)?;
+            buffer.truncate(count);
+            return Ok(buffer);
+        }
+        if self.termios.is_canonical() {
+            while self.queue.is_empty() {
+                let Some(byte) = self.next_input()? else {
+                    // End of the host input: the line typed, or end of file
+                    self.queue.extend(self.line.drain(..));
+                    break;
+                };
+                if self.edit(byte)? {
+                    return Ok(vec![]);
+                }
+            }
+        } else {
+            let min = (self.termios.cc[termios::VMIN] as usize).clamp(1, nbytes);
+            while self.queue.len() < min {
+                let Some(byte) = self.next_input()? else {
+                    break;
+                };
+                self.signal(byte)?;
+                if self.termios.lflag & termios::ECHO != 0 {
+                    self.echo(&[byte]);
+                }
+                self.queue.push_back(byte);
+            }
+        }
+        let count = nbytes.min(self.queue.len());
+        Ok(self.queue.drain(..count).collect())
+    }
+
+    /// Discard the input not read.
+    pub fn flush(&mut self) {
+        self.queue.clear();
+        self.line.clear();
+    }
+
+    /// Next input byte, after the input processing.
+    fn next_input(&mut self) -> Result<Option<u8>, i16> {
+        use termios::*;
+        loop {
+            let mut byte = [0];
+            match self.input.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => return Err(-errno::EIO),
+            }
+            let iflag = self.termios.iflag;
+            let byte = match byte[0] {
+                byte if iflag & ISTRIP != 0 => byte & 0x7f,
+                byte => byte,
+            };
+            return Ok(Some(match byte {
+                b'\r' if iflag & IGNCR != 0 => continue,
+                b'\r' if iflag & ICRNL != 0 => b'\n',
+                b'\n' if iflag & INLCR != 0 => b'\r',
+                byte => byte,
+            }));
+        }
+    }
+
+    /// Interrupt and quit characters discard the input and interrupt the read.
+    fn signal(&mut self, byte: u8) -> Result<(), i16> {
+        use termios::*;
+        if self.termios.lflag & ISIG != 0
+            && (self.termios.is_control(byte, VINTR) || self.termios.is_control(byte, VQUIT))
+        {
+            self.flush();
+            if self.termios.lflag & ECHO != 0 {
+                self.echo(&[byte, b'\n']);
+            }
+            return Err(-errno::EINTR);
+        }
+        Ok(())
+    }
+
+    /// Edit the line with the typed byte, and queue it when complete.
+    /// Return true on the end of file character at the start of a line.
+    fn edit(&mut self, byte: u8) -> Result<bool, i16> {
+        use termios::*;
+        self.signal(byte)?;
+        let lflag = self.termios.lflag;
+        let echo = lflag & ECHO != 0;
+        if self.termios.is_control(byte, VERASE) {
+            if self.line.pop().is_some() && echo && lflag & ECHOE != 0 {
+                self.echo(b"\x08 \x08");
+            }
+        } else if self.termios.is_control(byte, VKILL) {
+            self.line.clear();
+            if echo {
+                self.echo(&[byte]);
+                if lflag & ECHOK != 0 {
+                    self.echo(b"\n");
+                }
+            }
+        } else if self.termios.is_control(byte, VEOF) {
+            if self.line.is_empty() {
+                return Ok(true);
+            }
+            self.queue.extend(self.line.drain(..));
+        } else {
+            if echo || (byte == b'\n' && lflag & ECHONL != 0) {
+                self.echo(&[byte]);
+            }
+            self.line.push(byte);
+            if byte == b'\n' || self.termios.is_control(byte, VEOL) {
+                self.queue.extend(self.line.drain(..));
+            }
+        }
+        Ok(false)
+    }
+
+    /// Echo the bytes, with the control characters as `^X`.
+    fn echo(&mut self, bytes: &[u8]) {
+        let Some(output) = &mut self.echo else {
+            return;
+        };
+        let mut echoed = vec![];
+        for &byte in bytes {
+            match byte {
+                b'\n' | b'\t' | 0x08 | 0x20..=0x7e | 0x80.. => echoed.push(byte),
+                0x7f => echoed.extend(b"^?"),
+                _ => echoed.extend([b'^', byte + 0x40]),
+            }
+        }
+        // The echo is best effort, as on a terminal
+        let _ = output.write_all(&echoed).and_then(|_| output.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::SharedBuffer;
+
+    fn terminal(input: &'static [u8]) -> (Tty, SharedBuffer) {
+        let screen = SharedBuffer::default();
+        (Tty::with_input(input, screen.clone()), screen)
+    }
+
+    #[test]
+    fn test_canonical_read() {
+        let (mut tty, screen) = terminal(b"ab\x08c\rrest");
+        assert_eq!(tty.read(100), Ok(b"ac\n".to_vec()));
+        assert_eq!(tty.read(2), Ok(b"re".to_vec()));
+        // The line not ended by the host input
+        assert_eq!(tty.read(100), Ok(b"st".to_vec()));
+        assert_eq!(tty.read(100), Ok(vec![]));
+        assert_eq!(screen.bytes(), b"ab\x08 \x08c\nrest");
+
+        let (mut tty, screen) = terminal(b"x\x15y\x04\x04z\x7fw");
+        assert_eq!(tty.read(100), Ok(b"y".to_vec()));
+        assert_eq!(tty.read(100), Ok(vec![]));
+        assert_eq!(tty.read(100), Err(-errno::EINTR));
+        assert_eq!(tty.read(100), Ok(b"w".to_vec()));
+        assert_eq!(screen.bytes(), b"x^Uyz^?\nw");
+    }
+
+    #[test]
+    fn test_raw_and_cbreak() {
+        let mut data = Memory::new(0x100);
+        let (mut tty, screen) = terminal(b"a\rbc\x7f");
+        assert_eq!(tty.ioctl(&mut data, 0, ioctl::TIOCGETP, 0x10), 0);
+        assert_eq!(data.read_bytes(0x10, 4), &[13, 13, 0x08, 0x15]);
+        assert_eq!(
+            data.read_word(0x14),
+            sgtty::BITS8 | sgtty::CRMOD | sgtty::ECHO
+        );
+        data.write_word(0x14, sgtty::RAW);
+        assert_eq!(tty.ioctl(&mut data, 0, ioctl::TIOCSETP, 0x10), 0);
+        assert_eq!(tty.termios.lflag & termios::ISIG, 0);
+        assert_eq!(tty.read(1), Ok(b"a".to_vec()));
+        assert_eq!(tty.read(4), Ok(b"\r".to_vec()));
+
+        data.write_word(0x14, sgtty::CBREAK | sgtty::CRMOD | sgtty::ECHO);
+        assert_eq!(tty.ioctl(&mut data, 0, ioctl::TIOCSETP, 0x10), 0);
+        tty.termios.cc[termios::VMIN] = 2;
+        assert_eq!(tty.read(4), Ok(b"bc".to_vec()));
+        assert_eq!(tty.read(4), Err(-errno::EINTR));
+        assert_eq!(screen.bytes(), b"bc^?\n");
+    }
+
+    #[test]
+    fn test_termios_ioctls() {
+        let mut data = Memory::new(0x100);
+        let (mut tty, _) = terminal(b"");
+        assert_eq!(tty.ioctl(&mut data, 1, ioctl::TCGETS, 0x20), 0);
+        let mut termios = Termios::read(&data, 0x20);
+        assert_eq!(termios, Termios::default());
+        termios.lflag &= !(termios::ICANON | termios::ECHO);
+        termios.write(&mut data, 0x20);
+        assert_eq!(tty.ioctl(&mut data, 1, ioctl::TCSETSF, 0x20), 0);
+        assert!(!tty.termios.is_canonical());
+        // Through the old interface
+        assert_eq!(tty.ioctl(&mut data, 1, ioctl::TIOCGETP, 0x10), 0);
+        assert_eq!(
+            data.read_word(0x14),
+            sgtty::CBREAK | sgtty::CRMOD | sgtty::BITS8
+        );
+
+        assert_eq!(tty.ioctl(&mut data, 1, ioctl::TIOCGETC, 0x10), 0);
+        assert_eq!(
+            data.read_bytes(0x10, 6),
+            &[0x7f, 0x1c, 0x11, 0x13, 0x04, 0xff]
+        );
+        data.write(0x10, 0x03);
+        assert_eq!(tty.ioctl(&mut data, 1, ioctl::TIOCSETC, 0x10), 0);
+        assert_eq!(tty.termios.cc[termios::VINTR], 0x03);
+
+        assert_eq!(tty.ioctl(&mut data, 1, 0x7401, 0x10), -errno::ENOTTY);
+        assert_eq!(tty.ioctl(&mut data, 1, ioctl::TCGETS, 0xf0), -errno::EFAULT);
+        assert_eq!(tty.ioctl(&mut data, 3, ioctl::TCGETS, 0x20), -errno::EBADF);
+    }
+
+    #[test]
+    fn test_redirected() {
+        let mut data = Memory::new(0x100);
+        let mut tty = Tty {
+            input: Box::new(&b"a\x08b\r\n"[..]),
+            ..Tty::default()
+        };
+        assert!(!tty.is_terminal(0));
+        assert_eq!(tty.ioctl(&mut data, 0, ioctl::TCGETS, 0x20), -errno::ENOTTY);
+        // Read as is, without the line discipline
+        assert_eq!(tty.read(3), Ok(b"a\x08b".to_vec()));
+        assert_eq!(tty.read(10), Ok(b"\r\n".to_vec()));
+        assert_eq!(tty.read(10), Ok(vec![]));
+    }
+}
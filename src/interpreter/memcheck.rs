@@ -0,0 +1,471 @@
+use super::memory::{AccessKind, MemoryAccess};
+use super::snapshot::Snapshot;
+use super::vm::VM;
+use crate::minix::SymbolTable;
+use crate::x86::{Operand, Register, IR};
+
+/// Kind of error found by the memory checker.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemcheckError {
+    /// A conditional jump depends on uninitialized memory
+    UninitializedBranch,
+    /// A syscall reads uninitialized memory, in its message or its buffers
+    UninitializedSyscall { number: u16, address: u16 },
+    /// Access to memory released by lowering the break
+    PastBreak {
+        kind: AccessKind,
+        address: u16,
+        width: usize,
+    },
+    /// Access to memory between the heap and the stack
+    HeapStackGap {
+        kind: AccessKind,
+        address: u16,
+        width: usize,
+    },
+}
+
+impl std::fmt::Display for MemcheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let access = |kind: &AccessKind| match kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+        };
+        match self {
+            MemcheckError::UninitializedBranch => {
+                write!(f, "conditional jump depends on uninitialized value")
+            }
+            MemcheckError::UninitializedSyscall { number, address } => write!(
+                f,
+                "syscall {} reads uninitialized byte at {:04x}",
+                number, address
+            ),
+            MemcheckError::PastBreak {
+                kind,
+                address,
+                width,
+            } => write!(
+                f,
+                "invalid {} of size {} at {:04x}, past the break",
+                access(kind),
+                width,
+                address
+            ),
+            MemcheckError::HeapStackGap {
+                kind,
+                address,
+                width,
+            } => write!(
+                f,
+                "invalid {} of size {} at {:04x}, between the heap and the stack",
+                access(kind),
+                width,
+                address
+            ),
+        }
+    }
+}
+
+/// An error with the location it was found at.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MemcheckReport {
+    pub error: MemcheckError,
+    /// Address of the faulty instruction
+    pub ip: u16,
+    /// Return addresses of the calling frames, innermost first
+    pub backtrace: Vec<u16>,
+    /// Number of times the error was found at this instruction
+    pub count: usize,
+}
+
+impl MemcheckReport {
+    /// Describe the error, with function names from the symbol table.
+    pub fn describe(&self, symbols: &SymbolTable) -> String {
+        let name = |address: u16| match symbols.function_at(address) {
+            Some(symbol) => format!(" ({})", symbol.name),
+            None => String::new(),
+        };
+        let mut out = format!("{}\n", self.error);
+        out.push_str(&format!("    at {:04x}{}\n", self.ip, name(self.ip)));
+        for address in &self.backtrace {
+            out.push_str(&format!("    by {:04x}{}\n", address, name(*address)));
+        }
+        if self.count > 1 {
+            out.push_str(&format!("    ({} times)\n", self.count));
+        }
+        out
+    }
+}
+
+/// Shadow memory checker.
+///
+/// Tracks which bytes of the data memory and which registers hold initialized values,
+/// and reports uninitialized values reaching a conditional jump or a syscall,
+/// and accesses past the break or below the stack.
+/// Registers are tracked as whole words, and flags as a whole.
+pub struct Memcheck {
+    /// One entry per byte of data memory, true if initialized
+    pub shadow: Vec<bool>,
+    /// 16 bits registers, in `Register::iter_16` order
+    regs: [bool; 8],
+    flags: bool,
+    /// Current break, end of the heap
+    pub brk: u16,
+    /// Highest break set by the program
+    pub max_brk: u16,
+    pub reports: Vec<MemcheckReport>,
+}
+
+impl Memcheck {
+    /// Start checking a program whose data and bss segments end at `bss_end`.
+    /// The data and bss segments, and the stack above SP holding the arguments, are initialized.
+    pub fn new(vm: &VM, bss_end: u16) -> Self {
+        let sp = vm.regs.get(Register::SP) as usize;
+        let shadow = (0..vm.data.len())
+            .map(|address| address < bss_end as usize || address >= sp)
+            .collect();
+        Memcheck {
+            shadow,
+            regs: [true; 8],
+            flags: true,
+            brk: bss_end,
+            max_brk: bss_end,
+            reports: Vec::new(),
+        }
+    }
+
+    /// Return true if all bytes of the range are initialized.
+    pub fn is_initialized(&self, address: u16, width: usize) -> bool {
+        (0..width).all(|i| {
+            self.shadow
+                .get(address as usize + i)
+                .copied()
+                .unwrap_or(true)
+        })
+    }
+
+    /// Check the instruction executed from `snapshot`, and update the shadow state.
+    pub fn check(&mut self, snapshot: &Snapshot, vm: &VM, accesses: &[MemoryAccess]) {
+        let Some(instruction) = &snapshot.instruction else {
+            return;
+        };
+        let ir = &instruction.ir;
+
+        // Accesses outside of the heap and the stack
+        let stack = snapshot.sp().min(vm.regs.get(Register::SP));
+        for access in accesses {
+            if access.address < self.brk || access.address >= stack {
+                continue;
+            }
+            let (kind, address, width) = (access.kind, access.address, access.width());
+            let error = if address < self.max_brk {
+                MemcheckError::PastBreak {
+                    kind,
+                    address,
+                    width,
+                }
+            } else {
+                MemcheckError::HeapStackGap {
+                    kind,
+                    address,
+                    width,
+                }
+            };
+            self.report(error, snapshot.ip, vm);
+        }
+
+        if let IR::Int { int_type: 0x20 } = ir {
+            self.check_syscall(snapshot, vm, accesses);
+            return;
+        }
+
+        // Conditional jumps, the flags and CX are not changed by the jump itself
+        let branch_defined = match ir {
+            IR::Jcxz { .. } | IR::Loop { .. } => self.reg(Register::CX),
+            IR::Loopz { .. } | IR::Loopnz { .. } => self.reg(Register::CX) && self.flags,
+            ir if ir.is_conditional_jump() => self.flags,
+            _ => true,
+        };
+        if !branch_defined {
+            self.report(MemcheckError::UninitializedBranch, snapshot.ip, vm);
+        }
+
+        self.propagate(ir, accesses);
+    }
+
+    /// Update the shadow registers, flags and memory for the executed instruction.
+    fn propagate(&mut self, ir: &IR, accesses: &[MemoryAccess]) {
+        let reads_defined = accesses
+            .iter()
+            .filter(|a| a.kind == AccessKind::Read)
+            .all(|a| self.is_initialized(a.address, a.width()));
+        let operand = |memcheck: &Memcheck, op: &Operand| match op {
+            Operand::Register(r) => memcheck.reg(*r),
+            // Memory operands are checked from the reads
+            _ => true,
+        };
+
+        // Definedness of the result, written to the destination
+        let (value, dest, sets_flags) = match ir {
+            IR::Mov { dest, src, .. } => (operand(self, src) && reads_defined, Some(dest), false),
+            // Idioms clearing a register
+            IR::Xor { dest, src } | IR::Sub { dest, src }
+                if matches!(dest, Operand::Register(_)) && dest == src =>
+            {
+                (true, Some(dest), true)
+            }
+            IR::Add { dest, src }
+            | IR::Sub { dest, src }
+            | IR::Xor { dest, src }
+            | IR::Or { dest, src }
+            | IR::And { dest, src }
+            | IR::Shl { dest, src }
+            | IR::Sar { dest, src } => (
+                operand(self, dest) && operand(self, src) && reads_defined,
+                Some(dest),
+                true,
+            ),
+            IR::Cmp { dest, src, .. } | IR::Test { dest, src, .. } => (
+                operand(self, dest) && operand(self, src) && reads_defined,
+                None,
+                true,
+            ),
+            IR::Inc { dest } | IR::Dec { dest } | IR::Neg { dest } => {
+                (operand(self, dest) && reads_defined, Some(dest), true)
+            }
+            IR::Lea { dest, src } => {
+                let address = match src {
+                    Operand::MemoryAddress(a) => {
+                        a.base.map_or(true, |r| self.reg(r))
+                            && a.index.map_or(true, |r| self.reg(r))
+                    }
+                    _ => true,
+                };
+                (address, Some(dest), false)
+            }
+            IR::Push { src } => (operand(self, src) && reads_defined, None, false),
+            IR::Pop { dest } => (reads_defined, Some(dest), false),
+            IR::In { dest, .. } => (true, Some(dest), false),
+            IR::Cbw => {
+                let ax = self.reg(Register::AX);
+                self.set_reg(Register::AX, ax);
+                (ax, None, false)
+            }
+            IR::Cwd => {
+                let ax = self.reg(Register::AX);
+                self.set_reg(Register::DX, ax);
+                (ax, None, false)
+            }
+            IR::Div { dest } => {
+                let value = operand(self, dest)
+                    && self.reg(Register::AX)
+                    && self.reg(Register::DX)
+                    && reads_defined;
+                self.set_reg(Register::AX, value);
+                self.set_reg(Register::DX, value);
+                (value, None, false)
+            }
+            IR::Xchg { dest, src } => {
+                let (a, b) = (operand(self, dest), operand(self, src));
+                // The memory side of the exchange is read before being written
+                let (a, b) = match (dest, src) {
+                    (Operand::MemoryAddress(_), _) => (reads_defined, b),
+                    (_, Operand::MemoryAddress(_)) => (a, reads_defined),
+                    _ => (a, b),
+                };
+                if let Operand::Register(r) = dest {
+                    self.set_reg(*r, b);
+                }
+                if let Operand::Register(r) = src {
+                    self.set_reg(*r, a);
+                }
+                let written = if matches!(dest, Operand::MemoryAddress(_)) {
+                    b
+                } else {
+                    a
+                };
+                (written, None, false)
+            }
+            // Return address
+            IR::Call { .. } => (true, None, false),
+            _ => (true, None, false),
+        };
+
+        if let Some(Operand::Register(r)) = dest {
+            self.set_reg(*r, value);
+        }
+        if sets_flags {
+            self.flags = value;
+        }
+        for access in accesses.iter().filter(|a| a.kind == AccessKind::Write) {
+            self.set_initialized(access.address, access.width(), value);
+        }
+    }
+
+    fn check_syscall(&mut self, snapshot: &Snapshot, vm: &VM, accesses: &[MemoryAccess]) {
+        let syscall = snapshot.syscall(vm);
+        let number = syscall.map_or(0, |s| s.number);
+        let uninitialized = accesses
+            .iter()
+            .filter(|a| a.kind == AccessKind::Read)
+            .flat_map(|a| (0..a.width() as u16).map(move |i| a.address.wrapping_add(i)))
+            .find(|address| !self.is_initialized(*address, 1));
+        if let Some(address) = uninitialized {
+            self.report(
+                MemcheckError::UninitializedSyscall { number, address },
+                snapshot.ip,
+                vm,
+            );
+        }
+
+        // Replies are initialized
+        for access in accesses.iter().filter(|a| a.kind == AccessKind::Write) {
+            self.set_initialized(access.address, access.width(), true);
+        }
+        // AX is set by the syscall
+        self.set_reg(Register::AX, true);
+
+        if let Some(brk) = snapshot.new_break(vm) {
+            self.set_break(brk);
+        }
+    }
+
+    /// Move the break, memory past the new break is no longer initialized.
+    pub fn set_break(&mut self, brk: u16) {
+        if brk < self.brk {
+            self.set_initialized(brk, (self.brk - brk) as usize, false);
+        }
+        self.brk = brk;
+        self.max_brk = self.max_brk.max(brk);
+    }
+
+    fn report(&mut self, error: MemcheckError, ip: u16, vm: &VM) {
+        if let Some(report) = self
+            .reports
+            .iter_mut()
+            .find(|r| r.ip == ip && r.error == error)
+        {
+            report.count += 1;
+            return;
+        }
+        // The IP of the VM is already past the instruction, only keep the callers
+        let backtrace = vm.backtrace().into_iter().skip(1).collect();
+        self.reports.push(MemcheckReport {
+            error,
+            ip,
+            backtrace,
+            count: 1,
+        });
+    }
+
+    fn reg(&self, reg: Register) -> bool {
+        let reg = reg.to_word_register();
+        Register::iter_16()
+            .position(|r| r == reg)
+            .map_or(true, |i| self.regs[i])
+    }
+
+    fn set_reg(&mut self, reg: Register, initialized: bool) {
+        let reg = reg.to_word_register();
+        if let Some(i) = Register::iter_16().position(|r| r == reg) {
+            self.regs[i] = initialized;
+        }
+    }
+
+    fn set_initialized(&mut self, address: u16, width: usize, initialized: bool) {
+        for i in 0..width {
+            if let Some(byte) = self.shadow.get_mut(address as usize + i) {
+                *byte = initialized;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::{StepOutcome, VmIrExecutable};
+    use crate::test_utils::{program, vm_with_text};
+
+    fn checked_vm(text: &[u8]) -> VM {
+        let mut vm = vm_with_text(text);
+        vm.memcheck = Some(Memcheck::new(&vm, 0x0100));
+        vm
+    }
+
+    fn run(vm: &mut VM) {
+        while vm.step() == Ok(StepOutcome::Running) {}
+    }
+
+    #[test]
+    fn test_uninitialized_branch() {
+        let mut vm = checked_vm(&[
+            0x55, // push bp
+            0x89, 0xe5, // mov bp, sp
+            0x83, 0xec, 0x02, // sub sp, 2
+            0x8b, 0x46, 0xfe, // mov ax, [bp-2]
+            0x3d, 0x00, 0x00, // cmp ax, 0
+            0x74, 0x00, // je +0
+            0x31, 0xc0, // xor ax, ax
+            0x3d, 0x00, 0x00, // cmp ax, 0
+            0x74, 0x00, // je +0
+            0xf4, // hlt
+        ]);
+        run(&mut vm);
+
+        let reports = &vm.memcheck.unwrap().reports;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].error, MemcheckError::UninitializedBranch);
+        assert_eq!(reports[0].ip, 0x000c);
+    }
+
+    #[test]
+    fn test_invalid_accesses() {
+        let mut vm = checked_vm(&[
+            0x8b, 0x06, 0x80, 0x00, // mov ax, [0080]
+            0x8b, 0x06, 0x00, 0x02, // mov ax, [0200]
+            0x89, 0x06, 0xf0, 0x0e, // mov [0ef0], ax
+            0xf4, // hlt
+        ]);
+        vm.memcheck.as_mut().unwrap().set_break(0x0400);
+        vm.memcheck.as_mut().unwrap().set_break(0x0180);
+        run(&mut vm);
+
+        let errors: Vec<MemcheckError> = vm
+            .memcheck
+            .unwrap()
+            .reports
+            .iter()
+            .map(|r| r.error)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                MemcheckError::PastBreak {
+                    kind: AccessKind::Read,
+                    address: 0x0200,
+                    width: 2
+                },
+                MemcheckError::HeapStackGap {
+                    kind: AccessKind::Write,
+                    address: 0x0ef0,
+                    width: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clean_program() {
+        let program = program("4.c");
+        let bss_end = (program.header.data + program.header.bss) as u16;
+        let mut vm = VM::from(program);
+        vm.set_args(vec!["4.c.out".to_string()]);
+        vm.memcheck = Some(Memcheck::new(&vm, bss_end));
+        vm.run().unwrap();
+
+        let memcheck = vm.memcheck.unwrap();
+        assert_eq!(memcheck.reports, vec![]);
+        assert!(memcheck.brk > bss_end);
+    }
+}
@@ -1,5 +1,55 @@
+use std::cell::RefCell;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A single read or write of the memory.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub address: u16,
+    /// Bytes in memory before the access
+    pub before: Vec<u8>,
+    /// Bytes in memory after the access, same as `before` for reads
+    pub after: Vec<u8>,
+}
+
+impl MemoryAccess {
+    pub fn width(&self) -> usize {
+        self.after.len()
+    }
+
+    /// Value read or written, as a little endian byte or word.
+    pub fn value(&self) -> u16 {
+        word_value(&self.after)
+    }
+
+    /// Value in memory before the access, as a little endian byte or word.
+    pub fn old_value(&self) -> u16 {
+        word_value(&self.before)
+    }
+
+    /// Return true if the access touches the given address.
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.address && (address as usize) < self.address as usize + self.width()
+    }
+}
+
+fn word_value(bytes: &[u8]) -> u16 {
+    match bytes {
+        [] => 0,
+        [b] => *b as u16,
+        [lo, hi, ..] => u16::from_le_bytes([*lo, *hi]),
+    }
+}
+
 pub struct Memory {
     pub data: Vec<u8>,
+    // Every access made while logging is enabled
+    log: RefCell<Option<Vec<MemoryAccess>>>,
 }
 
 #[allow(dead_code)]
@@ -7,43 +57,153 @@ impl Memory {
     pub fn new(size: usize) -> Self {
         Memory {
             data: vec![0; size],
+            log: RefCell::new(None),
         }
     }
 
     pub fn from(data: Vec<u8>) -> Self {
-        Memory { data }
+        Memory {
+            data,
+            log: RefCell::new(None),
+        }
     }
 
     pub fn read(&self, address: u16) -> u8 {
+        self.log_read(address, 1);
         self.data[address as usize]
     }
 
     pub fn read_word(&self, address: u16) -> u16 {
+        self.log_read(address, 2);
+        u16::from_le_bytes([
+            self.data[address as usize],
+            self.data[(address + 1) as usize],
+        ])
+    }
+
+    /// Read a word without logging the access.
+    pub fn peek_word(&self, address: u16) -> u16 {
         u16::from_le_bytes([
             self.data[address as usize],
             self.data[(address + 1) as usize],
         ])
     }
 
+    /// Read bytes without logging the access, truncated at the end of the memory.
+    pub fn peek_bytes(&self, address: u16, size: usize) -> &[u8] {
+        let start = (address as usize).min(self.data.len());
+        let end = (address as usize + size).min(self.data.len());
+        &self.data[start..end]
+    }
+
     pub fn read_bytes(&self, address: u16, size: usize) -> &[u8] {
+        self.log_read(address, size);
         &self.data[address as usize..(address as usize + size)]
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
+        self.log_write(address, &[value]);
         self.data[address as usize] = value;
     }
 
     pub fn write_word(&mut self, address: u16, value: u16) {
         let bytes = value.to_le_bytes();
+        self.log_write(address, &bytes);
         self.data[address as usize] = bytes[0];
         self.data[(address + 1) as usize] = bytes[1];
     }
 
     pub fn write_bytes(&mut self, address: u16, data: &[u8]) {
+        self.log_write(address, data);
         self.data[address as usize..(address as usize + data.len())].copy_from_slice(data);
     }
 
     pub fn len(&self) -> usize {
         self.data.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Start logging every read and write.
+    pub fn start_log(&mut self) {
+        self.log.replace(Some(Vec::new()));
+    }
+
+    /// Stop logging and return the accesses, in order.
+    pub fn take_log(&mut self) -> Vec<MemoryAccess> {
+        self.log.take().unwrap_or_default()
+    }
+
+    fn log_read(&self, address: u16, size: usize) {
+        if let Some(log) = self.log.borrow_mut().as_mut() {
+            let bytes = self.bytes_at(address, size);
+            log.push(MemoryAccess {
+                kind: AccessKind::Read,
+                address,
+                before: bytes.clone(),
+                after: bytes,
+            });
+        }
+    }
+
+    fn log_write(&self, address: u16, data: &[u8]) {
+        if let Some(log) = self.log.borrow_mut().as_mut() {
+            log.push(MemoryAccess {
+                kind: AccessKind::Write,
+                address,
+                before: self.bytes_at(address, data.len()),
+                after: data.to_vec(),
+            });
+        }
+    }
+
+    fn bytes_at(&self, address: u16, size: usize) -> Vec<u8> {
+        let start = (address as usize).min(self.data.len());
+        let end = (address as usize + size).min(self.data.len());
+        self.data[start..end].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_records_accesses() {
+        let mut memory = Memory::new(0x10);
+        memory.write(0, 0x42);
+
+        memory.start_log();
+        memory.write_word(0, 0x1234);
+        assert_eq!(memory.read(4), 0x00);
+        let log = memory.take_log();
+
+        assert_eq!(
+            log,
+            vec![
+                MemoryAccess {
+                    kind: AccessKind::Write,
+                    address: 0,
+                    before: vec![0x42, 0x00],
+                    after: vec![0x34, 0x12],
+                },
+                MemoryAccess {
+                    kind: AccessKind::Read,
+                    address: 4,
+                    before: vec![0x00],
+                    after: vec![0x00],
+                },
+            ]
+        );
+        assert_eq!(log[0].value(), 0x1234);
+        assert_eq!(log[0].old_value(), 0x0042);
+        assert!(log[0].contains(1));
+        assert!(!log[0].contains(2));
+
+        // Log is disabled once taken
+        memory.write(5, 0x01);
+        assert!(memory.take_log().is_empty());
+    }
 }
@@ -1,12 +1,71 @@
+mod coverage;
+mod debugger;
 mod error;
 mod flag_set;
+mod hooks;
+mod ipc;
+mod memcheck;
 mod memory;
+mod profiler;
+mod recorder;
 mod register_set;
+mod shadow_stack;
+mod snapshot;
+mod strace;
+mod taint;
+mod tracer;
 mod vm;
+mod watchpoint;
 
 /// This trait can be used to interpret a given program binary.
 pub use vm::Interpretable;
 
+// Virtual machine
+pub use error::{InterpreterError, OpcodeExecErrors};
+pub use flag_set::{Flag, FlagSet};
+pub use memory::{AccessKind, Memory, MemoryAccess};
+pub use register_set::RegisterSet;
+pub use vm::{StepOutcome, VmIrExecutable, VM};
+
+// Reverse execution
+pub use debugger::Debugger;
+pub use recorder::{Recorder, Reversible, StepDelta};
+pub use snapshot::SyscallRecord;
+
+// Structured traces
+pub use tracer::{read_binary_trace, TraceFilter, TraceFormat, TraceRecord, Tracer};
+
+// Code coverage
+pub use coverage::{Coverage, CoverageLine};
+
+// Profiling
+pub use profiler::{cycles, ProfileMode, Profiler};
+
+// Watchpoints
+pub use watchpoint::{WatchHit, WatchKind, Watchpoint, Watchpoints};
+
+// Memory checker
+pub use memcheck::{Memcheck, MemcheckError, MemcheckReport};
+
+// Shadow call stack
+pub use shadow_stack::{ShadowFrame, ShadowStack, StackError, StackReport};
+
+// Kernel and servers
+pub use ipc::{
+    ioctl, sgtty, termios, FileSystem, Ipc, MemoryManager, Server, ServerState, SyscallHandler,
+    SyscallPolicy, SyscallTable, Termios, Tty, TtyState, UnknownSyscall,
+};
+pub(crate) use ipc::{BOTH, FS, MM};
+
+// Syscall trace
+pub use strace::{errno_name, Strace};
+
+// Embedding callbacks
+pub use hooks::{HookAction, Hooks};
+
+// Taint tracking
+pub use taint::{Taint, TaintLabel, TaintReport, TaintSink, TaintSource};
+
 // Cli interface
 pub use vm::vm_interpret;
 
@@ -0,0 +1,412 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::vm::VM;
+use crate::minix::SymbolTable;
+use crate::x86::{Operand, IR};
+
+/// What the profiler counts.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ProfileMode {
+    /// Each executed instruction counts as 1
+    Instructions,
+    /// Each executed instruction counts as its estimated 8086 cycles, see `cycles`
+    Cycles,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Frame {
+    /// Entry of the function, or an address inside it for frames recovered from the BP chain
+    function: u16,
+    /// Address the function returns to, `None` for the outermost frame
+    return_address: Option<u16>,
+}
+
+/// Attributes executed instructions to the functions of the call stack.
+///
+/// The call stack is tracked by the `call` and `ret` instructions.
+/// A sample of the call stack is taken every `period` instructions (or cycles),
+/// with `period` 1 the profile is exact.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    pub mode: ProfileMode,
+    pub period: u64,
+    stack: Vec<Frame>,
+    // instructions or cycles executed since the last sample
+    elapsed: u64,
+    /// Samples of each call stack, outermost function first
+    pub samples: BTreeMap<Vec<u16>, u64>,
+    /// Number of calls from a function (first) to another (second)
+    pub calls: BTreeMap<(u16, u16), u64>,
+}
+
+impl Profiler {
+    /// Start profiling at the current state of the VM.
+    /// Frames already on the stack are recovered by following the BP chain.
+    pub fn new(mode: ProfileMode, period: u64, vm: &VM) -> Self {
+        Profiler {
+            mode,
+            period: period.max(1),
+            stack: bp_chain(vm),
+            elapsed: 0,
+            samples: BTreeMap::new(),
+            calls: BTreeMap::new(),
+        }
+    }
+
+    /// Current call stack, as function addresses, outermost first.
+    pub fn stack(&self) -> Vec<u16> {
+        self.stack.iter().map(|f| f.function).collect()
+    }
+
+    /// Account for the instruction about to be executed by the current function.
+    pub fn tick(&mut self, ir: &IR) {
+        self.elapsed += match self.mode {
+            ProfileMode::Instructions => 1,
+            ProfileMode::Cycles => cycles(ir),
+        };
+        if self.elapsed >= self.period {
+            let count = self.elapsed / self.period;
+            self.elapsed %= self.period;
+            *self.samples.entry(self.stack()).or_insert(0) += count;
+        }
+    }
+
+    /// A `call` to `target`, which will return to `return_address`.
+    pub fn call(&mut self, target: u16, return_address: u16) {
+        if let Some(caller) = self.stack.last() {
+            *self.calls.entry((caller.function, target)).or_insert(0) += 1;
+        }
+        self.stack.push(Frame {
+            function: target,
+            return_address: Some(return_address),
+        });
+    }
+
+    /// A `ret` to `address`.
+    /// Frames are dropped up to the one returning to `address`, so that a `ret`
+    /// used as a jump or skipped frames do not corrupt the stack.
+    pub fn ret(&mut self, address: u16) {
+        if let Some(index) = self
+            .stack
+            .iter()
+            .rposition(|f| f.return_address == Some(address))
+        {
+            self.stack.truncate(index);
+        }
+    }
+
+    /// Samples whose leaf is each function.
+    pub fn self_samples(&self) -> BTreeMap<u16, u64> {
+        let mut result = BTreeMap::new();
+        for (stack, count) in &self.samples {
+            if let Some(leaf) = stack.last() {
+                *result.entry(*leaf).or_insert(0) += count;
+            }
+        }
+        result
+    }
+
+    /// Samples with each function on the stack, counted once for recursive calls.
+    pub fn total_samples(&self) -> BTreeMap<u16, u64> {
+        let mut result = BTreeMap::new();
+        for (stack, count) in &self.samples {
+            for function in stack.iter().collect::<BTreeSet<_>>() {
+                *result.entry(*function).or_insert(0) += count;
+            }
+        }
+        result
+    }
+
+    /// Flat profile, sorted by self samples.
+    pub fn flat(&self, symbols: &SymbolTable) -> String {
+        let self_samples = self.self_samples();
+        let total_samples = self.total_samples();
+        let all: u64 = self.samples.values().sum();
+
+        let mut functions: Vec<u16> = total_samples.keys().copied().collect();
+        functions.sort_by_key(|f| std::cmp::Reverse(self_samples.get(f).copied().unwrap_or(0)));
+
+        let mut out = format!(
+            "{:>7} {:>10} {:>10} {:>7}  {}\n",
+            "%self", "self", "total", "calls", "function"
+        );
+        for function in functions {
+            let self_count = self_samples.get(&function).copied().unwrap_or(0);
+            out.push_str(&format!(
+                "{:>7.2} {:>10} {:>10} {:>7}  {}\n",
+                percent(self_count, all),
+                self_count,
+                total_samples[&function],
+                self.calls_to(function),
+                function_name(function, symbols)
+            ));
+        }
+        out
+    }
+
+    /// Callers and callees of each function, with the number of calls
+    /// and the samples spent in the callee when called from the caller.
+    pub fn call_graph(&self, symbols: &SymbolTable) -> String {
+        // Samples of the callee (second) when called by the caller (first)
+        let mut edges: BTreeMap<(u16, u16), u64> = BTreeMap::new();
+        for (stack, count) in &self.samples {
+            let pairs: BTreeSet<(u16, u16)> = stack.windows(2).map(|w| (w[0], w[1])).collect();
+            for pair in pairs {
+                *edges.entry(pair).or_insert(0) += count;
+            }
+        }
+
+        let self_samples = self.self_samples();
+        let mut out = String::new();
+        for (function, total) in self.total_samples() {
+            out.push_str(&format!(
+                "{} [self {}, total {}, calls {}]\n",
+                function_name(function, symbols),
+                self_samples.get(&function).copied().unwrap_or(0),
+                total,
+                self.calls_to(function)
+            ));
+            for ((caller, _), count) in self.calls.iter().filter(|((_, f), _)| *f == function) {
+                out.push_str(&format!(
+                    "    <- {} [calls {}, samples {}]\n",
+                    function_name(*caller, symbols),
+                    count,
+                    edges.get(&(*caller, function)).copied().unwrap_or(0)
+                ));
+            }
+            for ((_, callee), count) in self.calls.iter().filter(|((f, _), _)| *f == function) {
+                out.push_str(&format!(
+                    "    -> {} [calls {}, samples {}]\n",
+                    function_name(*callee, symbols),
+                    count,
+                    edges.get(&(function, *callee)).copied().unwrap_or(0)
+                ));
+            }
+        }
+        out
+    }
+
+    /// Folded stacks, one `outer;inner count` line per stack, as read by flamegraph tools.
+    pub fn folded(&self, symbols: &SymbolTable) -> String {
+        self.samples
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<String> = stack.iter().map(|f| function_name(*f, symbols)).collect();
+                format!("{} {}\n", names.join(";"), count)
+            })
+            .collect::<Vec<String>>()
+            .concat()
+    }
+
+    fn calls_to(&self, function: u16) -> u64 {
+        self.calls
+            .iter()
+            .filter(|((_, callee), _)| *callee == function)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Frames of the VM stack, recovered from the BP chain, outermost first.
+fn bp_chain(vm: &VM) -> Vec<Frame> {
+    let addresses = vm.backtrace();
+
+    // Each frame returns into the next one
+    let mut frames: Vec<Frame> = addresses
+        .windows(2)
+        .map(|w| Frame {
+            function: w[0],
+            return_address: Some(w[1]),
+        })
+        .collect();
+    frames.push(Frame {
+        function: *addresses.last().unwrap_or(&0),
+        return_address: None,
+    });
+    frames.reverse();
+    frames
+}
+
+/// Name of the function at the given address, called functions can be local labels.
+fn function_name(address: u16, symbols: &SymbolTable) -> String {
+    match symbols
+        .label_at(address)
+        .or_else(|| symbols.function_at(address))
+    {
+        Some(symbol) => symbol.name.clone(),
+        None => format!("sub_{:04x}", address),
+    }
+}
+
+fn percent(count: u64, all: u64) -> f64 {
+    match all {
+        0 => 0.0,
+        _ => count as f64 * 100.0 / all as f64,
+    }
+}
+
+/// Estimated 8086 clock cycles of an instruction, from the base timings of the Intel manual.
+/// Effective address computation is counted as a fixed cost, and conditional jumps
+/// as the average of taken and not taken.
+pub fn cycles(ir: &IR) -> u64 {
+    const EA: u64 = 7;
+    let memory = |op: &Operand| matches!(op, Operand::MemoryAddress(_));
+    match ir {
+        IR::Mov { dest, src, .. } => match (memory(dest), memory(src)) {
+            (false, false) => 2,
+            _ => 8 + EA,
+        },
+        IR::Add { dest, src }
+        | IR::Adc { dest, src }
+        | IR::Sub { dest, src }
+        | IR::Ssb { dest, src }
+        | IR::And { dest, src }
+        | IR::Or { dest, src }
+        | IR::Xor { dest, src } => match (memory(dest), memory(src)) {
+            (true, _) => 16 + EA,
+            (_, true) => 9 + EA,
+            _ => 3,
+        },
+        IR::Cmp { dest, src, .. } | IR::Test { dest, src, .. } => {
+            if memory(dest) || memory(src) {
+                9 + EA
+            } else {
+                3
+            }
+        }
+        IR::Inc { dest } | IR::Dec { dest } | IR::Neg { dest } | IR::Not { dest } => {
+            if memory(dest) {
+                15 + EA
+            } else {
+                3
+            }
+        }
+        IR::Shl { dest, .. }
+        | IR::Shr { dest, .. }
+        | IR::Sar { dest, .. }
+        | IR::Rol { dest, .. }
+        | IR::Ror { dest, .. }
+        | IR::Rcl { dest, .. }
+        | IR::Rcr { dest, .. } => {
+            if memory(dest) {
+                15 + EA
+            } else {
+                2
+            }
+        }
+        IR::Xchg { dest, src } => {
+            if memory(dest) || memory(src) {
+                17 + EA
+            } else {
+                4
+            }
+        }
+        IR::Lea { .. } => 2 + EA,
+        IR::Push { src } => {
+            if memory(src) {
+                16 + EA
+            } else {
+                11
+            }
+        }
+        IR::Pop { dest } => {
+            if memory(dest) {
+                17 + EA
+            } else {
+                8
+            }
+        }
+        IR::Mul { .. } => 118,
+        IR::Imul { .. } => 128,
+        IR::Div { .. } => 144,
+        IR::Idiv { .. } => 165,
+        IR::Cbw => 2,
+        IR::Cwd => 5,
+        IR::Call { .. } => 19,
+        IR::Ret { src: None } => 8,
+        IR::Ret { src: Some(_) } => 12,
+        IR::Jmp { .. } => 15,
+        IR::Loop { .. } | IR::Loopz { .. } | IR::Loopnz { .. } | IR::Jcxz { .. } => 12,
+        ir if ir.is_conditional_jump() => 10,
+        IR::Int { .. } => 51,
+        IR::In { .. } | IR::Out { .. } => 10,
+        IR::Hlt => 2,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::VmIrExecutable;
+    use crate::test_utils::program;
+    use crate::x86::Register;
+
+    fn profile(file: &str, mode: ProfileMode, period: u64) -> (Profiler, SymbolTable) {
+        let program = program(file);
+        let symbols = program.symbols.clone();
+
+        let mut vm = VM::from(program);
+        vm.profiler = Some(Profiler::new(mode, period, &vm));
+        vm.run().unwrap();
+        (vm.profiler.unwrap(), symbols)
+    }
+
+    #[test]
+    fn test_profile_instructions() {
+        let (profiler, symbols) = profile("1.c", ProfileMode::Instructions, 1);
+
+        // crtso -> _main -> _write -> __sendre
+        assert_eq!(profiler.calls[&(0x0000, 0x0039)], 1);
+        assert_eq!(profiler.calls[&(0x0039, 0x008c)], 1);
+        assert_eq!(profiler.self_samples()[&0x0000], 23);
+
+        let total = profiler.total_samples();
+        assert_eq!(total[&0x0000], profiler.samples.values().sum::<u64>());
+        assert!(total[&0x0039] > total[&0x008c]);
+
+        let folded = profiler.folded(&symbols);
+        assert!(folded.starts_with("crtso 23\n"));
+        assert!(folded.contains("crtso;_main;_write "));
+
+        let flat = profiler.flat(&symbols);
+        assert!(flat.starts_with("  %self       self      total   calls  function\n"));
+        assert!(flat.contains("  _main\n"));
+
+        let graph = profiler.call_graph(&symbols);
+        assert!(graph.contains("_main [self "));
+        assert!(graph.contains("    <- crtso [calls 1, samples "));
+        assert!(graph.contains("    -> _write [calls 1, samples "));
+    }
+
+    #[test]
+    fn test_profile_sampling() {
+        let (exact, _) = profile("1.c", ProfileMode::Cycles, 1);
+        let (sampled, _) = profile("1.c", ProfileMode::Cycles, 100);
+
+        let exact_cycles: u64 = exact.samples.values().sum();
+        let samples: u64 = sampled.samples.values().sum();
+        assert_eq!(samples, exact_cycles / 100);
+    }
+
+    #[test]
+    fn test_bp_chain() {
+        let mut vm = VM {
+            ip: 0x0150,
+            ..VM::default()
+        };
+        // Frame of the current function, saved BP of the caller and return address
+        vm.regs.set(Register::BP, 0x0f00);
+        vm.data.write_word(0x0f00, 0x0f10);
+        vm.data.write_word(0x0f02, 0x0120);
+        // Frame of the caller, outermost
+        vm.data.write_word(0x0f10, 0x0000);
+        vm.data.write_word(0x0f12, 0x0034);
+
+        let mut profiler = Profiler::new(ProfileMode::Instructions, 1, &vm);
+        assert_eq!(profiler.stack(), vec![0x0034, 0x0120, 0x0150]);
+
+        profiler.ret(0x0120);
+        assert_eq!(profiler.stack(), vec![0x0034, 0x0120]);
+    }
+}
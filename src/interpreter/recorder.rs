@@ -0,0 +1,348 @@
+use std::collections::BTreeSet;
+
+use super::error::InterpreterError;
+use super::flag_set::Flag;
+use super::ipc::ServerState;
+use super::memory::{AccessKind, MemoryAccess};
+use super::snapshot::{Snapshot, SyscallRecord};
+use super::vm::{StepOutcome, VmIrExecutable, VM};
+use crate::x86::Register;
+
+/// State changes made by a single instruction.
+/// Every change holds both the old and the new value, so a delta can be undone and redone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StepDelta {
+    pub ip: (u16, u16),
+    pub regs: Vec<(Register, u16, u16)>,
+    pub flags: Vec<(Flag, bool, bool)>,
+    pub memory: Vec<(u16, u8, u8)>,
+    /// Reads and writes of the data memory, seen again by the watchpoints on replay
+    pub accesses: Vec<MemoryAccess>,
+    pub syscall: Option<SyscallRecord>,
+    /// State of the servers before and after a syscall
+    pub servers: Option<(ServerState, ServerState)>,
+    pub outcome: StepOutcome,
+}
+
+impl StepDelta {
+    /// Compare the snapshot taken before the instruction with the current state of the VM.
+    pub(super) fn new(
+        snapshot: &Snapshot,
+        vm: &VM,
+        accesses: &[MemoryAccess],
+        outcome: StepOutcome,
+    ) -> Self {
+        let regs = snapshot
+            .regs
+            .iter()
+            .filter_map(|(r, old)| {
+                let new = vm.regs.get(*r);
+                (*old != new).then_some((*r, *old, new))
+            })
+            .collect();
+        let flags = snapshot
+            .flags
+            .iter()
+            .filter_map(|(f, old)| {
+                let new = vm.flags.get(*f);
+                (*old != new).then_some((*f, *old, new))
+            })
+            .collect();
+
+        // Keep the first old value of every written byte
+        let mut memory: Vec<(u16, u8, u8)> = Vec::new();
+        for access in accesses.iter().filter(|a| a.kind == AccessKind::Write) {
+            for (i, old) in access.before.iter().enumerate() {
+                let addr = access.address.wrapping_add(i as u16);
+                if !memory.iter().any(|(a, _, _)| *a == addr) {
+                    memory.push((addr, *old, vm.data.data[addr as usize]));
+                }
+            }
+        }
+
+        StepDelta {
+            ip: (snapshot.ip, vm.ip),
+            regs,
+            flags,
+            memory,
+            accesses: accesses.to_vec(),
+            syscall: snapshot.syscall(vm),
+            servers: snapshot.servers(vm),
+            outcome,
+        }
+    }
+
+    /// Return true if the instruction wrote to the given data address.
+    pub fn writes(&self, address: u16) -> bool {
+        self.memory.iter().any(|(addr, _, _)| *addr == address)
+    }
+
+    fn undo(&self, vm: &mut VM) {
+        vm.ip = self.ip.0;
+        for (reg, old, _) in &self.regs {
+            vm.regs.set(*reg, *old);
+        }
+        for (flag, old, _) in &self.flags {
+            vm.flags.set(*flag, *old);
+        }
+        for (addr, old, _) in &self.memory {
+            vm.data.data[*addr as usize] = *old;
+        }
+        if let Some((old, _)) = &self.servers {
+            vm.ipc.restore(old);
+        }
+    }
+
+    fn redo(&self, vm: &mut VM) {
+        vm.ip = self.ip.1;
+        for (reg, _, new) in &self.regs {
+            vm.regs.set(*reg, *new);
+        }
+        for (flag, _, new) in &self.flags {
+            vm.flags.set(*flag, *new);
+        }
+        for (addr, _, new) in &self.memory {
+            vm.data.data[*addr as usize] = *new;
+        }
+        if let Some((_, new)) = &self.servers {
+            vm.ipc.restore(new);
+        }
+    }
+}
+
+/// Execution history of the VM, one `StepDelta` per executed instruction.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    history: Vec<StepDelta>,
+    // Number of deltas currently applied to the VM
+    position: usize,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of instructions executed to reach the current state.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn history(&self) -> &[StepDelta] {
+        &self.history
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Position of the last instruction before the current one that wrote to the given address.
+    pub fn last_write(&self, address: u16) -> Option<usize> {
+        self.history[..self.position]
+            .iter()
+            .rposition(|delta| delta.writes(address))
+    }
+
+    pub(super) fn push(&mut self, delta: StepDelta) {
+        self.history.truncate(self.position);
+        self.history.push(delta);
+        self.position += 1;
+    }
+}
+
+/// This trait can be used to record an execution and move backwards and forwards in it.
+pub trait Reversible {
+    /// Start recording every executed instruction.
+    fn start_recording(&mut self);
+    /// Number of instructions executed since the recording started.
+    fn instruction_count(&self) -> usize;
+    /// Redo the next recorded instruction, if the VM has been moved back in the history.
+    fn replay_step(&mut self) -> Option<StepOutcome>;
+    /// Undo the last executed instruction, returns false at the start of the recording.
+    fn reverse_step(&mut self) -> bool;
+    /// Undo instructions until reaching one of the breakpoints or the start of the recording.
+    /// Returns true if a breakpoint was reached.
+    fn reverse_continue(&mut self, breakpoints: &BTreeSet<u16>) -> bool;
+    /// Move to the state after `count` instructions, executing new instructions if needed.
+    fn goto(&mut self, count: usize) -> Result<StepOutcome, InterpreterError>;
+}
+
+impl Reversible for VM {
+    fn start_recording(&mut self) {
+        if self.recorder.is_none() {
+            self.recorder = Some(Recorder::new());
+        }
+    }
+
+    fn instruction_count(&self) -> usize {
+        self.recorder.as_ref().map_or(0, |r| r.position)
+    }
+
+    fn replay_step(&mut self) -> Option<StepOutcome> {
+        let mut recorder = self.recorder.take()?;
+        let outcome = recorder.history.get(recorder.position).map(|delta| {
+            delta.redo(self);
+            if let Some(watchpoints) = &mut self.watchpoints {
+                watchpoints.count = recorder.position;
+                watchpoints.check(delta.ip.0, &delta.accesses);
+            }
+            delta.outcome
+        });
+        if outcome.is_some() {
+            recorder.position += 1;
+        }
+        self.recorder = Some(recorder);
+        outcome
+    }
+
+    fn reverse_step(&mut self) -> bool {
+        let Some(mut recorder) = self.recorder.take() else {
+            return false;
+        };
+        let reversed = recorder.position > 0;
+        if reversed {
+            recorder.position -= 1;
+            recorder.history[recorder.position].undo(self);
+        }
+        self.recorder = Some(recorder);
+        reversed
+    }
+
+    fn reverse_continue(&mut self, breakpoints: &BTreeSet<u16>) -> bool {
+        while self.reverse_step() {
+            if breakpoints.contains(&self.ip) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn goto(&mut self, count: usize) -> Result<StepOutcome, InterpreterError> {
+        while self.instruction_count() > count {
+            self.reverse_step();
+        }
+        while self.instruction_count() < count {
+            match self.step()? {
+                StepOutcome::Running => {}
+                outcome => return Ok(outcome),
+            }
+        }
+        Ok(StepOutcome::Running)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Ipc, Memory};
+    use crate::minix::message::{BrkRequest, Request};
+    use crate::test_utils::program;
+
+    fn recorded_vm(file: &str) -> VM {
+        let mut vm = VM::from(program(file));
+        vm.start_recording();
+        vm
+    }
+
+    fn state(vm: &VM) -> (u16, Vec<u16>, Vec<u8>) {
+        (
+            vm.ip,
+            Register::iter_16().map(|r| vm.regs.get(r)).collect(),
+            vm.data.data.clone(),
+        )
+    }
+
+    #[test]
+    fn test_reverse_step_restores_state() {
+        let mut vm = recorded_vm("1.c");
+        let initial = state(&vm);
+
+        for _ in 0..20 {
+            assert_eq!(vm.step().unwrap(), StepOutcome::Running);
+        }
+        let after = state(&vm);
+        assert_eq!(vm.instruction_count(), 20);
+
+        while vm.reverse_step() {}
+        assert_eq!(vm.instruction_count(), 0);
+        assert_eq!(state(&vm), initial);
+
+        // Replaying forward reaches the same state
+        vm.goto(20).unwrap();
+        assert_eq!(state(&vm), after);
+    }
+
+    #[test]
+    fn test_reverse_continue_to_breakpoint() {
+        let mut vm = recorded_vm("1.c");
+        vm.goto(30).unwrap();
+
+        // `call 0039` at 0031 jumps to main, stop on the call
+        let breakpoints = BTreeSet::from([0x0031]);
+        assert!(vm.reverse_continue(&breakpoints));
+        assert_eq!(vm.ip, 0x0031);
+        assert_eq!(vm.instruction_count(), 20);
+    }
+
+    #[test]
+    fn test_record_syscall_and_last_write() {
+        let mut vm = recorded_vm("1.c");
+        // Run until exit
+        let outcome = vm.goto(usize::MAX).unwrap();
+        assert_eq!(outcome, StepOutcome::Exited(6));
+
+        let recorder = vm.recorder.as_ref().unwrap();
+        let syscalls: Vec<SyscallRecord> = recorder
+            .history()
+            .iter()
+            .filter_map(|d| d.syscall)
+            .collect();
+        assert_eq!(
+            syscalls,
+            vec![
+                SyscallRecord {
+                    number: 4,
+                    result: 6
+                },
+                SyscallRecord {
+                    number: 1,
+                    result: 1
+                },
+            ]
+        );
+
+        // `mov [si+2], dx` at 00e8 writes the message type of the exit call
+        let message_type_ea = vm.regs.get(Register::BX) + 2;
+        let index = recorder.last_write(message_type_ea).unwrap();
+        assert_eq!(recorder.history()[index].ip.0, 0x00e8);
+    }
+
+    #[test]
+    fn test_reverse_syscall_restores_servers() {
+        // mov ax, 0; mov bx, 0100; mov cx, 3; int 20; hlt
+        let mut vm = VM {
+            text: Memory::from(vec![
+                0xb8, 0x00, 0x00, 0xbb, 0x00, 0x01, 0xb9, 0x03, 0x00, 0xcd, 0x20, 0xf4,
+            ]),
+            ipc: Ipc::new(0x0200),
+            ..VM::default()
+        };
+        vm.regs.set(Register::SP, 0x0f00);
+        BrkRequest { addr: 0x0400 }
+            .to_message()
+            .write(&mut vm.data, 0x0100);
+        vm.start_recording();
+
+        vm.goto(4).unwrap();
+        assert_eq!(vm.ipc.mm.brk, 0x0400);
+        // The break moves back and forth with the brk call
+        assert!(vm.reverse_step());
+        assert_eq!(vm.ipc.mm.brk, 0x0200);
+        vm.goto(4).unwrap();
+        assert_eq!(vm.ipc.mm.brk, 0x0400);
+    }
+}
@@ -48,6 +48,12 @@ impl RegisterSet {
     }
 }
 
+impl Default for RegisterSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl std::fmt::Display for RegisterSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for reg in Register::iter() {
@@ -0,0 +1,305 @@
+use super::memory::{AccessKind, MemoryAccess};
+use super::snapshot::Snapshot;
+use super::vm::VM;
+use crate::minix::SymbolTable;
+use crate::x86::{Register, IR};
+
+/// A call that has not returned yet.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ShadowFrame {
+    /// Address of the `call` instruction
+    pub call_ip: u16,
+    /// Called function
+    pub target: u16,
+    pub return_address: u16,
+    /// SP after the call, where the return address is stored
+    pub sp: u16,
+    /// Last instruction that wrote over the return address slot
+    pub overwritten_by: Option<u16>,
+}
+
+/// Kind of error found by the shadow stack.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StackError {
+    /// `ret` to an address different from the one pushed by the matching `call`
+    BadReturn {
+        expected: u16,
+        actual: u16,
+        /// Stack slot of the return address
+        slot: u16,
+        /// Instruction that overwrote the slot, if any
+        written_by: Option<u16>,
+    },
+    /// `ret` with SP not pointing to the return address pushed by the matching `call`
+    Unbalanced {
+        expected_sp: u16,
+        actual_sp: u16,
+        return_address: u16,
+    },
+    /// SP went below the break
+    StackOverflow { sp: u16, brk: u16 },
+}
+
+impl std::fmt::Display for StackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackError::BadReturn {
+                expected,
+                actual,
+                slot,
+                written_by,
+            } => {
+                write!(
+                    f,
+                    "return to {:04x} instead of {:04x}, return address at {:04x}",
+                    actual, expected, slot
+                )?;
+                match written_by {
+                    Some(ip) => write!(f, " overwritten by {:04x}", ip),
+                    None => Ok(()),
+                }
+            }
+            StackError::Unbalanced {
+                expected_sp,
+                actual_sp,
+                return_address,
+            } => write!(
+                f,
+                "unbalanced stack at return: SP {:04x} instead of {:04x}, returning to {:04x}",
+                actual_sp, expected_sp, return_address
+            ),
+            StackError::StackOverflow { sp, brk } => {
+                write!(
+                    f,
+                    "stack overflow: SP {:04x} below the break {:04x}",
+                    sp, brk
+                )
+            }
+        }
+    }
+}
+
+/// An error with the location it was found at.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StackReport {
+    pub error: StackError,
+    /// Address of the faulty instruction
+    pub ip: u16,
+    /// Call sites of the active frames, innermost first
+    pub backtrace: Vec<u16>,
+}
+
+impl StackReport {
+    /// Describe the error, with function names from the symbol table.
+    pub fn describe(&self, symbols: &SymbolTable) -> String {
+        let name = |address: u16| match symbols.function_at(address) {
+            Some(symbol) => format!(" ({})", symbol.name),
+            None => String::new(),
+        };
+        let mut out = format!("{}\n", self.error);
+        out.push_str(&format!("    at {:04x}{}\n", self.ip, name(self.ip)));
+        for address in &self.backtrace {
+            out.push_str(&format!("    by {:04x}{}\n", address, name(*address)));
+        }
+        out
+    }
+}
+
+/// Shadow call stack, keeping the return address and SP of each `call`
+/// to check the matching `ret`, and the stack pointer against the break.
+#[derive(Debug, Clone)]
+pub struct ShadowStack {
+    /// Active calls, outermost first
+    pub frames: Vec<ShadowFrame>,
+    /// Current break, end of the heap
+    pub brk: u16,
+    pub reports: Vec<StackReport>,
+    // the stack is below the break, reported once until it goes back above
+    overflowed: bool,
+}
+
+impl ShadowStack {
+    /// Start checking a program whose data and bss segments end at `bss_end`.
+    pub fn new(bss_end: u16) -> Self {
+        ShadowStack {
+            frames: Vec::new(),
+            brk: bss_end,
+            reports: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Call sites of the active frames, innermost first.
+    pub fn backtrace(&self) -> Vec<u16> {
+        self.frames.iter().rev().map(|f| f.call_ip).collect()
+    }
+
+    /// Check the instruction executed from `snapshot`.
+    pub fn check(&mut self, snapshot: &Snapshot, vm: &VM, accesses: &[MemoryAccess]) {
+        let Some(instruction) = &snapshot.instruction else {
+            return;
+        };
+        let sp = vm.regs.get(Register::SP);
+
+        match &instruction.ir {
+            IR::Call { .. } => {
+                self.frames.push(ShadowFrame {
+                    call_ip: snapshot.ip,
+                    target: vm.ip,
+                    return_address: snapshot.ip.wrapping_add(instruction.raw.len() as u16),
+                    sp,
+                    overwritten_by: None,
+                });
+            }
+            IR::Ret { .. } => {
+                if let Some(frame) = self.frames.pop() {
+                    let slot = snapshot.sp();
+                    let error = if slot != frame.sp {
+                        Some(StackError::Unbalanced {
+                            expected_sp: frame.sp,
+                            actual_sp: slot,
+                            return_address: vm.ip,
+                        })
+                    } else if vm.ip != frame.return_address {
+                        Some(StackError::BadReturn {
+                            expected: frame.return_address,
+                            actual: vm.ip,
+                            slot,
+                            written_by: frame.overwritten_by,
+                        })
+                    } else {
+                        None
+                    };
+                    if let Some(error) = error {
+                        self.report(error, snapshot.ip);
+                    }
+                }
+            }
+            _ => {
+                // Writes over the saved return addresses
+                for access in accesses.iter().filter(|a| a.kind == AccessKind::Write) {
+                    for frame in self.frames.iter_mut() {
+                        if access.contains(frame.sp) || access.contains(frame.sp.wrapping_add(1)) {
+                            frame.overwritten_by = Some(snapshot.ip);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(brk) = snapshot.new_break(vm) {
+            self.brk = brk;
+        }
+        if sp < self.brk && !self.overflowed {
+            let brk = self.brk;
+            self.report(StackError::StackOverflow { sp, brk }, snapshot.ip);
+        }
+        self.overflowed = sp < self.brk;
+    }
+
+    fn report(&mut self, error: StackError, ip: u16) {
+        self.reports.push(StackReport {
+            error,
+            ip,
+            backtrace: self.backtrace(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::{StepOutcome, VmIrExecutable};
+    use crate::test_utils::{program, vm_with_text};
+
+    fn run_text(text: &[u8]) -> ShadowStack {
+        let mut vm = vm_with_text(text);
+        vm.shadow_stack = Some(ShadowStack::new(0x0100));
+        while vm.step() == Ok(StepOutcome::Running) {}
+        vm.shadow_stack.unwrap()
+    }
+
+    #[test]
+    fn test_overwritten_return_address() {
+        let shadow = run_text(&[
+            0xe8, 0x01, 0x00, // call 0004
+            0xf4, // hlt
+            0x55, // push bp
+            0x89, 0xe5, // mov bp, sp
+            0xc7, 0x46, 0x02, 0x34, 0x12, // mov [bp+2], 1234
+            0x5d, // pop bp
+            0xc3, // ret
+        ]);
+
+        assert_eq!(
+            shadow.reports,
+            vec![StackReport {
+                error: StackError::BadReturn {
+                    expected: 0x0003,
+                    actual: 0x1234,
+                    slot: 0x0efe,
+                    written_by: Some(0x0007),
+                },
+                ip: 0x000d,
+                backtrace: vec![],
+            }]
+        );
+        assert_eq!(
+            shadow.reports[0].error.to_string(),
+            "return to 1234 instead of 0003, return address at 0efe overwritten by 0007"
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_return() {
+        let shadow = run_text(&[
+            0xe8, 0x01, 0x00, // call 0004
+            0xf4, // hlt
+            0xb8, 0x03, 0x00, // mov ax, 0003
+            0x50, // push ax
+            0xc3, // ret
+        ]);
+
+        assert_eq!(
+            shadow.reports[0].error,
+            StackError::Unbalanced {
+                expected_sp: 0x0efe,
+                actual_sp: 0x0efc,
+                return_address: 0x0003,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let shadow = run_text(&[
+            0xbc, 0x02, 0x01, // mov sp, 0102
+            0x50, // push ax
+            0x50, // push ax
+            0x50, // push ax
+            0xf4, // hlt
+        ]);
+
+        assert_eq!(
+            shadow.reports.iter().map(|r| r.error).collect::<Vec<_>>(),
+            vec![StackError::StackOverflow {
+                sp: 0x00fe,
+                brk: 0x0100
+            }]
+        );
+    }
+
+    #[test]
+    fn test_clean_program() {
+        let program = program("4.c");
+        let bss_end = (program.header.data + program.header.bss) as u16;
+        let mut vm = VM::from(program);
+        vm.shadow_stack = Some(ShadowStack::new(bss_end));
+        vm.run().unwrap();
+
+        let shadow = vm.shadow_stack.unwrap();
+        assert_eq!(shadow.reports, vec![]);
+        // The exit syscall is made from _exit, __exit, __syscal and __sendre
+        assert_eq!(shadow.backtrace().len(), 4);
+    }
+}
@@ -0,0 +1,112 @@
+use super::flag_set::Flag;
+use super::ipc::ServerState;
+use super::vm::{VmIrExecutable, VM};
+use crate::minix::message::{call, BrkRequest, Message, Request};
+use crate::x86::{Instruction, Register, IR};
+
+/// Result of a syscall executed by an instruction.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SyscallRecord {
+    /// Minix call number (`m_type` of the request message)
+    pub number: u16,
+    /// `m_type` of the reply message
+    pub result: u16,
+}
+
+/// CPU state taken before executing an instruction,
+/// compared with the state after the instruction by the recorder and the tracer.
+pub struct Snapshot {
+    pub ip: u16,
+    pub instruction: Option<Instruction>,
+    pub regs: Vec<(Register, u16)>,
+    pub flags: Vec<(Flag, bool)>,
+    // (message address, request message) if the instruction is a syscall
+    syscall: Option<(u16, Message)>,
+    // State of the servers before a syscall
+    servers: Option<ServerState>,
+}
+
+impl Snapshot {
+    pub fn take(vm: &VM) -> Self {
+        let instruction = vm.fetch().and_then(|chunk| {
+            crate::disassembler::parse_instruction(chunk, vm.ip as usize)
+                .ok()
+                .map(|(instruction, _)| instruction)
+        });
+        let syscall = match instruction.as_ref().map(|i| &i.ir) {
+            Some(IR::Int { int_type: 0x20 }) => {
+                let message = vm.regs.get(Register::BX);
+                Some((message, Message::peek(&vm.data, message)))
+            }
+            _ => None,
+        };
+        Snapshot {
+            ip: vm.ip,
+            instruction,
+            regs: Register::iter_16().map(|r| (r, vm.regs.get(r))).collect(),
+            flags: Flag::iter().map(|f| (f, vm.flags.get(f))).collect(),
+            servers: syscall.is_some().then(|| vm.ipc.state()),
+            syscall,
+        }
+    }
+
+    /// The state before a `ret` instead of the instruction, for the function
+    /// a hook returns from without executing it.
+    pub fn returning(self) -> Self {
+        Snapshot {
+            instruction: Some(Instruction::new(IR::Ret { src: None }, vec![0xc3])),
+            syscall: None,
+            servers: None,
+            ..self
+        }
+    }
+
+    /// Call number and reply of the syscall made by the instruction, once executed.
+    pub fn syscall(&self, vm: &VM) -> Option<SyscallRecord> {
+        self.syscall
+            .as_ref()
+            .map(|(message, request)| SyscallRecord {
+                number: request.m_type(),
+                result: Message::peek(&vm.data, *message).m_type(),
+            })
+    }
+
+    /// State of the servers before and after the syscall made by the instruction, once executed.
+    pub fn servers(&self, vm: &VM) -> Option<(ServerState, ServerState)> {
+        self.servers
+            .as_ref()
+            .map(|before| (before.clone(), vm.ipc.state()))
+    }
+
+    /// Address and content of the request message, if the instruction is a syscall.
+    pub fn request(&self) -> Option<(u16, &Message)> {
+        self.syscall
+            .as_ref()
+            .map(|(message, request)| (*message, request))
+    }
+
+    /// The break set by the instruction, if it is a successful `brk` syscall.
+    pub fn new_break(&self, vm: &VM) -> Option<u16> {
+        let (_, request) = self.request()?;
+        let syscall = self.syscall(vm)?;
+        (syscall.number == call::BRK && syscall.result == 0)
+            .then(|| BrkRequest::from_message(request).addr)
+    }
+
+    /// Value of the SP register before the instruction.
+    pub fn sp(&self) -> u16 {
+        self.regs
+            .iter()
+            .find(|(r, _)| *r == Register::SP)
+            .map_or(0, |(_, v)| *v)
+    }
+
+    /// Value of the 8086 FLAGS register.
+    pub fn flags_word(&self) -> u16 {
+        self.flags
+            .iter()
+            .filter(|(_, set)| *set)
+            .filter_map(|(flag, _)| flag.bit())
+            .fold(0, |word, bit| word | (1 << bit))
+    }
+}
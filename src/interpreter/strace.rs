@@ -0,0 +1,278 @@
+use std::io::{self, Write};
+
+use super::snapshot::Snapshot;
+use super::vm::{StepOutcome, VM};
+use crate::minix::message::{call, Field, Message, OpenRequest, Request};
+use crate::utils::quote;
+
+// Longest string or buffer shown
+const MAX_STRING: usize = 32;
+
+/// How a message field is shown.
+#[derive(Debug, Clone, Copy)]
+enum Arg {
+    /// Signed word
+    Int(Field),
+    /// Word in hexadecimal (requests, options)
+    Hex(Field),
+    /// Word in octal (modes, flags)
+    Octal(Field),
+    Ptr(Field),
+    /// Signed long
+    Long(Field),
+    /// NUL terminated string at the pointer
+    Str(Field),
+    /// Buffer at the pointer, with the length in the second field
+    Buf(Field, Field),
+    /// Buffer at the pointer filled by the call, with the length returned
+    OutBuf(Field),
+    /// Two file descriptors returned in m1_i1 and m1_i2 of the reply
+    Fds,
+}
+
+/// Name and arguments of a Minix call, from the request message.
+fn decode(number: u16, request: &Message) -> (String, Vec<Arg>) {
+    use Arg::*;
+    use Field::*;
+    let (name, args): (&str, Vec<Arg>) = match number {
+        call::EXIT => ("exit", vec![Int(M1I1)]),
+        call::FORK => ("fork", vec![]),
+        call::READ => ("read", vec![Int(M1I1), OutBuf(M1P1), Int(M1I2)]),
+        call::WRITE => ("write", vec![Int(M1I1), Buf(M1P1, M1I2), Int(M1I2)]),
+        call::OPEN if OpenRequest::from_message(request).mode.is_some() => {
+            ("open", vec![Str(M1P1), Octal(M1I2), Octal(M1I3)])
+        }
+        call::OPEN => ("open", vec![Str(M3P1), Octal(M3I2)]),
+        call::CLOSE => ("close", vec![Int(M1I1)]),
+        call::WAIT => ("wait", vec![]),
+        call::CREAT => ("creat", vec![Str(M3P1), Octal(M3I2)]),
+        call::LINK => ("link", vec![Str(M1P1), Str(M1P2)]),
+        call::UNLINK => ("unlink", vec![Str(M3P1)]),
+        call::WAITPID => ("waitpid", vec![Int(M1I1), Hex(M1I2)]),
+        call::CHDIR => ("chdir", vec![Str(M3P1)]),
+        call::TIME => ("time", vec![]),
+        call::MKNOD => ("mknod", vec![Str(M1P1), Octal(M1I2), Hex(M1I3)]),
+        call::CHMOD => ("chmod", vec![Str(M3P1), Octal(M3I2)]),
+        call::CHOWN => ("chown", vec![Str(M1P1), Int(M1I2), Int(M1I3)]),
+        call::BRK => ("brk", vec![Ptr(M1P1)]),
+        call::STAT => ("stat", vec![Str(M1P1), Ptr(M1P2)]),
+        call::LSEEK => ("lseek", vec![Int(M2I1), Long(M2L1), Int(M2I2)]),
+        call::GETPID => ("getpid", vec![]),
+        call::MOUNT => ("mount", vec![Str(M1P1), Str(M1P2), Int(M1I3)]),
+        call::UMOUNT => ("umount", vec![Str(M3P1)]),
+        call::SETUID => ("setuid", vec![Int(M1I1)]),
+        call::GETUID => ("getuid", vec![]),
+        call::STIME => ("stime", vec![Long(M2L1)]),
+        call::PTRACE => ("ptrace", vec![Int(M2I2), Int(M2I1), Long(M2L1), Long(M2L2)]),
+        call::ALARM => ("alarm", vec![Int(M1I1)]),
+        call::FSTAT => ("fstat", vec![Int(M1I1), Ptr(M1P1)]),
+        call::PAUSE => ("pause", vec![]),
+        call::UTIME => ("utime", vec![Str(M2P1), Long(M2L1), Long(M2L2)]),
+        call::ACCESS => ("access", vec![Str(M3P1), Octal(M3I2)]),
+        call::SYNC => ("sync", vec![]),
+        call::KILL => ("kill", vec![Int(M1I1), Int(M1I2)]),
+        call::RENAME => ("rename", vec![Str(M1P1), Str(M1P2)]),
+        call::MKDIR => ("mkdir", vec![Str(M1P1), Octal(M1I2)]),
+        call::RMDIR => ("rmdir", vec![Str(M3P1)]),
+        call::DUP => ("dup", vec![Int(M1I1), Int(M1I2)]),
+        call::PIPE => ("pipe", vec![Fds]),
+        call::TIMES => ("times", vec![]),
+        call::SETGID => ("setgid", vec![Int(M1I1)]),
+        call::GETGID => ("getgid", vec![]),
+        call::SIGNAL => ("signal", vec![Int(M6I1), Ptr(M6F1)]),
+        call::IOCTL => ("ioctl", vec![Int(M2I1), Hex(M2I3), Ptr(M2P1)]),
+        call::FCNTL => ("fcntl", vec![Int(M1I1), Int(M1I2), Int(M1I3)]),
+        call::EXEC => ("exec", vec![Str(M1P1), Ptr(M1P2), Int(M1I2)]),
+        call::UMASK => ("umask", vec![Octal(M1I1)]),
+        call::CHROOT => ("chroot", vec![Str(M3P1)]),
+        call::SETSID => ("setsid", vec![]),
+        call::GETPGRP => ("getpgrp", vec![]),
+        call::SIGACTION => (
+            "sigaction",
+            vec![Int(M1I2), Ptr(M1P1), Ptr(M1P2), Ptr(M1P3)],
+        ),
+        call::SIGSUSPEND => ("sigsuspend", vec![Hex(M2L1)]),
+        call::SIGPENDING => ("sigpending", vec![]),
+        call::SIGPROCMASK => ("sigprocmask", vec![Int(M2I1), Hex(M2L1)]),
+        call::SIGRETURN => ("sigreturn", vec![Ptr(M2P1)]),
+        call::REBOOT => ("reboot", vec![Int(M1I1)]),
+        call::SVRCTL => ("svrctl", vec![Hex(M2I1), Ptr(M2P1)]),
+        _ => return (format!("syscall_{}", number), vec![]),
+    };
+    (name.to_string(), args)
+}
+
+/// Name and description of a Minix errno.
+pub fn errno_name(errno: u16) -> Option<(&'static str, &'static str)> {
+    let name = match errno {
+        1 => ("EPERM", "Operation not permitted"),
+        2 => ("ENOENT", "No such file or directory"),
+        3 => ("ESRCH", "No such process"),
+        4 => ("EINTR", "Interrupted system call"),
+        5 => ("EIO", "Input/output error"),
+        6 => ("ENXIO", "No such device or address"),
+        7 => ("E2BIG", "Argument list too long"),
+        8 => ("ENOEXEC", "Exec format error"),
+        9 => ("EBADF", "Bad file descriptor"),
+        10 => ("ECHILD", "No child processes"),
+        11 => ("EAGAIN", "Resource temporarily unavailable"),
+        12 => ("ENOMEM", "Not enough space"),
+        13 => ("EACCES", "Permission denied"),
+        14 => ("EFAULT", "Bad address"),
+        15 => ("ENOTBLK", "Extension: not a block special file"),
+        16 => ("EBUSY", "Resource busy"),
+        17 => ("EEXIST", "File exists"),
+        18 => ("EXDEV", "Improper link"),
+        19 => ("ENODEV", "No such device"),
+        20 => ("ENOTDIR", "Not a directory"),
+        21 => ("EISDIR", "Is a directory"),
+        22 => ("EINVAL", "Invalid argument"),
+        23 => ("ENFILE", "Too many open files in system"),
+        24 => ("EMFILE", "Too many open files"),
+        25 => ("ENOTTY", "Inappropriate I/O control operation"),
+        26 => ("ETXTBSY", "Text file busy"),
+        27 => ("EFBIG", "File too large"),
+        28 => ("ENOSPC", "No space left on device"),
+        29 => ("ESPIPE", "Invalid seek"),
+        30 => ("EROFS", "Read-only file system"),
+        31 => ("EMLINK", "Too many links"),
+        32 => ("EPIPE", "Broken pipe"),
+        33 => ("EDOM", "Domain error"),
+        34 => ("ERANGE", "Result too large"),
+        35 => ("EDEADLK", "Resource deadlock avoided"),
+        36 => ("ENAMETOOLONG", "File name too long"),
+        37 => ("ENOLCK", "No locks available"),
+        38 => ("ENOSYS", "Function not implemented"),
+        39 => ("ENOTEMPTY", "Directory not empty"),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// NUL terminated string of the data memory.
+fn string_at(vm: &VM, address: u16) -> String {
+    let bytes = vm.data.peek_bytes(address, MAX_STRING + 1);
+    match bytes.iter().position(|&b| b == 0) {
+        Some(end) => quote(&bytes[..end], MAX_STRING),
+        None => quote(bytes, MAX_STRING),
+    }
+}
+
+/// Writes a line per syscall with the decoded request and reply messages, like strace.
+pub struct Strace {
+    output: Box<dyn Write>,
+    // Number of syscalls made since the tracer was attached
+    count: usize,
+}
+
+impl Strace {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Strace { output, count: 0 }
+    }
+
+    /// Number of syscalls written.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Write the syscall made by the instruction executed from `snapshot`, if any.
+    pub fn record(&mut self, snapshot: &Snapshot, vm: &VM, outcome: StepOutcome) -> io::Result<()> {
+        let Some((address, request)) = snapshot.request() else {
+            return Ok(());
+        };
+        self.count += 1;
+        writeln!(
+            self.output,
+            "{}",
+            Self::format(address, request, vm, outcome)
+        )
+    }
+
+    /// Format a syscall, once executed, from its request message.
+    pub fn format(address: u16, request: &Message, vm: &VM, outcome: StepOutcome) -> String {
+        let number = request.m_type();
+        let reply = Message::peek(&vm.data, address);
+        let result = reply.result();
+        let (name, args) = decode(number, request);
+
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| match *arg {
+                Arg::Int(field) => format!("{}", request.word(field) as i16),
+                Arg::Hex(field) => format!("{:#x}", request.word(field)),
+                Arg::Octal(field) => format!("{:#o}", request.word(field)),
+                Arg::Ptr(field) => format!("{:#06x}", request.word(field)),
+                Arg::Long(field) => format!("{}", request.long(field)),
+                Arg::Str(field) => string_at(vm, request.word(field)),
+                Arg::Buf(pointer, length) => quote(
+                    vm.data
+                        .peek_bytes(request.word(pointer), request.word(length) as usize),
+                    MAX_STRING,
+                ),
+                Arg::OutBuf(pointer) if outcome == StepOutcome::Running && result >= 0 => quote(
+                    vm.data.peek_bytes(request.word(pointer), result as usize),
+                    MAX_STRING,
+                ),
+                Arg::OutBuf(pointer) => format!("{:#06x}", request.word(pointer)),
+                Arg::Fds if outcome == StepOutcome::Running && result >= 0 => {
+                    format!("[{}, {}]", reply.m1().i1, reply.m1().i2)
+                }
+                Arg::Fds => "[]".to_string(),
+            })
+            .collect();
+
+        let result = match outcome {
+            StepOutcome::Running if result < 0 => {
+                let errno = result.unsigned_abs();
+                match errno_name(errno) {
+                    Some((name, description)) => format!("-1 {} ({})", name, description),
+                    None => format!("-1 errno {}", errno),
+                }
+            }
+            // Results returned in the m2_l1 field
+            StepOutcome::Running if number == call::TIME || number == call::LSEEK => {
+                format!("{}", reply.m2().l1)
+            }
+            StepOutcome::Running => format!("{}", result),
+            StepOutcome::Faulted => "? <unimplemented>".to_string(),
+            _ => "?".to_string(),
+        };
+        format!("{}({}) = {}", name, args.join(", "), result)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::VmIrExecutable;
+    use crate::test_utils::{program, SharedBuffer};
+
+    fn strace(file: &str) -> Vec<String> {
+        let mut vm = VM::from(program(file));
+        let buffer = SharedBuffer::default();
+        vm.strace = Some(Strace::new(Box::new(buffer.clone())));
+        vm.run().unwrap();
+        buffer.text().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_strace_write_and_exit() {
+        // main returns the result of write
+        assert_eq!(
+            strace("1.c"),
+            vec!["write(1, \"hello\\n\", 6) = 6", "exit(6) = ?"]
+        );
+    }
+
+    #[test]
+    fn test_strace_errno() {
+        let lines = strace("4.c");
+        assert!(lines.iter().any(|l| l.starts_with("brk(")));
+        assert!(lines.iter().any(|l| l.starts_with("ioctl(")
+            && l.ends_with("= -1 ENOTTY (Inappropriate I/O control operation)")));
+    }
+}
@@ -0,0 +1,643 @@
+use super::error::OpcodeExecErrors;
+use super::flag_set::{Flag, FlagSet};
+use super::memory::{AccessKind, Memory, MemoryAccess};
+use super::register_set::RegisterSet;
+use super::snapshot::Snapshot;
+use super::vm::VM;
+use crate::il::{eval, BinaryOp, Expr, Liftable, Machine, MicroOp, UnaryOp};
+use crate::minix::message::{call, ReadRequest, Request, MESSAGE_SIZE};
+use crate::minix::SymbolTable;
+use crate::x86::{Register, IR};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// Origin of tainted data.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TaintSource {
+    /// Buffer filled by the `read` syscall
+    Read,
+    /// Argument strings written by `VM::set_args`
+    Argv,
+    /// Environment strings written by `VM::set_args_and_env`
+    Environment,
+}
+
+impl std::fmt::Display for TaintSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaintSource::Read => write!(f, "read"),
+            TaintSource::Argv => write!(f, "argv"),
+            TaintSource::Environment => write!(f, "environment"),
+        }
+    }
+}
+
+/// Taint of a byte: where it comes from, and the instructions that carried it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TaintLabel {
+    pub source: TaintSource,
+    /// Address of the source byte
+    pub address: u16,
+    /// Instructions that wrote the tainted value, in order, each one once
+    pub chain: Vec<u16>,
+}
+
+impl TaintLabel {
+    /// The label carried by the instruction at `ip`.
+    fn through(self: &Rc<Self>, ip: u16) -> Rc<Self> {
+        if self.chain.contains(&ip) {
+            return self.clone();
+        }
+        let mut label = (**self).clone();
+        label.chain.push(ip);
+        Rc::new(label)
+    }
+}
+
+/// Taint of the bytes of a value, low byte first.
+type Bytes = [Option<Rc<TaintLabel>>; 2];
+
+/// First taint of the bytes.
+fn any(bytes: &Bytes) -> Option<Rc<TaintLabel>> {
+    bytes.iter().flatten().next().cloned()
+}
+
+/// Byte by byte taint of two values.
+fn merge(a: Bytes, b: Bytes) -> Bytes {
+    let [a0, a1] = a;
+    let [b0, b1] = b;
+    [a0.or(b0), a1.or(b1)]
+}
+
+/// Use of tainted data found by the tracker.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TaintSink {
+    IndirectCall {
+        target: u16,
+    },
+    IndirectJump {
+        target: u16,
+    },
+    Return {
+        target: u16,
+    },
+    /// Byte of the syscall message
+    Syscall {
+        number: u16,
+        address: u16,
+    },
+}
+
+impl std::fmt::Display for TaintSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaintSink::IndirectCall { target } => {
+                write!(f, "call to {:04x} from a tainted target", target)
+            }
+            TaintSink::IndirectJump { target } => {
+                write!(f, "jump to {:04x} from a tainted target", target)
+            }
+            TaintSink::Return { target } => {
+                write!(f, "return to {:04x} from a tainted return address", target)
+            }
+            TaintSink::Syscall { number, address } => write!(
+                f,
+                "syscall {} reads a tainted byte at {:04x}",
+                number, address
+            ),
+        }
+    }
+}
+
+/// A sink reached by tainted data, with the instruction it was found at.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TaintReport {
+    pub sink: TaintSink,
+    pub ip: u16,
+    pub label: TaintLabel,
+    /// Number of times the sink was reached at this instruction
+    pub count: usize,
+}
+
+impl TaintReport {
+    /// Describe the flow from the source, with function names from the symbol table.
+    pub fn describe(&self, symbols: &SymbolTable) -> String {
+        let name = |address: u16| match symbols.function_at(address) {
+            Some(symbol) => format!(" ({})", symbol.name),
+            None => String::new(),
+        };
+        let mut out = format!("{}\n", self.sink);
+        out.push_str(&format!("    at {:04x}{}\n", self.ip, name(self.ip)));
+        out.push_str(&format!(
+            "    tainted by {} at {:04x}\n",
+            self.label.source, self.label.address
+        ));
+        for address in &self.label.chain {
+            out.push_str(&format!("    through {:04x}{}\n", address, name(*address)));
+        }
+        if self.count > 1 {
+            out.push_str(&format!("    ({} times)\n", self.count));
+        }
+        out
+    }
+}
+
+/// Copy of the registers and flags, with the memory writes aside,
+/// to find the addresses used by the micro-ops before the VM runs them.
+struct Scratch<'a> {
+    regs: RegisterSet,
+    flags: FlagSet,
+    data: &'a Memory,
+    written: BTreeMap<u16, u8>,
+}
+
+impl Machine for Scratch<'_> {
+    fn register(&self, reg: Register) -> u16 {
+        self.regs.get(reg)
+    }
+    fn set_register(&mut self, reg: Register, value: u16) {
+        self.regs.set(reg, value);
+    }
+    fn flag(&self, flag: Flag) -> bool {
+        self.flags.get(flag)
+    }
+    fn set_flag(&mut self, flag: Flag, value: bool) {
+        self.flags.set(flag, value);
+    }
+    fn load(&self, address: u16, byte: bool) -> u16 {
+        let byte_at = |address: u16| match self.written.get(&address) {
+            Some(value) => *value,
+            None => self
+                .data
+                .peek_bytes(address, 1)
+                .first()
+                .copied()
+                .unwrap_or(0),
+        };
+        match byte {
+            true => byte_at(address) as u16,
+            false => u16::from_le_bytes([byte_at(address), byte_at(address.wrapping_add(1))]),
+        }
+    }
+    fn store(&mut self, address: u16, value: u16, byte: bool) {
+        let [low, high] = value.to_le_bytes();
+        self.written.insert(address, low);
+        if !byte {
+            self.written.insert(address.wrapping_add(1), high);
+        }
+    }
+    fn set_ip(&mut self, _ip: u16) {}
+    fn interrupt(&mut self, _number: u8) -> Result<(), OpcodeExecErrors> {
+        Ok(())
+    }
+    fn port_in(&mut self, _port: u16) -> u16 {
+        0x42
+    }
+    fn port_out(&mut self, _port: u16, _value: u16) {}
+}
+
+/// Dynamic taint tracker.
+///
+/// Tracks the bytes of the data memory and of the registers, and the flags, holding
+/// data derived from the sources, through the micro-ops of each instruction.
+/// Reports tainted targets of indirect calls and jumps, tainted return addresses,
+/// and tainted syscall messages. Addresses don't taint the values loaded or stored
+/// through them.
+pub struct Taint {
+    /// One entry per byte of data memory
+    pub shadow: Vec<Option<Rc<TaintLabel>>>,
+    /// Low and high bytes of the 16 bits registers
+    regs: HashMap<Register, Bytes>,
+    flags: HashMap<Flag, Option<Rc<TaintLabel>>>,
+    pub reports: Vec<TaintReport>,
+}
+
+impl Taint {
+    /// Start tracking, with the argument and environment strings on the stack of
+    /// the VM as sources.
+    pub fn new(vm: &VM) -> Self {
+        let mut taint = Taint {
+            shadow: vec![None; vm.data.len()],
+            regs: Register::iter_16().map(|reg| (reg, [None, None])).collect(),
+            flags: Flag::iter().map(|flag| (flag, None)).collect(),
+            reports: Vec::new(),
+        };
+        // argc, the NULL terminated argv, then the NULL terminated envp
+        let sp = vm.regs.get(Register::SP);
+        let argc = vm.data.peek_word(sp);
+        let mut add_string = |pointer: u16, source| {
+            let length = (pointer..=u16::MAX)
+                .take_while(
+                    |&address| matches!(vm.data.peek_bytes(address, 1), [byte] if *byte != 0),
+                )
+                .count();
+            taint.add_source(pointer, length, source);
+        };
+        for i in 0..argc {
+            add_string(
+                vm.data.peek_word(sp.wrapping_add(2 + 2 * i)),
+                TaintSource::Argv,
+            );
+        }
+        let envp = sp.wrapping_add(4 + 2 * argc);
+        for pointer in (0..)
+            .map(|i: u16| vm.data.peek_word(envp.wrapping_add(2 * i)))
+            .take_while(|&pointer| pointer != 0)
+        {
+            add_string(pointer, TaintSource::Environment);
+        }
+        taint
+    }
+
+    /// Taint the bytes of memory, like a buffer filled by the host.
+    pub fn add_source(&mut self, address: u16, size: usize, source: TaintSource) {
+        for i in 0..size {
+            let address = address.wrapping_add(i as u16);
+            if let Some(byte) = self.shadow.get_mut(address as usize) {
+                *byte = Some(Rc::new(TaintLabel {
+                    source,
+                    address,
+                    chain: vec![],
+                }));
+            }
+        }
+    }
+
+    /// Return true if a byte of the range is tainted.
+    pub fn is_tainted(&self, address: u16, width: usize) -> bool {
+        (0..width).any(|i| self.byte(address.wrapping_add(i as u16)).is_some())
+    }
+
+    fn byte(&self, address: u16) -> Option<Rc<TaintLabel>> {
+        self.shadow.get(address as usize).cloned().flatten()
+    }
+
+    fn set_byte(&mut self, address: u16, label: Option<Rc<TaintLabel>>) {
+        if let Some(byte) = self.shadow.get_mut(address as usize) {
+            *byte = label;
+        }
+    }
+
+    /// Taint of the register, with a byte register as the low byte.
+    fn reg(&self, reg: Register) -> Bytes {
+        let [low, high] = self.regs[&reg.to_word_register()].clone();
+        if reg.is_low_byte() {
+            [low, None]
+        } else if reg.is_high_byte() {
+            [high, None]
+        } else {
+            [low, high]
+        }
+    }
+
+    /// Set the taint of the register, as `RegisterSet::set` sets its value:
+    /// a high byte register takes the high byte of the value.
+    fn set_reg(&mut self, reg: Register, bytes: Bytes) {
+        let [low, high] = bytes;
+        let word = self.regs.get_mut(&reg.to_word_register()).unwrap();
+        if reg.is_low_byte() {
+            word[0] = low;
+        } else if reg.is_high_byte() {
+            word[1] = high;
+        } else {
+            *word = [low, high];
+        }
+    }
+
+    /// Taint of the value of the expression, with the addresses from the scratch machine.
+    fn expr(&self, expr: &Expr, scratch: &Scratch, temps: &[u32], taints: &[Bytes]) -> Bytes {
+        let taint = |expr: &Expr| self.expr(expr, scratch, temps, taints);
+        // All the bytes of the result depend on all the bytes of the operands
+        let mixed = |bytes: Bytes| {
+            let label = any(&bytes);
+            [label.clone(), label]
+        };
+        match expr {
+            Expr::Const(_) => [None, None],
+            Expr::Reg(reg) => self.reg(*reg),
+            Expr::Flag(flag) => [self.flags[flag].clone(), None],
+            Expr::Temp(n) => taints.get(*n).cloned().unwrap_or_default(),
+            Expr::Load { address, byte } => {
+                let address = eval(address, scratch, temps) as u16;
+                match byte {
+                    true => [self.byte(address), None],
+                    false => [self.byte(address), self.byte(address.wrapping_add(1))],
+                }
+            }
+            Expr::Unary(op, value) => {
+                let value = taint(value);
+                match op {
+                    UnaryOp::Not => value,
+                    UnaryOp::SignExtendByte => [value[0].clone(), value[0].clone()],
+                    UnaryOp::Parity => [any(&value), None],
+                    _ => mixed(value),
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (taint(left), taint(right));
+                match op {
+                    BinaryOp::And | BinaryOp::Or | BinaryOp::Xor => merge(left, right),
+                    // Carries go up
+                    BinaryOp::Add | BinaryOp::Sub => {
+                        let [low, high] = merge(left, right);
+                        [low.clone(), high.or(low)]
+                    }
+                    BinaryOp::Eq
+                    | BinaryOp::Ne
+                    | BinaryOp::LtU
+                    | BinaryOp::LtS
+                    | BinaryOp::AddOverflows
+                    | BinaryOp::SubOverflows => [any(&merge(left, right)), None],
+                    _ => mixed(merge(left, right)),
+                }
+            }
+            Expr::Ite(cond, then, otherwise) => {
+                let value = merge(taint(then), taint(otherwise));
+                match any(&taint(cond)) {
+                    Some(label) => [Some(label.clone()), Some(label)],
+                    None => value,
+                }
+            }
+        }
+    }
+
+    /// Propagate the taint through the instruction about to be executed from `snapshot`,
+    /// and check its jump target.
+    pub fn propagate(&mut self, snapshot: &Snapshot, vm: &VM) {
+        let Some(instruction) = &snapshot.instruction else {
+            return;
+        };
+        let ip = snapshot.ip;
+        let mut scratch = Scratch {
+            regs: RegisterSet::new(),
+            flags: FlagSet::new(),
+            data: &vm.data,
+            written: BTreeMap::new(),
+        };
+        snapshot
+            .regs
+            .iter()
+            .for_each(|(reg, value)| scratch.regs.set(*reg, *value));
+        snapshot
+            .flags
+            .iter()
+            .for_each(|(flag, value)| scratch.flags.set(*flag, *value));
+        let carried = |bytes: Bytes| bytes.map(|label| label.map(|l| l.through(ip)));
+
+        let (mut temps, mut taints): (Vec<u32>, Vec<Bytes>) = (vec![], vec![]);
+        for op in instruction.lift(ip) {
+            match &op {
+                MicroOp::SetReg(reg, value) => {
+                    let bytes = self.expr(value, &scratch, &temps, &taints);
+                    self.set_reg(*reg, carried(bytes));
+                    let value = eval(value, &scratch, &temps) as u16;
+                    scratch.set_register(*reg, value);
+                }
+                MicroOp::SetFlag(flag, value) => {
+                    let label = any(&self.expr(value, &scratch, &temps, &taints));
+                    self.flags.insert(*flag, label.map(|l| l.through(ip)));
+                    let value = eval(value, &scratch, &temps) != 0;
+                    scratch.set_flag(*flag, value);
+                }
+                MicroOp::SetTemp(n, value) => {
+                    if temps.len() <= *n {
+                        temps.resize(*n + 1, 0);
+                        taints.resize(*n + 1, [None, None]);
+                    }
+                    taints[*n] = self.expr(value, &scratch, &temps, &taints);
+                    temps[*n] = eval(value, &scratch, &temps);
+                }
+                MicroOp::Store {
+                    address,
+                    value,
+                    byte,
+                } => {
+                    let [low, high] = carried(self.expr(value, &scratch, &temps, &taints));
+                    let address = eval(address, &scratch, &temps) as u16;
+                    self.set_byte(address, low);
+                    if !byte {
+                        self.set_byte(address.wrapping_add(1), high);
+                    }
+                    let value = eval(value, &scratch, &temps) as u16;
+                    scratch.store(address, value, *byte);
+                }
+                MicroOp::Jump(target) => {
+                    self.check_target(&instruction.ir, target, &scratch, &temps, &taints, ip);
+                    break;
+                }
+                MicroOp::Branch { cond, target } => {
+                    if eval(cond, &scratch, &temps) != 0 {
+                        self.check_target(&instruction.ir, target, &scratch, &temps, &taints, ip);
+                        break;
+                    }
+                }
+                MicroOp::DivideError(cond) => {
+                    if eval(cond, &scratch, &temps) != 0 {
+                        break;
+                    }
+                }
+                MicroOp::In { dest, .. } => self.set_reg(*dest, [None, None]),
+                _ => {}
+            }
+        }
+    }
+
+    fn check_target(
+        &mut self,
+        ir: &IR,
+        target: &Expr,
+        scratch: &Scratch,
+        temps: &[u32],
+        taints: &[Bytes],
+        ip: u16,
+    ) {
+        let Some(label) = any(&self.expr(target, scratch, temps, taints)) else {
+            return;
+        };
+        let target = eval(target, scratch, temps) as u16;
+        let sink = match ir {
+            IR::Call { .. } => TaintSink::IndirectCall { target },
+            IR::Ret { .. } => TaintSink::Return { target },
+            _ => TaintSink::IndirectJump { target },
+        };
+        self.report(sink, ip, &label);
+    }
+
+    /// Check the message of the syscall executed from `snapshot`, and taint the
+    /// buffer filled by `read`.
+    pub fn check_syscall(&mut self, snapshot: &Snapshot, vm: &VM, accesses: &[MemoryAccess]) {
+        let (Some(syscall), Some((message, request))) = (snapshot.syscall(vm), snapshot.request())
+        else {
+            return;
+        };
+        let in_message = |address: u16| address.wrapping_sub(message) < MESSAGE_SIZE as u16;
+        let tainted = accesses
+            .iter()
+            .filter(|a| a.kind == AccessKind::Read)
+            .flat_map(|a| (0..a.width() as u16).map(move |i| a.address.wrapping_add(i)))
+            .find(|&address| in_message(address) && self.byte(address).is_some());
+        if let Some(address) = tainted {
+            let label = self.byte(address).unwrap();
+            let sink = TaintSink::Syscall {
+                number: syscall.number,
+                address,
+            };
+            self.report(sink, snapshot.ip, &label);
+        }
+
+        // Replies are clean, except the data read
+        for access in accesses.iter().filter(|a| a.kind == AccessKind::Write) {
+            for i in 0..access.width() as u16 {
+                self.set_byte(access.address.wrapping_add(i), None);
+            }
+        }
+        self.set_reg(Register::AX, [None, None]);
+        let count = syscall.result as i16;
+        if syscall.number == call::READ && count > 0 {
+            let ReadRequest(read) = ReadRequest::from_message(request);
+            self.add_source(read.buffer, count as usize, TaintSource::Read);
+        }
+    }
+
+    fn report(&mut self, sink: TaintSink, ip: u16, label: &Rc<TaintLabel>) {
+        if let Some(report) = self
+            .reports
+            .iter_mut()
+            .find(|r| r.ip == ip && r.sink == sink)
+        {
+            report.count += 1;
+            return;
+        }
+        self.reports.push(TaintReport {
+            sink,
+            ip,
+            label: (**label).clone(),
+            count: 1,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::{StepOutcome, VmIrExecutable};
+    use crate::test_utils::vm_with_text;
+
+    fn run(mut vm: VM) -> Taint {
+        while vm.step() == Ok(StepOutcome::Running) {}
+        vm.taint.unwrap()
+    }
+
+    #[test]
+    fn test_tainted_return_address() {
+        let mut vm = vm_with_text(&[
+            0x8b, 0x06, 0x00, 0x02, // mov ax, [0200]
+            0x50, // push ax
+            0xc3, // ret
+            0xf4, // hlt
+        ]);
+        vm.data.write_word(0x0200, 0x0006);
+        let mut taint = Taint::new(&vm);
+        taint.add_source(0x0200, 2, TaintSource::Environment);
+        vm.taint = Some(taint);
+
+        let taint = run(vm);
+        assert_eq!(
+            taint.reports,
+            vec![TaintReport {
+                sink: TaintSink::Return { target: 0x0006 },
+                ip: 0x0005,
+                label: TaintLabel {
+                    source: TaintSource::Environment,
+                    address: 0x0200,
+                    chain: vec![0x0000, 0x0004],
+                },
+                count: 1,
+            }]
+        );
+        assert_eq!(
+            taint.reports[0].describe(&SymbolTable::default()),
+            "return to 0006 from a tainted return address\n    \
+             at 0005\n    \
+             tainted by environment at 0200\n    \
+             through 0000\n    \
+             through 0004\n"
+        );
+    }
+
+    #[test]
+    fn test_tainted_jump_from_argv() {
+        let mut vm = vm_with_text(&[
+            0x89, 0xe3, // mov bx, sp
+            0x8b, 0x5f, 0x04, // mov bx, [bx+4]
+            0x8b, 0x1f, // mov bx, [bx]
+            0xff, 0xe3, // jmp bx
+            0xf4, // hlt
+            0xf4, // hlt
+            0xf4, // hlt
+        ]);
+        vm.set_args(vec!["prog".to_string(), "\x0b".to_string()]);
+        vm.taint = Some(Taint::new(&vm));
+
+        let taint = run(vm);
+        assert_eq!(taint.reports.len(), 1);
+        let report = &taint.reports[0];
+        assert_eq!(report.sink, TaintSink::IndirectJump { target: 0x000b });
+        assert_eq!(report.ip, 0x0007);
+        assert_eq!(report.label.source, TaintSource::Argv);
+        assert_eq!(report.label.address, 0x0efe);
+        assert_eq!(report.label.chain, vec![0x0005]);
+    }
+
+    #[test]
+    fn test_tainted_syscall_message() {
+        let mut vm = vm_with_text(&[
+            0x8b, 0x06, 0x00, 0x02, // mov ax, [0200]
+            0x89, 0x06, 0x02, 0x03, // mov [0302], ax
+            0xbb, 0x00, 0x03, // mov bx, 0300
+            0xb8, 0x00, 0x00, // mov ax, 0000
+            0xb9, 0x03, 0x00, // mov cx, 0003
+            0xcd, 0x20, // int 20
+        ]);
+        // exit
+        vm.data.write_word(0x0200, call::EXIT);
+        let mut taint = Taint::new(&vm);
+        taint.add_source(0x0200, 2, TaintSource::Environment);
+        vm.taint = Some(taint);
+
+        let taint = run(vm);
+        assert_eq!(
+            taint.reports[0].sink,
+            TaintSink::Syscall {
+                number: call::EXIT,
+                address: 0x0302,
+            }
+        );
+        assert_eq!(taint.reports[0].label.chain, vec![0x0000, 0x0004]);
+    }
+
+    #[test]
+    fn test_tainted_jump_from_environment() {
+        let mut vm = vm_with_text(&[
+            0x89, 0xe3, // mov bx, sp
+            0x8b, 0x5f, 0x06, // mov bx, [bx+6]
+            0x8b, 0x1f, // mov bx, [bx]
+            0xff, 0xe3, // jmp bx
+            0xf4, // hlt
+            0xf4, // hlt
+            0xf4, // hlt
+        ]);
+        vm.set_args_and_env(vec!["prog".to_string()], vec!["\x0b".to_string()]);
+        // Word aligned below the 7 bytes of strings
+        assert_eq!(vm.regs.get(Register::SP), 0x0eee);
+        vm.taint = Some(Taint::new(&vm));
+
+        let taint = run(vm);
+        assert_eq!(taint.reports.len(), 1);
+        let report = &taint.reports[0];
+        assert_eq!(report.sink, TaintSink::IndirectJump { target: 0x000b });
+        assert_eq!(report.label.source, TaintSource::Environment);
+        assert_eq!(report.label.address, 0x0efe);
+        assert_eq!(report.label.chain, vec![0x0005]);
+    }
+}
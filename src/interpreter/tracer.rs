@@ -0,0 +1,440 @@
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use super::memory::{AccessKind, MemoryAccess};
+use super::snapshot::{Snapshot, SyscallRecord};
+use super::vm::VM;
+use crate::utils::json_string;
+use crate::x86::Register;
+
+const BINARY_MAGIC: &[u8; 4] = b"MXT1";
+
+/// Output format of the structured trace.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TraceFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// Compact little endian records, see `TraceRecord::write_binary`
+    Binary,
+}
+
+/// Selects the instructions written to the trace.
+/// An instruction is traced if its address is in one of the `ranges` (or `ranges` is empty),
+/// and its index is in the `window` (or `window` is `None`).
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TraceFilter {
+    pub ranges: Vec<Range<u16>>,
+    pub window: Option<Range<usize>>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, index: usize, ip: u16) -> bool {
+        let in_range = self.ranges.is_empty() || self.ranges.iter().any(|r| r.contains(&ip));
+        let in_window = self.window.as_ref().map_or(true, |w| w.contains(&index));
+        in_range && in_window
+    }
+}
+
+/// Trace entry for a single executed instruction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceRecord {
+    /// Number of instructions executed before this one
+    pub index: usize,
+    pub ip: u16,
+    pub raw: Vec<u8>,
+    pub ir: String,
+    /// 16 bits registers, in `Register::iter_16` order
+    pub regs_before: [u16; 8],
+    pub regs_after: [u16; 8],
+    /// 8086 FLAGS register
+    pub flags_before: u16,
+    pub flags_after: u16,
+    pub memory: Vec<MemoryAccess>,
+    pub syscall: Option<SyscallRecord>,
+}
+
+impl TraceRecord {
+    pub(super) fn new(
+        index: usize,
+        snapshot: &Snapshot,
+        vm: &VM,
+        accesses: &[MemoryAccess],
+    ) -> Self {
+        let mut regs_before = [0; 8];
+        for (i, (_, value)) in snapshot.regs.iter().enumerate() {
+            regs_before[i] = *value;
+        }
+        let mut regs_after = [0; 8];
+        for (i, reg) in Register::iter_16().enumerate() {
+            regs_after[i] = vm.regs.get(reg);
+        }
+        let (raw, ir) = match &snapshot.instruction {
+            Some(instruction) => (instruction.raw.clone(), instruction.ir.to_string()),
+            None => (vec![], String::new()),
+        };
+
+        TraceRecord {
+            index,
+            ip: snapshot.ip,
+            raw,
+            ir,
+            regs_before,
+            regs_after,
+            flags_before: snapshot.flags_word(),
+            flags_after: vm.flags.to_word(),
+            memory: accesses.to_vec(),
+            syscall: snapshot.syscall(vm),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let regs = |values: &[u16; 8]| {
+            Register::iter_16()
+                .zip(values.iter())
+                .map(|(reg, value)| format!("\"{}\":{}", reg, value))
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        let memory = self
+            .memory
+            .iter()
+            .map(|access| match access.kind {
+                AccessKind::Read => format!(
+                    "{{\"kind\":\"read\",\"address\":{},\"width\":{},\"value\":{}}}",
+                    access.address,
+                    access.width(),
+                    access.value()
+                ),
+                AccessKind::Write => format!(
+                    "{{\"kind\":\"write\",\"address\":{},\"width\":{},\"value\":{},\"old\":{}}}",
+                    access.address,
+                    access.width(),
+                    access.value(),
+                    access.old_value()
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let syscall = match &self.syscall {
+            Some(s) => format!("{{\"number\":{},\"result\":{}}}", s.number, s.result),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"index\":{},\"ip\":{},\"raw\":\"{}\",\"ir\":{},\"regs_before\":{{{}}},\"regs_after\":{{{}}},\"flags_before\":{},\"flags_after\":{},\"memory\":[{}],\"syscall\":{}}}",
+            self.index,
+            self.ip,
+            self.raw
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>()
+                .concat(),
+            json_string(&self.ir),
+            regs(&self.regs_before),
+            regs(&self.regs_after),
+            self.flags_before,
+            self.flags_after,
+            memory,
+            syscall
+        )
+    }
+
+    /// Write the record in the binary trace format:
+    /// ```text
+    /// u32 index, u16 ip, u8 raw length, raw bytes, u16 ir length, ir text,
+    /// 8 x u16 registers before, 8 x u16 registers after, u16 flags before, u16 flags after,
+    /// u16 access count, for each access: u8 kind (0 read, 1 write), u16 address, u16 width,
+    ///     bytes before, bytes after,
+    /// u8 syscall present, if present: u16 number, u16 result
+    /// ```
+    pub fn write_binary<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&(self.index as u32).to_le_bytes())?;
+        w.write_all(&self.ip.to_le_bytes())?;
+        w.write_all(&[self.raw.len() as u8])?;
+        w.write_all(&self.raw)?;
+        w.write_all(&(self.ir.len() as u16).to_le_bytes())?;
+        w.write_all(self.ir.as_bytes())?;
+        for value in self.regs_before.iter().chain(self.regs_after.iter()) {
+            w.write_all(&value.to_le_bytes())?;
+        }
+        w.write_all(&self.flags_before.to_le_bytes())?;
+        w.write_all(&self.flags_after.to_le_bytes())?;
+        w.write_all(&(self.memory.len() as u16).to_le_bytes())?;
+        for access in &self.memory {
+            let kind = match access.kind {
+                AccessKind::Read => 0,
+                AccessKind::Write => 1,
+            };
+            w.write_all(&[kind])?;
+            w.write_all(&access.address.to_le_bytes())?;
+            w.write_all(&(access.width() as u16).to_le_bytes())?;
+            w.write_all(&access.before)?;
+            w.write_all(&access.after)?;
+        }
+        match &self.syscall {
+            Some(s) => {
+                w.write_all(&[1])?;
+                w.write_all(&s.number.to_le_bytes())?;
+                w.write_all(&s.result.to_le_bytes())?;
+            }
+            None => w.write_all(&[0])?,
+        }
+        Ok(())
+    }
+
+    /// Read a record written by `write_binary`, returns `None` at the end of the input.
+    pub fn read_binary<R: Read>(r: &mut R) -> io::Result<Option<Self>> {
+        let mut index = [0; 4];
+        match r.read_exact(&mut index) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let index = u32::from_le_bytes(index) as usize;
+        let ip = read_u16(r)?;
+        let raw_len = read_bytes(r, 1)?[0] as usize;
+        let raw = read_bytes(r, raw_len)?;
+        let ir_len = read_u16(r)? as usize;
+        let ir = String::from_utf8(read_bytes(r, ir_len)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut regs_before = [0; 8];
+        for value in regs_before.iter_mut() {
+            *value = read_u16(r)?;
+        }
+        let mut regs_after = [0; 8];
+        for value in regs_after.iter_mut() {
+            *value = read_u16(r)?;
+        }
+        let flags_before = read_u16(r)?;
+        let flags_after = read_u16(r)?;
+        let count = read_u16(r)?;
+        let mut memory = Vec::new();
+        for _ in 0..count {
+            let kind = match read_bytes(r, 1)?[0] {
+                0 => AccessKind::Read,
+                _ => AccessKind::Write,
+            };
+            let address = read_u16(r)?;
+            let width = read_u16(r)? as usize;
+            memory.push(MemoryAccess {
+                kind,
+                address,
+                before: read_bytes(r, width)?,
+                after: read_bytes(r, width)?,
+            });
+        }
+        let syscall = match read_bytes(r, 1)?[0] {
+            0 => None,
+            _ => Some(SyscallRecord {
+                number: read_u16(r)?,
+                result: read_u16(r)?,
+            }),
+        };
+
+        Ok(Some(TraceRecord {
+            index,
+            ip,
+            raw,
+            ir,
+            regs_before,
+            regs_after,
+            flags_before,
+            flags_after,
+            memory,
+            syscall,
+        }))
+    }
+}
+
+fn read_bytes<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<u8>> {
+    let mut buffer = vec![0; n];
+    r.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let b = read_bytes(r, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+/// Read all the records of a binary trace.
+pub fn read_binary_trace<R: Read>(r: &mut R) -> io::Result<Vec<TraceRecord>> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a binary trace",
+        ));
+    }
+    let mut records = Vec::new();
+    while let Some(record) = TraceRecord::read_binary(r)? {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Writes a structured trace of the executed instructions.
+pub struct Tracer {
+    pub format: TraceFormat,
+    pub filter: TraceFilter,
+    output: Box<dyn Write>,
+    // Number of instructions executed since the tracer was attached
+    count: usize,
+}
+
+impl Tracer {
+    /// Tracer writing to `output`, starting with the magic of the binary format.
+    pub fn new(
+        format: TraceFormat,
+        filter: TraceFilter,
+        mut output: Box<dyn Write>,
+    ) -> io::Result<Self> {
+        if format == TraceFormat::Binary {
+            output.write_all(BINARY_MAGIC)?;
+        }
+        Ok(Tracer {
+            format,
+            filter,
+            output,
+            count: 0,
+        })
+    }
+
+    /// Index of the next executed instruction.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Return true if the next instruction, at `ip`, should be traced.
+    pub fn wants(&self, ip: u16) -> bool {
+        self.filter.matches(self.count, ip)
+    }
+
+    /// Count an executed instruction, and write its record if given.
+    pub fn record(&mut self, record: Option<TraceRecord>) -> io::Result<()> {
+        self.count += 1;
+        match record {
+            Some(record) => match self.format {
+                TraceFormat::JsonLines => writeln!(self.output, "{}", record.to_json()),
+                TraceFormat::Binary => record.write_binary(&mut self.output),
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::{StepOutcome, VmIrExecutable};
+    use crate::interpreter::Memory;
+    use crate::test_utils::{program, SharedBuffer};
+
+    fn traced_vm(format: TraceFormat, filter: TraceFilter) -> (VM, SharedBuffer) {
+        let mut vm = VM::from(program("1.c"));
+        let buffer = SharedBuffer::default();
+        vm.tracer = Some(Tracer::new(format, filter, Box::new(buffer.clone())).unwrap());
+        (vm, buffer)
+    }
+
+    fn run_to_exit(vm: &mut VM) {
+        while vm.step().unwrap() == StepOutcome::Running {}
+    }
+
+    #[test]
+    fn test_json_lines_trace() {
+        let filter = TraceFilter {
+            ranges: vec![],
+            window: Some(0..3),
+        };
+        let (mut vm, buffer) = traced_vm(TraceFormat::JsonLines, filter);
+        run_to_exit(&mut vm);
+
+        let output = buffer.text();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[2],
+            "{\"index\":2,\"ip\":4,\"raw\":\"8b07\",\"ir\":\"mov ax, [bx]\",\
+            \"regs_before\":{\"ax\":0,\"cx\":0,\"dx\":0,\"bx\":65498,\"sp\":65498,\"bp\":0,\"si\":0,\"di\":0},\
+            \"regs_after\":{\"ax\":1,\"cx\":0,\"dx\":0,\"bx\":65498,\"sp\":65498,\"bp\":0,\"si\":0,\"di\":0},\
+            \"flags_before\":68,\"flags_after\":68,\
+            \"memory\":[{\"kind\":\"read\",\"address\":65498,\"width\":2,\"value\":1}],\"syscall\":null}"
+        );
+    }
+
+    #[test]
+    fn test_binary_trace_round_trip() {
+        // Only the `__sendrec` function, which makes the syscalls
+        let sendrec = 0x121..0x133;
+        let filter = TraceFilter {
+            ranges: vec![sendrec.clone()],
+            window: None,
+        };
+        let (mut vm, buffer) = traced_vm(TraceFormat::Binary, filter);
+        run_to_exit(&mut vm);
+
+        let bytes = buffer.bytes();
+        let records = read_binary_trace(&mut bytes.as_slice()).unwrap();
+        assert!(records.iter().all(|r| sendrec.contains(&r.ip)));
+
+        let syscalls: Vec<SyscallRecord> = records.iter().filter_map(|r| r.syscall).collect();
+        assert_eq!(
+            syscalls,
+            vec![
+                SyscallRecord {
+                    number: 4,
+                    result: 6
+                },
+                SyscallRecord {
+                    number: 1,
+                    result: 1
+                }
+            ]
+        );
+
+        // The reply of the write syscall replaced the message,
+        // with the number of bytes written as its type
+        let write = records.iter().find(|r| r.syscall.is_some()).unwrap();
+        let reply = write
+            .memory
+            .iter()
+            .find(|a| a.kind == AccessKind::Write)
+            .unwrap();
+        assert_eq!(
+            (&reply.before[2..4], &reply.after[2..4]),
+            (&[4, 0][..], &[6, 0][..])
+        );
+    }
+
+    #[test]
+    fn test_flush_on_error() {
+        // mov ax, 1; int 21
+        let mut vm = VM {
+            text: Memory::from(vec![0xb8, 0x01, 0x00, 0xcd, 0x21]),
+            ..VM::default()
+        };
+        let buffer = SharedBuffer::default();
+        let output = Box::new(io::BufWriter::new(buffer.clone()));
+        vm.tracer =
+            Some(Tracer::new(TraceFormat::JsonLines, TraceFilter::default(), output).unwrap());
+        assert!(vm.run_until_stop().is_err());
+        assert_eq!(buffer.text().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_magic_error() {
+        // No room for the magic
+        let output = Box::new(io::Cursor::new([0u8; 2]));
+        let tracer = Tracer::new(TraceFormat::Binary, TraceFilter::default(), output);
+        assert_eq!(
+            tracer.err().map(|e| e.kind()),
+            Some(io::ErrorKind::WriteZero)
+        );
+    }
+}
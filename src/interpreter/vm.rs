@@ -1,20 +1,39 @@
 use core::str;
 
+use super::coverage::Coverage;
+use super::debugger::Debugger;
 use super::error::{InterpreterError, OpcodeExecErrors};
 use super::flag_set::FlagSet;
+use super::hooks::{HookAction, Hooks};
+use super::ipc::{Ipc, SyscallPolicy, SyscallTable, Tty, UnknownSyscall};
+use super::memcheck::Memcheck;
 use super::memory::Memory;
+use super::profiler::{ProfileMode, Profiler};
+use super::recorder::{Recorder, Reversible, StepDelta};
 use super::register_set::RegisterSet;
+use super::shadow_stack::ShadowStack;
+use super::snapshot::Snapshot;
+use super::strace::Strace;
+use super::taint::Taint;
+use super::tracer::{TraceFilter, TraceFormat, TraceRecord, Tracer};
+use super::watchpoint::{WatchKind, Watchpoint, Watchpoints};
+use crate::disassembler::{Disassemblable, DisassembledProgram, Signatures};
 use crate::interpreter::flag_set::Flag;
+use crate::minix::{Program, SymbolTable};
 use crate::utils::{min, HexdumpFormatter};
+use crate::x86::IR;
 use crate::x86::{Address, Displacement, Operand, Register};
-use crate::{minix::Program, x86::IR};
 
 // Opcode implementations
 mod opcodes;
 use opcodes::OpcodeExecutable;
 
+// Micro-op execution
+mod machine;
+
+/// The 8086 virtual machine running a Minix 2 program.
 #[allow(dead_code)]
-struct VM {
+pub struct VM {
     // cpu
     pub ip: u16,
     // memory
@@ -23,8 +42,43 @@ struct VM {
     // registers, flags
     pub regs: RegisterSet,
     pub flags: FlagSet,
+    // kernel and servers
+    pub ipc: Ipc,
     // configs
     pub trace: bool,
+    // execution history, when recording is enabled
+    pub recorder: Option<Recorder>,
+    // structured trace output
+    pub tracer: Option<Tracer>,
+    // instruction and branch counts
+    pub coverage: Option<Coverage>,
+    // call stack samples
+    pub profiler: Option<Profiler>,
+    // data memory watchpoints
+    pub watchpoints: Option<Watchpoints>,
+    // shadow memory checker
+    pub memcheck: Option<Memcheck>,
+    // shadow call stack checker
+    pub shadow_stack: Option<ShadowStack>,
+    // decoded syscall trace
+    pub strace: Option<Strace>,
+    // taint tracker
+    pub taint: Option<Taint>,
+    // callbacks of an embedding program
+    pub hooks: Option<Hooks>,
+}
+
+/// The state of the VM after executing a single instruction.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StepOutcome {
+    /// The instruction was executed, the VM can continue.
+    Running,
+    /// The VM reached a `hlt` instruction or the end of the text segment.
+    Halted,
+    /// The program called `exit`, with the given status.
+    Exited(u16),
+    /// The instruction failed to execute.
+    Faulted,
 }
 
 impl Default for VM {
@@ -35,13 +89,25 @@ impl Default for VM {
         let regs = RegisterSet::new();
         let flags = FlagSet::new();
         let ip = 0;
+        let ipc = Ipc::default();
         VM {
             ip,
             text,
             data,
             regs,
             flags,
+            ipc,
             trace: false,
+            recorder: None,
+            tracer: None,
+            coverage: None,
+            profiler: None,
+            watchpoints: None,
+            memcheck: None,
+            shadow_stack: None,
+            strace: None,
+            taint: None,
+            hooks: None,
         }
     }
 }
@@ -61,13 +127,25 @@ impl From<Program> for VM {
 
         let flags = FlagSet::new();
         let ip = 0;
+        let ipc = Ipc::new((program.header.data + program.header.bss) as u16);
         VM {
             ip,
             text,
             data,
             regs,
             flags,
+            ipc,
             trace: false,
+            recorder: None,
+            tracer: None,
+            coverage: None,
+            profiler: None,
+            watchpoints: None,
+            memcheck: None,
+            shadow_stack: None,
+            strace: None,
+            taint: None,
+            hooks: None,
         }
     }
 }
@@ -80,11 +158,15 @@ pub trait VmIrExecutable: OpcodeExecutable {
     // Execute the decoded instruction
     // + Implicit store
     fn execute(&mut self, ir: IR) -> Result<(), OpcodeExecErrors>;
+    // Fetch, decode and execute the instruction at ip
+    fn step(&mut self) -> Result<StepOutcome, InterpreterError>;
     // Run the VM from the program loaded in memory
     fn run(&mut self) -> Result<(), InterpreterError>;
 }
 
 const MAX_INSTRUCTION_SIZE: usize = 15;
+// Frames deeper than this are not recovered from the BP chain
+const MAX_BACKTRACE: usize = 64;
 impl VmIrExecutable for VM {
     fn fetch(&self) -> Option<&[u8]> {
         let ip = self.ip;
@@ -151,100 +233,280 @@ impl VmIrExecutable for VM {
         }
     }
 
-    fn run(&mut self) -> Result<(), InterpreterError> {
-        self.trace(" AX   BX   CX   DX   SP   BP   SI   DI  FLAGS IP\n");
-        let mut cycle_count = 0;
-        while let Some(ir) = self.fetch() {
-            let (decoded_ir, ir_len) = self.decode(ir);
-
-            match decoded_ir {
-                IR::Hlt => {
-                    return Ok(());
+    fn step(&mut self) -> Result<StepOutcome, InterpreterError> {
+        // Replay the recorded history instead of executing again
+        if let Some(outcome) = self.replay_step() {
+            return Ok(outcome);
+        }
+        let ip = self.ip;
+        if self.profiler.is_some() {
+            if let Some((ir, _)) = self.fetch().map(|chunk| self.decode(chunk)) {
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.tick(&ir);
                 }
+            }
+        }
+        let observed = self.recorder.is_some()
+            || self.tracer.is_some()
+            || self.watchpoints.is_some()
+            || self.memcheck.is_some()
+            || self.shadow_stack.is_some()
+            || self.strace.is_some()
+            || self.taint.is_some()
+            || self.hooks.is_some();
+        let result = if !observed {
+            self.step_live().map(|outcome| (outcome, true))
+        } else {
+            self.step_observed()
+        };
+
+        if let Some(coverage) = &mut self.coverage {
+            match result {
+                Ok((StepOutcome::Running, true)) => coverage.hit(ip, Some(self.ip)),
+                Ok((_, true)) if (ip as usize) < self.text.len() => coverage.hit(ip, None),
                 _ => {}
             }
+        }
+        result.map(|(outcome, _)| outcome)
+    }
 
-            self.trace(
-                format!(
-                    "{:<62} {}",
-                    {
-                        let mut regs = String::new();
-                        for reg in vec![
-                            Register::AX,
-                            Register::BX,
-                            Register::CX,
-                            Register::DX,
-                            Register::SP,
-                            Register::BP,
-                            Register::SI,
-                            Register::DI,
-                        ] {
-                            regs.push_str(&format!("{:04x} ", self.regs.get(reg)));
-                        }
-                        let mut flags = String::new();
-                        // if self.flags.get(Flag::Parity) {
-                        //     flags.push('P');
-                        // } else {
-                        //     flags.push('-');
-                        // }
-                        flags.push('-'); // ?
-                        if self.flags.get(Flag::Sign) {
-                            flags.push('S');
-                        } else {
-                            flags.push('-');
-                        }
-                        if self.flags.get(Flag::Zero) {
-                            flags.push('Z');
-                        } else {
-                            flags.push('-');
-                        }
-                        if self.flags.get(Flag::Carry) {
-                            flags.push('C');
-                        } else {
-                            flags.push('-');
-                        }
-
-                        format!(
-                            "{}{} {:04x}:{}",
-                            regs,
-                            flags,
-                            self.ip,
-                            &ir[..ir_len]
-                                .iter()
-                                .map(|b| format!("{:02x}", b))
-                                .collect::<String>(),
-                        )
-                    },
-                    decoded_ir
-                )
-                .as_str(),
-            );
+    fn run(&mut self) -> Result<(), InterpreterError> {
+        if let StepOutcome::Exited(status) = self.run_until_stop()? {
+            exec_exit(status as i32);
+        }
+        Ok(())
+    }
+}
 
-            // Increment the instruction pointer (ip) appropriately
-            self.ip += ir_len as u16;
+// Small trick to not exit the program when running tests
+#[cfg(not(test))]
+fn exec_exit(code: i32) {
+    std::process::exit(code);
+}
 
-            match self.execute(decoded_ir) {
-                Ok(_) => {}
-                Err(e) => match e {
-                    OpcodeExecErrors::ExitCatch => {
-                        self.trace("\n");
-                        return Ok(());
-                    }
-                    _ => {
-                        return Err(InterpreterError::OpcodeExecutionError(e));
-                    }
-                },
-            };
+#[cfg(test)]
+fn exec_exit(_code: i32) {}
+
+impl VM {
+    /// Run the program until it halts, exits or faults, without exiting the process.
+    pub fn run_until_stop(&mut self) -> Result<StepOutcome, InterpreterError> {
+        self.trace(" AX   BX   CX   DX   SP   BP   SI   DI  FLAGS IP\n");
+        let outcome = self.run_steps();
+        // The traces end with the run, whatever stopped it
+        let flushed = self.flush_trace();
+        let outcome = outcome?;
+        flushed?;
+        Ok(outcome)
+    }
+
+    fn run_steps(&mut self) -> Result<StepOutcome, InterpreterError> {
+        let mut cycle_count = 0;
+        loop {
+            match self.step()? {
+                StepOutcome::Running => {}
+                outcome => return Ok(outcome),
+            }
 
-            self.trace("\n");
             // Check cycle count
             cycle_count += 1;
             if cycle_count > 999999 {
                 return Err(InterpreterError::CycleLimitExceeded);
             }
         }
+    }
+
+    /// Step while recording the instruction effects for the recorder, the tracer,
+    /// the watchpoints, the memory checker, the shadow stack, the syscall tracer,
+    /// the taint tracker and the hooks. Also return false if a hook skipped the
+    /// instruction.
+    fn step_observed(&mut self) -> Result<(StepOutcome, bool), InterpreterError> {
+        let snapshot = Snapshot::take(self);
+        // The writes of the hooks are part of the step
+        self.data.start_log();
+        // A hook of the address may return from the function instead
+        let action = match self.hooks.take() {
+            Some(mut hooks) => {
+                let action = hooks.before(self, &snapshot);
+                self.hooks = Some(hooks);
+                action
+            }
+            None => HookAction::Continue,
+        };
+        // The observers see the skipped function return
+        let snapshot = match action {
+            HookAction::Continue => snapshot,
+            HookAction::Return(_) => snapshot.returning(),
+        };
+        // Taint flows through the instruction from the state before it
+        if let Some(mut taint) = self.taint.take() {
+            taint.propagate(&snapshot, self);
+            self.taint = Some(taint);
+        }
+        let result = match action {
+            HookAction::Continue => self.step_live(),
+            HookAction::Return(value) => self.emulate_return(value),
+        };
+        let accesses = self.data.take_log();
+        let outcome = match &result {
+            Ok(outcome) => *outcome,
+            Err(_) => StepOutcome::Faulted,
+        };
+
+        if self.recorder.is_some() {
+            let delta = StepDelta::new(&snapshot, self, &accesses, outcome);
+            if let Some(recorder) = &mut self.recorder {
+                recorder.push(delta);
+            }
+        }
+        if let Some(mut tracer) = self.tracer.take() {
+            let record = tracer
+                .wants(snapshot.ip)
+                .then(|| TraceRecord::new(tracer.count(), &snapshot, self, &accesses));
+            let written = tracer.record(record);
+            self.tracer = Some(tracer);
+            written.map_err(|e| InterpreterError::IoError(e.to_string()))?;
+        }
+        if let Some(watchpoints) = &mut self.watchpoints {
+            // The recorder knows the index when moving in the history
+            if let Some(recorder) = &self.recorder {
+                watchpoints.count = recorder.position().saturating_sub(1);
+            }
+            watchpoints.check(snapshot.ip, &accesses);
+        }
+        if let Some(mut memcheck) = self.memcheck.take() {
+            memcheck.check(&snapshot, self, &accesses);
+            self.memcheck = Some(memcheck);
+        }
+        if let Some(mut shadow_stack) = self.shadow_stack.take() {
+            shadow_stack.check(&snapshot, self, &accesses);
+            self.shadow_stack = Some(shadow_stack);
+        }
+        if let Some(mut strace) = self.strace.take() {
+            let written = strace.record(&snapshot, self, outcome);
+            self.strace = Some(strace);
+            written.map_err(|e| InterpreterError::IoError(e.to_string()))?;
+        }
+        if let Some(mut taint) = self.taint.take() {
+            taint.check_syscall(&snapshot, self, &accesses);
+            self.taint = Some(taint);
+        }
+        if let Some(mut hooks) = self.hooks.take() {
+            hooks.after(self, snapshot.ip, &accesses, outcome);
+            self.hooks = Some(hooks);
+        }
+        result.map(|outcome| (outcome, action == HookAction::Continue))
+    }
+
+    /// Return from the function entered at ip to its caller, with the value in AX.
+    fn emulate_return(&mut self, value: u16) -> Result<StepOutcome, InterpreterError> {
+        self.regs.set(Register::AX, value);
+        self.ret(None)
+            .map_err(InterpreterError::OpcodeExecutionError)?;
+        Ok(StepOutcome::Running)
+    }
+
+    fn flush_trace(&mut self) -> Result<(), InterpreterError> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer
+                .flush()
+                .map_err(|e| InterpreterError::IoError(e.to_string()))?;
+        }
+        if let Some(strace) = &mut self.strace {
+            strace
+                .flush()
+                .map_err(|e| InterpreterError::IoError(e.to_string()))?;
+        }
         Ok(())
     }
+
+    fn step_live(&mut self) -> Result<StepOutcome, InterpreterError> {
+        let Some(ir) = self.fetch() else {
+            return Ok(StepOutcome::Halted);
+        };
+        let (decoded_ir, ir_len) = self.decode(ir);
+
+        match decoded_ir {
+            IR::Hlt => {
+                return Ok(StepOutcome::Halted);
+            }
+            _ => {}
+        }
+
+        self.trace(
+            format!(
+                "{:<62} {}",
+                {
+                    let mut regs = String::new();
+                    for reg in vec![
+                        Register::AX,
+                        Register::BX,
+                        Register::CX,
+                        Register::DX,
+                        Register::SP,
+                        Register::BP,
+                        Register::SI,
+                        Register::DI,
+                    ] {
+                        regs.push_str(&format!("{:04x} ", self.regs.get(reg)));
+                    }
+                    let mut flags = String::new();
+                    // if self.flags.get(Flag::Parity) {
+                    //     flags.push('P');
+                    // } else {
+                    //     flags.push('-');
+                    // }
+                    flags.push('-'); // ?
+                    if self.flags.get(Flag::Sign) {
+                        flags.push('S');
+                    } else {
+                        flags.push('-');
+                    }
+                    if self.flags.get(Flag::Zero) {
+                        flags.push('Z');
+                    } else {
+                        flags.push('-');
+                    }
+                    if self.flags.get(Flag::Carry) {
+                        flags.push('C');
+                    } else {
+                        flags.push('-');
+                    }
+
+                    format!(
+                        "{}{} {:04x}:{}",
+                        regs,
+                        flags,
+                        self.ip,
+                        &ir[..ir_len]
+                            .iter()
+                            .map(|b| format!("{:02x}", b))
+                            .collect::<String>(),
+                    )
+                },
+                decoded_ir
+            )
+            .as_str(),
+        );
+
+        // Increment the instruction pointer (ip) appropriately
+        self.ip += ir_len as u16;
+
+        match self.execute(decoded_ir) {
+            Ok(_) => {}
+            Err(e) => match e {
+                OpcodeExecErrors::ExitCatch(status) => {
+                    self.trace("\n");
+                    return Ok(StepOutcome::Exited(status));
+                }
+                _ => {
+                    return Err(InterpreterError::OpcodeExecutionError(e));
+                }
+            },
+        };
+
+        self.trace("\n");
+        Ok(StepOutcome::Running)
+    }
 }
 
 pub trait Interpretable {
@@ -256,6 +518,10 @@ impl Interpretable for Program {
         let mut vm = VM::from(self);
         vm.set_args(args);
         vm.set_trace(trace);
+        // The trace doesn't depend on the host terminal
+        if !trace {
+            vm.ipc.fs.tty = Tty::host();
+        }
         vm.run()
     }
 }
@@ -297,14 +563,14 @@ impl VirtualMemory for Address {
 
     fn write_value(&self, vm: &mut VM, value: u16) {
         let ea = self.get_effective_address(vm);
-        let ev = vm.data.read_word(ea);
+        let ev = vm.data.peek_word(ea);
         vm.trace(format!(" ;[{:04x}]{:04x}", ea, ev).as_str());
         vm.data.write_word(ea, value);
     }
 
     fn trace(&self, vm: &VM) {
         let ea = self.get_effective_address(vm);
-        let ev = vm.data.read_word(ea);
+        let ev = vm.data.peek_word(ea);
         vm.trace(format!(" ;[{:04x}]{:04x}", ea, ev).as_str());
     }
 }
@@ -333,33 +599,62 @@ impl VM {
         }
     }
 
-    fn set_trace(&mut self, trace: bool) {
+    pub fn set_trace(&mut self, trace: bool) {
         self.trace = trace;
     }
 
-    fn trace(&self, str: &str) {
+    pub(crate) fn trace(&self, str: &str) {
         if self.trace {
             print!("{}", str);
         }
     }
 
-    fn set_args(&mut self, args: Vec<String>) {
+    /// Current IP followed by the return address of each frame, innermost first,
+    /// following the saved BP of each `push bp; mov bp, sp` prologue.
+    pub fn backtrace(&self) -> Vec<u16> {
+        let mut addresses = vec![self.ip];
+        let mut bp = self.regs.get(Register::BP);
+        while bp != 0 && (bp as usize) + 4 <= self.data.len() && addresses.len() < MAX_BACKTRACE {
+            addresses.push(self.data.peek_word(bp + 2));
+            let saved_bp = self.data.peek_word(bp);
+            // The stack grows down, saved frames are above
+            if saved_bp <= bp {
+                break;
+            }
+            bp = saved_bp;
+        }
+        addresses
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.set_args_and_env(args, Vec::new());
+    }
+
+    /// Write the strings on the stack, below them the NULL terminated envp, then
+    /// the NULL terminated argv and argc, as the Minix exec.
+    pub fn set_args_and_env(&mut self, args: Vec<String>, env: Vec<String>) {
         let mut argv_pointers = Vec::new();
+        let mut envp_pointers = Vec::new();
         let mut total_length = 0;
 
-        for arg in &args {
-            total_length += arg.len() + 1; // each argument string + null terminator
+        for string in args.iter().chain(&env) {
+            total_length += string.len() + 1; // each string + null terminator
         }
 
         let initial_sp = self.regs.get(Register::SP);
         let arg_data_start = initial_sp.wrapping_sub(total_length as u16);
 
         let mut current_sp = arg_data_start;
-        for arg in &args {
-            argv_pointers.push(current_sp); // Record the pointer to this argument
+        for (index, string) in args.iter().chain(&env).enumerate() {
+            // Record the pointer to this string
+            if index < args.len() {
+                argv_pointers.push(current_sp);
+            } else {
+                envp_pointers.push(current_sp);
+            }
 
-            // Copy the argument string and null terminator into memory
-            for byte in arg.bytes() {
+            // Copy the string and null terminator into memory
+            for byte in string.bytes() {
                 self.data.write_bytes(current_sp, &[byte]);
                 current_sp = current_sp.wrapping_add(1);
             }
@@ -367,16 +662,19 @@ impl VM {
             current_sp = current_sp.wrapping_add(1);
         }
 
-        self.regs.set(Register::SP, arg_data_start);
+        // The pointers are words, aligned below the strings
+        self.regs.set(Register::SP, arg_data_start & !1);
 
-        self.regs
-            .set(Register::SP, self.regs.get(Register::SP).wrapping_sub(2));
-        self.data.write_word(self.regs.get(Register::SP), 0);
-
-        for &pointer in argv_pointers.iter().rev() {
+        for pointers in [envp_pointers, argv_pointers.clone()] {
             self.regs
                 .set(Register::SP, self.regs.get(Register::SP).wrapping_sub(2));
-            self.data.write_word(self.regs.get(Register::SP), pointer);
+            self.data.write_word(self.regs.get(Register::SP), 0);
+
+            for &pointer in pointers.iter().rev() {
+                self.regs
+                    .set(Register::SP, self.regs.get(Register::SP).wrapping_sub(2));
+                self.data.write_word(self.regs.get(Register::SP), pointer);
+            }
         }
 
         // Push argc
@@ -401,21 +699,454 @@ impl std::fmt::Display for VM {
     }
 }
 
-pub fn vm_interpret(args: Vec<String>) {
+/// Remove the `--name=value` option from the arguments following the binary file, and return its value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let index = args
+        .iter()
+        .skip(2)
+        .position(|arg| arg.starts_with(&prefix))?
+        + 2;
+    Some(args.remove(index)[prefix.len()..].to_string())
+}
+
+/// Remove every `--name=value` option following the binary file, and return the values.
+fn take_options(args: &mut Vec<String>, name: &str) -> Vec<String> {
+    std::iter::from_fn(|| take_option(args, name)).collect()
+}
+
+/// Remove the `--name` flag from the arguments following the binary file, and return true if it was present.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().skip(2).position(|arg| arg == name) {
+        Some(index) => {
+            args.remove(index + 2);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Parse `start-end` into a range, with the given radix.
+fn parse_range<T: TryFrom<u32>>(s: &str, radix: u32) -> Option<std::ops::Range<T>> {
+    let (start, end) = s.split_once('-')?;
+    let start = u32::from_str_radix(start.trim_start_matches("0x"), radix).ok()?;
+    let end = u32::from_str_radix(end.trim_start_matches("0x"), radix).ok()?;
+    Some(T::try_from(start).ok()?..T::try_from(end).ok()?)
+}
+
+/// Symbols of the functions recognized by the built-in signatures,
+/// and by those of the `--signatures=FILE` option.
+fn recognize_functions(program: &Program, path: Option<String>) -> Result<SymbolTable, String> {
+    let mut signatures = Signatures::minix();
+    if let Some(path) = path {
+        let file = Signatures::from_file(&path).map_err(|e| format!("{}: {}", path, e))?;
+        signatures.merge(file);
+    }
+    // The interpreter still runs a text that doesn't disassemble
+    match program.disassemble() {
+        Ok(disassembled) => Ok(signatures.recognize(&disassembled)),
+        Err(e) => {
+            eprintln!("warning: no function recognized: {}", e);
+            Ok(SymbolTable::default())
+        }
+    }
+}
+
+/// Build the structured tracer from the `--trace-*` options.
+fn tracer_from_args(
+    args: &mut Vec<String>,
+    symbols: &SymbolTable,
+) -> Result<Option<Tracer>, String> {
+    let json = take_option(args, "--trace-json");
+    let binary = take_option(args, "--trace-bin");
+    let range = take_option(args, "--trace-range");
+    let function = take_option(args, "--trace-function");
+    let window = take_option(args, "--trace-window");
+
+    let (format, path) = match (json, binary) {
+        (Some(path), None) => (TraceFormat::JsonLines, path),
+        (None, Some(path)) => (TraceFormat::Binary, path),
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => return Err("Only one trace format can be used".to_string()),
+    };
+
+    let mut filter = TraceFilter::default();
+    if let Some(range) = range {
+        let range = parse_range(&range, 16).ok_or(format!("Invalid address range: {}", range))?;
+        filter.ranges.push(range);
+    }
+    if let Some(function) = function {
+        let range = symbols
+            .function_range(&function)
+            .ok_or(format!("Unknown function: {}", function))?;
+        filter.ranges.push(range);
+    }
+    if let Some(window) = window {
+        filter.window =
+            Some(parse_range(&window, 10).ok_or(format!("Invalid window: {}", window))?);
+    }
+
+    let file = std::fs::File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let output = Box::new(std::io::BufWriter::new(file));
+    let tracer = Tracer::new(format, filter, output).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(Some(tracer))
+}
+
+/// Syscall tracer from the `--strace` flag, writing to stderr, or the `--strace=FILE` option.
+fn strace_from_args(args: &mut Vec<String>) -> Result<Option<Strace>, String> {
+    if take_flag(args, "--strace") {
+        return Ok(Some(Strace::new(Box::new(std::io::stderr()))));
+    }
+    let Some(path) = take_option(args, "--strace") else {
+        return Ok(None);
+    };
+    let file = std::fs::File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(Some(Strace::new(Box::new(std::io::BufWriter::new(file)))))
+}
+
+/// Syscall table from the `--allow-syscalls=N,...`, `--deny-syscalls=N,...` and `--enosys` options.
+fn syscalls_from_args(args: &mut Vec<String>) -> Result<Option<SyscallTable>, String> {
+    let allowed = take_option(args, "--allow-syscalls");
+    let denied = take_option(args, "--deny-syscalls");
+    let enosys = take_flag(args, "--enosys");
+    if allowed.is_none() && denied.is_none() && !enosys {
+        return Ok(None);
+    }
+    let numbers = |list: &str| -> Result<Vec<u16>, String> {
+        list.split(',')
+            .map(|n| {
+                n.parse()
+                    .map_err(|_| format!("Invalid syscall number: {}", n))
+            })
+            .collect()
+    };
+    let mut syscalls = SyscallTable::new();
+    if let Some(allowed) = allowed {
+        syscalls.policy = SyscallPolicy::DenyByDefault;
+        for number in numbers(&allowed)? {
+            syscalls.allow(number);
+        }
+    }
+    if let Some(denied) = denied {
+        for number in numbers(&denied)? {
+            syscalls.deny(number);
+        }
+    }
+    if enosys {
+        syscalls.unknown = UnknownSyscall::Enosys;
+    }
+    Ok(Some(syscalls))
+}
+
+/// Log only watchpoints from the `--watch-log=START-END[,START-END...]` option.
+fn watchpoints_from_args(args: &mut Vec<String>) -> Result<Option<Watchpoints>, String> {
+    let Some(ranges) = take_option(args, "--watch-log") else {
+        return Ok(None);
+    };
+    let mut watchpoints = Watchpoints::new();
+    for range in ranges.split(',') {
+        let range = parse_range(range, 16).ok_or(format!("Invalid address range: {}", range))?;
+        watchpoints.add(Watchpoint {
+            range,
+            kind: WatchKind::Access,
+            log_only: true,
+        });
+    }
+    Ok(Some(watchpoints))
+}
+
+/// Output files of the `--coverage*` options.
+struct CoverageOptions {
+    /// Raw counts, merged with the counts of previous runs
+    data: Option<String>,
+    lcov: Option<String>,
+    cobertura: Option<String>,
+    listing: Option<String>,
+}
+
+impl CoverageOptions {
+    fn from_args(args: &mut Vec<String>) -> Option<Self> {
+        let options = CoverageOptions {
+            data: take_option(args, "--coverage"),
+            lcov: take_option(args, "--coverage-lcov"),
+            cobertura: take_option(args, "--coverage-xml"),
+            listing: take_option(args, "--coverage-listing"),
+        };
+        let any = options.data.is_some()
+            || options.lcov.is_some()
+            || options.cobertura.is_some()
+            || options.listing.is_some();
+        any.then_some(options)
+    }
+
+    fn write(
+        &self,
+        mut coverage: Coverage,
+        program: &DisassembledProgram,
+        symbols: &SymbolTable,
+        source: &str,
+    ) -> std::io::Result<()> {
+        if let Some(path) = &self.data {
+            if let Ok(file) = std::fs::File::open(path) {
+                coverage.merge(&Coverage::load(std::io::BufReader::new(file))?);
+            }
+            coverage.save(std::io::BufWriter::new(std::fs::File::create(path)?))?;
+        }
+        if let Some(path) = &self.lcov {
+            std::fs::write(path, coverage.lcov(program, symbols, source))?;
+        }
+        if let Some(path) = &self.cobertura {
+            std::fs::write(path, coverage.cobertura(program, symbols, source))?;
+        }
+        if let Some(path) = &self.listing {
+            std::fs::write(path, coverage.listing(program, symbols))?;
+        }
+        Ok(())
+    }
+}
+
+/// Options of the `--profile*` arguments.
+struct ProfileOptions {
+    mode: ProfileMode,
+    period: u64,
+    /// Flat profile and call graph
+    report: Option<String>,
+    folded: Option<String>,
+}
+
+impl ProfileOptions {
+    fn from_args(args: &mut Vec<String>) -> Result<Option<Self>, String> {
+        let report = take_option(args, "--profile");
+        let folded = take_option(args, "--profile-folded");
+        let mode = match take_option(args, "--profile-mode").as_deref() {
+            None | Some("instructions") => ProfileMode::Instructions,
+            Some("cycles") => ProfileMode::Cycles,
+            Some(mode) => return Err(format!("Invalid profile mode: {}", mode)),
+        };
+        let period = match take_option(args, "--profile-period") {
+            Some(period) => period
+                .parse()
+                .map_err(|_| format!("Invalid profile period: {}", period))?,
+            None => 1,
+        };
+        if report.is_none() && folded.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(ProfileOptions {
+            mode,
+            period,
+            report,
+            folded,
+        }))
+    }
+
+    fn write(&self, profiler: &Profiler, symbols: &SymbolTable) -> std::io::Result<()> {
+        if let Some(path) = &self.report {
+            let report = format!(
+                "Flat profile:\n\n{}\nCall graph:\n\n{}",
+                profiler.flat(symbols),
+                profiler.call_graph(symbols)
+            );
+            std::fs::write(path, report)?;
+        }
+        if let Some(path) = &self.folded {
+            std::fs::write(path, profiler.folded(symbols))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn vm_interpret(mut args: Vec<String>) {
     // Args validation
     if args.len() < 2 {
-        println!("Usage: {} <binary file> [-m] additional_args", args[0]);
+        println!(
+            "Usage: {} <binary file> [-m|-d] [--trace-json=FILE|--trace-bin=FILE] \
+            [--trace-range=START-END] [--trace-function=NAME] [--trace-window=FROM-TO] \
+            [--coverage=FILE] [--coverage-lcov=FILE] [--coverage-xml=FILE] [--coverage-listing=FILE] \
+            [--profile=FILE] [--profile-folded=FILE] [--profile-mode=instructions|cycles] [--profile-period=N] \
+            [--watch-log=START-END[,START-END...]] [--memcheck] [--shadow-stack] [--taint] [--strace[=FILE]] \
+            [--allow-syscalls=N[,N...]] [--deny-syscalls=N[,N...]] [--enosys] \
+            [--env=NAME=VALUE...] [--signatures=FILE] additional_args",
+            args[0]
+        );
         return;
     }
 
+    // Open file
+    let file = std::fs::File::open(&args[1]).unwrap();
+    let mut program = Program::from_file(file).unwrap();
+
+    // Names of the library functions of a binary without symbols, for the reports
+    let signatures = take_option(&mut args, "--signatures");
+    let reports_functions = args.iter().skip(2).any(|arg| {
+        [
+            "--trace-",
+            "--coverage",
+            "--profile",
+            "--memcheck",
+            "--shadow-stack",
+        ]
+        .iter()
+        .any(|option| arg.starts_with(option))
+    });
+    if program.symbols.is_empty() && reports_functions {
+        match recognize_functions(&program, signatures) {
+            Ok(symbols) => program.symbols = symbols,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+
+    // Structured trace
+    let tracer = match tracer_from_args(&mut args, &program.symbols) {
+        Ok(tracer) => tracer,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // Coverage
+    let coverage = CoverageOptions::from_args(&mut args);
+    // Profiler
+    let profile = match ProfileOptions::from_args(&mut args) {
+        Ok(profile) => profile,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // Watchpoints
+    let watchpoints = match watchpoints_from_args(&mut args) {
+        Ok(watchpoints) => watchpoints,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    // Memory checker
+    let memcheck = take_flag(&mut args, "--memcheck");
+    // Shadow call stack
+    let shadow_stack = take_flag(&mut args, "--shadow-stack");
+    // Taint tracking
+    let taint = take_flag(&mut args, "--taint");
+    // Environment strings of the program, tainted with --taint
+    let env = take_options(&mut args, "--env");
+    // Syscall trace
+    let strace = match strace_from_args(&mut args) {
+        Ok(strace) => strace,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // Syscall policy
+    let syscalls = match syscalls_from_args(&mut args) {
+        Ok(syscalls) => syscalls,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
     // Logger
     let trace = args.len() > 2 && args[2] == "-m";
+    // Debugger
+    let debug = args.len() > 2 && args[2] == "-d";
     // remove the first arg
     let parsed_args = args[1..].to_vec();
 
-    // Open file
-    let file = std::fs::File::open(&args[1]).unwrap();
-    let program = Program::from_file(file).unwrap();
+    if debug {
+        let mut vm = VM::from(program);
+        vm.set_args_and_env(parsed_args, env);
+        let mut debugger = Debugger::new(vm);
+        debugger
+            .repl(std::io::stdin().lock(), std::io::stdout())
+            .unwrap();
+        return;
+    }
+
+    if tracer.is_some()
+        || coverage.is_some()
+        || profile.is_some()
+        || watchpoints.is_some()
+        || memcheck
+        || shadow_stack
+        || taint
+        || strace.is_some()
+        || syscalls.is_some()
+        || !env.is_empty()
+    {
+        let disassembled = coverage.as_ref().map(|_| program.disassemble().unwrap());
+        let symbols = program.symbols.clone();
+        let bss_end = (program.header.data + program.header.bss) as u16;
+        let mut vm = VM::from(program);
+        vm.set_args_and_env(parsed_args, env);
+        vm.set_trace(trace);
+        if !trace {
+            vm.ipc.fs.tty = Tty::host();
+        }
+        vm.tracer = tracer;
+        vm.coverage = coverage.as_ref().map(|_| Coverage::new());
+        vm.profiler = profile
+            .as_ref()
+            .map(|options| Profiler::new(options.mode, options.period, &vm));
+        vm.watchpoints = watchpoints;
+        vm.memcheck = memcheck.then(|| Memcheck::new(&vm, bss_end));
+        vm.shadow_stack = shadow_stack.then(|| ShadowStack::new(bss_end));
+        vm.strace = strace;
+        vm.taint = taint.then(|| Taint::new(&vm));
+        if let Some(syscalls) = syscalls {
+            vm.ipc.syscalls = syscalls;
+        }
+
+        let outcome = vm.run_until_stop().unwrap();
+        if let (Some(options), Some(counts), Some(disassembled)) =
+            (coverage, vm.coverage.take(), disassembled)
+        {
+            let source = format!("{}.s", args[1]);
+            if let Err(e) = options.write(counts, &disassembled, &symbols, &source) {
+                println!("Coverage: {}", e);
+            }
+        }
+        if let (Some(options), Some(profiler)) = (profile, &vm.profiler) {
+            if let Err(e) = options.write(profiler, &symbols) {
+                println!("Profile: {}", e);
+            }
+        }
+        if let Some(watchpoints) = &vm.watchpoints {
+            for hit in &watchpoints.log {
+                eprintln!("{}", hit);
+            }
+        }
+        if let Some(memcheck) = &vm.memcheck {
+            for report in &memcheck.reports {
+                eprint!("{}", report.describe(&symbols));
+            }
+            eprintln!("memcheck: {} errors", memcheck.reports.len());
+        }
+        if let Some(shadow_stack) = &vm.shadow_stack {
+            for report in &shadow_stack.reports {
+                eprint!("{}", report.describe(&symbols));
+            }
+            eprintln!("shadow stack: {} errors", shadow_stack.reports.len());
+        }
+        if let Some(taint) = &vm.taint {
+            for report in &taint.reports {
+                eprint!("{}", report.describe(&symbols));
+            }
+            eprintln!("taint: {} reports", taint.reports.len());
+        }
+        for number in &vm.ipc.syscalls.log {
+            eprintln!("unimplemented syscall {}", number);
+        }
+        if let StepOutcome::Exited(status) = outcome {
+            exec_exit(status as i32);
+        }
+        return;
+    }
 
     // Interpreter
     program.interpret(trace, parsed_args).unwrap();
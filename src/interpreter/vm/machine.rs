@@ -0,0 +1,110 @@
+use super::{OpcodeExecutable, StepOutcome, VmIrExecutable, VM};
+use crate::il::{execute, Liftable, Machine};
+use crate::interpreter::{error::OpcodeExecErrors, Flag, InterpreterError};
+use crate::x86::{Register, IR};
+
+impl Machine for VM {
+    fn register(&self, reg: Register) -> u16 {
+        self.regs.get(reg)
+    }
+    fn set_register(&mut self, reg: Register, value: u16) {
+        self.regs.set(reg, value);
+    }
+    fn flag(&self, flag: Flag) -> bool {
+        self.flags.get(flag)
+    }
+    fn set_flag(&mut self, flag: Flag, value: bool) {
+        self.flags.set(flag, value);
+    }
+    fn load(&self, address: u16, byte: bool) -> u16 {
+        if byte {
+            self.data.read(address) as u16
+        } else {
+            self.data.read_word(address)
+        }
+    }
+    fn store(&mut self, address: u16, value: u16, byte: bool) {
+        if byte {
+            self.data.write(address, value as u8);
+        } else {
+            self.data.write_word(address, value);
+        }
+    }
+    fn set_ip(&mut self, ip: u16) {
+        self.ip = ip;
+    }
+    fn interrupt(&mut self, number: u8) -> Result<(), OpcodeExecErrors> {
+        self.int(number)
+    }
+    fn port_in(&mut self, _port: u16) -> u16 {
+        0x42
+    }
+    fn port_out(&mut self, _port: u16, _value: u16) {}
+}
+
+impl VM {
+    /// Execute the instruction at ip from its micro-ops, without tracing.
+    pub fn step_lifted(&mut self) -> Result<StepOutcome, InterpreterError> {
+        let Some(chunk) = self.fetch() else {
+            return Ok(StepOutcome::Halted);
+        };
+        let (instruction, len) = match crate::disassembler::parse_instruction(chunk, self.ip.into())
+        {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("Error decoding instruction: {:?}", e),
+        };
+        if instruction.ir == IR::Hlt {
+            return Ok(StepOutcome::Halted);
+        }
+        let ops = instruction.lift(self.ip);
+        self.ip = self.ip.wrapping_add(len as u16);
+        match execute(&ops, self) {
+            Ok(outcome) => Ok(outcome),
+            Err(OpcodeExecErrors::ExitCatch(status)) => Ok(StepOutcome::Exited(status)),
+            Err(e) => Err(InterpreterError::OpcodeExecutionError(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::program;
+
+    fn state(vm: &VM) -> (u16, Vec<u16>, Vec<bool>) {
+        (
+            vm.ip,
+            Register::iter_16().map(|r| vm.regs.get(r)).collect(),
+            Flag::iter().map(|f| vm.flags.get(f)).collect(),
+        )
+    }
+
+    /// Run the program on two VMs, one executing the micro-ops,
+    /// and compare the states after each instruction.
+    fn assert_lockstep(file: &str) {
+        let (mut vm, mut lifted) = (VM::from(program(file)), VM::from(program(file)));
+        loop {
+            let ip = vm.ip;
+            let outcome = vm.step();
+            assert_eq!(lifted.step_lifted(), outcome, "{}: at {:04x}", file, ip);
+            assert_eq!(state(&lifted), state(&vm), "{}: at {:04x}", file, ip);
+            assert!(
+                lifted.data.data == vm.data.data,
+                "{}: memory differs at {:04x}",
+                file,
+                ip
+            );
+            if outcome != Ok(StepOutcome::Running) {
+                assert!(matches!(outcome, Ok(StepOutcome::Exited(_))), "{}", file);
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_lifted_lockstep() {
+        for file in ["1.c", "2.c", "3.c", "4.c", "5.c", "6.c", "7.c"] {
+            assert_lockstep(file);
+        }
+    }
+}
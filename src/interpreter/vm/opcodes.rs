@@ -1,6 +1,6 @@
 use super::{VirtualMemory, VM};
 use crate::{
-    interpreter::{error::OpcodeExecErrors, flag_set::Flag},
+    interpreter::{error::OpcodeExecErrors, flag_set::Flag, ipc::Ipc},
     x86::{Operand, Register},
 };
 
@@ -45,15 +45,6 @@ pub trait OpcodeExecutable {
     fn sar(&mut self, dest: Operand, src: Operand) -> Result<(), OpcodeExecErrors>;
 }
 
-// Small trick to not exit the program when running tests
-#[cfg(not(test))]
-fn exec_exit(code: i32) {
-    std::process::exit(code);
-}
-
-#[cfg(test)]
-fn exec_exit(_code: i32) {}
-
 impl OpcodeExecutable for VM {
     fn mov(&mut self, dest: Operand, src: Operand, _byte: bool) -> Result<(), OpcodeExecErrors> {
         let src_value = self.read_value(&src);
@@ -64,91 +55,14 @@ impl OpcodeExecutable for VM {
         match int_type {
             // Syscalls
             0x20 => {
-                // struct message {
-                //     uint16_t m_source;
-                //     uint16_t m_type;
-                //     union m_u;
-                // };
-                let message_struct_ea = self.regs.get(Register::BX) as u16;
-                let message_type_ea = message_struct_ea + 2;
-                let i1_ea = message_struct_ea + 4;
-                let i2_ea = message_struct_ea + 6;
-                let i3_ea = message_struct_ea + 8;
-                let m1_p1_ea = message_struct_ea + 10;
-                let m2_p1_ea = message_struct_ea + 18;
-
-                let message_type = self.data.read_word(message_type_ea);
-                match message_type {
-                    1 => {
-                        // exit(status)
-                        // status = m1_i1
-                        let status = self.data.read_word(i1_ea);
-                        self.trace(format!("\n<exit({})>", status).as_str());
-                        exec_exit(status as i32);
-                        return Err(OpcodeExecErrors::ExitCatch);
-                    }
-                    4 => {
-                        // write(fd, buffer, nbytes)
-                        // fd = m1_i1
-                        // nbytes = m1_i2
-                        // buffer = m1_p1
-                        let nbytes = self.data.read_word(i2_ea);
-                        let buffer = self.data.read_word(m1_p1_ea);
-
-                        // set AX to 0
-                        self.regs.set(Register::AX, 0);
-                        // Return nb of bytes written
-                        let return_value = nbytes;
-                        self.data.write_word(message_type_ea, nbytes);
-
-                        let content =
-                            String::from_utf8_lossy(self.data.read_bytes(buffer, nbytes as usize));
-                        self.trace(
-                            format!(
-                                "\n<write({}, {:#06x}, {}){} => {}>",
-                                1, buffer, nbytes, content, return_value
-                            )
-                            .as_str(),
-                        );
-                        // if not trace
-                        if !self.trace {
-                            print!("{}", content);
-                        }
-                        Ok(())
-                    }
-                    17 => {
-                        // brk(addr)
-                        // addr = m1_p1
-                        let addr = self.data.read_word(m1_p1_ea);
-
-                        // return 0
-                        self.data.write_word(message_type_ea, 0);
-                        self.trace(format!("\n<brk({:#06x}) => {}>", addr, 0).as_str());
-                        Ok(())
-                    }
-                    54 => {
-                        // ioctl(fd, request, data)
-                        // fd = TTY_LINE = DEVICE = m2_i1
-                        // request = TTY_REQUEST = COUNT = m2_i3
-                        // data = ADDRESS = m2_p1
-                        let fd = self.data.read_word(i1_ea);
-                        let request = self.data.read_word(i3_ea);
-                        let data = self.data.read_word(m2_p1_ea);
-                        self.trace(
-                            format!("\n<ioctl({}, {:#04x}, {:#04x})>", fd, request, data).as_str(),
-                        );
-
-                        // return ffea (not sure why yet)
-                        self.data.write_word(message_type_ea, 0xffea);
-
-                        // set AX to 0
-                        self.regs.set(Register::AX, 0);
-                        Ok(())
-                    }
-                    _ => Err(OpcodeExecErrors::UnimplementedSyscall(
-                        message_type as usize,
-                    )),
-                }
+                // sendrec(src_dest, message), function in CX
+                let src_dest = self.regs.get(Register::AX);
+                let message = self.regs.get(Register::BX);
+                let function = self.regs.get(Register::CX);
+
+                let status = Ipc::sys_call(self, function, src_dest, message)?;
+                self.regs.set(Register::AX, status as u16);
+                Ok(())
             }
             _ => Err(OpcodeExecErrors::UnimplementedInterrupt(int_type as usize)),
         }
@@ -324,6 +238,9 @@ impl OpcodeExecutable for VM {
         let ea = self.regs.get(Register::SP).wrapping_sub(2) as u16;
         self.data.write_word(ea, self.ip);
         self.regs.set(Register::SP, ea);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(value, self.ip);
+        }
         self.ip = value;
         Ok(())
     }
@@ -340,6 +257,9 @@ impl OpcodeExecutable for VM {
         };
         self.regs
             .set(Register::SP, ea.wrapping_add(2 + released_bytes));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.ret(value);
+        }
         self.ip = value;
         Ok(())
     }
@@ -0,0 +1,237 @@
+use std::ops::Range;
+
+use super::memory::{AccessKind, MemoryAccess};
+
+/// Accesses that trigger a watchpoint.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Read or write
+    Access,
+}
+
+impl WatchKind {
+    pub fn matches(&self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+/// A watched range of the data memory.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Watchpoint {
+    pub range: Range<u16>,
+    pub kind: WatchKind,
+    /// Only log the accesses, without stopping the execution
+    pub log_only: bool,
+}
+
+impl Watchpoint {
+    /// Return true if the access overlaps the watched range.
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        let start = access.address as usize;
+        let end = start + access.width();
+        self.kind.matches(access.kind)
+            && start < self.range.end as usize
+            && (self.range.start as usize) < end
+    }
+}
+
+/// An access that triggered a watchpoint.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WatchHit {
+    /// Index of the watchpoint
+    pub watchpoint: usize,
+    /// Number of instructions executed before the accessing one
+    pub index: usize,
+    /// Address of the accessing instruction
+    pub ip: u16,
+    pub kind: AccessKind,
+    pub address: u16,
+    /// Access width, in bytes
+    pub width: usize,
+    pub old_value: u16,
+    pub new_value: u16,
+}
+
+impl std::fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+        };
+        write!(
+            f,
+            "watchpoint {}: #{} ip={:04x} {} [{:04x}] {:0w$x} -> {:0w$x} ({} bytes)",
+            self.watchpoint,
+            self.index,
+            self.ip,
+            kind,
+            self.address,
+            self.old_value,
+            self.new_value,
+            self.width,
+            w = self.width * 2
+        )
+    }
+}
+
+/// Watchpoints on the data memory, checked against the memory accesses of each instruction,
+/// made by the operands, the stack (`push`, `pop`, `call`, `ret`) and the syscalls.
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    /// Watchpoints by index, removed ones are `None`
+    pub watchpoints: Vec<Option<Watchpoint>>,
+    /// Hits of stopping watchpoints, not yet handled
+    pub hits: Vec<WatchHit>,
+    /// Hits of log only watchpoints
+    pub log: Vec<WatchHit>,
+    /// Index of the next checked instruction
+    pub count: usize,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Watchpoints::default()
+    }
+
+    /// Add a watchpoint and return its index.
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(Some(watchpoint));
+        self.watchpoints.len() - 1
+    }
+
+    /// Remove a watchpoint, return false if it doesn't exist.
+    pub fn remove(&mut self, index: usize) -> bool {
+        matches!(
+            self.watchpoints.get_mut(index).map(Option::take),
+            Some(Some(_))
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| w.as_ref().map(|w| (i, w)))
+    }
+
+    /// Check the accesses made by the instruction at `ip`.
+    pub fn check(&mut self, ip: u16, accesses: &[MemoryAccess]) {
+        for access in accesses {
+            for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+                let Some(watchpoint) = watchpoint else {
+                    continue;
+                };
+                if !watchpoint.matches(access) {
+                    continue;
+                }
+                let hit = WatchHit {
+                    watchpoint: index,
+                    index: self.count,
+                    ip,
+                    kind: access.kind,
+                    address: access.address,
+                    width: access.width(),
+                    old_value: access.old_value(),
+                    new_value: access.value(),
+                };
+                if watchpoint.log_only {
+                    self.log.push(hit);
+                } else {
+                    self.hits.push(hit);
+                }
+            }
+        }
+        self.count += 1;
+    }
+
+    /// Take the hits of stopping watchpoints.
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::{StepOutcome, VmIrExecutable, VM};
+    use crate::test_utils::program;
+
+    fn vm(file: &str) -> VM {
+        VM::from(program(file))
+    }
+
+    #[test]
+    fn test_watchpoint_matches() {
+        let watchpoint = Watchpoint {
+            range: 0x10..0x12,
+            kind: WatchKind::Write,
+            log_only: false,
+        };
+        let access = |kind, address| MemoryAccess {
+            kind,
+            address,
+            before: vec![0, 0],
+            after: vec![1, 0],
+        };
+        assert!(watchpoint.matches(&access(AccessKind::Write, 0x0f)));
+        assert!(watchpoint.matches(&access(AccessKind::Write, 0x11)));
+        assert!(!watchpoint.matches(&access(AccessKind::Write, 0x12)));
+        assert!(!watchpoint.matches(&access(AccessKind::Read, 0x10)));
+    }
+
+    #[test]
+    fn test_watch_global_write() {
+        let mut vm = vm("1.c");
+        let mut watchpoints = Watchpoints::new();
+        // mov [0002], bx in crtso
+        watchpoints.add(Watchpoint {
+            range: 0x0002..0x0004,
+            kind: WatchKind::Write,
+            log_only: false,
+        });
+        vm.watchpoints = Some(watchpoints);
+
+        while vm.watchpoints.as_ref().unwrap().hits.is_empty() {
+            assert_eq!(vm.step(), Ok(StepOutcome::Running));
+        }
+        let hits = vm.watchpoints.as_mut().unwrap().take_hits();
+        assert_eq!(
+            hits[0].to_string(),
+            "watchpoint 0: #14 ip=0024 write [0002] 0014 -> 0010 (2 bytes)"
+        );
+    }
+
+    #[test]
+    fn test_watch_log_stack() {
+        let mut vm = vm("1.c");
+        let mut watchpoints = Watchpoints::new();
+        // Stack below the initial stack pointer
+        watchpoints.add(Watchpoint {
+            range: 0xffc0..0xffda,
+            kind: WatchKind::Access,
+            log_only: true,
+        });
+        vm.watchpoints = Some(watchpoints);
+        vm.run().unwrap();
+
+        let watchpoints = vm.watchpoints.unwrap();
+        assert!(watchpoints.hits.is_empty());
+        // push cx in crtso
+        let push = &watchpoints.log[0];
+        assert_eq!(
+            (push.ip, push.kind, push.address, push.width),
+            (0x002e, AccessKind::Write, 0xffd8, 2)
+        );
+        // ret of main
+        assert!(watchpoints
+            .log
+            .iter()
+            .any(|hit| hit.ip == 0x0138 && hit.kind == AccessKind::Read));
+    }
+}
@@ -48,7 +48,14 @@ pub mod x86;
 
 /// Disassembler
 pub mod disassembler;
+/// Micro-op intermediate language lifted from the instructions
+pub mod il;
 /// Interpreter
 pub mod interpreter;
+/// Symbolic execution of the micro-ops, for test input generation
+pub mod symbolic;
 
 mod utils;
+
+#[cfg(test)]
+mod test_utils;
@@ -3,6 +3,7 @@ use crate::utils::HexdumpFormatter;
 use std::fmt;
 
 #[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     pub(crate) raw: [u8; 32],
     /* Short form: 32 bytes */
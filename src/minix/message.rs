@@ -0,0 +1,926 @@
+use crate::interpreter::Memory;
+
+/// Size of a message, in bytes.
+pub const MESSAGE_SIZE: usize = 24;
+
+// Size of the m3_ca1 string
+pub const M3_STRING: usize = 14;
+
+/// Minix call numbers.
+pub mod call {
+    pub const EXIT: u16 = 1;
+    pub const FORK: u16 = 2;
+    pub const READ: u16 = 3;
+    pub const WRITE: u16 = 4;
+    pub const OPEN: u16 = 5;
+    pub const CLOSE: u16 = 6;
+    pub const WAIT: u16 = 7;
+    pub const CREAT: u16 = 8;
+    pub const LINK: u16 = 9;
+    pub const UNLINK: u16 = 10;
+    pub const WAITPID: u16 = 11;
+    pub const CHDIR: u16 = 12;
+    pub const TIME: u16 = 13;
+    pub const MKNOD: u16 = 14;
+    pub const CHMOD: u16 = 15;
+    pub const CHOWN: u16 = 16;
+    pub const BRK: u16 = 17;
+    pub const STAT: u16 = 18;
+    pub const LSEEK: u16 = 19;
+    pub const GETPID: u16 = 20;
+    pub const MOUNT: u16 = 21;
+    pub const UMOUNT: u16 = 22;
+    pub const SETUID: u16 = 23;
+    pub const GETUID: u16 = 24;
+    pub const STIME: u16 = 25;
+    pub const PTRACE: u16 = 26;
+    pub const ALARM: u16 = 27;
+    pub const FSTAT: u16 = 28;
+    pub const PAUSE: u16 = 29;
+    pub const UTIME: u16 = 30;
+    pub const ACCESS: u16 = 33;
+    pub const SYNC: u16 = 36;
+    pub const KILL: u16 = 37;
+    pub const RENAME: u16 = 38;
+    pub const MKDIR: u16 = 39;
+    pub const RMDIR: u16 = 40;
+    pub const DUP: u16 = 41;
+    pub const PIPE: u16 = 42;
+    pub const TIMES: u16 = 43;
+    pub const SETGID: u16 = 46;
+    pub const GETGID: u16 = 47;
+    pub const SIGNAL: u16 = 48;
+    pub const IOCTL: u16 = 54;
+    pub const FCNTL: u16 = 55;
+    pub const EXEC: u16 = 59;
+    pub const UMASK: u16 = 60;
+    pub const CHROOT: u16 = 61;
+    pub const SETSID: u16 = 62;
+    pub const GETPGRP: u16 = 63;
+    pub const SIGACTION: u16 = 71;
+    pub const SIGSUSPEND: u16 = 72;
+    pub const SIGPENDING: u16 = 73;
+    pub const SIGPROCMASK: u16 = 74;
+    pub const SIGRETURN: u16 = 75;
+    pub const REBOOT: u16 = 76;
+    pub const SVRCTL: u16 = 77;
+}
+
+/// Error numbers, negated in the `m_type` of replies.
+pub mod errno {
+    pub const EPERM: i16 = 1;
+    pub const ENOENT: i16 = 2;
+    pub const EINTR: i16 = 4;
+    pub const EIO: i16 = 5;
+    pub const EBADF: i16 = 9;
+    pub const ENOMEM: i16 = 12;
+    pub const EFAULT: i16 = 14;
+    pub const EINVAL: i16 = 22;
+    pub const ENOTTY: i16 = 25;
+    pub const ENOSYS: i16 = 38;
+}
+
+/// A field of the message, `m_source`, `m_type` or a field of the union.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Field {
+    Source,
+    Type,
+    M1I1,
+    M1I2,
+    M1I3,
+    M1P1,
+    M1P2,
+    M1P3,
+    M2I1,
+    M2I2,
+    M2I3,
+    M2L1,
+    M2L2,
+    M2P1,
+    M3I1,
+    M3I2,
+    M3P1,
+    M3Ca1,
+    M4L1,
+    M4L2,
+    M4L3,
+    M4L4,
+    M5C1,
+    M5C2,
+    M5I1,
+    M5I2,
+    M5L1,
+    M5L2,
+    M5L3,
+    M6I1,
+    M6I2,
+    M6I3,
+    M6L1,
+    M6F1,
+}
+
+impl Field {
+    /// Offset in the message.
+    pub fn offset(&self) -> usize {
+        match self {
+            Field::Source => 0,
+            Field::Type => 2,
+            Field::M1I1 | Field::M2I1 | Field::M3I1 | Field::M4L1 | Field::M5C1 | Field::M6I1 => 4,
+            Field::M5C2 => 5,
+            Field::M1I2 | Field::M2I2 | Field::M3I2 | Field::M5I1 | Field::M6I2 => 6,
+            Field::M1I3 | Field::M2I3 | Field::M3P1 | Field::M4L2 | Field::M5I2 | Field::M6I3 => 8,
+            Field::M1P1 | Field::M2L1 | Field::M3Ca1 | Field::M5L1 | Field::M6L1 => 10,
+            Field::M1P2 | Field::M4L3 => 12,
+            Field::M1P3 | Field::M2L2 | Field::M5L2 | Field::M6F1 => 14,
+            Field::M4L4 => 16,
+            Field::M2P1 | Field::M5L3 => 18,
+        }
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Field::M5C1 | Field::M5C2 => 1,
+            Field::M2L1
+            | Field::M2L2
+            | Field::M4L1
+            | Field::M4L2
+            | Field::M4L3
+            | Field::M4L4
+            | Field::M5L1
+            | Field::M5L2
+            | Field::M5L3
+            | Field::M6L1 => 4,
+            Field::M3Ca1 => M3_STRING,
+            _ => 2,
+        }
+    }
+}
+
+/// `mess_1`: three ints and three pointers.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Mess1 {
+    pub i1: i16,
+    pub i2: i16,
+    pub i3: i16,
+    pub p1: u16,
+    pub p2: u16,
+    pub p3: u16,
+}
+
+/// `mess_2`: three ints, two longs and a pointer.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Mess2 {
+    pub i1: i16,
+    pub i2: i16,
+    pub i3: i16,
+    pub l1: i32,
+    pub l2: i32,
+    pub p1: u16,
+}
+
+/// `mess_3`: two ints, a pointer and a short string.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Mess3 {
+    pub i1: i16,
+    pub i2: i16,
+    pub p1: u16,
+    pub ca1: [u8; M3_STRING],
+}
+
+/// `mess_4`: four longs.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Mess4 {
+    pub l1: i32,
+    pub l2: i32,
+    pub l3: i32,
+    pub l4: i32,
+}
+
+/// `mess_5`: two chars, two ints and three longs.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Mess5 {
+    pub c1: u8,
+    pub c2: u8,
+    pub i1: i16,
+    pub i2: i16,
+    pub l1: i32,
+    pub l2: i32,
+    pub l3: i32,
+}
+
+/// `mess_6`: three ints, a long and a function pointer.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Mess6 {
+    pub i1: i16,
+    pub i2: i16,
+    pub i3: i16,
+    pub l1: i32,
+    pub f1: u16,
+}
+
+/// A Minix 2 message: source, type and the union of `mess_1` to `mess_6`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Message {
+    raw: [u8; MESSAGE_SIZE],
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Message {
+            raw: [0; MESSAGE_SIZE],
+        }
+    }
+}
+
+impl Message {
+    pub fn new(m_type: u16) -> Self {
+        let mut message = Message::default();
+        message.set_word(Field::Type, m_type);
+        message
+    }
+
+    /// Message from its bytes, missing bytes are zero.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut message = Message::default();
+        let size = bytes.len().min(MESSAGE_SIZE);
+        message.raw[..size].copy_from_slice(&bytes[..size]);
+        message
+    }
+
+    pub fn as_bytes(&self) -> &[u8; MESSAGE_SIZE] {
+        &self.raw
+    }
+
+    /// Whether a message at `address` lies inside the data memory.
+    pub fn fits(data: &Memory, address: u16) -> bool {
+        address as usize + MESSAGE_SIZE <= data.len()
+    }
+
+    /// Read the whole message from the data memory, `None` if it goes past its end.
+    pub fn read(data: &Memory, address: u16) -> Option<Self> {
+        Message::fits(data, address)
+            .then(|| Message::from_bytes(data.read_bytes(address, MESSAGE_SIZE)))
+    }
+
+    /// Read the message without logging the access.
+    pub fn peek(data: &Memory, address: u16) -> Self {
+        Message::from_bytes(data.peek_bytes(address, MESSAGE_SIZE))
+    }
+
+    /// Write the whole message to the data memory.
+    pub fn write(&self, data: &mut Memory, address: u16) {
+        data.write_bytes(address, &self.raw);
+    }
+
+    /// Read only the given fields from the data memory, the others are zero.
+    /// `None` if the message goes past the end of the memory.
+    pub fn read_fields(data: &Memory, address: u16, fields: &[Field]) -> Option<Self> {
+        if !Message::fits(data, address) {
+            return None;
+        }
+        let mut message = Message::default();
+        for field in fields {
+            let bytes = data.read_bytes(address + field.offset() as u16, field.size());
+            message.bytes_mut(*field).copy_from_slice(bytes);
+        }
+        Some(message)
+    }
+
+    /// Write only the given fields to the data memory.
+    pub fn write_fields(&self, data: &mut Memory, address: u16, fields: &[Field]) {
+        for field in fields {
+            data.write_bytes(
+                address.wrapping_add(field.offset() as u16),
+                self.bytes(*field),
+            );
+        }
+    }
+
+    pub fn bytes(&self, field: Field) -> &[u8] {
+        &self.raw[field.offset()..field.offset() + field.size()]
+    }
+
+    fn bytes_mut(&mut self, field: Field) -> &mut [u8] {
+        &mut self.raw[field.offset()..field.offset() + field.size()]
+    }
+
+    /// Value of a field of one or two bytes.
+    pub fn word(&self, field: Field) -> u16 {
+        match self.bytes(field) {
+            [byte] => *byte as u16,
+            bytes => u16::from_le_bytes([bytes[0], bytes[1]]),
+        }
+    }
+
+    pub fn set_word(&mut self, field: Field, value: u16) {
+        match self.bytes_mut(field) {
+            [byte] => *byte = value as u8,
+            bytes => bytes[..2].copy_from_slice(&value.to_le_bytes()),
+        }
+    }
+
+    /// Value of a long field.
+    pub fn long(&self, field: Field) -> i32 {
+        let bytes = self.bytes(field);
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    pub fn set_long(&mut self, field: Field, value: i32) {
+        self.bytes_mut(field)[..4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn source(&self) -> u16 {
+        self.word(Field::Source)
+    }
+
+    pub fn set_source(&mut self, source: u16) {
+        self.set_word(Field::Source, source);
+    }
+
+    pub fn m_type(&self) -> u16 {
+        self.word(Field::Type)
+    }
+
+    pub fn set_m_type(&mut self, m_type: u16) {
+        self.set_word(Field::Type, m_type);
+    }
+
+    /// `m_type` of a reply: the result, negative for an errno.
+    pub fn result(&self) -> i16 {
+        self.m_type() as i16
+    }
+
+    pub fn m1(&self) -> Mess1 {
+        Mess1 {
+            i1: self.word(Field::M1I1) as i16,
+            i2: self.word(Field::M1I2) as i16,
+            i3: self.word(Field::M1I3) as i16,
+            p1: self.word(Field::M1P1),
+            p2: self.word(Field::M1P2),
+            p3: self.word(Field::M1P3),
+        }
+    }
+
+    pub fn set_m1(&mut self, m1: Mess1) {
+        self.set_word(Field::M1I1, m1.i1 as u16);
+        self.set_word(Field::M1I2, m1.i2 as u16);
+        self.set_word(Field::M1I3, m1.i3 as u16);
+        self.set_word(Field::M1P1, m1.p1);
+        self.set_word(Field::M1P2, m1.p2);
+        self.set_word(Field::M1P3, m1.p3);
+    }
+
+    pub fn m2(&self) -> Mess2 {
+        Mess2 {
+            i1: self.word(Field::M2I1) as i16,
+            i2: self.word(Field::M2I2) as i16,
+            i3: self.word(Field::M2I3) as i16,
+            l1: self.long(Field::M2L1),
+            l2: self.long(Field::M2L2),
+            p1: self.word(Field::M2P1),
+        }
+    }
+
+    pub fn set_m2(&mut self, m2: Mess2) {
+        self.set_word(Field::M2I1, m2.i1 as u16);
+        self.set_word(Field::M2I2, m2.i2 as u16);
+        self.set_word(Field::M2I3, m2.i3 as u16);
+        self.set_long(Field::M2L1, m2.l1);
+        self.set_long(Field::M2L2, m2.l2);
+        self.set_word(Field::M2P1, m2.p1);
+    }
+
+    pub fn m3(&self) -> Mess3 {
+        Mess3 {
+            i1: self.word(Field::M3I1) as i16,
+            i2: self.word(Field::M3I2) as i16,
+            p1: self.word(Field::M3P1),
+            ca1: self.bytes(Field::M3Ca1).try_into().unwrap(),
+        }
+    }
+
+    pub fn set_m3(&mut self, m3: Mess3) {
+        self.set_word(Field::M3I1, m3.i1 as u16);
+        self.set_word(Field::M3I2, m3.i2 as u16);
+        self.set_word(Field::M3P1, m3.p1);
+        self.bytes_mut(Field::M3Ca1).copy_from_slice(&m3.ca1);
+    }
+
+    pub fn m4(&self) -> Mess4 {
+        Mess4 {
+            l1: self.long(Field::M4L1),
+            l2: self.long(Field::M4L2),
+            l3: self.long(Field::M4L3),
+            l4: self.long(Field::M4L4),
+        }
+    }
+
+    pub fn set_m4(&mut self, m4: Mess4) {
+        self.set_long(Field::M4L1, m4.l1);
+        self.set_long(Field::M4L2, m4.l2);
+        self.set_long(Field::M4L3, m4.l3);
+        self.set_long(Field::M4L4, m4.l4);
+    }
+
+    pub fn m5(&self) -> Mess5 {
+        Mess5 {
+            c1: self.word(Field::M5C1) as u8,
+            c2: self.word(Field::M5C2) as u8,
+            i1: self.word(Field::M5I1) as i16,
+            i2: self.word(Field::M5I2) as i16,
+            l1: self.long(Field::M5L1),
+            l2: self.long(Field::M5L2),
+            l3: self.long(Field::M5L3),
+        }
+    }
+
+    pub fn set_m5(&mut self, m5: Mess5) {
+        self.set_word(Field::M5C1, m5.c1 as u16);
+        self.set_word(Field::M5C2, m5.c2 as u16);
+        self.set_word(Field::M5I1, m5.i1 as u16);
+        self.set_word(Field::M5I2, m5.i2 as u16);
+        self.set_long(Field::M5L1, m5.l1);
+        self.set_long(Field::M5L2, m5.l2);
+        self.set_long(Field::M5L3, m5.l3);
+    }
+
+    pub fn m6(&self) -> Mess6 {
+        Mess6 {
+            i1: self.word(Field::M6I1) as i16,
+            i2: self.word(Field::M6I2) as i16,
+            i3: self.word(Field::M6I3) as i16,
+            l1: self.long(Field::M6L1),
+            f1: self.word(Field::M6F1),
+        }
+    }
+
+    pub fn set_m6(&mut self, m6: Mess6) {
+        self.set_word(Field::M6I1, m6.i1 as u16);
+        self.set_word(Field::M6I2, m6.i2 as u16);
+        self.set_word(Field::M6I3, m6.i3 as u16);
+        self.set_long(Field::M6L1, m6.l1);
+        self.set_word(Field::M6F1, m6.f1);
+    }
+}
+
+/// The request message of a call.
+pub trait Request: Sized {
+    /// Minix call number, the `m_type` of the request
+    const NUMBER: u16;
+    /// Fields of the union used by the call
+    const FIELDS: &'static [Field];
+
+    fn from_message(message: &Message) -> Self;
+    fn to_message(&self) -> Message;
+
+    /// Read the request from the data memory, accessing only its fields.
+    /// `None` if the message goes past the end of the memory.
+    fn read(data: &Memory, address: u16) -> Option<Self> {
+        Message::read_fields(data, address, Self::FIELDS)
+            .map(|message| Self::from_message(&message))
+    }
+}
+
+/// The reply message of a call.
+pub trait Reply: Sized {
+    /// Fields of the union set by the reply, besides `m_type`
+    const FIELDS: &'static [Field];
+
+    fn from_message(message: &Message) -> Self;
+    fn to_message(&self) -> Message;
+
+    /// Write `m_type` and the fields of the reply over the request.
+    fn write(&self, data: &mut Memory, address: u16) {
+        let message = self.to_message();
+        message.write_fields(data, address, &[Field::Type]);
+        message.write_fields(data, address, Self::FIELDS);
+    }
+}
+
+/// Reply with only a result, the common case.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct StatusReply {
+    /// Result, or a negative errno
+    pub result: i16,
+}
+
+impl Reply for StatusReply {
+    const FIELDS: &'static [Field] = &[];
+
+    fn from_message(message: &Message) -> Self {
+        StatusReply {
+            result: message.result(),
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        Message::new(self.result as u16)
+    }
+}
+
+/// `exit(status)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ExitRequest {
+    pub status: i16,
+}
+
+impl Request for ExitRequest {
+    const NUMBER: u16 = call::EXIT;
+    const FIELDS: &'static [Field] = &[Field::M1I1];
+
+    fn from_message(message: &Message) -> Self {
+        ExitRequest {
+            status: message.m1().i1,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(Self::NUMBER);
+        message.set_word(Field::M1I1, self.status as u16);
+        message
+    }
+}
+
+/// `read(fd, buffer, nbytes)` and `write(fd, buffer, nbytes)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TransferRequest {
+    pub fd: i16,
+    pub buffer: u16,
+    pub nbytes: u16,
+}
+
+impl TransferRequest {
+    fn from_m1(message: &Message) -> Self {
+        let m1 = message.m1();
+        TransferRequest {
+            fd: m1.i1,
+            buffer: m1.p1,
+            nbytes: m1.i2 as u16,
+        }
+    }
+
+    fn to_m1(self, number: u16) -> Message {
+        let mut message = Message::new(number);
+        message.set_m1(Mess1 {
+            i1: self.fd,
+            i2: self.nbytes as i16,
+            p1: self.buffer,
+            ..Mess1::default()
+        });
+        message
+    }
+}
+
+/// `read(fd, buffer, nbytes)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReadRequest(pub TransferRequest);
+
+impl Request for ReadRequest {
+    const NUMBER: u16 = call::READ;
+    const FIELDS: &'static [Field] = &[Field::M1I1, Field::M1I2, Field::M1P1];
+
+    fn from_message(message: &Message) -> Self {
+        ReadRequest(TransferRequest::from_m1(message))
+    }
+
+    fn to_message(&self) -> Message {
+        self.0.to_m1(Self::NUMBER)
+    }
+}
+
+/// `write(fd, buffer, nbytes)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WriteRequest(pub TransferRequest);
+
+impl Request for WriteRequest {
+    const NUMBER: u16 = call::WRITE;
+    const FIELDS: &'static [Field] = &[Field::M1I1, Field::M1I2, Field::M1P1];
+
+    fn from_message(message: &Message) -> Self {
+        WriteRequest(TransferRequest::from_m1(message))
+    }
+
+    fn to_message(&self) -> Message {
+        self.0.to_m1(Self::NUMBER)
+    }
+}
+
+// open flag selecting the m1 layout, with a mode
+pub const O_CREAT: i16 = 0o100;
+
+/// `open(name, flags[, mode])`, with the m1 layout when creating the file and m3 otherwise.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct OpenRequest {
+    pub name: u16,
+    /// Length of the name, including the NUL
+    pub length: i16,
+    pub flags: i16,
+    pub mode: Option<i16>,
+}
+
+impl Request for OpenRequest {
+    const NUMBER: u16 = call::OPEN;
+    // Fields common to both layouts: the length and the flags
+    const FIELDS: &'static [Field] = &[Field::M1I1, Field::M1I2];
+
+    fn read(data: &Memory, address: u16) -> Option<Self> {
+        let flags = Message::read_fields(data, address, &[Field::M1I2])?.m1().i2;
+        let fields: &[Field] = if flags & O_CREAT != 0 {
+            &[Field::M1I1, Field::M1I2, Field::M1I3, Field::M1P1]
+        } else {
+            // m3_ca1 is only a copy of short names
+            &[Field::M3I1, Field::M3I2, Field::M3P1]
+        };
+        Message::read_fields(data, address, fields).map(|message| Self::from_message(&message))
+    }
+
+    fn from_message(message: &Message) -> Self {
+        let m1 = message.m1();
+        if m1.i2 & O_CREAT != 0 {
+            OpenRequest {
+                name: m1.p1,
+                length: m1.i1,
+                flags: m1.i2,
+                mode: Some(m1.i3),
+            }
+        } else {
+            let m3 = message.m3();
+            OpenRequest {
+                name: m3.p1,
+                length: m3.i1,
+                flags: m3.i2,
+                mode: None,
+            }
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(Self::NUMBER);
+        match self.mode {
+            Some(mode) => message.set_m1(Mess1 {
+                i1: self.length,
+                i2: self.flags,
+                i3: mode,
+                p1: self.name,
+                ..Mess1::default()
+            }),
+            None => message.set_m3(Mess3 {
+                i1: self.length,
+                i2: self.flags,
+                p1: self.name,
+                ..Mess3::default()
+            }),
+        }
+        message
+    }
+}
+
+/// `close(fd)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CloseRequest {
+    pub fd: i16,
+}
+
+impl Request for CloseRequest {
+    const NUMBER: u16 = call::CLOSE;
+    const FIELDS: &'static [Field] = &[Field::M1I1];
+
+    fn from_message(message: &Message) -> Self {
+        CloseRequest {
+            fd: message.m1().i1,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(Self::NUMBER);
+        message.set_word(Field::M1I1, self.fd as u16);
+        message
+    }
+}
+
+/// `brk(addr)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BrkRequest {
+    pub addr: u16,
+}
+
+impl Request for BrkRequest {
+    const NUMBER: u16 = call::BRK;
+    const FIELDS: &'static [Field] = &[Field::M1P1];
+
+    fn from_message(message: &Message) -> Self {
+        BrkRequest {
+            addr: message.m1().p1,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(Self::NUMBER);
+        message.set_word(Field::M1P1, self.addr);
+        message
+    }
+}
+
+/// Reply of `brk`, with the new break.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BrkReply {
+    pub result: i16,
+    pub addr: u16,
+}
+
+impl Reply for BrkReply {
+    const FIELDS: &'static [Field] = &[Field::M2P1];
+
+    fn from_message(message: &Message) -> Self {
+        BrkReply {
+            result: message.result(),
+            addr: message.m2().p1,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(self.result as u16);
+        message.set_word(Field::M2P1, self.addr);
+        message
+    }
+}
+
+/// `lseek(fd, offset, whence)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LseekRequest {
+    pub fd: i16,
+    pub offset: i32,
+    pub whence: i16,
+}
+
+impl Request for LseekRequest {
+    const NUMBER: u16 = call::LSEEK;
+    const FIELDS: &'static [Field] = &[Field::M2I1, Field::M2I2, Field::M2L1];
+
+    fn from_message(message: &Message) -> Self {
+        let m2 = message.m2();
+        LseekRequest {
+            fd: m2.i1,
+            offset: m2.l1,
+            whence: m2.i2,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(Self::NUMBER);
+        message.set_m2(Mess2 {
+            i1: self.fd,
+            i2: self.whence,
+            l1: self.offset,
+            ..Mess2::default()
+        });
+        message
+    }
+}
+
+/// Reply of `lseek` and `time`, with a long result in m2_l1.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LongReply {
+    pub result: i16,
+    pub value: i32,
+}
+
+impl Reply for LongReply {
+    const FIELDS: &'static [Field] = &[Field::M2L1];
+
+    fn from_message(message: &Message) -> Self {
+        LongReply {
+            result: message.result(),
+            value: message.m2().l1,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(self.result as u16);
+        message.set_long(Field::M2L1, self.value);
+        message
+    }
+}
+
+/// `ioctl(fd, request, data)`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct IoctlRequest {
+    /// TTY_LINE
+    pub fd: i16,
+    /// TTY_REQUEST
+    pub request: u16,
+    /// ADDRESS
+    pub data: u16,
+}
+
+impl Request for IoctlRequest {
+    const NUMBER: u16 = call::IOCTL;
+    const FIELDS: &'static [Field] = &[Field::M2I1, Field::M2I3, Field::M2P1];
+
+    fn from_message(message: &Message) -> Self {
+        let m2 = message.m2();
+        IoctlRequest {
+            fd: m2.i1,
+            request: m2.i3 as u16,
+            data: m2.p1,
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::new(Self::NUMBER);
+        message.set_m2(Mess2 {
+            i1: self.fd,
+            i3: self.request as i16,
+            p1: self.data,
+            ..Mess2::default()
+        });
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_layout() {
+        let mut message = Message::new(call::WRITE);
+        message.set_m1(Mess1 {
+            i1: 1,
+            i2: 6,
+            i3: -1,
+            p1: 0x1234,
+            p2: 0,
+            p3: 0xabcd,
+        });
+        assert_eq!(
+            message.as_bytes(),
+            &[
+                0, 0, 4, 0, 1, 0, 6, 0, 0xff, 0xff, 0x34, 0x12, 0, 0, 0xcd, 0xab, 0, 0, 0, 0, 0, 0,
+                0, 0
+            ]
+        );
+        // Views of the same bytes
+        assert_eq!(message.m2().l1, 0x1234);
+        assert_eq!(message.m3().p1, 0xffff);
+        assert_eq!(message.m5().c1, 1);
+        assert_eq!(message.m6().f1, 0xabcd);
+
+        let mut message = Message::default();
+        message.set_m2(Mess2 {
+            l2: -2,
+            p1: 0x0800,
+            ..Mess2::default()
+        });
+        assert_eq!(message.m4().l3 >> 16, -2);
+        assert_eq!(message.word(Field::M2P1), 0x0800);
+    }
+
+    #[test]
+    fn test_requests() {
+        let write = WriteRequest(TransferRequest {
+            fd: 1,
+            buffer: 0x0020,
+            nbytes: 6,
+        });
+        assert_eq!(WriteRequest::from_message(&write.to_message()), write);
+
+        let open = OpenRequest {
+            name: 0x0100,
+            length: 4,
+            flags: 2,
+            mode: None,
+        };
+        assert_eq!(OpenRequest::from_message(&open.to_message()), open);
+        let create = OpenRequest {
+            mode: Some(0o644),
+            flags: 2 | O_CREAT,
+            ..open
+        };
+        assert_eq!(OpenRequest::from_message(&create.to_message()), create);
+    }
+
+    #[test]
+    fn test_memory_fields() {
+        let mut data = Memory::new(0x100);
+        let brk = BrkRequest { addr: 0x0800 };
+        brk.to_message().write(&mut data, 0x40);
+
+        data.start_log();
+        assert_eq!(BrkRequest::read(&data, 0x40), Some(brk));
+        BrkReply {
+            result: 0,
+            addr: 0x0800,
+        }
+        .write(&mut data, 0x40);
+        let accesses = data.take_log();
+        // Only m1_p1 is read, only m_type and m2_p1 are written
+        assert_eq!(
+            accesses.iter().map(|a| a.address).collect::<Vec<_>>(),
+            vec![0x4a, 0x42, 0x52]
+        );
+        let reply = BrkReply::from_message(&Message::peek(&data, 0x40));
+        assert_eq!(reply.addr, 0x0800);
+    }
+}
@@ -1,6 +1,9 @@
 mod error;
 mod header;
+pub mod message;
 mod program;
 mod segment;
+mod symbol;
 
 pub use self::program::Program;
+pub use self::symbol::{Symbol, SymbolSection, SymbolTable};
@@ -1,24 +1,33 @@
 use super::error::MinixError;
 use super::header::Header;
 use super::segment::{Data, Segment, Text};
+use super::symbol::SymbolTable;
 
 use std::fs::File;
 use std::io::Read;
 
 /// A `Program` is the main structure of this crate.
-/// It contains the header, text and data segment of a Minix2 binary program.
+/// It contains the header, text and data segment of a Minix2 binary program,
+/// and its symbol table when present.
 pub struct Program {
     pub header: Header,
     pub text_segment: Segment<Text>,
     pub data_segment: Segment<Data>,
+    pub symbols: SymbolTable,
 }
 
 impl Program {
-    fn new(header: Header, text_segment: Segment<Text>, data_segment: Segment<Data>) -> Self {
+    fn new(
+        header: Header,
+        text_segment: Segment<Text>,
+        data_segment: Segment<Data>,
+        symbols: SymbolTable,
+    ) -> Self {
         Program {
             header,
             text_segment,
             data_segment,
+            symbols,
         }
     }
 
@@ -27,12 +36,18 @@ impl Program {
             .bytes()
             .map(|b| b.map_err(|_| MinixError::InvalidFile))
             .collect::<Result<Vec<u8>, MinixError>>()?;
+        Program::from_bytes(&binary)
+    }
 
-        let header = Header::parse(&binary)?;
-        let text_segment = Text::parse(&binary, header.text)?;
-        let data_segment = Data::parse(&binary, header.text, header.data)?;
+    pub fn from_bytes(binary: &[u8]) -> Result<Self, MinixError> {
+        let header = Header::parse(binary)?;
+        let text_segment = Text::parse(binary, header.text)?;
+        let data_segment = Data::parse(binary, header.text, header.data)?;
+        // A bad symbol table doesn't prevent running or disassembling the program
+        let symbols =
+            SymbolTable::parse(binary, header.text + header.data, header.syms).unwrap_or_default();
 
-        Ok(Program::new(header, text_segment, data_segment))
+        Ok(Program::new(header, text_segment, data_segment, symbols))
     }
 }
 
@@ -71,4 +86,30 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_program_symbols() {
+        let file = File::open("./tests_data/1.c.out").unwrap();
+        let program = Program::from_file(file).unwrap();
+
+        assert_eq!(program.symbols.symbols.len(), 44);
+        assert_eq!(program.symbols.lookup("_main").unwrap().value, 0x39);
+        assert_eq!(
+            program.symbols.function_at(0x0130).unwrap().name,
+            "__sendre"
+        );
+        assert_eq!(program.symbols.function_range("_main"), Some(0x39..0x6b));
+    }
+
+    #[test]
+    fn test_bad_symbol_table() {
+        let mut binary = std::fs::read("./tests_data/1.c.out").unwrap();
+        // Odd-sized and truncated symbol tables
+        binary[28] += 1;
+        let program = Program::from_bytes(&binary).unwrap();
+        assert!(program.symbols.is_empty());
+        assert_eq!(program.text_segment.len(), 0x0140);
+        binary.truncate(binary.len() - 100);
+        assert!(Program::from_bytes(&binary).unwrap().symbols.is_empty());
+    }
 }
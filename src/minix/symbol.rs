@@ -0,0 +1,200 @@
+use super::error::MinixError;
+use std::ops::Range;
+
+const SYMBOL_SIZE: usize = 16;
+
+/// Section of a symbol, from the low bits of `n_sclass`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SymbolSection {
+    Undefined,
+    Absolute,
+    Text,
+    Data,
+    Bss,
+    Common,
+    Other(u8),
+}
+
+/// An entry of the symbol table (`struct nlist`).
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol {
+    pub name: String,
+    pub value: u32,
+    pub sclass: u8,
+    pub numaux: u8,
+    pub type_: u16,
+}
+
+impl Symbol {
+    pub fn parse(b: &[u8]) -> Result<Symbol, MinixError> {
+        let b = b.get(0..SYMBOL_SIZE).ok_or(MinixError::InvalidSize)?;
+        let name = b[0..8]
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as char)
+            .collect();
+
+        Ok(Symbol {
+            name,
+            value: u32::from_le_bytes(b[8..12].try_into().map_err(|_| MinixError::CorruptedData)?),
+            sclass: b[12],
+            numaux: b[13],
+            type_: u16::from_le_bytes(
+                b[14..16]
+                    .try_into()
+                    .map_err(|_| MinixError::CorruptedData)?,
+            ),
+        })
+    }
+
+    pub fn section(&self) -> SymbolSection {
+        match self.sclass & 0x07 {
+            0 => SymbolSection::Undefined,
+            1 => SymbolSection::Absolute,
+            2 => SymbolSection::Text,
+            3 => SymbolSection::Data,
+            4 => SymbolSection::Bss,
+            5 => SymbolSection::Common,
+            n => SymbolSection::Other(n),
+        }
+    }
+
+    /// External (`C_EXT`) or static (`C_STAT`) symbol, as opposed to a local label.
+    pub fn is_global(&self) -> bool {
+        self.sclass & 0xf8 != 0
+    }
+}
+
+/// Symbol table of a Minix2 binary program, stored after the data segment.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn parse(binary: &[u8], offset: u32, size: u32) -> Result<SymbolTable, MinixError> {
+        let start = 32 + offset as usize;
+        let b = binary
+            .get(start..start + size as usize)
+            .ok_or(MinixError::InvalidSize)?;
+
+        let symbols = b
+            .chunks(SYMBOL_SIZE)
+            .map(Symbol::parse)
+            .collect::<Result<Vec<Symbol>, MinixError>>()?;
+        Ok(SymbolTable { symbols })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// Global symbols of the text segment, sorted by address.
+    pub fn functions(&self) -> Vec<&Symbol> {
+        let mut functions: Vec<&Symbol> = self
+            .symbols
+            .iter()
+            .filter(|s| s.section() == SymbolSection::Text && s.is_global())
+            .collect();
+        functions.sort_by_key(|s| s.value);
+        functions
+    }
+
+    /// The function containing the given text address.
+    /// When several symbols share an address, the last one of the table is used.
+    pub fn function_at(&self, address: u16) -> Option<&Symbol> {
+        self.functions()
+            .into_iter()
+            .filter(|s| s.value <= address as u32)
+            .last()
+    }
+
+    /// The text symbol at exactly the given address, including local labels.
+    /// Global symbols are preferred, as in `function_at`.
+    pub fn label_at(&self, address: u16) -> Option<&Symbol> {
+        let labels = self
+            .symbols
+            .iter()
+            .filter(|s| s.section() == SymbolSection::Text && s.value == address as u32);
+        labels
+            .clone()
+            .filter(|s| s.is_global())
+            .last()
+            .or(labels.last())
+    }
+
+    /// Text address range of the given function, up to the next function.
+    pub fn function_range(&self, name: &str) -> Option<Range<u16>> {
+        let functions = self.functions();
+        let symbol = functions.iter().find(|s| s.name == name)?;
+        let end = functions
+            .iter()
+            .map(|s| s.value)
+            .find(|value| *value > symbol.value)
+            .unwrap_or(0x10000);
+        Some(symbol.value as u16..end.min(0xffff) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol_bytes(name: &str, value: u32, sclass: u8) -> Vec<u8> {
+        let mut b = vec![0; SYMBOL_SIZE];
+        b[..name.len()].copy_from_slice(name.as_bytes());
+        b[8..12].copy_from_slice(&value.to_le_bytes());
+        b[12] = sclass;
+        b
+    }
+
+    #[test]
+    fn test_parse_symbol() {
+        let symbol = Symbol::parse(&symbol_bytes("_main", 0x39, 0x12)).unwrap();
+        assert_eq!(symbol.name, "_main");
+        assert_eq!(symbol.value, 0x39);
+        assert_eq!(symbol.section(), SymbolSection::Text);
+        assert!(symbol.is_global());
+
+        let label = Symbol::parse(&symbol_bytes("L0", 0x126, 0x02)).unwrap();
+        assert!(!label.is_global());
+    }
+
+    #[test]
+    fn test_parse_invalid_symbol_table() {
+        let binary = vec![0; 40];
+        assert_eq!(
+            SymbolTable::parse(&binary, 0, 16),
+            Err(MinixError::InvalidSize)
+        );
+    }
+
+    #[test]
+    fn test_function_lookup() {
+        let mut binary = vec![0; 32];
+        binary.extend(symbol_bytes("begtext", 0x0, 0x12));
+        binary.extend(symbol_bytes("crtso", 0x0, 0x12));
+        binary.extend(symbol_bytes("_main", 0x39, 0x12));
+        binary.extend(symbol_bytes("L0", 0x40, 0x02));
+        binary.extend(symbol_bytes("_environ", 0x10, 0x13));
+        binary.extend(symbol_bytes("_exit", 0x6b, 0x12));
+
+        let table = SymbolTable::parse(&binary, 0, 6 * 16).unwrap();
+        assert_eq!(table.symbols.len(), 6);
+        assert_eq!(table.function_at(0x20).unwrap().name, "crtso");
+        assert_eq!(table.function_at(0x45).unwrap().name, "_main");
+        assert_eq!(table.label_at(0x0).unwrap().name, "crtso");
+        assert_eq!(table.label_at(0x40).unwrap().name, "L0");
+        assert_eq!(table.label_at(0x41), None);
+        assert_eq!(table.function_range("_main"), Some(0x39..0x6b));
+        assert_eq!(table.function_range("_exit"), Some(0x6b..0xffff));
+        assert_eq!(table.function_range("_environ"), None);
+    }
+}
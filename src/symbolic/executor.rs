@@ -0,0 +1,453 @@
+use super::smtlib::to_smtlib;
+use super::solver::{solve, solve_for, Solution};
+use super::state::State;
+use super::term::{Term, Variable};
+use crate::il::{BinaryOp, Liftable, MicroOp};
+use crate::interpreter::{BOTH, FS, MM, VM};
+use crate::minix::message::{call, errno, Field};
+use crate::x86::{Register, IR};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// Reason a path stopped.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PathEnd {
+    /// `exit` with the status
+    Exited(Rc<Term>),
+    Halted,
+    /// Fault of the machine, like a divide error
+    Faulted(String),
+    /// Instruction without semantics in the IL, by mnemonic
+    Unsupported(&'static str),
+    /// Address, jump target or syscall argument the solver couldn't make constant
+    Symbolic(Rc<Term>),
+    /// Stopped by `max_steps` or `max_paths`
+    Limit,
+}
+
+/// Path explored by the executor.
+#[derive(Debug, Clone)]
+pub struct Path {
+    /// Conditions of the branches taken, all of them not 0
+    pub constraints: Vec<Rc<Term>>,
+    pub end: PathEnd,
+    /// Address of the last instruction run
+    pub ip: u16,
+    /// Bytes read from the standard input
+    pub input: usize,
+    pub steps: usize,
+}
+
+impl Path {
+    fn new(state: State, end: PathEnd) -> Self {
+        Path {
+            constraints: state.constraints,
+            end,
+            ip: state.ip,
+            input: state.input,
+            steps: state.steps,
+        }
+    }
+
+    /// Constraints of the path as an SMT-LIB script.
+    pub fn to_smtlib(&self) -> String {
+        to_smtlib(&self.constraints)
+    }
+}
+
+/// Concrete values of the symbolic inputs, taking the program down a path.
+#[derive(Debug, PartialEq, Default)]
+pub struct TestInput {
+    pub registers: Vec<(Register, u16)>,
+    pub memory: Vec<(u16, u8)>,
+    /// Bytes to give to `read` on the standard input
+    pub stdin: Vec<u8>,
+}
+
+/// State following an instruction.
+enum Next {
+    Running(State),
+    Ended(State, PathEnd),
+}
+
+/// Symbolic executor of the instructions lifted to micro-ops.
+///
+/// The selected registers and memory bytes, and the bytes read from the standard
+/// input, are variables. Paths fork on the branches depending on them, with the
+/// condition of each side added to the path constraints, and the solver finds the
+/// inputs taking a path. Memory addresses and jump targets are made constant with
+/// a value allowed by the path.
+///
+/// `int 20` is modelled for `sendrec` only: `exit` ends the path, `read` of the
+/// standard input returns the number of bytes asked, all of them variables, and the
+/// other calls succeed without effect.
+pub struct Executor {
+    pub initial: State,
+    /// Instructions run by a path before it is stopped
+    pub max_steps: usize,
+    /// Paths, running or stopped, before the search is stopped
+    pub max_paths: usize,
+    variables: Vec<Rc<Variable>>,
+    registers: Vec<(Register, Rc<Variable>)>,
+    memory: Vec<(u16, Rc<Variable>)>,
+    /// Variables of the standard input bytes, in order
+    stdin: Vec<Rc<Variable>>,
+}
+
+impl Executor {
+    /// Executor starting from the state of the VM, like `VM::from(program)`.
+    pub fn new(vm: &VM) -> Self {
+        Executor {
+            initial: State::from(vm),
+            max_steps: 10000,
+            max_paths: 256,
+            variables: vec![],
+            registers: vec![],
+            memory: vec![],
+            stdin: vec![],
+        }
+    }
+
+    fn variable(&mut self, name: String, bits: u32) -> Rc<Variable> {
+        let id = self.variables.len();
+        let variable = Rc::new(Variable { id, name, bits });
+        self.variables.push(variable.clone());
+        variable
+    }
+
+    /// Make the word register a variable at the start.
+    pub fn symbolic_register(&mut self, reg: Register) {
+        let reg = reg.to_word_register();
+        let variable = self.variable(reg.to_string(), 16);
+        self.initial.set_register(reg, Term::var(&variable));
+        self.registers.push((reg, variable));
+    }
+
+    /// Make the bytes of the data memory variables at the start.
+    pub fn symbolic_memory(&mut self, address: u16, size: u16) {
+        for i in 0..size {
+            let address = address.wrapping_add(i);
+            let variable = self.variable(format!("mem_{:04x}", address), 8);
+            self.initial.store(address, Term::var(&variable), true);
+            self.memory.push((address, variable));
+        }
+    }
+
+    /// Variable of the standard input byte at the offset.
+    fn stdin_byte(&mut self, offset: usize) -> Rc<Variable> {
+        while self.stdin.len() <= offset {
+            let variable = self.variable(format!("input_{}", self.stdin.len()), 8);
+            self.stdin.push(variable);
+        }
+        self.stdin[offset].clone()
+    }
+
+    /// Explore the paths from the initial state, in turn one instruction at a time,
+    /// and stop at the first state where `stop` is true.
+    fn search(&mut self, mut stop: impl FnMut(&State) -> bool) -> (Vec<Path>, Option<State>) {
+        let mut paths = vec![];
+        let mut queue = VecDeque::from([self.initial.clone()]);
+        while let Some(state) = queue.pop_front() {
+            if stop(&state) {
+                return (paths, Some(state));
+            }
+            if state.steps >= self.max_steps || paths.len() + queue.len() >= self.max_paths {
+                paths.push(Path::new(state, PathEnd::Limit));
+                continue;
+            }
+            for next in self.step(state) {
+                match next {
+                    Next::Running(state) => queue.push_back(state),
+                    Next::Ended(state, end) => paths.push(Path::new(state, end)),
+                }
+            }
+        }
+        (paths, None)
+    }
+
+    /// All the paths from the initial state, up to the limits.
+    pub fn explore(&mut self) -> Vec<Path> {
+        self.search(|_| false).0
+    }
+
+    /// Inputs running the instruction at the target address.
+    pub fn reach(&mut self, target: u16) -> Option<TestInput> {
+        let (_, state) = self.search(|state| state.ip == target);
+        let state = state?;
+        self.test_input(&Path::new(state, PathEnd::Limit))
+    }
+
+    /// Inputs taking the path, or None if the solver finds none.
+    pub fn test_input(&self, path: &Path) -> Option<TestInput> {
+        let Solution::Sat(model) = solve(&path.constraints) else {
+            return None;
+        };
+        let value = |variable: &Rc<Variable>| model.get(&variable.id).copied().unwrap_or(0);
+        Some(TestInput {
+            registers: self
+                .registers
+                .iter()
+                .map(|(reg, variable)| (*reg, value(variable) as u16))
+                .collect(),
+            memory: self
+                .memory
+                .iter()
+                .map(|(address, variable)| (*address, value(variable) as u8))
+                .collect(),
+            stdin: self
+                .stdin
+                .iter()
+                .take(path.input)
+                .map(|variable| value(variable) as u8)
+                .collect(),
+        })
+    }
+
+    /// Run the instruction at ip, as `VM::step_lifted`.
+    fn step(&mut self, mut state: State) -> Vec<Next> {
+        let Some(chunk) = state.fetch() else {
+            return vec![Next::Ended(state, PathEnd::Halted)];
+        };
+        let (instruction, len) =
+            match crate::disassembler::parse_instruction(chunk, state.ip.into()) {
+                Ok(parsed) => parsed,
+                Err(e) => return vec![Next::Ended(state, PathEnd::Faulted(format!("{:?}", e)))],
+            };
+        if instruction.ir == IR::Hlt {
+            return vec![Next::Ended(state, PathEnd::Halted)];
+        }
+        let ops = instruction.lift(state.ip);
+        state.ip = state.ip.wrapping_add(len as u16);
+        state.steps += 1;
+        self.execute(state, &ops)
+    }
+
+    /// Run the micro-ops symbolically, as `il::execute`, forking on the branches
+    /// that can go both ways.
+    fn execute(&mut self, mut state: State, ops: &[MicroOp]) -> Vec<Next> {
+        let mut next = vec![];
+        let mut temps: Vec<Rc<Term>> = vec![];
+        macro_rules! eval {
+            ($expr:expr) => {
+                match state.eval($expr, &temps) {
+                    Ok(value) => value,
+                    Err(end) => {
+                        next.push(Next::Ended(state, end));
+                        return next;
+                    }
+                }
+            };
+        }
+        macro_rules! concretize {
+            ($term:expr) => {
+                match state.concretize(&$term) {
+                    Ok(value) => value,
+                    Err(end) => {
+                        next.push(Next::Ended(state, end));
+                        return next;
+                    }
+                }
+            };
+        }
+        for op in ops {
+            match op {
+                MicroOp::SetReg(reg, value) => {
+                    let value = eval!(value);
+                    state.set_register(*reg, value);
+                }
+                MicroOp::SetFlag(flag, value) => {
+                    let value = eval!(value);
+                    state.set_flag(*flag, value);
+                }
+                MicroOp::SetTemp(n, value) => {
+                    let value = eval!(value);
+                    if temps.len() <= *n {
+                        temps.resize(*n + 1, Term::constant(0));
+                    }
+                    temps[*n] = value;
+                }
+                MicroOp::Store {
+                    address,
+                    value,
+                    byte,
+                } => {
+                    let (address, value) = (eval!(address), eval!(value));
+                    let address = Term::binary(BinaryOp::And, address, Term::constant(0xffff));
+                    let address = concretize!(address);
+                    state.store(address, value, *byte);
+                }
+                MicroOp::Jump(target) => {
+                    let target = eval!(target);
+                    state.ip = concretize!(target);
+                    break;
+                }
+                MicroOp::Branch { cond, target } => {
+                    let cond = eval!(cond);
+                    let (taken, not_taken) = fork(&state, &cond);
+                    if let Some(mut taken) = taken {
+                        let target = match taken.eval(target, &temps) {
+                            Ok(target) => taken.concretize(&target),
+                            Err(end) => Err(end),
+                        };
+                        match target {
+                            Ok(target) => {
+                                taken.ip = target;
+                                next.push(Next::Running(taken));
+                            }
+                            Err(end) => next.push(Next::Ended(taken, end)),
+                        }
+                    }
+                    match not_taken {
+                        Some(not_taken) => state = not_taken,
+                        None => return next,
+                    }
+                }
+                MicroOp::DivideError(cond) => {
+                    let cond = eval!(cond);
+                    let (fault, no_fault) = fork(&state, &cond);
+                    if let Some(fault) = fault {
+                        let end = PathEnd::Faulted("divide error".to_string());
+                        next.push(Next::Ended(fault, end));
+                    }
+                    match no_fault {
+                        Some(no_fault) => state = no_fault,
+                        None => return next,
+                    }
+                }
+                MicroOp::Interrupt(0x20) => {
+                    if let Err(end) = self.syscall(&mut state) {
+                        next.push(Next::Ended(state, end));
+                        return next;
+                    }
+                }
+                MicroOp::Interrupt(number) => {
+                    let end = PathEnd::Faulted(format!("unimplemented interrupt {:x}", number));
+                    next.push(Next::Ended(state, end));
+                    return next;
+                }
+                // As the VM ports
+                MicroOp::In { dest, .. } => state.set_register(*dest, Term::constant(0x42)),
+                MicroOp::Out { .. } => {}
+                MicroOp::Halt => {
+                    next.push(Next::Ended(state, PathEnd::Halted));
+                    return next;
+                }
+                MicroOp::Unsupported(mnemonic) => {
+                    next.push(Next::Ended(state, PathEnd::Unsupported(mnemonic)));
+                    return next;
+                }
+            }
+        }
+        next.push(Next::Running(state));
+        next
+    }
+
+    /// Minix call of `int 20`, replying in the caller's message.
+    fn syscall(&mut self, state: &mut State) -> Result<(), PathEnd> {
+        let function = state.concretize(&state.register(Register::CX))?;
+        let src_dest = state.concretize(&state.register(Register::AX))?;
+        let message = state.concretize(&state.register(Register::BX))?;
+        let field = |field: Field| message.wrapping_add(field.offset() as u16);
+        state.set_register(Register::AX, Term::constant(0));
+        if function != BOTH {
+            return Ok(());
+        }
+        let m_type = state.concretize(&state.load(field(Field::Type), false))?;
+        let reply = match (src_dest, m_type) {
+            (MM, call::EXIT) => return Err(PathEnd::Exited(state.load(field(Field::M1I1), false))),
+            (FS, call::READ) => {
+                let fd = state.concretize(&state.load(field(Field::M1I1), false))?;
+                let nbytes = state.concretize(&state.load(field(Field::M1I2), false))?;
+                let buffer = state.concretize(&state.load(field(Field::M1P1), false))?;
+                if fd == 0 {
+                    for i in 0..nbytes {
+                        let variable = self.stdin_byte(state.input);
+                        state.store(buffer.wrapping_add(i), Term::var(&variable), true);
+                        state.input += 1;
+                    }
+                    Term::constant(nbytes as u32)
+                } else {
+                    Term::constant(-errno::EBADF as u16 as u32)
+                }
+            }
+            (FS, call::WRITE) => state.load(field(Field::M1I2), false),
+            _ => Term::constant(0),
+        };
+        state.store(field(Field::Source), Term::constant(src_dest as u32), false);
+        state.store(field(Field::Type), reply, false);
+        Ok(())
+    }
+}
+
+/// States where the condition isn't 0 and is 0, when the path allows it.
+fn fork(state: &State, cond: &Rc<Term>) -> (Option<State>, Option<State>) {
+    match cond.as_const() {
+        Some(0) => return (None, Some(state.clone())),
+        Some(_) => return (Some(state.clone()), None),
+        None => {}
+    }
+    let feasible = |constraint: &Rc<Term>| {
+        let mut constraints = state.constraints.clone();
+        constraints.push(constraint.clone());
+        solve_for(&constraints, &[constraint]) != Solution::Unsat
+    };
+    let (yes, no) = (Term::is_true(cond.clone()), Term::is_false(cond.clone()));
+    let (taken, not_taken) = (feasible(&yes), feasible(&no));
+    let side = |constraint: Rc<Term>, both: bool| {
+        let mut state = state.clone();
+        // A side always taken adds nothing
+        if both {
+            state.constraints.push(constraint);
+        }
+        state
+    };
+    let both = taken && not_taken;
+    (
+        taken.then(|| side(yes, both)),
+        not_taken.then(|| side(no, both)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::vm_with_text;
+
+    #[test]
+    fn test_reach_register() {
+        // cmp ax, 1234; je 0006; hlt; hlt
+        let vm = vm_with_text(&[0x3d, 0x34, 0x12, 0x74, 0x01, 0xf4, 0xf4]);
+        let mut executor = Executor::new(&vm);
+        executor.symbolic_register(Register::AX);
+        let input = executor.reach(0x0006).unwrap();
+        assert_eq!(input.registers, vec![(Register::AX, 0x1234)]);
+
+        let paths = executor.explore();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|path| path.end == PathEnd::Halted));
+        assert_eq!(paths[0].constraints[0].to_string(), "(ax - 0x1234) == 0");
+    }
+
+    #[test]
+    fn test_reach_stdin() {
+        // sendrec(FS, read(0, 0200, 2)); cmp [0200], 6b6f; jne 0014; hlt; hlt
+        let mut vm = vm_with_text(&[
+            0xb8, 0x01, 0x00, 0xbb, 0x00, 0x01, 0xb9, 0x03, 0x00, 0xcd, 0x20, 0x81, 0x3e, 0x00,
+            0x02, 0x6f, 0x6b, 0x75, 0x01, 0xf4, 0xf4,
+        ]);
+        vm.data.write_word(0x0102, call::READ);
+        vm.data.write_word(0x0104, 0);
+        vm.data.write_word(0x0106, 2);
+        vm.data.write_word(0x010a, 0x0200);
+        let mut executor = Executor::new(&vm);
+        let input = executor.reach(0x0013).unwrap();
+        assert_eq!(input.stdin, b"ok");
+
+        let paths = executor.explore();
+        assert_eq!(paths.len(), 2);
+        let smtlib = paths[1].to_smtlib();
+        assert!(smtlib.contains("(declare-fun input_0 () (_ BitVec 8))\n"));
+        assert!(smtlib.contains("(declare-fun input_1 () (_ BitVec 8))\n"));
+        assert!(smtlib.ends_with("(check-sat)\n(get-model)\n"));
+    }
+}
@@ -0,0 +1,11 @@
+mod executor;
+mod smtlib;
+mod solver;
+mod state;
+mod term;
+
+pub use self::executor::{Executor, Path, PathEnd, TestInput};
+pub use self::smtlib::to_smtlib;
+pub use self::solver::{solve, Solution};
+pub use self::state::State;
+pub use self::term::{Model, Term, Variable};
@@ -0,0 +1,166 @@
+use super::term::Term;
+use crate::il::{BinaryOp, UnaryOp};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Low word of a 32 bits value.
+fn word(value: &str) -> String {
+    format!("((_ extract 15 0) {})", value)
+}
+
+fn zero_extend(bits: u32, value: String) -> String {
+    format!("((_ zero_extend {}) {})", bits, value)
+}
+
+/// 1 or 0 on 32 bits, from a boolean.
+fn condition(value: String) -> String {
+    format!("(ite {} (_ bv1 32) (_ bv0 32))", value)
+}
+
+/// The term as a 32 bits vector, with the operations on the low words
+/// as `BinaryOp::apply`.
+fn term(term: &Term) -> String {
+    match term {
+        Term::Const(value) => format!("(_ bv{} 32)", value),
+        Term::Var(variable) => zero_extend(32 - variable.bits, variable.name.clone()),
+        Term::Unary(op, value) => {
+            let value = self::term(value);
+            let low = word(&value);
+            match op {
+                UnaryOp::Not => zero_extend(16, format!("(bvnot {})", low)),
+                UnaryOp::Neg => zero_extend(16, format!("(bvneg {})", low)),
+                UnaryOp::Parity => {
+                    let bits: Vec<String> = (0..16)
+                        .map(|i| format!("((_ extract {} {}) {})", i, i, value))
+                        .collect();
+                    let odd = bits.join(" ");
+                    condition(format!("(= (bvxor {}) #b0)", odd))
+                }
+                UnaryOp::SignExtendByte => zero_extend(
+                    16,
+                    format!("((_ sign_extend 8) ((_ extract 7 0) {}))", value),
+                ),
+                UnaryOp::SignExtendWord => format!("((_ sign_extend 16) {})", low),
+                UnaryOp::High => zero_extend(16, format!("((_ extract 31 16) {})", value)),
+            }
+        }
+        Term::Binary(op, left, right) => {
+            let (left, right) = (self::term(left), self::term(right));
+            let (a, b) = (word(&left), word(&right));
+            let on_words = |op: &str| zero_extend(16, format!("({} {} {})", op, a, b));
+            // Shift counts are taken modulo 16, as `wrapping_shl`
+            let shift = |op: &str| {
+                let count = format!("(bvand {} (_ bv15 16))", b);
+                zero_extend(16, format!("({} {} {})", op, a, count))
+            };
+            let sign = |value: &str| format!("((_ extract 15 15) {})", value);
+            match op {
+                BinaryOp::Add => on_words("bvadd"),
+                BinaryOp::Sub => on_words("bvsub"),
+                BinaryOp::MulWide => format!(
+                    "(bvmul {} {})",
+                    zero_extend(16, a.clone()),
+                    zero_extend(16, b.clone())
+                ),
+                BinaryOp::And => on_words("bvand"),
+                BinaryOp::Or => on_words("bvor"),
+                BinaryOp::Xor => on_words("bvxor"),
+                BinaryOp::Shl => shift("bvshl"),
+                BinaryOp::Shr => shift("bvlshr"),
+                BinaryOp::Sar => shift("bvashr"),
+                BinaryOp::Concat => format!("(concat {} {})", a, b),
+                // 0 when dividing by 0
+                BinaryOp::DivU | BinaryOp::RemU => {
+                    let name = if *op == BinaryOp::DivU {
+                        "bvudiv"
+                    } else {
+                        "bvurem"
+                    };
+                    format!(
+                        "(ite (= {} (_ bv0 32)) (_ bv0 32) ({} {} {}))",
+                        right, name, left, right
+                    )
+                }
+                BinaryOp::Eq => condition(format!("(= {} {})", left, right)),
+                BinaryOp::Ne => condition(format!("(distinct {} {})", left, right)),
+                BinaryOp::LtU => condition(format!("(bvult {} {})", left, right)),
+                BinaryOp::LtS => condition(format!("(bvslt {} {})", a, b)),
+                BinaryOp::AddOverflows => {
+                    let result = format!("(bvadd {} {})", a, b);
+                    condition(format!(
+                        "(and (= {} {}) (distinct {} {}))",
+                        sign(&a),
+                        sign(&b),
+                        sign(&a),
+                        sign(&result)
+                    ))
+                }
+                BinaryOp::SubOverflows => {
+                    let result = format!("(bvsub {} {})", a, b);
+                    condition(format!(
+                        "(and (distinct {} {}) (distinct {} {}))",
+                        sign(&a),
+                        sign(&b),
+                        sign(&a),
+                        sign(&result)
+                    ))
+                }
+            }
+        }
+        Term::Ite(cond, then, otherwise) => format!(
+            "(ite (distinct {} (_ bv0 32)) {} {})",
+            self::term(cond),
+            self::term(then),
+            self::term(otherwise)
+        ),
+    }
+}
+
+/// SMT-LIB script asserting that the constraints aren't 0, with the variables
+/// as bit-vectors of their width, for an external solver.
+pub fn to_smtlib(constraints: &[Rc<Term>]) -> String {
+    let mut variables = BTreeMap::new();
+    constraints
+        .iter()
+        .for_each(|constraint| constraint.variables(&mut variables));
+    let mut out = String::from("(set-logic QF_BV)\n");
+    for variable in variables.values() {
+        let _ = writeln!(
+            out,
+            "(declare-fun {} () (_ BitVec {}))",
+            variable.name, variable.bits
+        );
+    }
+    for constraint in constraints {
+        let _ = writeln!(out, "; {}", constraint);
+        let _ = writeln!(out, "(assert (distinct {} (_ bv0 32)))", term(constraint));
+    }
+    out.push_str("(check-sat)\n(get-model)\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbolic::Variable;
+
+    #[test]
+    fn test_to_smtlib() {
+        let variable = Rc::new(Variable {
+            id: 0,
+            name: "input_0".to_string(),
+            bits: 8,
+        });
+        let sum = Term::binary(BinaryOp::Add, Term::var(&variable), Term::constant(1));
+        let constraint = Term::binary(BinaryOp::Eq, sum, Term::constant(0x6f));
+        assert_eq!(
+            to_smtlib(&[constraint]),
+            "(set-logic QF_BV)\n\
+             (declare-fun input_0 () (_ BitVec 8))\n\
+             ; (input_0 + 1) == 0x6f\n\
+             (assert (distinct (ite (= ((_ zero_extend 16) (bvadd ((_ extract 15 0) ((_ zero_extend 24) input_0)) ((_ extract 15 0) (_ bv1 32)))) (_ bv111 32)) (_ bv1 32) (_ bv0 32)) (_ bv0 32)))\n\
+             (check-sat)\n(get-model)\n"
+        );
+    }
+}
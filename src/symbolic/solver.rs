@@ -0,0 +1,220 @@
+use super::term::{Model, Term, Variable};
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+/// Values tried by a search before it gives up.
+const BUDGET: usize = 1 << 20;
+
+/// Result of the solver.
+#[derive(Debug, PartialEq)]
+pub enum Solution {
+    /// Values of the variables meeting all the constraints
+    Sat(Model),
+    Unsat,
+    /// The search ran out of its budget
+    Unknown,
+}
+
+/// Constraints sharing their variables, directly or through other constraints.
+struct Component {
+    constraints: Vec<Rc<Term>>,
+    variables: BTreeMap<usize, Rc<Variable>>,
+}
+
+fn components(constraints: &[Rc<Term>]) -> Vec<Component> {
+    let mut components: Vec<Component> = vec![];
+    for constraint in constraints {
+        let mut variables = BTreeMap::new();
+        constraint.variables(&mut variables);
+        let mut merged = Component {
+            constraints: vec![constraint.clone()],
+            variables,
+        };
+        // Merge the components sharing a variable with the constraint
+        let mut index = 0;
+        while index < components.len() {
+            let shared = components[index]
+                .variables
+                .keys()
+                .any(|id| merged.variables.contains_key(id));
+            if shared {
+                let component = components.swap_remove(index);
+                merged.constraints.extend(component.constraints);
+                merged.variables.extend(component.variables);
+            } else {
+                index += 1;
+            }
+        }
+        components.push(merged);
+    }
+    components
+}
+
+/// Find values of the variables making all the constraints not 0.
+///
+/// The constraints are split in groups of shared variables, and each group is
+/// searched by backtracking over the values of its variables, the constants of
+/// the constraints and their neighbours first. The search is complete, but can
+/// run out of its budget when many word variables are tied together.
+pub fn solve(constraints: &[Rc<Term>]) -> Solution {
+    solve_components(components(constraints))
+}
+
+/// Solve the constraints sharing variables with the terms only,
+/// the other ones being known to be satisfiable.
+pub(crate) fn solve_for(constraints: &[Rc<Term>], terms: &[&Rc<Term>]) -> Solution {
+    let mut variables = BTreeMap::new();
+    terms.iter().for_each(|term| term.variables(&mut variables));
+    let related = components(constraints)
+        .into_iter()
+        // Constant constraints too
+        .filter(|c| {
+            c.variables.is_empty() || c.variables.keys().any(|id| variables.contains_key(id))
+        })
+        .collect();
+    solve_components(related)
+}
+
+fn solve_components(components: Vec<Component>) -> Solution {
+    let mut model = Model::new();
+    let mut unknown = false;
+    for component in components {
+        match Search::new(&component).run() {
+            Solution::Sat(values) => model.extend(values),
+            Solution::Unsat => return Solution::Unsat,
+            Solution::Unknown => unknown = true,
+        }
+    }
+    match unknown {
+        true => Solution::Unknown,
+        false => Solution::Sat(model),
+    }
+}
+
+struct Search {
+    variables: Vec<Rc<Variable>>,
+    /// Values tried first, for each variable
+    hints: Vec<Vec<u32>>,
+    /// Constraints checked once the variable at the index has a value
+    checks: Vec<Vec<Rc<Term>>>,
+    model: Model,
+    budget: usize,
+}
+
+impl Search {
+    fn new(component: &Component) -> Self {
+        let variables: Vec<Rc<Variable>> = component.variables.values().cloned().collect();
+        let position = |id: usize| variables.iter().position(|v| v.id == id).unwrap();
+        let mut checks = vec![vec![]; variables.len().max(1)];
+        let mut constants = BTreeSet::new();
+        for constraint in &component.constraints {
+            let mut used = BTreeMap::new();
+            constraint.variables(&mut used);
+            let last = used.keys().map(|&id| position(id)).max().unwrap_or(0);
+            checks[last].push(constraint.clone());
+            let mut values = vec![];
+            constraint.constants(&mut values);
+            for value in values {
+                constants.extend([value, value.wrapping_add(1), value.wrapping_sub(1)]);
+            }
+        }
+        constants.insert(0);
+        let hints = variables
+            .iter()
+            .map(|v| {
+                let mut hints: Vec<u32> = constants.iter().map(|c| c & v.max()).collect();
+                hints.sort_unstable();
+                hints.dedup();
+                hints
+            })
+            .collect();
+        Search {
+            variables,
+            hints,
+            checks,
+            model: Model::new(),
+            budget: BUDGET,
+        }
+    }
+
+    fn run(mut self) -> Solution {
+        if self.variables.is_empty() {
+            let sat = self.checks[0].iter().all(|c| c.eval(&self.model) != 0);
+            return match sat {
+                true => Solution::Sat(Model::new()),
+                false => Solution::Unsat,
+            };
+        }
+        match self.assign(0) {
+            Some(true) => Solution::Sat(self.model),
+            Some(false) => Solution::Unsat,
+            None => Solution::Unknown,
+        }
+    }
+
+    /// Try the values of the variable at the index and the following ones,
+    /// and return None when out of budget.
+    fn assign(&mut self, index: usize) -> Option<bool> {
+        if index == self.variables.len() {
+            return Some(true);
+        }
+        let variable = self.variables[index].clone();
+        let hints = std::mem::take(&mut self.hints[index]);
+        let rest = (0..=variable.max()).filter(|v| hints.binary_search(v).is_err());
+        for value in hints.iter().copied().chain(rest) {
+            if self.budget == 0 {
+                return None;
+            }
+            self.budget -= 1;
+            self.model.insert(variable.id, value);
+            let model = &self.model;
+            if self.checks[index].iter().all(|c| c.eval(model) != 0) {
+                match self.assign(index + 1) {
+                    Some(false) => {}
+                    found => return found,
+                }
+            }
+        }
+        self.model.remove(&variable.id);
+        self.hints[index] = hints;
+        Some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::il::BinaryOp;
+
+    fn variable(id: usize, bits: u32) -> Rc<Term> {
+        let name = format!("v{}", id);
+        Term::var(&Rc::new(Variable { id, name, bits }))
+    }
+
+    #[test]
+    fn test_solve() {
+        let (x, y) = (variable(0, 16), variable(1, 8));
+        // x + 3 == 0x1005, y < 0x20, (x ^ y) & 0xff == 0x13
+        let sum = Term::binary(BinaryOp::Add, x.clone(), Term::constant(3));
+        let xor = Term::binary(BinaryOp::Xor, x.clone(), y.clone());
+        let constraints = vec![
+            Term::binary(BinaryOp::Eq, sum, Term::constant(0x1005)),
+            Term::binary(BinaryOp::LtU, y.clone(), Term::constant(0x20)),
+            Term::binary(
+                BinaryOp::Eq,
+                Term::binary(BinaryOp::And, xor, Term::constant(0xff)),
+                Term::constant(0x13),
+            ),
+        ];
+        let Solution::Sat(model) = solve(&constraints) else {
+            panic!("no solution");
+        };
+        assert_eq!(model[&0], 0x1002);
+        assert_eq!(model[&1], 0x11);
+
+        // A byte is never above 0xff
+        let constraints = vec![Term::binary(BinaryOp::LtU, Term::constant(0xff), y)];
+        assert_eq!(solve(&constraints), Solution::Unsat);
+        assert_eq!(solve(&[Term::constant(0)]), Solution::Unsat);
+    }
+}
@@ -0,0 +1,201 @@
+use super::solver::{solve_for, Solution};
+use super::term::Term;
+use super::PathEnd;
+use crate::il::{BinaryOp, Expr};
+use crate::interpreter::{Flag, VM};
+use crate::x86::Register;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// Byte of the memory written by the program.
+#[derive(Debug, Clone)]
+enum Cell {
+    Byte(Rc<Term>),
+    /// Low or high byte of a stored word, read back as the word
+    Low(Rc<Term>),
+    High(Rc<Term>),
+}
+
+/// Machine state of a path, with symbolic registers, flags and memory.
+#[derive(Debug, Clone)]
+pub struct State {
+    pub ip: u16,
+    registers: HashMap<Register, Rc<Term>>,
+    flags: HashMap<Flag, Rc<Term>>,
+    text: Rc<[u8]>,
+    /// Data memory at the start, below the written cells
+    data: Rc<[u8]>,
+    memory: BTreeMap<u16, Cell>,
+    /// Conditions of the branches taken by the path, all of them not 0
+    pub constraints: Vec<Rc<Term>>,
+    /// Bytes read from the standard input
+    pub input: usize,
+    /// Instructions run
+    pub steps: usize,
+}
+
+impl From<&VM> for State {
+    fn from(vm: &VM) -> Self {
+        State {
+            ip: vm.ip,
+            registers: Register::iter_16()
+                .map(|reg| (reg, Term::constant(vm.regs.get(reg) as u32)))
+                .collect(),
+            flags: Flag::iter()
+                .map(|flag| (flag, Term::constant(vm.flags.get(flag) as u32)))
+                .collect(),
+            text: vm.text.peek_bytes(0, vm.text.len()).into(),
+            data: vm.data.peek_bytes(0, vm.data.len()).into(),
+            memory: BTreeMap::new(),
+            constraints: vec![],
+            input: 0,
+            steps: 0,
+        }
+    }
+}
+
+impl State {
+    /// Bytes of the instruction at ip, as `VmIrExecutable::fetch`.
+    pub fn fetch(&self) -> Option<&[u8]> {
+        let ip = self.ip as usize;
+        (ip < self.text.len()).then(|| &self.text[ip..self.text.len().min(ip + 15)])
+    }
+
+    /// Value of the register, with the byte registers as the `RegisterSet`.
+    pub fn register(&self, reg: Register) -> Rc<Term> {
+        let word = self.registers[&reg.to_word_register()].clone();
+        if reg.is_low_byte() {
+            Term::binary(BinaryOp::And, word, Term::constant(0xff))
+        } else if reg.is_high_byte() {
+            let high = Term::binary(BinaryOp::Shr, word, Term::constant(8));
+            Term::binary(BinaryOp::And, high, Term::constant(0xff))
+        } else {
+            word
+        }
+    }
+
+    /// Set the register, keeping the other byte of a byte register as the
+    /// `RegisterSet`: the high byte of a word value for a high byte register.
+    pub fn set_register(&mut self, reg: Register, value: Rc<Term>) {
+        let word_reg = reg.to_word_register();
+        let old = self.registers[&word_reg].clone();
+        let keep = |value: Rc<Term>, mask: u32| {
+            let value = Term::binary(BinaryOp::And, value, Term::constant(mask));
+            let old = Term::binary(BinaryOp::And, old.clone(), Term::constant(0xffff ^ mask));
+            Term::binary(BinaryOp::Or, value, old)
+        };
+        let value = if reg.is_low_byte() {
+            keep(value, 0xff)
+        } else if reg.is_high_byte() {
+            keep(value, 0xff00)
+        } else {
+            Term::binary(BinaryOp::And, value, Term::constant(0xffff))
+        };
+        self.registers.insert(word_reg, value);
+    }
+
+    pub fn flag(&self, flag: Flag) -> Rc<Term> {
+        self.flags[&flag].clone()
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, value: Rc<Term>) {
+        self.flags.insert(flag, Term::is_true(value));
+    }
+
+    fn byte(&self, address: u16) -> Rc<Term> {
+        let high = |word| Term::binary(BinaryOp::Shr, word, Term::constant(8));
+        let value = match self.memory.get(&address) {
+            Some(Cell::Byte(value)) => return value.clone(),
+            Some(Cell::Low(word)) => word.clone(),
+            Some(Cell::High(word)) => high(word.clone()),
+            None => {
+                let value = self.data.get(address as usize).copied().unwrap_or(0);
+                return Term::constant(value as u32);
+            }
+        };
+        Term::binary(BinaryOp::And, value, Term::constant(0xff))
+    }
+
+    /// Word, or byte, of the data memory.
+    pub fn load(&self, address: u16, byte: bool) -> Rc<Term> {
+        if byte {
+            return self.byte(address);
+        }
+        let next = address.wrapping_add(1);
+        // A stored word read back entirely
+        if let (Some(Cell::Low(low)), Some(Cell::High(high))) =
+            (self.memory.get(&address), self.memory.get(&next))
+        {
+            if Rc::ptr_eq(low, high) {
+                return low.clone();
+            }
+        }
+        let high = Term::binary(BinaryOp::Shl, self.byte(next), Term::constant(8));
+        Term::binary(BinaryOp::Or, self.byte(address), high)
+    }
+
+    pub fn store(&mut self, address: u16, value: Rc<Term>, byte: bool) {
+        if byte {
+            let value = Term::binary(BinaryOp::And, value, Term::constant(0xff));
+            self.memory.insert(address, Cell::Byte(value));
+            return;
+        }
+        let word = Term::binary(BinaryOp::And, value, Term::constant(0xffff));
+        // Constant bytes stay plain
+        if let Some(word) = word.as_const() {
+            self.memory
+                .insert(address, Cell::Byte(Term::constant(word & 0xff)));
+            self.memory.insert(
+                address.wrapping_add(1),
+                Cell::Byte(Term::constant(word >> 8)),
+            );
+            return;
+        }
+        self.memory.insert(address, Cell::Low(word.clone()));
+        self.memory
+            .insert(address.wrapping_add(1), Cell::High(word));
+    }
+
+    /// Constant value of the term on the path: a value allowed by the constraints,
+    /// which is then added to them.
+    pub fn concretize(&mut self, term: &Rc<Term>) -> Result<u16, PathEnd> {
+        if let Some(value) = term.as_const() {
+            return Ok(value as u16);
+        }
+        match solve_for(&self.constraints, &[term]) {
+            Solution::Sat(model) => {
+                let value = term.eval(&model) & 0xffff;
+                let fixed = Term::binary(BinaryOp::Eq, term.clone(), Term::constant(value));
+                self.constraints.push(fixed);
+                Ok(value as u16)
+            }
+            _ => Err(PathEnd::Symbolic(term.clone())),
+        }
+    }
+
+    /// Value of the expression, with the temporaries of the instruction.
+    pub fn eval(&mut self, expr: &Expr, temps: &[Rc<Term>]) -> Result<Rc<Term>, PathEnd> {
+        Ok(match expr {
+            Expr::Const(value) => Term::constant(*value as u32),
+            Expr::Reg(reg) => self.register(*reg),
+            Expr::Flag(flag) => self.flag(*flag),
+            Expr::Temp(n) => temps.get(*n).cloned().unwrap_or(Term::constant(0)),
+            Expr::Load { address, byte } => {
+                let address = self.eval(address, temps)?;
+                let address = Term::binary(BinaryOp::And, address, Term::constant(0xffff));
+                let address = self.concretize(&address)?;
+                self.load(address, *byte)
+            }
+            Expr::Unary(op, value) => Term::unary(*op, self.eval(value, temps)?),
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left, temps)?;
+                Term::binary(*op, left, self.eval(right, temps)?)
+            }
+            Expr::Ite(cond, then, otherwise) => {
+                let cond = self.eval(cond, temps)?;
+                let then = self.eval(then, temps)?;
+                Term::ite(cond, then, self.eval(otherwise, temps)?)
+            }
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use crate::il::{BinaryOp, UnaryOp};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Symbolic input of the program: a register, a byte of memory or a byte read
+/// from the standard input.
+#[derive(Debug, PartialEq)]
+pub struct Variable {
+    pub id: usize,
+    pub name: String,
+    /// Width in bits, 8 or 16
+    pub bits: u32,
+}
+
+impl Variable {
+    /// Largest value of the variable.
+    pub fn max(&self) -> u32 {
+        (1 << self.bits) - 1
+    }
+}
+
+/// Values of the variables, by id. Missing variables are 0.
+pub type Model = BTreeMap<usize, u32>;
+
+/// Value computed from the variables, with the semantics of the IL expressions:
+/// words are in the low 16 bits, conditions are 0 or 1.
+#[derive(Debug, PartialEq)]
+pub enum Term {
+    Const(u32),
+    Var(Rc<Variable>),
+    Unary(UnaryOp, Rc<Term>),
+    Binary(BinaryOp, Rc<Term>, Rc<Term>),
+    /// Second value if the condition is 0, first value otherwise
+    Ite(Rc<Term>, Rc<Term>, Rc<Term>),
+}
+
+impl Term {
+    pub fn constant(value: u32) -> Rc<Term> {
+        Rc::new(Term::Const(value))
+    }
+
+    pub fn var(variable: &Rc<Variable>) -> Rc<Term> {
+        Rc::new(Term::Var(variable.clone()))
+    }
+
+    /// The operation, computed if the operand is constant.
+    pub fn unary(op: UnaryOp, value: Rc<Term>) -> Rc<Term> {
+        match value.as_const() {
+            Some(value) => Term::constant(op.apply(value)),
+            None => Rc::new(Term::Unary(op, value)),
+        }
+    }
+
+    /// The operation, computed if the operands are constant.
+    pub fn binary(op: BinaryOp, left: Rc<Term>, right: Rc<Term>) -> Rc<Term> {
+        match (left.as_const(), right.as_const()) {
+            (Some(left), Some(right)) => Term::constant(op.apply(left, right)),
+            // Masking a byte or a word already in range
+            (_, Some(mask)) if op == BinaryOp::And && left.fits(mask) => left,
+            _ => Rc::new(Term::Binary(op, left, right)),
+        }
+    }
+
+    pub fn ite(cond: Rc<Term>, then: Rc<Term>, otherwise: Rc<Term>) -> Rc<Term> {
+        match cond.as_const() {
+            Some(0) => otherwise,
+            Some(_) => then,
+            None => Rc::new(Term::Ite(cond, then, otherwise)),
+        }
+    }
+
+    /// 1 if the value isn't 0.
+    pub fn is_true(value: Rc<Term>) -> Rc<Term> {
+        match value.is_condition() {
+            true => value,
+            false => Term::binary(BinaryOp::Ne, value, Term::constant(0)),
+        }
+    }
+
+    /// 1 if the value is 0.
+    pub fn is_false(value: Rc<Term>) -> Rc<Term> {
+        match &*value {
+            Term::Binary(BinaryOp::Eq, left, right) => {
+                Term::binary(BinaryOp::Ne, left.clone(), right.clone())
+            }
+            Term::Binary(BinaryOp::Ne, left, right) => {
+                Term::binary(BinaryOp::Eq, left.clone(), right.clone())
+            }
+            _ => Term::binary(BinaryOp::Eq, value, Term::constant(0)),
+        }
+    }
+
+    /// Return true if the value is 0 or 1.
+    fn is_condition(&self) -> bool {
+        match self {
+            Term::Unary(UnaryOp::Parity, _) => true,
+            Term::Binary(op, ..) => matches!(
+                op,
+                BinaryOp::Eq
+                    | BinaryOp::Ne
+                    | BinaryOp::LtU
+                    | BinaryOp::LtS
+                    | BinaryOp::AddOverflows
+                    | BinaryOp::SubOverflows
+            ),
+            Term::Ite(_, then, otherwise) => then.is_condition() && otherwise.is_condition(),
+            Term::Const(value) => *value <= 1,
+            Term::Unary(..) | Term::Var(_) => false,
+        }
+    }
+
+    pub fn as_const(&self) -> Option<u32> {
+        match self {
+            Term::Const(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Return true if the value is known to be in the bits of the mask.
+    fn fits(&self, mask: u32) -> bool {
+        let bits = match self {
+            Term::Var(variable) => variable.max(),
+            Term::Binary(BinaryOp::And, _, right) => match right.as_const() {
+                Some(right) => right,
+                None => return false,
+            },
+            _ => return false,
+        };
+        bits & !mask == 0
+    }
+
+    /// Value of the term, with the values of the variables in the model.
+    pub fn eval(&self, model: &Model) -> u32 {
+        match self {
+            Term::Const(value) => *value,
+            Term::Var(variable) => model.get(&variable.id).copied().unwrap_or(0),
+            Term::Unary(op, value) => op.apply(value.eval(model)),
+            Term::Binary(op, left, right) => op.apply(left.eval(model), right.eval(model)),
+            Term::Ite(cond, then, otherwise) => match cond.eval(model) {
+                0 => otherwise.eval(model),
+                _ => then.eval(model),
+            },
+        }
+    }
+
+    /// Variables of the term, by id.
+    pub fn variables(&self, variables: &mut BTreeMap<usize, Rc<Variable>>) {
+        match self {
+            Term::Const(_) => {}
+            Term::Var(variable) => {
+                variables.insert(variable.id, variable.clone());
+            }
+            Term::Unary(_, value) => value.variables(variables),
+            Term::Binary(_, left, right) => {
+                left.variables(variables);
+                right.variables(variables);
+            }
+            Term::Ite(cond, then, otherwise) => {
+                cond.variables(variables);
+                then.variables(variables);
+                otherwise.variables(variables);
+            }
+        }
+    }
+
+    /// Constants of the term.
+    pub fn constants(&self, constants: &mut Vec<u32>) {
+        match self {
+            Term::Const(value) => constants.push(*value),
+            Term::Var(_) => {}
+            Term::Unary(_, value) => value.constants(constants),
+            Term::Binary(_, left, right) => {
+                left.constants(constants);
+                right.constants(constants);
+            }
+            Term::Ite(cond, then, otherwise) => {
+                cond.constants(constants);
+                then.constants(constants);
+                otherwise.constants(constants);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Nested operations are parenthesized, as the IL expressions
+        let operand = |term: &Term| match term {
+            Term::Binary(..) | Term::Ite(..) => format!("({})", term),
+            _ => term.to_string(),
+        };
+        match self {
+            Term::Const(value) if *value < 10 => write!(f, "{}", value),
+            Term::Const(value) => write!(f, "0x{:x}", value),
+            Term::Var(variable) => write!(f, "{}", variable.name),
+            Term::Unary(op, value) => {
+                let text = crate::il::format_unary(*op, value, operand(value));
+                write!(f, "{}", text)
+            }
+            Term::Binary(op, left, right) => {
+                let text = crate::il::format_binary(*op, operand(left), operand(right));
+                write!(f, "{}", text)
+            }
+            Term::Ite(cond, then, otherwise) => write!(
+                f,
+                "{} ? {} : {}",
+                operand(cond),
+                operand(then),
+                operand(otherwise)
+            ),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Fixtures of the unit tests.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::interpreter::{Memory, VM};
+use crate::minix::Program;
+use crate::x86::Register;
+
+/// The program of `tests_data/{name}.out`, like `1.c`.
+pub fn program(name: &str) -> Program {
+    let file = std::fs::File::open(format!("./tests_data/{}.out", name)).unwrap();
+    Program::from_file(file).unwrap()
+}
+
+/// VM running the machine code, with the stack at 0x0f00.
+pub fn vm_with_text(text: &[u8]) -> VM {
+    let mut vm = VM {
+        text: Memory::from(text.to_vec()),
+        ..VM::default()
+    };
+    vm.regs.set(Register::SP, 0x0f00);
+    vm
+}
+
+/// Writer keeping the bytes written, still readable once boxed in a tracer.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8(self.bytes()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -30,3 +30,62 @@ pub fn min<T: PartialOrd>(a: T, b: T) -> T {
         b
     }
 }
+
+/// Quote and escape a string as a JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quote bytes as a C string, with octal escapes for the other bytes than
+/// printable ASCII, truncated to `max` bytes followed by `...`.
+pub fn quote(bytes: &[u8], max: usize) -> String {
+    let mut out = String::from("\"");
+    for &byte in bytes.iter().take(max) {
+        match byte {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:o}", byte)),
+        }
+    }
+    out.push('"');
+    if bytes.len() > max {
+        out.push_str("...");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("mov ax, [bx]"), "\"mov ax, [bx]\"");
+        assert_eq!(json_string("a\"b\\c\nd\x01"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(b"a\"b\\\x01\n", 32), "\"a\\\"b\\\\\\1\\n\"");
+        assert_eq!(quote(b"\x1b[0m\xe9", 32), "\"\\33[0m\\351\"");
+        assert_eq!(quote(&[b'x'; 40], 32), format!("\"{}\"...", "x".repeat(32)));
+    }
+}
@@ -4,6 +4,7 @@ use super::Register;
 /// Addressing mode,
 /// can be of different types, such as direct, indirect, with displacement, ...
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Address {
     pub base: Option<Register>,
     pub index: Option<Register>,
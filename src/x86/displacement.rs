@@ -1,5 +1,6 @@
 /// Displacement is a value that is added to an address to access a memory location. Similar to an offset.
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Displacement {
     Short(i8),
     Long(i16),
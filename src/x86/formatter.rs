@@ -0,0 +1,507 @@
+use super::{Address, Displacement, Instruction, Operand, Register, IR};
+use std::collections::BTreeMap;
+
+/// Assembly syntax used by the formatter.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Syntax {
+    /// objdump like, with bare hexadecimal numbers: `mov bx, 0010`, `int 20`
+    #[default]
+    Objdump,
+    /// NASM: `mov bx, 0x10`, `inc word [bx]`
+    Nasm,
+    /// Intel (MASM): `mov bx, 0x10`, `inc word ptr [bx]`
+    Intel,
+    /// AT&T (GAS): `mov $0x10, %bx`, `incw (%bx)`
+    Att,
+    /// ACK `as`, the Minix assembler: `mov bx,0x10`, `inc 4(bp)`
+    Ack,
+}
+
+impl std::str::FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "objdump" => Ok(Syntax::Objdump),
+            "nasm" => Ok(Syntax::Nasm),
+            "intel" => Ok(Syntax::Intel),
+            "att" | "gas" => Ok(Syntax::Att),
+            "ack" => Ok(Syntax::Ack),
+            _ => Err(format!("Unknown syntax: {}", s)),
+        }
+    }
+}
+
+/// Options of the formatter.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FormatterOptions {
+    pub syntax: Syntax,
+    /// Show the raw bytes before each instruction
+    pub raw_bytes: bool,
+    pub uppercase: bool,
+    /// Show negative displacements as `[bp-14]` instead of `[bp+ffec]`
+    pub signed_displacements: bool,
+}
+
+impl Default for FormatterOptions {
+    fn default() -> Self {
+        FormatterOptions {
+            syntax: Syntax::Objdump,
+            raw_bytes: true,
+            uppercase: false,
+            signed_displacements: true,
+        }
+    }
+}
+
+/// Formats instructions in a given syntax, the default being the `Display` format.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Formatter {
+    pub options: FormatterOptions,
+    /// Names of text addresses, shown instead of jump and call targets
+    pub labels: BTreeMap<u16, String>,
+    /// Names of data addresses, shown instead of direct memory operands
+    pub data_labels: BTreeMap<u16, String>,
+    /// Names of data addresses, shown instead of the immediates moved into a register
+    pub address_labels: BTreeMap<u16, String>,
+    /// Names of the frame variables, shown instead of `[bp+N]` operands
+    pub frame_labels: BTreeMap<i16, String>,
+}
+
+impl Formatter {
+    pub fn new(options: FormatterOptions) -> Self {
+        Formatter {
+            options,
+            ..Formatter::default()
+        }
+    }
+
+    /// Format an instruction, with its raw bytes if enabled.
+    pub fn format_instruction(&self, instruction: &Instruction) -> String {
+        let ir = self.render(&instruction.ir, &instruction.raw);
+        let out = if self.options.raw_bytes {
+            let raw = instruction
+                .raw
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>()
+                .concat();
+            format!("{:<14}{}", raw, ir)
+        } else {
+            ir
+        };
+        self.case(out)
+    }
+
+    /// Format an instruction without its raw bytes.
+    pub fn format_ir(&self, ir: &IR) -> String {
+        self.case(self.render(ir, &[]))
+    }
+
+    /// Format a number, like an address, in the syntax and case of the formatter.
+    pub fn format_number(&self, value: u16) -> String {
+        self.case(self.number(value, 4))
+    }
+
+    fn case(&self, s: String) -> String {
+        if self.options.uppercase {
+            s.to_uppercase().replace("0X", "0x")
+        } else {
+            s
+        }
+    }
+
+    fn objdump(&self) -> bool {
+        self.options.syntax == Syntax::Objdump
+    }
+
+    /// Hexadecimal number, padded with zeros to `width` digits in objdump syntax.
+    fn number(&self, value: u16, width: usize) -> String {
+        if self.objdump() {
+            format!("{:0w$x}", value, w = width)
+        } else {
+            format!("0x{:x}", value)
+        }
+    }
+
+    fn signed(&self, value: i16) -> String {
+        let abs = (value as i32).unsigned_abs() as u16;
+        match (value < 0, self.objdump()) {
+            (true, true) => format!("-{:x}", abs),
+            (true, false) => format!("-0x{:x}", abs),
+            (false, _) => self.number(value as u16, 0),
+        }
+    }
+
+    fn operand(&self, operand: &Operand) -> String {
+        let att = self.options.syntax == Syntax::Att;
+        match operand {
+            Operand::Register(r) if att => format!("%{}", r),
+            Operand::Register(r) => format!("{}", r),
+            Operand::Immediate(i) => self.immediate(self.number(*i as u16, 0)),
+            Operand::LongImmediate(i) => self.immediate(self.number(*i, 4)),
+            Operand::SignExtendedImmediate(i) => self.immediate(self.signed(*i as i16)),
+            Operand::MemoryAddress(address) => self.address(address),
+            Operand::Displacement(Displacement::Long(d))
+                if self.labels.contains_key(&(*d as u16)) =>
+            {
+                self.labels[&(*d as u16)].clone()
+            }
+            Operand::Displacement(d) => match d {
+                Displacement::Short(d) => self.number(*d as u8 as u16, 2),
+                Displacement::Long(d) => self.number(*d as u16, 4),
+            },
+        }
+    }
+
+    fn immediate(&self, number: String) -> String {
+        match self.options.syntax {
+            Syntax::Att => format!("${}", number),
+            _ => number,
+        }
+    }
+
+    fn address(&self, address: &Address) -> String {
+        let disp = address.disp.map(|d| match d {
+            Displacement::Short(d) => d as i16,
+            Displacement::Long(d) => d,
+        });
+        let register = |r: Option<_>| r.map(|r| format!("{}", r));
+        let (base, index) = (register(address.base), register(address.index));
+
+        // Only a displacement, direct address
+        if base.is_none() && index.is_none() {
+            let value = disp.unwrap_or(0) as u16;
+            let label = self.data_labels.get(&value);
+            return match (self.options.syntax, label) {
+                (Syntax::Att, Some(label)) => label.clone(),
+                (Syntax::Ack, Some(label)) => format!("({})", label),
+                (_, Some(label)) => format!("[{}]", label),
+                (Syntax::Objdump, None) => format!("[{:0>4x}]", value),
+                (Syntax::Nasm | Syntax::Intel, None) => format!("[0x{:x}]", value),
+                (Syntax::Att, None) => format!("0x{:x}", value),
+                (Syntax::Ack, None) => format!("(0x{:x})", value),
+            };
+        }
+
+        // Only bp and a displacement, frame variable
+        if address.base == Some(Register::BP) && address.index.is_none() {
+            if let Some(label) = self.frame_labels.get(&disp.unwrap_or(0)) {
+                return match self.options.syntax {
+                    Syntax::Att => label.clone(),
+                    Syntax::Ack => format!("({})", label),
+                    _ => format!("[{}]", label),
+                };
+            }
+        }
+
+        let disp = match disp {
+            Some(d) if d < 0 && self.options.signed_displacements => self.signed(d),
+            Some(d) => self.number(d as u16, 0),
+            None => String::new(),
+        };
+        let registers = [base, index].into_iter().flatten();
+        match self.options.syntax {
+            Syntax::Objdump | Syntax::Nasm | Syntax::Intel => {
+                let mut out = registers.collect::<Vec<String>>().join("+");
+                if !disp.is_empty() && !disp.starts_with('-') {
+                    out.push('+');
+                }
+                format!("[{}{}]", out, disp)
+            }
+            Syntax::Att => format!(
+                "{}({})",
+                disp,
+                registers
+                    .map(|r| format!("%{}", r))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Syntax::Ack => format!(
+                "{}{}",
+                disp,
+                registers
+                    .map(|r| format!("({})", r))
+                    .collect::<Vec<String>>()
+                    .concat()
+            ),
+        }
+    }
+
+    /// Format the instruction, `raw` being used to find the size of memory operands.
+    fn render(&self, ir: &IR, raw: &[u8]) -> String {
+        let syntax = self.options.syntax;
+        match ir {
+            IR::Rep { z, string_ir } => {
+                let prefix = match (syntax, string_ir.as_ref()) {
+                    (Syntax::Objdump, _) => "rep",
+                    (_, IR::Cmps { .. } | IR::Scas { .. }) if *z => "repe",
+                    (_, IR::Cmps { .. } | IR::Scas { .. }) => "repne",
+                    _ => "rep",
+                };
+                return format!("{} {}", prefix, self.render(string_ir, &[]));
+            }
+            IR::Int { int_type: 3 } => {
+                return match syntax {
+                    Syntax::Objdump => "int".to_string(),
+                    _ => "int3".to_string(),
+                }
+            }
+            IR::Int { int_type } => {
+                return format!("int {}", self.immediate(self.number(*int_type as u16, 2)))
+            }
+            IR::Undefined => return "(undefined)".to_string(),
+            _ => {}
+        }
+
+        let mut mnemonic = ir.mnemonic().to_string();
+        // The address of `mov` between accumulator and memory is kept as an immediate
+        let moffs = syntax != Syntax::Objdump && matches!(raw.first(), Some(0xa0..=0xa3));
+        let operands: Vec<Operand> = ir
+            .operands()
+            .into_iter()
+            .map(|operand| match operand {
+                Operand::LongImmediate(address) if moffs => {
+                    Operand::MemoryAddress(Address::from_word_imm(*address))
+                }
+                operand => *operand,
+            })
+            .collect();
+        // Size of memory operands, when not given by a register
+        let memory = operands
+            .iter()
+            .position(|o| matches!(o, Operand::MemoryAddress(_)));
+        let mut operands: Vec<String> = operands.iter().map(|o| self.operand(o)).collect();
+        if let IR::Mov {
+            dest: Operand::Register(_),
+            src: Operand::LongImmediate(value),
+            ..
+        } = ir
+        {
+            if let Some(label) = self.address_labels.get(value).filter(|_| !moffs) {
+                operands[1] = self.immediate(label.clone());
+            }
+        }
+
+        let byte = match ir {
+            IR::Mov { byte, .. } | IR::Cmp { byte, .. } | IR::Test { byte, .. } => Some(*byte),
+            _ => None,
+        };
+        match syntax {
+            Syntax::Objdump => {
+                // show byte for word registers, and memory
+                let register = match ir.operands().first() {
+                    Some(Operand::Register(r)) => Some(r.is_word_register()),
+                    _ => None,
+                };
+                if byte == Some(true) && register != Some(false) {
+                    operands[0] = format!("byte {}", operands[0]);
+                }
+            }
+            _ => {
+                if let Some(index) = memory.filter(|_| !sized_by_register(ir)) {
+                    let word = match byte {
+                        Some(byte) => !byte,
+                        None => raw.first().map_or(true, |opcode| opcode & 1 == 1),
+                    };
+                    let size = if word { "word" } else { "byte" };
+                    match syntax {
+                        Syntax::Nasm => operands[index] = format!("{} {}", size, operands[index]),
+                        Syntax::Intel => {
+                            operands[index] = format!("{} ptr {}", size, operands[index])
+                        }
+                        Syntax::Att => mnemonic.push(if word { 'w' } else { 'b' }),
+                        Syntax::Ack if !word => mnemonic.push('b'),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        match ir {
+            IR::Jmp { short: true, .. } if matches!(syntax, Syntax::Objdump | Syntax::Nasm) => {
+                mnemonic.push_str(" short")
+            }
+            // Keep NASM from shortening the jump
+            IR::Jmp {
+                dest: Operand::Displacement(_),
+                short: false,
+            } if syntax == Syntax::Nasm => mnemonic.push_str(" near"),
+            IR::Call { dest } | IR::Jmp { dest, .. }
+                if syntax == Syntax::Att && !matches!(dest, Operand::Displacement(_)) =>
+            {
+                operands[0] = format!("*{}", operands[0])
+            }
+            _ => {}
+        }
+
+        let separator = match syntax {
+            Syntax::Ack => ",",
+            _ => ", ",
+        };
+        if syntax == Syntax::Att {
+            operands.reverse();
+        }
+        if operands.is_empty() {
+            mnemonic
+        } else {
+            format!("{} {}", mnemonic, operands.join(separator))
+        }
+    }
+}
+
+/// Return true if a register operand gives the size of the memory operand.
+fn sized_by_register(ir: &IR) -> bool {
+    match ir {
+        // The count register doesn't give the size
+        IR::Shl { dest, .. }
+        | IR::Shr { dest, .. }
+        | IR::Sar { dest, .. }
+        | IR::Rol { dest, .. }
+        | IR::Ror { dest, .. }
+        | IR::Rcl { dest, .. }
+        | IR::Rcr { dest, .. } => matches!(dest, Operand::Register(_)),
+        // Addresses, no memory access
+        IR::Lea { .. } | IR::Lds { .. } | IR::Les { .. } => true,
+        _ => ir
+            .operands()
+            .iter()
+            .any(|o| matches!(o, Operand::Register(_))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(syntax: Syntax, instruction: &Instruction) -> String {
+        Formatter::new(FormatterOptions {
+            syntax,
+            raw_bytes: false,
+            ..FormatterOptions::default()
+        })
+        .format_instruction(instruction)
+    }
+
+    fn bp(disp: i16) -> Operand {
+        Operand::MemoryAddress(Address::new(
+            Some(Register::BP),
+            None,
+            Some(Displacement::Long(disp)),
+        ))
+    }
+
+    #[test]
+    fn test_syntaxes() {
+        let mov = Instruction::new(
+            IR::Mov {
+                dest: bp(-0x14),
+                src: Operand::Register(Register::DX),
+                byte: false,
+            },
+            vec![0x89, 0x56, 0xec],
+        );
+        let inc = Instruction::new(IR::Inc { dest: bp(4) }, vec![0xfe, 0x46, 0x04]);
+        let immediate = Instruction::new(
+            IR::Mov {
+                dest: Operand::Register(Register::BX),
+                src: Operand::LongImmediate(0x10),
+                byte: false,
+            },
+            vec![0xbb, 0x10, 0x00],
+        );
+
+        let expected = [
+            (
+                Syntax::Objdump,
+                "mov [bp-14], dx",
+                "inc [bp+4]",
+                "mov bx, 0010",
+            ),
+            (
+                Syntax::Nasm,
+                "mov [bp-0x14], dx",
+                "inc byte [bp+0x4]",
+                "mov bx, 0x10",
+            ),
+            (
+                Syntax::Intel,
+                "mov [bp-0x14], dx",
+                "inc byte ptr [bp+0x4]",
+                "mov bx, 0x10",
+            ),
+            (
+                Syntax::Att,
+                "mov %dx, -0x14(%bp)",
+                "incb 0x4(%bp)",
+                "mov $0x10, %bx",
+            ),
+            (
+                Syntax::Ack,
+                "mov -0x14(bp),dx",
+                "incb 0x4(bp)",
+                "mov bx,0x10",
+            ),
+        ];
+        for (syntax, mov_text, inc_text, immediate_text) in expected {
+            assert_eq!(format(syntax, &mov), mov_text);
+            assert_eq!(format(syntax, &inc), inc_text);
+            assert_eq!(format(syntax, &immediate), immediate_text);
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        let instruction = Instruction::new(
+            IR::Mov {
+                dest: bp(-0x14),
+                src: Operand::Register(Register::DX),
+                byte: false,
+            },
+            vec![0x89, 0x56, 0xec],
+        );
+        assert_eq!(
+            Formatter::default().format_instruction(&instruction),
+            instruction.to_string()
+        );
+
+        let formatter = Formatter::new(FormatterOptions {
+            syntax: Syntax::Nasm,
+            raw_bytes: true,
+            uppercase: true,
+            signed_displacements: false,
+        });
+        assert_eq!(
+            formatter.format_instruction(&instruction),
+            "8956EC        MOV [BP+0xFFEC], DX"
+        );
+    }
+
+    #[test]
+    fn test_jumps_and_interrupts() {
+        let jmp = IR::Jmp {
+            dest: Operand::Displacement(Displacement::Long(0x0126)),
+            short: true,
+        };
+        let call = IR::Call {
+            dest: Operand::Register(Register::BX),
+        };
+        let int = IR::Int { int_type: 0x20 };
+        let nasm = Formatter::new(FormatterOptions {
+            syntax: Syntax::Nasm,
+            ..FormatterOptions::default()
+        });
+        let att = Formatter::new(FormatterOptions {
+            syntax: Syntax::Att,
+            ..FormatterOptions::default()
+        });
+
+        assert_eq!(Formatter::default().format_ir(&jmp), "jmp short 0126");
+        assert_eq!(nasm.format_ir(&jmp), "jmp short 0x126");
+        assert_eq!(att.format_ir(&jmp), "jmp 0x126");
+        assert_eq!(att.format_ir(&call), "call *%bx");
+        assert_eq!(Formatter::default().format_ir(&int), "int 20");
+        assert_eq!(nasm.format_ir(&int), "int 0x20");
+        assert_eq!(att.format_ir(&int), "int $0x20");
+    }
+}
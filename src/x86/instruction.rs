@@ -1,7 +1,8 @@
-use super::Operand;
+use super::{Address, Formatter, Operand};
 
 /// `IR` is the enum of possible instructions, see `Instruction` as well
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IR {
     Mov {
         dest: Operand,
@@ -253,6 +254,7 @@ pub enum IR {
 
 ///  `Instruction` is the struct that represent a CPU instruction, and holds both the `IR` kind and the raw bytes of the instruction.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instruction {
     pub ir: IR,
     pub raw: Vec<u8>,
@@ -262,6 +264,266 @@ impl Instruction {
     pub fn new(ir: IR, raw: Vec<u8>) -> Self {
         Instruction { ir, raw }
     }
+
+    /// Operands of the instruction, with the address of `mov` between
+    /// the accumulator and memory as a memory operand.
+    pub fn operands(&self) -> Vec<Operand> {
+        let moffs = matches!(self.raw.first(), Some(0xa0..=0xa3));
+        self.ir
+            .operands()
+            .into_iter()
+            .map(|operand| match operand {
+                Operand::LongImmediate(address) if moffs => {
+                    Operand::MemoryAddress(Address::from_word_imm(*address))
+                }
+                operand => *operand,
+            })
+            .collect()
+    }
+
+    /// Data address of a memory operand without base and index register.
+    pub fn direct_address(&self) -> Option<u16> {
+        self.operands()
+            .into_iter()
+            .find_map(|operand| match operand {
+                Operand::MemoryAddress(address)
+                    if address.base.is_none() && address.index.is_none() =>
+                {
+                    address.disp.map(|d| d.into())
+                }
+                _ => None,
+            })
+    }
+}
+
+impl IR {
+    /// Jumps that depend on the flags or on CX, and may fall through to the next instruction.
+    pub fn is_conditional_jump(&self) -> bool {
+        matches!(
+            self,
+            IR::Je { .. }
+                | IR::Jl { .. }
+                | IR::Jle { .. }
+                | IR::Jb { .. }
+                | IR::Jbe { .. }
+                | IR::Jp { .. }
+                | IR::Jo { .. }
+                | IR::Js { .. }
+                | IR::Jne { .. }
+                | IR::Jnl { .. }
+                | IR::Jnle { .. }
+                | IR::Jnb { .. }
+                | IR::Jnbe { .. }
+                | IR::Jnp { .. }
+                | IR::Jno { .. }
+                | IR::Jns { .. }
+                | IR::Loop { .. }
+                | IR::Loopz { .. }
+                | IR::Loopnz { .. }
+                | IR::Jcxz { .. }
+        )
+    }
+
+    /// Intel mnemonic of the instruction, without prefix and size suffix.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            IR::Mov { .. } => "mov",
+            IR::Push { .. } => "push",
+            IR::Pop { .. } => "pop",
+            IR::Xchg { .. } => "xchg",
+            IR::In { .. } => "in",
+            IR::Out { .. } => "out",
+            IR::Xlat => "xlat",
+            IR::Lea { .. } => "lea",
+            IR::Lds { .. } => "lds",
+            IR::Les { .. } => "les",
+            IR::Lahf => "lahf",
+            IR::Sahf => "sahf",
+            IR::Pushf => "pushf",
+            IR::Popf => "popf",
+            IR::Add { .. } => "add",
+            IR::Adc { .. } => "adc",
+            IR::Inc { .. } => "inc",
+            IR::Aaa => "aaa",
+            IR::Baa => "baa",
+            IR::Sub { .. } => "sub",
+            IR::Ssb { .. } => "sbb",
+            IR::Dec { .. } => "dec",
+            IR::Neg { .. } => "neg",
+            IR::Cmp { .. } => "cmp",
+            IR::Aas => "aas",
+            IR::Das => "das",
+            IR::Mul { .. } => "mul",
+            IR::Imul { .. } => "imul",
+            IR::Aam => "aam",
+            IR::Div { .. } => "div",
+            IR::Idiv { .. } => "idiv",
+            IR::Aad => "aad",
+            IR::Cbw => "cbw",
+            IR::Cwd => "cwd",
+            IR::Not { .. } => "not",
+            IR::Shl { .. } => "shl",
+            IR::Shr { .. } => "shr",
+            IR::Sar { .. } => "sar",
+            IR::Rol { .. } => "rol",
+            IR::Ror { .. } => "ror",
+            IR::Rcl { .. } => "rcl",
+            IR::Rcr { .. } => "rcr",
+            IR::And { .. } => "and",
+            IR::Test { .. } => "test",
+            IR::Or { .. } => "or",
+            IR::Xor { .. } => "xor",
+            IR::Movs { word } => {
+                if *word {
+                    "movsw"
+                } else {
+                    "movsb"
+                }
+            }
+            IR::Cmps { word } => {
+                if *word {
+                    "cmpsw"
+                } else {
+                    "cmpsb"
+                }
+            }
+            IR::Scas { word } => {
+                if *word {
+                    "scasw"
+                } else {
+                    "scasb"
+                }
+            }
+            IR::Lods { word } => {
+                if *word {
+                    "lodsw"
+                } else {
+                    "lodsb"
+                }
+            }
+            IR::Stos { word } => {
+                if *word {
+                    "stosw"
+                } else {
+                    "stosb"
+                }
+            }
+            IR::Call { .. } => "call",
+            IR::Jmp { .. } => "jmp",
+            IR::Ret { .. } => "ret",
+            IR::Je { .. } => "je",
+            IR::Jl { .. } => "jl",
+            IR::Jle { .. } => "jle",
+            IR::Jb { .. } => "jb",
+            IR::Jbe { .. } => "jbe",
+            IR::Jp { .. } => "jp",
+            IR::Jo { .. } => "jo",
+            IR::Js { .. } => "js",
+            IR::Jne { .. } => "jne",
+            IR::Jnl { .. } => "jnl",
+            IR::Jnle { .. } => "jnle",
+            IR::Jnb { .. } => "jnb",
+            IR::Jnbe { .. } => "jnbe",
+            IR::Jnp { .. } => "jnp",
+            IR::Jno { .. } => "jno",
+            IR::Jns { .. } => "jns",
+            IR::Loop { .. } => "loop",
+            IR::Loopz { .. } => "loopz",
+            IR::Loopnz { .. } => "loopnz",
+            IR::Jcxz { .. } => "jcxz",
+            IR::Into => "into",
+            IR::Iret => "iret",
+            IR::Clc => "clc",
+            IR::Cmc => "cmc",
+            IR::Stc => "stc",
+            IR::Cld => "cld",
+            IR::Std => "std",
+            IR::Cli => "cli",
+            IR::Sti => "sti",
+            IR::Hlt => "hlt",
+            IR::Wait => "wait",
+            IR::Esc { .. } => "esc",
+            IR::Lock => "lock",
+            IR::Rep { .. } => "rep",
+            IR::Int { .. } => "int",
+            IR::Undefined => "(undefined)",
+        }
+    }
+
+    /// Operands of the instruction, in Intel order: destination first.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            IR::Mov { dest, src, .. } => vec![dest, src],
+            IR::Push { src } => vec![src],
+            IR::Pop { dest } => vec![dest],
+            IR::Xchg { dest, src } => vec![dest, src],
+            IR::In { dest, src } => vec![dest, src],
+            IR::Out { dest, src } => vec![dest, src],
+            IR::Lea { dest, src } => vec![dest, src],
+            IR::Lds { dest, src } => vec![dest, src],
+            IR::Les { dest, src } => vec![dest, src],
+            IR::Add { dest, src } => vec![dest, src],
+            IR::Adc { dest, src } => vec![dest, src],
+            IR::Inc { dest } => vec![dest],
+            IR::Sub { dest, src } => vec![dest, src],
+            IR::Ssb { dest, src } => vec![dest, src],
+            IR::Dec { dest } => vec![dest],
+            IR::Neg { dest } => vec![dest],
+            IR::Cmp { dest, src, .. } => vec![dest, src],
+            IR::Mul { dest } => vec![dest],
+            IR::Imul { dest } => vec![dest],
+            IR::Div { dest } => vec![dest],
+            IR::Idiv { dest } => vec![dest],
+            IR::Not { dest } => vec![dest],
+            IR::Shl { dest, src } => vec![dest, src],
+            IR::Shr { dest, src } => vec![dest, src],
+            IR::Sar { dest, src } => vec![dest, src],
+            IR::Rol { dest, src } => vec![dest, src],
+            IR::Ror { dest, src } => vec![dest, src],
+            IR::Rcl { dest, src } => vec![dest, src],
+            IR::Rcr { dest, src } => vec![dest, src],
+            IR::And { dest, src } => vec![dest, src],
+            IR::Test { dest, src, .. } => vec![dest, src],
+            IR::Or { dest, src } => vec![dest, src],
+            IR::Xor { dest, src } => vec![dest, src],
+            IR::Call { dest } => vec![dest],
+            IR::Jmp { dest, .. } => vec![dest],
+            IR::Ret { src } => src.iter().collect(),
+            IR::Je { dest } => vec![dest],
+            IR::Jl { dest } => vec![dest],
+            IR::Jle { dest } => vec![dest],
+            IR::Jb { dest } => vec![dest],
+            IR::Jbe { dest } => vec![dest],
+            IR::Jp { dest } => vec![dest],
+            IR::Jo { dest } => vec![dest],
+            IR::Js { dest } => vec![dest],
+            IR::Jne { dest } => vec![dest],
+            IR::Jnl { dest } => vec![dest],
+            IR::Jnle { dest } => vec![dest],
+            IR::Jnb { dest } => vec![dest],
+            IR::Jnbe { dest } => vec![dest],
+            IR::Jnp { dest } => vec![dest],
+            IR::Jno { dest } => vec![dest],
+            IR::Jns { dest } => vec![dest],
+            IR::Loop { dest } => vec![dest],
+            IR::Loopz { dest } => vec![dest],
+            IR::Loopnz { dest } => vec![dest],
+            IR::Jcxz { dest } => vec![dest],
+            IR::Esc { dest } => vec![dest],
+            _ => vec![],
+        }
+    }
+
+    /// Text address of a direct jump or call, `None` for other instructions.
+    pub fn branch_target(&self) -> Option<u16> {
+        if !matches!(self, IR::Call { .. } | IR::Jmp { .. }) && !self.is_conditional_jump() {
+            return None;
+        }
+        match self.operands().first() {
+            Some(Operand::Displacement(d)) => Some((*d).into()),
+            _ => None,
+        }
+    }
 }
 
 impl Into<Instruction> for IR {
@@ -272,184 +534,12 @@ impl Into<Instruction> for IR {
 
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{:<14}{}",
-            self.raw
-                .iter()
-                .map(|b| format!("{:02x}", b))
-                .collect::<String>(),
-            self.ir
-        )
+        write!(f, "{}", Formatter::default().format_instruction(self))
     }
 }
 
 impl std::fmt::Display for IR {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            IR::Mov { dest, src, byte } => write!(
-                f,
-                "mov {}{}, {}",
-                // show byte for word registers
-                match dest {
-                    Operand::Register(reg) => {
-                        if reg.is_word_register() && *byte {
-                            "byte "
-                        } else {
-                            ""
-                        }
-                    }
-                    _ =>
-                        if *byte {
-                            "byte "
-                        } else {
-                            ""
-                        },
-                },
-                dest,
-                src
-            ),
-            IR::Push { src } => write!(f, "push {}", src),
-            IR::Pop { dest } => write!(f, "pop {}", dest),
-            IR::Xchg { dest, src } => write!(f, "xchg {}, {}", dest, src),
-            IR::In { dest, src } => write!(f, "in {}, {}", dest, src),
-            IR::Out { dest, src } => write!(f, "out {}, {}", dest, src),
-            IR::Xlat => write!(f, "xlat"),
-            IR::Lea { dest, src } => write!(f, "lea {}, {}", dest, src),
-            IR::Lds { dest, src } => write!(f, "lds {}, {}", dest, src),
-            IR::Les { dest, src } => write!(f, "les {}, {}", dest, src),
-            IR::Lahf => write!(f, "lahf"),
-            IR::Sahf => write!(f, "sahf"),
-            IR::Pushf => write!(f, "pushf"),
-            IR::Popf => write!(f, "popf"),
-            IR::Add { dest, src } => write!(f, "add {}, {}", dest, src),
-            IR::Adc { dest, src } => write!(f, "adc {}, {}", dest, src),
-            IR::Inc { dest } => write!(f, "inc {}", dest),
-            IR::Aaa => write!(f, "aaa"),
-            IR::Baa => write!(f, "baa"),
-            IR::Sub { dest, src } => write!(f, "sub {}, {}", dest, src),
-            IR::Ssb { dest, src } => write!(f, "sbb {}, {}", dest, src),
-            IR::Dec { dest } => write!(f, "dec {}", dest),
-            IR::Neg { dest } => write!(f, "neg {}", dest),
-            IR::Cmp { dest, src, byte } => write!(
-                f,
-                "cmp {}{}, {}",
-                match dest {
-                    Operand::Register(reg) => {
-                        if reg.is_word_register() && *byte {
-                            "byte "
-                        } else {
-                            ""
-                        }
-                    }
-                    _ =>
-                        if *byte {
-                            "byte "
-                        } else {
-                            ""
-                        },
-                },
-                dest,
-                src
-            ),
-            IR::Aas => write!(f, "aas"),
-            IR::Das => write!(f, "das"),
-            IR::Mul { dest } => write!(f, "mul {}", dest),
-            IR::Imul { dest } => write!(f, "imul {}", dest),
-            IR::Aam => write!(f, "aam"),
-            IR::Div { dest } => write!(f, "div {}", dest),
-            IR::Idiv { dest } => write!(f, "idiv {}", dest),
-            IR::Aad => write!(f, "aad"),
-            IR::Cbw => write!(f, "cbw"),
-            IR::Cwd => write!(f, "cwd"),
-            IR::Not { dest } => write!(f, "not {}", dest),
-            IR::Shl { dest, src } => write!(f, "shl {}, {}", dest, src),
-            IR::Shr { dest, src } => write!(f, "shr {}, {}", dest, src),
-            IR::Sar { dest, src } => write!(f, "sar {}, {}", dest, src),
-            IR::Rol { dest, src } => write!(f, "rol {}, {}", dest, src),
-            IR::Ror { dest, src } => write!(f, "ror {}, {}", dest, src),
-            IR::Rcl { dest, src } => write!(f, "rcl {}, {}", dest, src),
-            IR::Rcr { dest, src } => write!(f, "rcr {}, {}", dest, src),
-            IR::And { dest, src } => write!(f, "and {}, {}", dest, src),
-            IR::Test { dest, src, byte } => write!(
-                f,
-                "test {}{}, {}",
-                // show byte for word registers
-                match dest {
-                    Operand::Register(reg) => {
-                        if reg.is_word_register() && *byte {
-                            "byte "
-                        } else {
-                            ""
-                        }
-                    }
-                    _ =>
-                        if *byte {
-                            "byte "
-                        } else {
-                            ""
-                        },
-                },
-                dest,
-                src
-            ),
-            IR::Or { dest, src } => write!(f, "or {}, {}", dest, src),
-            IR::Xor { dest, src } => write!(f, "xor {}, {}", dest, src),
-            IR::Rep { z: _, string_ir } => write!(f, "rep {}", string_ir),
-            IR::Movs { word } => write!(f, "movs{}", if *word { "w" } else { "b" }),
-            IR::Cmps { word } => write!(f, "cmps{}", if *word { "w" } else { "b" }),
-            IR::Scas { word } => write!(f, "scas{}", if *word { "w" } else { "b" }),
-            IR::Lods { word } => write!(f, "lods{}", if *word { "w" } else { "b" }),
-            IR::Stos { word } => write!(f, "stos{}", if *word { "w" } else { "b" }),
-            IR::Call { dest } => write!(f, "call {}", dest),
-            IR::Jmp { dest, short } => {
-                write!(f, "jmp {}{}", if *short { "short " } else { "" }, dest)
-            }
-            IR::Ret { src } => match src {
-                Some(src) => write!(f, "ret {}", src),
-                None => write!(f, "ret"),
-            },
-            IR::Je { dest } => write!(f, "je {}", dest),
-            IR::Jl { dest } => write!(f, "jl {}", dest),
-            IR::Jle { dest } => write!(f, "jle {}", dest),
-            IR::Jb { dest } => write!(f, "jb {}", dest),
-            IR::Jbe { dest } => write!(f, "jbe {}", dest),
-            IR::Jp { dest } => write!(f, "jp {}", dest),
-            IR::Jo { dest } => write!(f, "jo {}", dest),
-            IR::Js { dest } => write!(f, "js {}", dest),
-            IR::Jne { dest } => write!(f, "jne {}", dest),
-            IR::Jnl { dest } => write!(f, "jnl {}", dest),
-            IR::Jnle { dest } => write!(f, "jnle {}", dest),
-            IR::Jnb { dest } => write!(f, "jnb {}", dest),
-            IR::Jnbe { dest } => write!(f, "jnbe {}", dest),
-            IR::Jnp { dest } => write!(f, "jnp {}", dest),
-            IR::Jno { dest } => write!(f, "jno {}", dest),
-            IR::Jns { dest } => write!(f, "jns {}", dest),
-            IR::Loop { dest } => write!(f, "loop {}", dest),
-            IR::Loopz { dest } => write!(f, "loopz {}", dest),
-            IR::Loopnz { dest } => write!(f, "loopnz {}", dest),
-            IR::Jcxz { dest } => write!(f, "jcxz {}", dest),
-            IR::Int { int_type } => {
-                if *int_type == 3 {
-                    write!(f, "int")
-                } else {
-                    write!(f, "int {:02x}", int_type)
-                }
-            }
-            IR::Into => write!(f, "into"),
-            IR::Iret => write!(f, "iret"),
-            IR::Clc => write!(f, "clc"),
-            IR::Cmc => write!(f, "cmc"),
-            IR::Stc => write!(f, "stc"),
-            IR::Cld => write!(f, "cld"),
-            IR::Std => write!(f, "std"),
-            IR::Cli => write!(f, "cli"),
-            IR::Sti => write!(f, "sti"),
-            IR::Hlt => write!(f, "hlt"),
-            IR::Wait => write!(f, "wait"),
-            IR::Esc { dest } => write!(f, "esc {}", dest),
-            IR::Lock => write!(f, "lock"),
-            IR::Undefined => write!(f, "(undefined)"),
-        }
+        write!(f, "{}", Formatter::default().format_ir(self))
     }
 }
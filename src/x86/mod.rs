@@ -1,11 +1,13 @@
 mod address;
 mod displacement;
+mod formatter;
 mod instruction;
 mod operand;
 mod register;
 
 pub use self::address::Address;
 pub use self::displacement::Displacement;
+pub use self::formatter::{Formatter, FormatterOptions, Syntax};
 pub use self::instruction::{Instruction, IR};
 pub use self::operand::Operand;
 pub use self::register::Register;
@@ -5,6 +5,7 @@ use super::Register;
 /// An operand represent the possible values that can be used as an argument for instructions,
 /// such as registers, memory addresses, immediates, ...
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operand {
     Register(Register),
     Immediate(u8),
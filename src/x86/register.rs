@@ -1,5 +1,6 @@
 /// Register enum for x86 architecture.
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Register {
     AL,
     CL,